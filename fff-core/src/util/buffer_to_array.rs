@@ -4,9 +4,10 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-FileCopyrightText: Copyright The Lance Authors
 
-use std::sync::Arc;
+use std::{iter::Peekable, sync::Arc};
 
 use arrow_array::{
+    cast::AsArray,
     new_null_array,
     types::{
         ArrowPrimitiveType, BinaryViewType, ByteArrayType, ByteViewType, Date32Type, Date64Type,
@@ -19,10 +20,13 @@ use arrow_array::{
         TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
     },
     ArrayRef, BooleanArray, FixedSizeBinaryArray, FixedSizeListArray, GenericByteArray,
-    GenericByteViewArray, GenericListArray, NullArray, OffsetSizeTrait, PrimitiveArray,
+    GenericByteViewArray, GenericListArray, MapArray, NullArray, OffsetSizeTrait, PrimitiveArray,
+    StructArray,
 };
-use arrow_buffer::{BooleanBuffer, Buffer, NullBuffer, OffsetBuffer, ScalarBuffer};
-use arrow_schema::{DataType, Field, FieldRef, IntervalUnit, TimeUnit};
+use arrow_buffer::{
+    ArrowNativeType, BooleanBuffer, Buffer, NullBuffer, OffsetBuffer, ScalarBuffer,
+};
+use arrow_schema::{DataType, Field, FieldRef, Fields, IntervalUnit, TimeUnit};
 use bytes::BytesMut;
 use snafu::location;
 
@@ -563,6 +567,315 @@ pub fn primitive_array_from_arrow_buffers_iter(
     }
 }
 
+/// Number of rows of the buffers of an array as passed to [`primitive_array_from_arrow_buffers_iter`],
+/// from the length of the values buffer. None if the buffers do not tell, e.g., for Boolean.
+pub fn num_rows_from_arrow_buffers(data_type: &DataType, buffers: &[Buffer]) -> Option<u64> {
+    let width = match data_type {
+        // Output as views, see above.
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Binary | DataType::LargeBinary => 16,
+        _ => data_type.primitive_width()?,
+    };
+    buffers.get(1).map(|values| (values.len() / width) as u64)
+}
+
+/// Reconstruct an array of any supported type, nested or not, from the buffers output by a
+/// Wasm decoder. The buffers follow the layout in `format/wasm_buffers.md`: arrays in pre-order,
+/// each as its validity (empty if all valid), its Arrow buffers, then its children.
+///
+/// Non-nested types go through [`primitive_array_from_arrow_buffers_iter`], nested arrays are
+/// checked against their declared lengths since the buffers come from an untrusted decoder.
+/// Strings and binaries are read as views, also inside nested types.
+pub fn array_from_arrow_buffers_iter(
+    data_type: &DataType,
+    buffer_iter: impl Iterator<Item = Buffer>,
+    num_rows: u64,
+) -> Result<ArrayRef> {
+    let non_nested = matches!(
+        data_type,
+        DataType::Boolean
+            | DataType::Null
+            | DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Binary
+            | DataType::LargeBinary
+    ) || data_type.is_primitive();
+    if non_nested {
+        primitive_array_from_arrow_buffers_iter(data_type, buffer_iter, num_rows)
+    } else {
+        array_from_buffer_tree(
+            data_type,
+            &mut buffer_iter.peekable(),
+            num_rows as usize,
+            true,
+        )
+    }
+}
+
+/// Reconstruct the array at the front of `buffer_iter` and its children.
+/// `last` is set if no other array follows in pre-order, the array may then take all the
+/// remaining buffers, e.g., the data buffers of views.
+fn array_from_buffer_tree<I: Iterator<Item = Buffer>>(
+    data_type: &DataType,
+    buffer_iter: &mut Peekable<I>,
+    num_rows: usize,
+    last: bool,
+) -> Result<ArrayRef> {
+    match data_type {
+        DataType::Null => {
+            // Null arrays only have their (empty) validity buffer.
+            next_buf(buffer_iter, "Null (validity)")?;
+            Ok(new_null_array(data_type, num_rows))
+        }
+        DataType::Boolean => {
+            let validity = validity_from_buffer_tree(buffer_iter, num_rows, "Boolean (validity)")?;
+            let values = next_buf(buffer_iter, "Boolean (values)")?;
+            let values = sized_buf(values, num_rows.div_ceil(8), "Boolean (values)")?;
+            Ok(Arc::new(BooleanArray::new(
+                BooleanBuffer::new(values, 0, num_rows),
+                validity,
+            )))
+        }
+        _ if data_type.is_primitive() => {
+            let validity = next_buf(buffer_iter, "primitive (validity)")?;
+            if !validity.is_empty() {
+                sized_buf(
+                    validity.clone(),
+                    num_rows.div_ceil(8),
+                    "primitive (validity)",
+                )?;
+            }
+            let width = data_type.primitive_width().unwrap_or_default();
+            let values = next_buf(buffer_iter, "primitive (values)")?;
+            let values = aligned_buf(
+                sized_buf(values, num_rows * width, "primitive (values)")?,
+                width,
+            );
+            primitive_array_from_arrow_buffers_iter(
+                data_type,
+                [validity, values].into_iter(),
+                num_rows as u64,
+            )
+        }
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+            byte_view_array_from_buffer_tree::<StringViewType, _>(buffer_iter, num_rows, last)
+        }
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => {
+            byte_view_array_from_buffer_tree::<BinaryViewType, _>(buffer_iter, num_rows, last)
+        }
+        DataType::FixedSizeBinary(size) => {
+            let validity =
+                validity_from_buffer_tree(buffer_iter, num_rows, "FixedSizeBinary (validity)")?;
+            let values = next_buf(buffer_iter, "FixedSizeBinary (values)")?;
+            let values = sized_buf(
+                values,
+                num_rows * fixed_size(*size)?,
+                "FixedSizeBinary (values)",
+            )?;
+            Ok(Arc::new(FixedSizeBinaryArray::try_new(
+                *size, values, validity,
+            )?))
+        }
+        DataType::List(field) => {
+            list_from_buffer_tree::<i32, _>(field, buffer_iter, num_rows, last)
+        }
+        DataType::LargeList(field) => {
+            list_from_buffer_tree::<i64, _>(field, buffer_iter, num_rows, last)
+        }
+        DataType::FixedSizeList(field, size) => {
+            let validity =
+                validity_from_buffer_tree(buffer_iter, num_rows, "FixedSizeList (validity)")?;
+            let values = array_from_buffer_tree(
+                field.data_type(),
+                buffer_iter,
+                num_rows * fixed_size(*size)?,
+                last,
+            )?;
+            Ok(Arc::new(FixedSizeListArray::try_new(
+                with_child_type(field, &values),
+                *size,
+                values,
+                validity,
+            )?))
+        }
+        DataType::Map(field, sorted) => {
+            let validity = validity_from_buffer_tree(buffer_iter, num_rows, "Map (validity)")?;
+            let offsets = next_buf(buffer_iter, "Map (offsets)")?;
+            let offsets = offsets_from_buffer::<i32>(offsets, num_rows, "Map (offsets)")?;
+            let entries = array_from_buffer_tree(
+                field.data_type(),
+                buffer_iter,
+                offsets.last().as_usize(),
+                last,
+            )?;
+            let field = with_child_type(field, &entries);
+            let entries = entries
+                .as_struct_opt()
+                .ok_or_else(|| Error::General("Map entries must be a Struct".to_string()))?
+                .clone();
+            Ok(Arc::new(MapArray::try_new(
+                field, offsets, entries, validity, *sorted,
+            )?))
+        }
+        DataType::Struct(fields) => {
+            let validity = validity_from_buffer_tree(buffer_iter, num_rows, "Struct (validity)")?;
+            let children = fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    array_from_buffer_tree(
+                        field.data_type(),
+                        buffer_iter,
+                        num_rows,
+                        last && i + 1 == fields.len(),
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if children.is_empty() {
+                return Ok(Arc::new(StructArray::new_empty_fields(num_rows, validity)));
+            }
+            let fields = fields
+                .iter()
+                .zip(&children)
+                .map(|(field, child)| with_child_type(field, child))
+                .collect::<Fields>();
+            Ok(Arc::new(StructArray::try_new(fields, children, validity)?))
+        }
+        _ => Err(Error::IO(
+            format!(
+                "The data type {} cannot be decoded from Wasm output buffers",
+                data_type
+            ),
+            location!(),
+        )),
+    }
+}
+
+/// The values of a List are decoded with the child column when its EncUnit only holds the
+/// validity and the offsets, as for the built-in encoding. They are then dummy nulls as in
+/// [`new_list_offsets_validity_from_buffer_iter`].
+fn list_from_buffer_tree<O: OffsetSizeTrait, I: Iterator<Item = Buffer>>(
+    field: &FieldRef,
+    buffer_iter: &mut Peekable<I>,
+    num_rows: usize,
+    last: bool,
+) -> Result<ArrayRef> {
+    let validity = validity_from_buffer_tree(buffer_iter, num_rows, "List (validity)")?;
+    let offsets = next_buf(buffer_iter, "List (offsets)")?;
+    let offsets = offsets_from_buffer::<O>(offsets, num_rows, "List (offsets)")?;
+    let num_values = offsets.last().as_usize();
+    let (field, values) = if last && buffer_iter.peek().is_none() {
+        (
+            DUMMY_NULL_FIELD.clone(),
+            Arc::new(NullArray::new(num_values)) as ArrayRef,
+        )
+    } else {
+        let values = array_from_buffer_tree(field.data_type(), buffer_iter, num_values, last)?;
+        (with_child_type(field, &values), values)
+    };
+    Ok(Arc::new(GenericListArray::<O>::try_new(
+        field, offsets, values, validity,
+    )?))
+}
+
+/// Views longer than 12 bytes refer to their data buffer by index, so an array that is not the
+/// last one takes as many data buffers as the largest index it refers to.
+fn byte_view_array_from_buffer_tree<T: ByteViewType + ?Sized, I: Iterator<Item = Buffer>>(
+    buffer_iter: &mut Peekable<I>,
+    num_rows: usize,
+    last: bool,
+) -> Result<ArrayRef> {
+    let validity = validity_from_buffer_tree(buffer_iter, num_rows, "view (validity)")?;
+    let views = next_buf(buffer_iter, "view (views)")?;
+    let views = aligned_buf(sized_buf(views, num_rows * 16, "view (views)")?, 16);
+    let views = ScalarBuffer::<u128>::new(views, 0, num_rows);
+    let data_buffers = if last {
+        buffer_iter.collect::<Vec<_>>()
+    } else {
+        let num_data_buffers = views
+            .iter()
+            .filter(|view| (**view as u32) > 12)
+            .map(|view| (*view >> 64) as u32 as usize + 1)
+            .max()
+            .unwrap_or(0);
+        (0..num_data_buffers)
+            .map(|_| next_buf(buffer_iter, "view (data)"))
+            .collect::<Result<Vec<_>>>()?
+    };
+    Ok(Arc::new(GenericByteViewArray::<T>::try_new(
+        views,
+        data_buffers,
+        validity,
+    )?))
+}
+
+fn validity_from_buffer_tree(
+    buffer_iter: &mut impl Iterator<Item = Buffer>,
+    num_rows: usize,
+    context: &str,
+) -> Result<Option<NullBuffer>> {
+    let validity = next_buf(buffer_iter, context)?;
+    if validity.is_empty() {
+        return Ok(None);
+    }
+    let validity = sized_buf(validity, num_rows.div_ceil(8), context)?;
+    Ok(Some(NullBuffer::new(BooleanBuffer::new(
+        validity, 0, num_rows,
+    ))))
+}
+
+fn offsets_from_buffer<O: OffsetSizeTrait>(
+    buffer: Buffer,
+    num_rows: usize,
+    context: &str,
+) -> Result<OffsetBuffer<O>> {
+    let width = std::mem::size_of::<O>();
+    let buffer = aligned_buf(sized_buf(buffer, (num_rows + 1) * width, context)?, width);
+    let offsets = ScalarBuffer::<O>::new(buffer, 0, num_rows + 1);
+    if offsets[0] < O::usize_as(0) || offsets.windows(2).any(|w| w[0] > w[1]) {
+        return Err(Error::General(format!(
+            "Offsets in {} must be non-negative and non-decreasing",
+            context
+        )));
+    }
+    Ok(OffsetBuffer::new(offsets))
+}
+
+/// Check that `buffer` holds at least `len` bytes and cut it to `len` bytes.
+fn sized_buf(buffer: Buffer, len: usize, context: &str) -> Result<Buffer> {
+    if buffer.len() < len {
+        return Err(Error::General(format!(
+            "Buffer in {} has {} bytes, expected {}",
+            context,
+            buffer.len(),
+            len
+        )));
+    }
+    Ok(buffer.slice_with_length(0, len))
+}
+
+/// Copy `buffer` if it is not aligned for values of `width` bytes.
+fn aligned_buf(buffer: Buffer, width: usize) -> Buffer {
+    if width == 0 || buffer.as_ptr().align_offset(width.min(16)) == 0 {
+        buffer
+    } else {
+        Buffer::from_slice_ref(buffer.as_slice())
+    }
+}
+
+fn fixed_size(size: i32) -> Result<usize> {
+    usize::try_from(size).map_err(|_| Error::General(format!("Invalid fixed size {}", size)))
+}
+
+/// `field` with the type of the reconstructed child, e.g., with views instead of strings.
+fn with_child_type(field: &FieldRef, child: &ArrayRef) -> FieldRef {
+    Arc::new(
+        field
+            .as_ref()
+            .clone()
+            .with_data_type(child.data_type().clone()),
+    )
+}
+
 pub fn primitive_array_from_buffers(
     data_type: &DataType,
     buffers: Vec<BytesMut>,
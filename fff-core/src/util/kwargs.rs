@@ -0,0 +1,45 @@
+//! Serialization of the kwargs passed to a stateful Wasm decoder, see format/kwargs.md.
+//! Shared by the host, which serializes them, and the decoders, which parse them.
+
+/// num_keys (i32)
+/// key_lens (i32 * num_keys)
+/// word_lens (i32 * num_keys)
+/// key-word * num_keys (var len)
+pub fn kwargs_serialize(kwargs: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let num_keys = kwargs.len() as i32;
+    let mut key_lens = Vec::with_capacity(kwargs.len());
+    let mut word_lens = Vec::with_capacity(kwargs.len());
+
+    for &(key, word) in kwargs {
+        key_lens.push(key.len() as i32);
+        word_lens.push(word.len() as i32);
+    }
+
+    let mut result = Vec::new();
+
+    // Serialize num_keys
+    result.extend_from_slice(&num_keys.to_le_bytes());
+
+    // Serialize key_lens array
+    for len in &key_lens {
+        result.extend_from_slice(&len.to_le_bytes());
+    }
+
+    // Serialize word_lens array
+    for len in &word_lens {
+        result.extend_from_slice(&len.to_le_bytes());
+    }
+
+    // Serialize each key and word in order
+    for &(key, word) in kwargs {
+        result.extend_from_slice(key);
+        result.extend_from_slice(word);
+    }
+
+    result
+}
+
+/// Keys of the kwargs, see format/kwargs.md.
+pub const SPD: &str = "spd";
+pub const PPD: &str = "ppd";
+pub const PARTIAL_DECODE: &str = "partial_decode";
@@ -1,2 +1,3 @@
 pub mod bit_util;
 pub mod buffer_to_array;
+pub mod kwargs;
@@ -0,0 +1,144 @@
+/// Reconstruction of nested arrays from the buffers output by Wasm decoders,
+/// laid out as in format/wasm_buffers.md.
+
+#[cfg(test)]
+mod nested_type_tests {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        Array, ArrayRef, Int32Array, Int64Array, ListArray, NullArray, StringViewArray, StructArray,
+    };
+    use arrow_buffer::{Buffer, OffsetBuffer};
+    use arrow_schema::{DataType, Field, Fields};
+    use fff_core::util::buffer_to_array::{array_from_arrow_buffers_iter, DUMMY_NULL_FIELD};
+
+    fn view_buffers(array: &StringViewArray) -> Vec<Buffer> {
+        [
+            vec![Buffer::from_vec(Vec::<u8>::new())],
+            vec![array.views().inner().clone()],
+            array.data_buffers().to_vec(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_list_of_struct() {
+        let strings = StringViewArray::from(vec!["x", "a string longer than 12 bytes", "y"]);
+        let buffers = [
+            // List: validity, offsets
+            vec![
+                Buffer::from_vec(Vec::<u8>::new()),
+                Buffer::from_vec(vec![0i32, 1, 3]),
+            ],
+            // Struct: validity
+            vec![Buffer::from_vec(Vec::<u8>::new())],
+            // Int32 with a null
+            vec![
+                Buffer::from_vec(vec![0b011u8]),
+                Buffer::from_vec(vec![1i32, 2, 0]),
+            ],
+            view_buffers(&strings),
+        ]
+        .concat();
+        let data_type = DataType::List(Arc::new(Field::new(
+            "item",
+            DataType::Struct(Fields::from(vec![
+                Field::new("a", DataType::Int32, true),
+                Field::new("b", DataType::Utf8, true),
+            ])),
+            true,
+        )));
+
+        let array = array_from_arrow_buffers_iter(&data_type, buffers.into_iter(), 2).unwrap();
+
+        let values = StructArray::from(vec![
+            (
+                Arc::new(Field::new("a", DataType::Int32, true)),
+                Arc::new(Int32Array::from(vec![Some(1), Some(2), None])) as ArrayRef,
+            ),
+            (
+                Arc::new(Field::new("b", DataType::Utf8View, true)),
+                Arc::new(strings) as ArrayRef,
+            ),
+        ]);
+        let expected = ListArray::new(
+            Arc::new(Field::new("item", values.data_type().clone(), true)),
+            OffsetBuffer::new(vec![0, 1, 3].into()),
+            Arc::new(values),
+            None,
+        );
+        assert_eq!(array.as_ref(), &expected as &dyn Array);
+    }
+
+    #[test]
+    fn test_views_before_other_arrays() {
+        let strings = StringViewArray::from(vec!["a string longer than 12 bytes", "b"]);
+        let buffers = [
+            vec![Buffer::from_vec(Vec::<u8>::new())],
+            view_buffers(&strings),
+            vec![
+                Buffer::from_vec(Vec::<u8>::new()),
+                Buffer::from_vec(vec![7i64, 8]),
+            ],
+        ]
+        .concat();
+        let data_type = DataType::Struct(Fields::from(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("i", DataType::Int64, true),
+        ]));
+
+        let array = array_from_arrow_buffers_iter(&data_type, buffers.into_iter(), 2).unwrap();
+
+        let array = array.as_any().downcast_ref::<StructArray>().unwrap();
+        assert_eq!(array.column(0).as_ref(), &strings as &dyn Array);
+        assert_eq!(
+            array.column(1).as_ref(),
+            &Int64Array::from(vec![7, 8]) as &dyn Array
+        );
+    }
+
+    #[test]
+    fn test_list_without_values() {
+        let buffers = vec![
+            Buffer::from_vec(vec![0b101u8]),
+            Buffer::from_vec(vec![0i32, 2, 2, 5]),
+        ];
+        let data_type = DataType::List(Arc::new(Field::new("item", DataType::Int32, true)));
+
+        let array = array_from_arrow_buffers_iter(&data_type, buffers.into_iter(), 3).unwrap();
+
+        let array = array.as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(array.data_type(), &DataType::List(DUMMY_NULL_FIELD.clone()));
+        assert_eq!(array.null_count(), 1);
+        assert_eq!(array.values().as_ref(), &NullArray::new(5) as &dyn Array);
+    }
+
+    #[test]
+    fn test_invalid_offsets() {
+        let data_type = DataType::List(Arc::new(Field::new("item", DataType::Int32, true)));
+        // Too short for 3 rows.
+        let buffers = vec![
+            Buffer::from_vec(Vec::<u8>::new()),
+            Buffer::from_vec(vec![0i32, 2]),
+        ];
+        assert!(array_from_arrow_buffers_iter(&data_type, buffers.into_iter(), 3).is_err());
+        // Decreasing.
+        let buffers = vec![
+            Buffer::from_vec(Vec::<u8>::new()),
+            Buffer::from_vec(vec![0i32, 2, 1]),
+        ];
+        assert!(array_from_arrow_buffers_iter(&data_type, buffers.into_iter(), 2).is_err());
+    }
+
+    #[test]
+    fn test_missing_child_buffers() {
+        // The Int32 child is missing its values.
+        let buffers = vec![
+            Buffer::from_vec(Vec::<u8>::new()),
+            Buffer::from_vec(Vec::<u8>::new()),
+        ];
+        let data_type =
+            DataType::Struct(Fields::from(vec![Field::new("a", DataType::Int32, true)]));
+        assert!(array_from_arrow_buffers_iter(&data_type, buffers.into_iter(), 1).is_err());
+    }
+}
@@ -1,10 +1,13 @@
-use std::{io::Cursor, rc::Rc};
+use std::{io::Cursor, ops::Range, rc::Rc};
 
 use arrow_array::ArrayRef;
 use arrow_buffer::Buffer;
 use byteorder::{LittleEndian, ReadBytesExt};
 use bytes::Bytes;
-use fff_core::{errors::Result, nyi_err};
+use fff_core::{
+    errors::{Error, Result},
+    nyi_err,
+};
 use rkyv::{Archive, Deserialize as rkyvDe, Serialize as rkyvSer};
 
 use crate::enc_unit::{EncUnit, Encoding, ALIGNMENT};
@@ -49,12 +52,54 @@ pub trait Decoder {
 )]
 // Derives can be passed through to the generated type:
 #[archive_attr(derive(Debug))]
-pub(crate) struct EncUnitMetadata {
+pub struct EncUnitMetadata {
     num_values: u32,
     mini_blocks_offsets: Vec<u32>,
     metadata: Option<Vec<u8>>,
 }
 
+impl EncUnitMetadata {
+    /// Metadata of `num_values` values stored as consecutive mini blocks of `mini_block_sizes` bytes.
+    pub fn with_mini_block_sizes(num_values: u32, mini_block_sizes: &[u32]) -> Self {
+        let mut mini_blocks_offsets = Vec::with_capacity(mini_block_sizes.len() + 1);
+        mini_blocks_offsets.push(0);
+        let mut offset = 0;
+        for size in mini_block_sizes {
+            offset += size;
+            mini_blocks_offsets.push(offset);
+        }
+        Self {
+            num_values,
+            mini_blocks_offsets,
+            metadata: None,
+        }
+    }
+
+    pub fn num_values(&self) -> u32 {
+        self.num_values
+    }
+
+    /// Byte offset of each mini block, followed by the end of the last one.
+    pub fn mini_blocks_offsets(&self) -> &[u32] {
+        &self.mini_blocks_offsets
+    }
+
+    pub fn num_mini_blocks(&self) -> usize {
+        self.mini_blocks_offsets.len().saturating_sub(1)
+    }
+
+    /// Byte range of the mini blocks in `mini_blocks`, None if out of bounds.
+    pub fn mini_blocks_range(&self, mini_blocks: Range<usize>) -> Option<Range<usize>> {
+        if mini_blocks.start > mini_blocks.end || mini_blocks.end > self.num_mini_blocks() {
+            return None;
+        }
+        Some(
+            self.mini_blocks_offsets[mini_blocks.start] as usize
+                ..self.mini_blocks_offsets[mini_blocks.end] as usize,
+        )
+    }
+}
+
 pub fn encode_to_bytes(encoder: Rc<dyn Encoder>, arr: ArrayRef) -> Bytes {
     let encblock = encoder
         .encode(arr)
@@ -66,6 +111,35 @@ pub fn encode_to_bytes(encoder: Rc<dyn Encoder>, arr: ArrayRef) -> Bytes {
         .expect("encode_to_bytes: serialization failed");
     buffer.into()
 }
+/// Encode `arr` as independent mini blocks of `mini_block_len` values, so that each one can be decoded on its own.
+/// Returns the encoded mini blocks, along with the metadata recording their offsets.
+pub fn encode_mini_blocks(
+    encoder: Rc<dyn Encoder>,
+    arr: ArrayRef,
+    mini_block_len: usize,
+) -> Result<(Vec<Bytes>, EncUnitMetadata)> {
+    if mini_block_len == 0 {
+        return Err(Error::General("Invalid mini block length 0".to_string()));
+    }
+    let mini_blocks = (0..arr.len())
+        .step_by(mini_block_len)
+        .map(|offset| {
+            let len = std::cmp::min(mini_block_len, arr.len() - offset);
+            let mut buffer = Vec::new();
+            encoder
+                .encode(arr.slice(offset, len))?
+                .try_serialize(Cursor::new(&mut buffer))?;
+            Ok(Bytes::from(buffer))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let sizes = mini_blocks
+        .iter()
+        .map(|b| b.len() as u32)
+        .collect::<Vec<_>>();
+    let metadata = EncUnitMetadata::with_mini_block_sizes(arr.len() as u32, &sizes);
+    Ok((mini_blocks, metadata))
+}
+
 pub(crate) struct NonNullDecoderState {
     _vector_index: usize,
     metadata_bytes: Bytes,
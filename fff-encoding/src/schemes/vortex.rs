@@ -51,17 +51,32 @@ pub struct VortexFooter {
 
 pub struct VortexEncoder {
     enable_dict: bool,
+    /// Ids of the Vortex compressors not to try, e.g., "vortex.runend".
+    excluded_compressors: Vec<String>,
 }
 
 impl VortexEncoder {
     pub fn new(enable_dict: bool) -> Self {
-        Self { enable_dict }
+        Self {
+            enable_dict,
+            excluded_compressors: vec![],
+        }
+    }
+
+    pub fn with_excluded_compressors(mut self, excluded_compressors: Vec<String>) -> Self {
+        self.excluded_compressors = excluded_compressors;
+        self
+    }
+
+    /// Whether `id` is the id of a compressor the encoder tries, and can thus be excluded.
+    pub fn is_known_compressor(id: &str) -> bool {
+        DEFAULT_COMPRESSORS.iter().any(|c| c.id() == id)
     }
 }
 
 impl Default for VortexEncoder {
     fn default() -> Self {
-        Self { enable_dict: true }
+        Self::new(true)
     }
 }
 
@@ -70,14 +85,20 @@ impl VortexEncoder {
         let compress_options = CompressConfig::default();
         // .with_sample_size(512)
         // .with_sample_count(32);
+        let compressors = DEFAULT_COMPRESSORS.into_iter().filter(|c| {
+            !self
+                .excluded_compressors
+                .iter()
+                .any(|id| id.as_str() == c.id())
+        });
         let compressor: &dyn CompressionStrategy = if self.enable_dict {
             &SamplingCompressor::new_with_options(
-                vortex_array::aliases::hash_set::HashSet::from_iter(DEFAULT_COMPRESSORS),
+                vortex_array::aliases::hash_set::HashSet::from_iter(compressors),
                 compress_options,
             )
         } else {
             &SamplingCompressor::new_with_options(
-                vortex_array::aliases::hash_set::HashSet::from_iter(DEFAULT_COMPRESSORS),
+                vortex_array::aliases::hash_set::HashSet::from_iter(compressors),
                 compress_options,
             )
             .excluding(&DictCompressor)
@@ -241,6 +262,22 @@ pub fn vortex_deser(encblock: &mut Bytes, context: Arc<Context>) -> Result<Array
     Ok(array_reader.into_array(context, dtype)?)
 }
 
+/// Decode all the arrays serialized back to back in an EncUnit to Arrow, e.g., the validity
+/// and the offsets of a List EncUnit.
+pub fn vortex_deser_all_to_arrow(
+    mut encblock: Bytes,
+    context: Arc<Context>,
+) -> Result<Vec<ArrayRef>> {
+    let mut arrays = vec![];
+    while !encblock.is_empty() {
+        arrays.push(vortex_array_to_arrow(vortex_deser(
+            &mut encblock,
+            context.clone(),
+        )?)?);
+    }
+    Ok(arrays)
+}
+
 /// For testing usage here, only limited expressions for now.
 pub struct VtxPPD {
     right: Scalar,
@@ -645,6 +682,29 @@ mod tests {
         assert_eq!(*arr, *decoded);
     }
 
+    #[test]
+    fn test_vortex_mini_blocks() {
+        use super::*;
+        use crate::schemes::encode_mini_blocks;
+        let arr = Arc::new(UInt32Array::from_iter_values((0..5000).map(|x| x * 7))) as ArrayRef;
+        let enc = Rc::new(VortexEncoder::default()) as Rc<dyn Encoder>;
+        let (mini_blocks, metadata) = encode_mini_blocks(enc, arr.clone(), 2048).unwrap();
+        assert_eq!(mini_blocks.len(), 3);
+        assert_eq!(metadata.num_values(), 5000);
+        assert_eq!(metadata.mini_blocks_offsets().len(), 4);
+        let data = mini_blocks.concat();
+        for (i, offset) in [0, 2048, 4096].into_iter().enumerate() {
+            let range = metadata.mini_blocks_range(i..i + 1).unwrap();
+            assert_eq!(range.len(), mini_blocks[i].len());
+            let bytes = Bytes::copy_from_slice(&data[range]);
+            let mut dec = VortexDecoder::try_new(bytes, ALL_ENCODINGS_CONTEXT.clone()).unwrap();
+            let decoded = dec.decode_all_as_array().unwrap();
+            let len = std::cmp::min(2048, 5000 - offset);
+            assert_eq!(*arr.slice(offset, len), *decoded);
+        }
+        assert!(metadata.mini_blocks_range(2..4).is_none());
+    }
+
     #[test]
     fn test_vortex_long_rle() {
         use super::*;
@@ -5,6 +5,8 @@ pub mod File;
 pub const MAJOR_VERSION: u16 = 0;
 pub const MINOR_VERSION: u16 = 1;
 pub const MAGIC: &[u8; 2] = b"F3";
+/// Magic of files whose footer is encrypted.
+pub const ENCRYPTED_FOOTER_MAGIC: &[u8; 2] = b"E3";
 pub const POSTSCRIPT_SIZE: u64 = 32;
 
 pub trait ToFlatBuffer {
@@ -0,0 +1,572 @@
+//! Split-block Bloom filters (SBBF) for point lookups.
+//! The bitset layout and hashing follow Parquet's [Bloom filter spec](https://github.com/apache/parquet-format/blob/master/BloomFilter.md),
+//! so a filter is a sequence of 256-bit blocks and each value sets one bit in each of the 8 words of a block.
+//!
+//! Filters are built per physical column per row group, and optionally per IOUnit.
+//! They are stored in the "BloomFilters" optional metadata section.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use arrow::array::AsArray;
+use arrow::compute::cast;
+use arrow_array::{Array, ArrayRef};
+use arrow_schema::{DataType, Schema};
+use byteorder::{ByteOrder, LittleEndian};
+use fff_core::errors::{Error, Result};
+use fff_core::non_nest_types;
+use fff_format::File::fff::flatbuf as fb;
+use fff_format::ToFlatBuffer;
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
+use tracing::debug;
+use xxhash_rust::xxh64::xxh64;
+
+use crate::file::footer::MetadataSection;
+use crate::io::reader::Reader;
+
+pub const DEFAULT_BLOOM_FILTER_NDV: u64 = 1_000_000;
+pub const DEFAULT_BLOOM_FILTER_FPP: f64 = 0.05;
+pub(crate) const BLOOM_FILTERS_SECTION_NAME: &str = "BloomFilters";
+
+const BITSET_MIN_LENGTH: usize = 32;
+const BITSET_MAX_LENGTH: usize = 128 * 1024 * 1024;
+const SALT: [u32; 8] = [
+    0x47b6137b, 0x44974d91, 0x8824ad5b, 0xa2b7289d, 0x705495c7, 0x2df1424b, 0x9efc4947, 0x5c6bfb31,
+];
+
+/// Per-column Bloom filter configuration.
+#[derive(Clone, Copy, Debug)]
+pub struct BloomFilterOptions {
+    /// Expected number of distinct values in a row group. Used to size the row group filter.
+    ndv: u64,
+    /// Target false positive probability.
+    fpp: f64,
+    /// Also build a filter for each IOUnit, sized by the distinct values actually in it.
+    per_iounit: bool,
+}
+
+impl Default for BloomFilterOptions {
+    fn default() -> Self {
+        Self {
+            ndv: DEFAULT_BLOOM_FILTER_NDV,
+            fpp: DEFAULT_BLOOM_FILTER_FPP,
+            per_iounit: false,
+        }
+    }
+}
+
+impl BloomFilterOptions {
+    pub fn new(ndv: u64, fpp: f64) -> Self {
+        Self {
+            ndv,
+            fpp,
+            per_iounit: false,
+        }
+    }
+
+    pub fn with_per_iounit(mut self, per_iounit: bool) -> Self {
+        self.per_iounit = per_iounit;
+        self
+    }
+
+    pub fn ndv(&self) -> u64 {
+        self.ndv
+    }
+
+    pub fn fpp(&self) -> f64 {
+        self.fpp
+    }
+
+    pub fn per_iounit(&self) -> bool {
+        self.per_iounit
+    }
+}
+
+type Block = [u32; 8];
+
+fn block_mask(key: u32) -> Block {
+    let mut mask = [0u32; 8];
+    for (m, salt) in mask.iter_mut().zip(SALT) {
+        *m = 1 << (key.wrapping_mul(salt) >> 27);
+    }
+    mask
+}
+
+/// A split-block Bloom filter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sbbf(Vec<Block>);
+
+impl Sbbf {
+    /// Create a filter sized for `ndv` distinct values with false positive probability `fpp`.
+    pub fn new_with_ndv_fpp(ndv: u64, fpp: f64) -> Result<Self> {
+        if fpp <= 0.0 || fpp >= 1.0 {
+            return Err(Error::General(format!(
+                "Bloom filter fpp should be in (0, 1), got {}",
+                fpp
+            )));
+        }
+        let num_bits = -8.0 * ndv as f64 / (1.0 - fpp.powf(1.0 / 8.0)).ln();
+        let num_bytes = (num_bits as usize / 8)
+            .clamp(BITSET_MIN_LENGTH, BITSET_MAX_LENGTH)
+            .next_power_of_two();
+        Ok(Self(vec![[0; 8]; num_bytes / BITSET_MIN_LENGTH]))
+    }
+
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.is_empty() || bytes.len() % BITSET_MIN_LENGTH != 0 {
+            return Err(Error::ParseError(format!(
+                "Bloom filter bitset size {} is not a positive multiple of {}",
+                bytes.len(),
+                BITSET_MIN_LENGTH
+            )));
+        }
+        Ok(Self(
+            bytes
+                .chunks_exact(BITSET_MIN_LENGTH)
+                .map(|chunk| {
+                    let mut block = [0u32; 8];
+                    LittleEndian::read_u32_into(chunk, &mut block);
+                    block
+                })
+                .collect(),
+        ))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; self.0.len() * BITSET_MIN_LENGTH];
+        for (chunk, block) in bytes.chunks_exact_mut(BITSET_MIN_LENGTH).zip(&self.0) {
+            LittleEndian::write_u32_into(block, chunk);
+        }
+        bytes
+    }
+
+    fn block_index(&self, hash: u64) -> usize {
+        (((hash >> 32) * self.0.len() as u64) >> 32) as usize
+    }
+
+    pub fn insert_hash(&mut self, hash: u64) {
+        let idx = self.block_index(hash);
+        let mask = block_mask(hash as u32);
+        for (word, m) in self.0[idx].iter_mut().zip(mask) {
+            *word |= m;
+        }
+    }
+
+    pub fn check_hash(&self, hash: u64) -> bool {
+        let idx = self.block_index(hash);
+        let mask = block_mask(hash as u32);
+        self.0[idx].iter().zip(mask).all(|(word, m)| word & m != 0)
+    }
+}
+
+/// Hash each value of a non-nested array, or of a DictionaryArray of one. Null values are `None`.
+/// Fixed-width values are hashed on their little-endian bytes, variable-width ones on their raw bytes.
+pub(crate) fn hash_array(array: &dyn Array) -> Result<Vec<Option<u64>>> {
+    let hash = |v: &[u8]| xxh64(v, 0);
+    Ok(match array.data_type() {
+        DataType::Boolean => array
+            .as_boolean()
+            .iter()
+            .map(|v| v.map(|v| hash(&[v as u8])))
+            .collect(),
+        DataType::Utf8 => array
+            .as_string::<i32>()
+            .iter()
+            .map(|v| v.map(|v| hash(v.as_bytes())))
+            .collect(),
+        DataType::LargeUtf8 => array
+            .as_string::<i64>()
+            .iter()
+            .map(|v| v.map(|v| hash(v.as_bytes())))
+            .collect(),
+        DataType::Binary => array
+            .as_binary::<i32>()
+            .iter()
+            .map(|v| v.map(hash))
+            .collect(),
+        DataType::LargeBinary => array
+            .as_binary::<i64>()
+            .iter()
+            .map(|v| v.map(hash))
+            .collect(),
+        non_nest_types!() => {
+            let width = array.data_type().primitive_width().ok_or_else(|| {
+                Error::General(format!(
+                    "Cannot hash values of type {} for Bloom filter",
+                    array.data_type()
+                ))
+            })?;
+            let data = array.to_data();
+            let values = &data.buffers()[0].as_slice()
+                [data.offset() * width..(data.offset() + data.len()) * width];
+            values
+                .chunks_exact(width)
+                .enumerate()
+                .map(|(i, v)| array.is_valid(i).then(|| hash(v)))
+                .collect()
+        }
+        DataType::Dictionary(_, _) => {
+            // Each value of the dictionary is hashed once.
+            let dictionary = array.as_any_dictionary();
+            let value_hashes = hash_array(dictionary.values().as_ref())?;
+            let nulls = array.logical_nulls();
+            dictionary
+                .normalized_keys()
+                .into_iter()
+                .enumerate()
+                .map(|(i, key)| {
+                    if nulls.as_ref().is_some_and(|nulls| nulls.is_null(i)) {
+                        None
+                    } else {
+                        value_hashes.get(key).copied().flatten()
+                    }
+                })
+                .collect()
+        }
+        other => {
+            return Err(Error::NYI(format!(
+                "Bloom filter for data type {:?}",
+                other
+            )))
+        }
+    })
+}
+
+struct ColumnBloomFilterBuilder {
+    field_id: usize,
+    options: BloomFilterOptions,
+    filter: Sbbf,
+    /// Hashes of rows not yet flushed in an IOUnit. Only kept if per-IOUnit filters are enabled.
+    pending_hashes: VecDeque<Option<u64>>,
+    iounit_filters: Vec<Sbbf>,
+}
+
+impl ColumnBloomFilterBuilder {
+    fn try_new(field_id: usize, options: BloomFilterOptions) -> Result<Self> {
+        Ok(Self {
+            field_id,
+            options,
+            filter: Sbbf::new_with_ndv_fpp(options.ndv, options.fpp)?,
+            pending_hashes: VecDeque::new(),
+            iounit_filters: vec![],
+        })
+    }
+}
+
+/// Bloom filters of a physical column inside a row group, ready to be written.
+pub(crate) struct ColumnBloomFilter {
+    pub(crate) row_group_idx: u32,
+    pub(crate) field_id: u32,
+    pub(crate) column_idx: u32,
+    pub(crate) filter: Sbbf,
+    pub(crate) iounit_filters: Vec<Sbbf>,
+}
+
+/// Collects hashes of the configured columns during writing, and builds their filters
+/// at IOUnit and row group boundaries.
+#[derive(Default)]
+pub(crate) struct BloomFilterWriter {
+    /// Root-level field id to its physical column index and options.
+    columns: HashMap<usize, (u32, BloomFilterOptions)>,
+    /// Filters of the current row group, keyed by physical column index.
+    cur_row_group: BTreeMap<u32, ColumnBloomFilterBuilder>,
+    num_row_groups: u32,
+    finished: Vec<ColumnBloomFilter>,
+}
+
+impl BloomFilterWriter {
+    pub(crate) fn try_new(columns: HashMap<usize, (u32, BloomFilterOptions)>) -> Result<Self> {
+        let mut res = Self {
+            columns,
+            ..Default::default()
+        };
+        res.cur_row_group = res.new_row_group()?;
+        Ok(res)
+    }
+
+    fn new_row_group(&self) -> Result<BTreeMap<u32, ColumnBloomFilterBuilder>> {
+        self.columns
+            .iter()
+            .map(|(field_id, (column_idx, options))| {
+                Ok((
+                    *column_idx,
+                    ColumnBloomFilterBuilder::try_new(*field_id, *options)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Hash the values of a root-level column, if it has a Bloom filter.
+    pub(crate) fn insert(&mut self, field_id: usize, array: &dyn Array) -> Result<()> {
+        let Some((column_idx, _)) = self.columns.get(&field_id) else {
+            return Ok(());
+        };
+        let builder = self
+            .cur_row_group
+            .get_mut(column_idx)
+            .ok_or_else(|| Error::General(format!("No Bloom filter for column {}", column_idx)))?;
+        let hashes = hash_array(array)?;
+        hashes
+            .iter()
+            .flatten()
+            .for_each(|hash| builder.filter.insert_hash(*hash));
+        if builder.options.per_iounit {
+            builder.pending_hashes.extend(hashes);
+        }
+        Ok(())
+    }
+
+    /// Build the IOUnit filter from the first `num_rows` pending hashes of the column.
+    pub(crate) fn finish_iounit(&mut self, column_idx: u32, num_rows: usize) -> Result<()> {
+        let Some(builder) = self.cur_row_group.get_mut(&column_idx) else {
+            return Ok(());
+        };
+        if !builder.options.per_iounit {
+            return Ok(());
+        }
+        let num_rows = num_rows.min(builder.pending_hashes.len());
+        let hashes = builder
+            .pending_hashes
+            .drain(..num_rows)
+            .flatten()
+            .collect::<HashSet<_>>();
+        let mut filter = Sbbf::new_with_ndv_fpp(hashes.len() as u64, builder.options.fpp)?;
+        hashes.into_iter().for_each(|hash| filter.insert_hash(hash));
+        builder.iounit_filters.push(filter);
+        Ok(())
+    }
+
+    pub(crate) fn finish_row_group(&mut self) -> Result<()> {
+        let cur_row_group = std::mem::replace(&mut self.cur_row_group, self.new_row_group()?);
+        for (column_idx, builder) in cur_row_group {
+            self.finished.push(ColumnBloomFilter {
+                row_group_idx: self.num_row_groups,
+                field_id: builder.field_id as u32,
+                column_idx,
+                filter: builder.filter,
+                iounit_filters: builder.iounit_filters,
+            });
+        }
+        self.num_row_groups += 1;
+        Ok(())
+    }
+
+    pub(crate) fn take_finished(&mut self) -> Vec<ColumnBloomFilter> {
+        std::mem::take(&mut self.finished)
+    }
+}
+
+/// Location of the bitsets of a ColumnBloomFilter, for writer to use.
+/// Reader should use [fb::ColumnBloomFilter](fff_format::File::fff::flatbuf::ColumnBloomFilter) directly.
+pub(crate) struct ColumnBloomFilterLocation {
+    pub(crate) row_group_idx: u32,
+    pub(crate) field_id: u32,
+    pub(crate) column_idx: u32,
+    pub(crate) filter: MetadataSection,
+    pub(crate) iounit_filters: Vec<MetadataSection>,
+}
+
+impl ToFlatBuffer for ColumnBloomFilterLocation {
+    type Target<'a> = fb::ColumnBloomFilter<'a>;
+
+    fn to_fb<'fb>(&self, fbb: &mut FlatBufferBuilder<'fb>) -> WIPOffset<Self::Target<'fb>> {
+        let filter = self.filter.to_fb(fbb);
+        let iounit_filters = self
+            .iounit_filters
+            .iter()
+            .map(|x| x.to_fb(fbb))
+            .collect::<Vec<_>>();
+        let iounit_filters = fbb.create_vector(&iounit_filters);
+        fb::ColumnBloomFilter::create(
+            fbb,
+            &fb::ColumnBloomFilterArgs {
+                row_group_idx: self.row_group_idx,
+                field_id: self.field_id,
+                column_idx: self.column_idx,
+                filter: Some(filter),
+                iounit_filters: Some(iounit_filters),
+            },
+        )
+    }
+}
+
+/// Equality or IN predicate on a root-level column, used to skip row groups with Bloom filters.
+/// Skipping is conservative: rows of the remaining row groups still need to be filtered by the caller.
+#[derive(Clone, Debug)]
+pub struct BloomFilterPredicate {
+    field_id: usize,
+    values: ArrayRef,
+}
+
+impl BloomFilterPredicate {
+    /// `column = value`, where `value` is an array of a single element.
+    pub fn equal(field_id: usize, value: ArrayRef) -> Result<Self> {
+        if value.len() != 1 {
+            return Err(Error::General(format!(
+                "Equality predicate expects a single value, got {}",
+                value.len()
+            )));
+        }
+        Ok(Self::in_list(field_id, value))
+    }
+
+    /// `column IN (values)`.
+    pub fn in_list(field_id: usize, values: ArrayRef) -> Self {
+        Self { field_id, values }
+    }
+
+    pub fn field_id(&self) -> usize {
+        self.field_id
+    }
+}
+
+fn read_sbbf<R: Reader>(reader: &R, section: &fb::MetadataSection) -> Result<Sbbf> {
+    let mut buf = vec![0u8; section.size_() as usize];
+    reader.read_exact_at(&mut buf, section.offset())?;
+    Sbbf::try_from_bytes(&buf)
+}
+
+/// Which rows of a row group may match a [`BloomFilterPredicate`].
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum RowGroupMatch {
+    /// No row matches, the row group is skipped.
+    None,
+    /// Any row may match.
+    All,
+    /// Only the rows of the IOUnits of physical column `column_idx` whose `mask` is true may match.
+    IOUnits { column_idx: usize, mask: Vec<bool> },
+}
+
+fn match_row_group<R: Reader>(
+    reader: &R,
+    column_filter: &fb::ColumnBloomFilter,
+    hashes: &[u64],
+) -> Result<RowGroupMatch> {
+    let filter = column_filter
+        .filter()
+        .ok_or_else(|| Error::ParseError("Bloom filter bitset not found".to_string()))?;
+    let filter = read_sbbf(reader, &filter)?;
+    if !hashes.iter().any(|hash| filter.check_hash(*hash)) {
+        return Ok(RowGroupMatch::None);
+    }
+    // IOUnit filters are sized by their actual distinct values, so they are usually more selective.
+    let mask = match column_filter.iounit_filters() {
+        Some(iounit_filters) if !iounit_filters.is_empty() => iounit_filters
+            .iter()
+            .map(|iounit_filter| {
+                let iounit_filter = read_sbbf(reader, &iounit_filter)?;
+                Ok(hashes.iter().any(|hash| iounit_filter.check_hash(*hash)))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => return Ok(RowGroupMatch::All),
+    };
+    Ok(if mask.iter().all(|keep| *keep) {
+        RowGroupMatch::All
+    } else if !mask.iter().any(|keep| *keep) {
+        RowGroupMatch::None
+    } else {
+        RowGroupMatch::IOUnits {
+            column_idx: column_filter.column_idx() as usize,
+            mask,
+        }
+    })
+}
+
+/// Evaluate `predicate` against the Bloom filters stored in `section`.
+/// Returns which rows of each row group may contain matching rows,
+/// or `None` if the predicate's column has no Bloom filter.
+pub(crate) fn prune_row_groups<R: Reader>(
+    reader: &R,
+    section: &MetadataSection,
+    schema: &Schema,
+    predicate: &BloomFilterPredicate,
+    num_row_groups: usize,
+) -> Result<Option<Vec<RowGroupMatch>>> {
+    let field = schema
+        .fields()
+        .get(predicate.field_id)
+        .ok_or_else(|| Error::IndexOutOfBound(predicate.field_id, schema.fields().len()))?;
+    let values = cast(&predicate.values, field.data_type())?;
+    // Nulls never compare equal, so they can not match any row.
+    let hashes = hash_array(values.as_ref())?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    let mut buf = vec![0u8; section.size as usize];
+    reader.read_exact_at(&mut buf, section.offset)?;
+    let bloom_filters = flatbuffers::root::<fb::BloomFilters>(&buf)?;
+    let column_filters = bloom_filters
+        .column_filters()
+        .ok_or_else(|| Error::ParseError("Bloom filters not found".to_string()))?;
+    let mut row_group_matches: Option<Vec<RowGroupMatch>> = None;
+    for column_filter in column_filters
+        .iter()
+        .filter(|f| f.field_id() as usize == predicate.field_id)
+    {
+        let matches =
+            row_group_matches.get_or_insert_with(|| vec![RowGroupMatch::All; num_row_groups]);
+        let row_group_idx = column_filter.row_group_idx() as usize;
+        let row_group_match = matches
+            .get_mut(row_group_idx)
+            .ok_or_else(|| Error::IndexOutOfBound(row_group_idx, num_row_groups))?;
+        *row_group_match = match_row_group(reader, &column_filter, &hashes)?;
+    }
+    if let Some(matches) = &row_group_matches {
+        debug!(
+            skipped = matches
+                .iter()
+                .filter(|m| **m == RowGroupMatch::None)
+                .count(),
+            partially_skipped = matches
+                .iter()
+                .filter(|m| matches!(m, RowGroupMatch::IOUnits { .. }))
+                .count(),
+            total = num_row_groups,
+            "Row groups skipped by Bloom filter"
+        );
+    }
+    Ok(row_group_matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int64Array, StringArray};
+
+    use super::*;
+
+    #[test]
+    fn test_sbbf_insert_and_check() {
+        let mut sbbf = Sbbf::new_with_ndv_fpp(1000, 0.01).unwrap();
+        for i in 0..1000u64 {
+            sbbf.insert_hash(xxh64(&i.to_le_bytes(), 0));
+        }
+        for i in 0..1000u64 {
+            assert!(sbbf.check_hash(xxh64(&i.to_le_bytes(), 0)));
+        }
+        let false_positives = (1000..11000u64)
+            .filter(|i| sbbf.check_hash(xxh64(&i.to_le_bytes(), 0)))
+            .count();
+        assert!(false_positives < 500, "too many false positives");
+    }
+
+    #[test]
+    fn test_sbbf_bytes_roundtrip() {
+        let mut sbbf = Sbbf::new_with_ndv_fpp(100, 0.05).unwrap();
+        sbbf.insert_hash(42);
+        let bytes = sbbf.to_bytes();
+        assert_eq!(bytes.len() % BITSET_MIN_LENGTH, 0);
+        assert_eq!(Sbbf::try_from_bytes(&bytes).unwrap(), sbbf);
+        assert!(Sbbf::try_from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_hash_array() {
+        let arr = Arc::new(Int64Array::from(vec![Some(1), None, Some(1)])) as ArrayRef;
+        let hashes = hash_array(&arr.slice(1, 2)).unwrap();
+        assert_eq!(hashes, vec![None, Some(xxh64(&1i64.to_le_bytes(), 0))]);
+        let arr = StringArray::from(vec!["a", "b"]);
+        let hashes = hash_array(&arr).unwrap();
+        assert_eq!(hashes, vec![Some(xxh64(b"a", 0)), Some(xxh64(b"b", 0))]);
+    }
+}
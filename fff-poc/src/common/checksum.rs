@@ -1,10 +1,19 @@
 use fff_core::errors::{Error, Result};
+use xxhash_rust::xxh3::Xxh3 as Xxh3State;
 use xxhash_rust::xxh64::Xxh64;
 
+/// Checksum algorithm, stored in the postscript. All checksums are widened or truncated to u64.
 #[repr(u8)]
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum ChecksumType {
-    XxHash,
+    /// XXH64
+    XxHash = 0,
+    /// CRC-32C (Castagnoli), hardware accelerated on most CPUs.
+    Crc32c = 1,
+    /// 64-bit XXH3
+    Xxh3 = 2,
+    /// The first 8 bytes of BLAKE3, for a cryptographic hash.
+    Blake3 = 3,
 }
 
 impl TryFrom<u8> for ChecksumType {
@@ -13,6 +22,9 @@ impl TryFrom<u8> for ChecksumType {
     fn try_from(v: u8) -> Result<ChecksumType> {
         match v {
             0 => Ok(ChecksumType::XxHash),
+            1 => Ok(ChecksumType::Crc32c),
+            2 => Ok(ChecksumType::Xxh3),
+            3 => Ok(ChecksumType::Blake3),
             _ => Err(Error::General(format!("Invalid checksum type: {}", v))),
         }
     }
@@ -43,12 +55,84 @@ impl Checksum for XxHash {
     }
 }
 
+#[derive(Default)]
+pub struct Crc32c {
+    state: u32,
+}
+
+impl Checksum for Crc32c {
+    fn update(&mut self, data: &[u8]) {
+        self.state = crc32c::crc32c_append(self.state, data);
+    }
+
+    fn finalize(&self) -> u64 {
+        self.state as u64
+    }
+
+    fn reset(&mut self) {
+        self.state = 0
+    }
+}
+
+#[derive(Default)]
+pub struct Xxh3 {
+    state: Xxh3State,
+}
+
+impl Checksum for Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    fn finalize(&self) -> u64 {
+        self.state.digest()
+    }
+
+    fn reset(&mut self) {
+        self.state.reset()
+    }
+}
+
+#[derive(Default)]
+pub struct Blake3 {
+    state: blake3::Hasher,
+}
+
+impl Checksum for Blake3 {
+    fn update(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    fn finalize(&self) -> u64 {
+        let hash = self.state.finalize();
+        u64::from_le_bytes(
+            hash.as_bytes()[..8]
+                .try_into()
+                .expect("BLAKE3 hash is 32 bytes"),
+        )
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+    }
+}
+
 pub fn create_checksum(checksum_type: &ChecksumType) -> Box<dyn Checksum> {
     match checksum_type {
         ChecksumType::XxHash => Box::new(XxHash::default()),
+        ChecksumType::Crc32c => Box::new(Crc32c::default()),
+        ChecksumType::Xxh3 => Box::new(Xxh3::default()),
+        ChecksumType::Blake3 => Box::new(Blake3::default()),
     }
 }
 
+/// Checksum of a single buffer.
+pub fn checksum_of(checksum_type: &ChecksumType, data: &[u8]) -> u64 {
+    let mut checksum = create_checksum(checksum_type);
+    checksum.update(data);
+    checksum.finalize()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,18 +163,55 @@ mod tests {
         assert_ne!(c3, c4);
     }
 
+    #[test]
+    fn test_streaming_checksums() {
+        for checksum_type in [
+            ChecksumType::XxHash,
+            ChecksumType::Crc32c,
+            ChecksumType::Xxh3,
+            ChecksumType::Blake3,
+        ] {
+            let mut checksum = create_checksum(&checksum_type);
+            checksum.update(b"hello");
+            checksum.update(b"world");
+            assert_eq!(
+                checksum.finalize(),
+                checksum_of(&checksum_type, b"helloworld")
+            );
+            assert_ne!(
+                checksum.finalize(),
+                checksum_of(&checksum_type, b"worldhello")
+            );
+            checksum.reset();
+            checksum.update(b"helloworld");
+            assert_eq!(
+                checksum.finalize(),
+                checksum_of(&checksum_type, b"helloworld")
+            );
+        }
+    }
+
+    #[test]
+    fn test_crc32c_known_value() {
+        // Check value from RFC 3720, B.4.
+        assert_eq!(checksum_of(&ChecksumType::Crc32c, &[0u8; 32]), 0x8a9136aa);
+    }
+
     #[test]
     fn test_checksum_type_from_u8_valid() {
         // Test valid checksum type
         let checksum_type = ChecksumType::try_from(0u8);
         assert!(checksum_type.is_ok());
         assert_eq!(checksum_type.unwrap(), ChecksumType::XxHash);
+        assert_eq!(ChecksumType::try_from(1u8).unwrap(), ChecksumType::Crc32c);
+        assert_eq!(ChecksumType::try_from(2u8).unwrap(), ChecksumType::Xxh3);
+        assert_eq!(ChecksumType::try_from(3u8).unwrap(), ChecksumType::Blake3);
     }
 
     #[test]
     fn test_checksum_type_from_u8_invalid() {
         // Test invalid checksum types should return error, not panic
-        let invalid_values = [1u8, 2, 10, 100, 255];
+        let invalid_values = [4u8, 10, 100, 255];
         for value in invalid_values {
             let result = ChecksumType::try_from(value);
             assert!(result.is_err(), "Expected error for value {}", value);
@@ -100,9 +221,15 @@ mod tests {
     #[test]
     fn test_checksum_type_roundtrip() {
         // Test that we can convert to u8 and back
-        let original = ChecksumType::XxHash;
-        let as_u8 = original as u8;
-        let back = ChecksumType::try_from(as_u8).unwrap();
-        assert_eq!(original, back);
+        for original in [
+            ChecksumType::XxHash,
+            ChecksumType::Crc32c,
+            ChecksumType::Xxh3,
+            ChecksumType::Blake3,
+        ] {
+            let as_u8 = original as u8;
+            let back = ChecksumType::try_from(as_u8).unwrap();
+            assert_eq!(original, back);
+        }
     }
 }
@@ -1,26 +1,171 @@
 /// Block compression is not recommended because it is both compute-heavy and hinder random access.
+use std::io::{Read, Write};
+
 use bytes::Bytes;
 use fff_core::errors::{Error, Result};
 use fff_format::File::fff::flatbuf as fb;
 
-/// Compress data based on the compression type
-pub fn compress_data(data: Bytes, compression_type: fb::CompressionType) -> Result<Bytes> {
-    match compression_type {
-        fb::CompressionType::Uncompressed => Ok(data),
-        fb::CompressionType::Lz4 => {
-            let compressed = lz4_flex::compress_prepend_size(data.as_ref());
-            Ok(Bytes::from(compressed))
+/// Brotli quality used if no level is set. The maximum (11) is too slow for writing files.
+const BROTLI_DEFAULT_QUALITY: i32 = 6;
+/// Log2 of the Brotli window size.
+const BROTLI_LG_WINDOW_SIZE: u32 = 22;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Block compression applied to EncUnits after encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockCompression {
+    compression_type: fb::CompressionType,
+    /// Codec specific level, e.g., 1-22 for Zstd and 0-11 for Brotli. The codec default if None.
+    level: Option<i32>,
+    /// Keep a block compressed only if it is at least this percentage smaller than the uncompressed one.
+    /// Always compress if None.
+    min_savings_percent: Option<u8>,
+}
+
+impl Default for BlockCompression {
+    fn default() -> Self {
+        Self::new(fb::CompressionType::Uncompressed)
+    }
+}
+
+impl BlockCompression {
+    pub fn new(compression_type: fb::CompressionType) -> Self {
+        Self {
+            compression_type,
+            level: None,
+            min_savings_percent: None,
+        }
+    }
+
+    pub fn with_level(mut self, level: Option<i32>) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn with_min_savings_percent(mut self, min_savings_percent: Option<u8>) -> Self {
+        self.min_savings_percent = min_savings_percent;
+        self
+    }
+
+    pub fn compression_type(&self) -> fb::CompressionType {
+        self.compression_type
+    }
+
+    pub fn level(&self) -> Option<i32> {
+        self.level
+    }
+
+    pub fn min_savings_percent(&self) -> Option<u8> {
+        self.min_savings_percent
+    }
+
+    fn saves_enough(&self, uncompressed_size: usize, compressed_size: usize) -> bool {
+        match self.min_savings_percent {
+            None => true,
+            Some(percent) => {
+                compressed_size as u64 * 100
+                    <= uncompressed_size as u64 * (100 - percent.min(100) as u64)
+            }
         }
+    }
+}
+
+impl From<fb::CompressionType> for BlockCompression {
+    fn from(compression_type: fb::CompressionType) -> Self {
+        Self::new(compression_type)
+    }
+}
+
+fn compress_block(
+    data: &[u8],
+    compression_type: fb::CompressionType,
+    level: Option<i32>,
+) -> Result<Bytes> {
+    match compression_type {
+        fb::CompressionType::Uncompressed => Ok(Bytes::copy_from_slice(data)),
+        // LZ4 block format has no levels.
+        fb::CompressionType::Lz4 => Ok(Bytes::from(lz4_flex::compress_prepend_size(data))),
         fb::CompressionType::Zstd => {
-            let compressed = zstd::stream::encode_all(data.as_ref(), 0)?;
-            Ok(Bytes::from(compressed))
+            // 0 selects the default level of zstd.
+            let level = level.unwrap_or(0);
+            let levels = zstd::compression_level_range();
+            if !levels.contains(&level) {
+                return Err(Error::General(format!(
+                    "Zstd level must be between {} and {}, got {}",
+                    levels.start(),
+                    levels.end(),
+                    level
+                )));
+            }
+            Ok(Bytes::from(zstd::stream::encode_all(data, level)?))
         }
-        _ => Err(fff_core::errors::Error::General(
-            "Unsupported compression type".to_string(),
+        // Snappy has no levels either.
+        fb::CompressionType::Snappy => Ok(Bytes::from(
+            snap::raw::Encoder::new()
+                .compress_vec(data)
+                .map_err(|e| Error::External(Box::new(e)))?,
         )),
+        fb::CompressionType::Brotli => {
+            let quality = level.unwrap_or(BROTLI_DEFAULT_QUALITY);
+            if !(0..=11).contains(&quality) {
+                return Err(Error::General(format!(
+                    "Brotli level must be between 0 and 11, got {}",
+                    quality
+                )));
+            }
+            let mut writer = brotli::CompressorWriter::new(
+                Vec::new(),
+                BROTLI_BUFFER_SIZE,
+                quality as u32,
+                BROTLI_LG_WINDOW_SIZE,
+            );
+            writer.write_all(data)?;
+            Ok(Bytes::from(writer.into_inner()))
+        }
+        _ => Err(Error::General(format!(
+            "Unsupported compression type: {:?}",
+            compression_type
+        ))),
     }
 }
 
+/// Compress blocks that are stored under a single compression type, e.g., the mini EncUnits of an EncUnit.
+/// Returns the blocks uncompressed if compressing them does not save enough space in total,
+/// together with the compression type that was applied.
+pub fn compress_blocks(
+    blocks: Vec<Bytes>,
+    compression: BlockCompression,
+) -> Result<(Vec<Bytes>, fb::CompressionType)> {
+    if compression.compression_type == fb::CompressionType::Uncompressed {
+        return Ok((blocks, fb::CompressionType::Uncompressed));
+    }
+    let compressed = blocks
+        .iter()
+        .map(|block| compress_block(block, compression.compression_type, compression.level))
+        .collect::<Result<Vec<_>>>()?;
+    let uncompressed_size = blocks.iter().map(Bytes::len).sum();
+    let compressed_size = compressed.iter().map(Bytes::len).sum();
+    if compression.saves_enough(uncompressed_size, compressed_size) {
+        Ok((compressed, compression.compression_type))
+    } else {
+        Ok((blocks, fb::CompressionType::Uncompressed))
+    }
+}
+
+/// Compress data based on the compression type and level.
+/// Returns the data uncompressed if compressing it does not save enough space,
+/// together with the compression type that was applied.
+pub fn compress_data(
+    data: Bytes,
+    compression: BlockCompression,
+) -> Result<(Bytes, fb::CompressionType)> {
+    let (mut blocks, compression_type) = compress_blocks(vec![data], compression)?;
+    let data = blocks
+        .pop()
+        .ok_or_else(|| Error::General("Compressing data returned no block".to_string()))?;
+    Ok((data, compression_type))
+}
+
 /// Decompress data based on the compression type
 pub fn decompress_data(data: Bytes, compression_type: fb::CompressionType) -> Result<Bytes> {
     match compression_type {
@@ -30,9 +175,84 @@ pub fn decompress_data(data: Bytes, compression_type: fb::CompressionType) -> Re
                 .map_err(|e| Error::External(Box::new(e)))?,
         )),
         fb::CompressionType::Zstd => Ok(Bytes::from(zstd::stream::decode_all(data.as_ref())?)),
+        fb::CompressionType::Snappy => Ok(Bytes::from(
+            snap::raw::Decoder::new()
+                .decompress_vec(data.as_ref())
+                .map_err(|e| Error::External(Box::new(e)))?,
+        )),
+        fb::CompressionType::Brotli => {
+            let mut decompressed = Vec::new();
+            brotli::Decompressor::new(data.as_ref(), BROTLI_BUFFER_SIZE)
+                .read_to_end(&mut decompressed)?;
+            Ok(Bytes::from(decompressed))
+        }
         _ => Err(Error::General(format!(
             "Unsupported compression type: {:?}",
             compression_type
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let data = Bytes::from(
+            (0..10000u32)
+                .flat_map(|i| (i % 100).to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+        for compression_type in [
+            fb::CompressionType::Uncompressed,
+            fb::CompressionType::Zstd,
+            fb::CompressionType::Lz4,
+            fb::CompressionType::Snappy,
+            fb::CompressionType::Brotli,
+        ] {
+            for level in [None, Some(1), Some(9)] {
+                let compression = BlockCompression::new(compression_type).with_level(level);
+                let (compressed, applied) = compress_data(data.clone(), compression).unwrap();
+                assert_eq!(applied, compression_type);
+                assert_eq!(decompress_data(compressed, applied).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        let data = Bytes::from_static(b"data");
+        for (compression_type, level) in [
+            (
+                fb::CompressionType::Zstd,
+                *zstd::compression_level_range().end() + 1,
+            ),
+            (fb::CompressionType::Brotli, 12),
+        ] {
+            let compression = BlockCompression::new(compression_type).with_level(Some(level));
+            assert!(compress_data(data.clone(), compression).is_err());
+        }
+    }
+
+    #[test]
+    fn test_min_savings() {
+        // Random bytes do not compress.
+        let random = Bytes::from((0..4096).map(|_| rand::random::<u8>()).collect::<Vec<_>>());
+        let compression = BlockCompression::new(fb::CompressionType::Zstd);
+        let (_, applied) = compress_data(random.clone(), compression).unwrap();
+        assert_eq!(applied, fb::CompressionType::Zstd);
+        let (stored, applied) = compress_data(
+            random.clone(),
+            compression.with_min_savings_percent(Some(0)),
+        )
+        .unwrap();
+        assert_eq!(applied, fb::CompressionType::Uncompressed);
+        assert_eq!(stored, random);
+
+        let zeros = Bytes::from(vec![0u8; 4096]);
+        let (_, applied) =
+            compress_data(zeros, compression.with_min_savings_percent(Some(90))).unwrap();
+        assert_eq!(applied, fb::CompressionType::Zstd);
+    }
+}
@@ -2,37 +2,80 @@ use std::{
     collections::HashMap,
     path::PathBuf,
     rc::Rc,
-    sync::{Arc, OnceLock},
+    sync::{Arc, Mutex, OnceLock},
 };
 
 use arrow_schema::DataType;
 use fff_format::File::fff::flatbuf as fb;
 use fff_test_util::BUILTIN_WASM_PATH;
-use fff_ude_wasm::Runtime;
+use fff_ude_wasm::{registry::RuntimeRegistry, Runtime};
 use semver::Version;
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::{file::footer::MetadataSection, io::reader::Reader};
+use crate::{
+    decoder::policy::{DecoderMismatch, DecoderPolicy, DecoderVerification},
+    encoder::custom::WASM_ENCODE_FUNC,
+    file::footer::{self, MetadataSection, PluginEncoding, WASMEncoding},
+    io::reader::Reader,
+    options::CustomEncoderSelection,
+    registry::{latest_encoding, EncodingPlugin},
+};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct WASMId(pub u32);
 
+/// The encoder of a custom encoding.
+#[derive(Debug, Clone)]
+pub enum EncoderLib {
+    /// Shared library exporting `encode`, which runs unsandboxed in the writer process.
+    Native(Rc<PathBuf>),
+    /// Wasm module exporting `encode_ffi`, which runs in the same sandbox as the decoders.
+    Wasm(Arc<Runtime>),
+}
+
+impl PartialEq for EncoderLib {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Native(a), Self::Native(b)) => a == b,
+            (Self::Wasm(a), Self::Wasm(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct WasmLib {
-    encode_lib_path: Rc<PathBuf>,
+    encoder: EncoderLib,
     decode_wasm_binary: Rc<Vec<u8>>,
 }
 
 impl WasmLib {
+    /// Encode with the native shared library at `enc_path`.
+    /// Only use libraries you trust, since they are loaded into the writer process.
     pub fn new(enc_path: PathBuf, dec_wasm: Vec<u8>) -> Self {
         Self {
-            encode_lib_path: Rc::new(enc_path),
+            encoder: EncoderLib::Native(Rc::new(enc_path)),
             decode_wasm_binary: Rc::new(dec_wasm),
         }
     }
 
-    pub fn encode_lib_path(&self) -> Rc<PathBuf> {
-        self.encode_lib_path.clone()
+    /// Encode with the Wasm module `enc_wasm`, which may be the same module as `dec_wasm`.
+    pub fn try_new_sandboxed(enc_wasm: &[u8], dec_wasm: Vec<u8>) -> fff_core::errors::Result<Self> {
+        let runtime = Runtime::try_new(enc_wasm)?;
+        if !runtime.functions().any(|f| f == WASM_ENCODE_FUNC) {
+            return Err(fff_core::errors::Error::General(format!(
+                "Wasm encoder does not export {}",
+                WASM_ENCODE_FUNC
+            )));
+        }
+        Ok(Self {
+            encoder: EncoderLib::Wasm(Arc::new(runtime)),
+            decode_wasm_binary: Rc::new(dec_wasm),
+        })
+    }
+
+    pub fn encoder(&self) -> &EncoderLib {
+        &self.encoder
     }
 }
 
@@ -48,6 +91,10 @@ pub struct WASMWritingContext {
     always_set_custom_wasm_for_built_in: bool,
     /// WasmId for built-in
     builtin_wasm_id: Option<WASMId>,
+    /// Encoding plugins used in the file, along with the WASMId of their Wasm decoders
+    plugins: HashMap<String, (Arc<dyn EncodingPlugin>, Option<WASMId>)>,
+    /// DataType to the encoding plugin of a column
+    data_type_to_plugin: HashMap<DataType, Arc<dyn EncodingPlugin>>,
 }
 
 impl WASMWritingContext {
@@ -69,14 +116,13 @@ impl WASMWritingContext {
         Ok(Self {
             wasms: HashMap::from([(
                 WASMId(0),
-                WasmLib {
-                    encode_lib_path: BUILTIN_WASM_PATH.clone().into(),
-                    decode_wasm_binary: wasm_binary.into(),
-                },
+                WasmLib::new(BUILTIN_WASM_PATH.clone(), wasm_binary),
             )]),
             data_type_to_wasm_id: HashMap::default(),
             always_set_custom_wasm_for_built_in: false,
             builtin_wasm_id: Some(WASMId(0)),
+            plugins: HashMap::new(),
+            data_type_to_plugin: HashMap::new(),
         })
     }
 
@@ -93,6 +139,8 @@ impl WASMWritingContext {
             data_type_to_wasm_id: HashMap::new(),
             always_set_custom_wasm_for_built_in: false,
             builtin_wasm_id: None,
+            plugins: HashMap::new(),
+            data_type_to_plugin: HashMap::new(),
         }
     }
 
@@ -106,6 +154,8 @@ impl WASMWritingContext {
             data_type_to_wasm_id,
             always_set_custom_wasm_for_built_in: false,
             builtin_wasm_id: None,
+            plugins: HashMap::new(),
+            data_type_to_plugin: HashMap::new(),
         }
     }
 
@@ -135,6 +185,109 @@ impl WASMWritingContext {
     pub fn builtin_wasm_id(&self) -> Option<WASMId> {
         self.builtin_wasm_id
     }
+
+    /// Add the latest version of the registered encoding `id` to the encodings of the file,
+    /// along with its Wasm decoder.
+    pub fn add_plugin(&mut self, id: &str) -> fff_core::errors::Result<()> {
+        if self.plugins.contains_key(id) {
+            return Ok(());
+        }
+        let plugin = latest_encoding(id)?.ok_or_else(|| {
+            fff_core::errors::Error::General(format!("Encoding plugin {} is not registered", id))
+        })?;
+        let wasm_id = match plugin.wasm_decoder() {
+            Some(wasm) => {
+                // Take the id after the custom Wasms, which may not be contiguous.
+                let wasm_id = match self.wasms.keys().map(|wasm_id| wasm_id.0).max() {
+                    None => WASMId(0),
+                    Some(max) => WASMId(max.checked_add(1).ok_or_else(|| {
+                        fff_core::errors::Error::General(format!(
+                            "No Wasm id left for the decoder of encoding plugin {}",
+                            id
+                        ))
+                    })?),
+                };
+                self.wasms
+                    .insert(wasm_id, WasmLib::new(PathBuf::new(), wasm));
+                Some(wasm_id)
+            }
+            None => None,
+        };
+        self.plugins.insert(id.to_string(), (plugin, wasm_id));
+        Ok(())
+    }
+
+    pub fn data_type_to_plugin(&self, dt: &DataType) -> Option<&Arc<dyn EncodingPlugin>> {
+        self.data_type_to_plugin.get(dt)
+    }
+
+    /// Metadata of an EncUnit of `data_type`, whose built-in encoder reported `encoding`.
+    pub(crate) fn encunit_encoding(
+        &self,
+        encoding: fff_encoding::enc_unit::Encoding,
+        data_type: &DataType,
+    ) -> fff_core::errors::Result<footer::Encoding> {
+        let wasm_encoding =
+            |wasm_id: Option<WASMId>| wasm_id.map(|id| WASMEncoding::new(id.0, Vec::new()));
+        if let Some(plugin) = self.data_type_to_plugin(data_type) {
+            return Ok(footer::Encoding::try_new(
+                fb::EncodingType::PLUGIN,
+                wasm_encoding(self.data_type_to_wasm_id(data_type)),
+            )?
+            .with_plugin(PluginEncoding::new(plugin.id(), plugin.version())));
+        }
+        if self.always_set_custom_wasm_for_built_in {
+            return footer::Encoding::try_new(
+                fb::EncodingType::CUSTOM_WASM,
+                wasm_encoding(self.builtin_wasm_id),
+            );
+        }
+        footer::Encoding::try_new(
+            encoding.to_fbs_encoding(),
+            wasm_encoding(self.data_type_to_wasm_id(data_type)),
+        )
+    }
+
+    /// The context of a column that selects its encoder explicitly instead of by data type.
+    pub fn with_column_encoder(
+        &self,
+        data_type: &DataType,
+        encoder: &CustomEncoderSelection,
+    ) -> fff_core::errors::Result<Self> {
+        let mut data_type_to_plugin = HashMap::new();
+        let data_type_to_wasm_id = match encoder {
+            CustomEncoderSelection::Wasm(wasm_id) => {
+                if !self.wasms.contains_key(wasm_id) {
+                    return Err(fff_core::errors::Error::General(format!(
+                        "Unknown custom encoding {:?} for data type {}",
+                        wasm_id, data_type
+                    )));
+                }
+                HashMap::from([(data_type.clone(), *wasm_id)])
+            }
+            CustomEncoderSelection::Plugin(id) => {
+                let (plugin, wasm_id) = self.plugins.get(id).ok_or_else(|| {
+                    fff_core::errors::Error::General(format!(
+                        "Encoding plugin {} is not added to the file",
+                        id
+                    ))
+                })?;
+                data_type_to_plugin.insert(data_type.clone(), plugin.clone());
+                wasm_id
+                    .map(|wasm_id| HashMap::from([(data_type.clone(), wasm_id)]))
+                    .unwrap_or_default()
+            }
+            CustomEncoderSelection::BuiltIn => HashMap::new(),
+        };
+        Ok(Self {
+            wasms: self.wasms.clone(),
+            data_type_to_wasm_id,
+            always_set_custom_wasm_for_built_in: self.always_set_custom_wasm_for_built_in,
+            builtin_wasm_id: self.builtin_wasm_id,
+            plugins: self.plugins.clone(),
+            data_type_to_plugin,
+        })
+    }
 }
 
 pub struct WASMReadingContext<R> {
@@ -144,6 +297,9 @@ pub struct WASMReadingContext<R> {
     r: Option<R>,
     /// Mapping of encoding types to their semantic versions
     encoding_versions: Option<HashMap<fb::EncodingType, Version>>,
+    decoder_policy: DecoderPolicy,
+    /// What [`DecoderPolicy::Verify`] found so far.
+    verification: Mutex<DecoderVerification>,
 }
 
 impl<R: Reader> WASMReadingContext<R> {
@@ -159,7 +315,42 @@ impl<R: Reader> WASMReadingContext<R> {
             wasm_locations,
             r,
             encoding_versions,
+            decoder_policy: DecoderPolicy::default(),
+            verification: Mutex::new(DecoderVerification::default()),
+        }
+    }
+
+    pub fn with_decoder_policy(mut self, decoder_policy: DecoderPolicy) -> Self {
+        self.decoder_policy = decoder_policy;
+        self
+    }
+
+    pub fn decoder_policy(&self) -> DecoderPolicy {
+        self.decoder_policy
+    }
+
+    /// Count an EncUnit cross-checked with [`DecoderPolicy::Verify`], and its mismatch if any.
+    pub(crate) fn record_verification(
+        &self,
+        mismatch: Option<DecoderMismatch>,
+    ) -> fff_core::errors::Result<()> {
+        let mut verification = self.verification.lock().map_err(|e| {
+            fff_core::errors::Error::General(format!("Verification lock poisoned: {}", e))
+        })?;
+        verification.num_verified += 1;
+        if let Some(mismatch) = mismatch {
+            warn!(%mismatch, "Native and Wasm decoders disagree");
+            verification.mismatches.push(mismatch);
         }
+        Ok(())
+    }
+
+    /// What [`DecoderPolicy::Verify`] found so far.
+    pub fn decoder_verification(&self) -> DecoderVerification {
+        self.verification
+            .lock()
+            .map(|verification| verification.clone())
+            .unwrap_or_default()
     }
 
     // For lazy loading from file
@@ -241,12 +432,15 @@ impl<R: Reader> WASMReadingContext<R> {
                         "Creating WASM runtime"
                     );
 
-                    let rt = Arc::new(Runtime::try_new(&buf).map_err(|e| {
-                        fff_core::errors::Error::General(format!(
-                            "Failed to create WASM runtime for id {}: {}",
-                            id, e
-                        ))
-                    })?);
+                    // Readers share the runtimes, and so the instance pools, of identical binaries.
+                    let rt = RuntimeRegistry::global()
+                        .get_or_try_insert(&buf)
+                        .map_err(|e| {
+                            fff_core::errors::Error::General(format!(
+                                "Failed to create WASM runtime for id {}: {}",
+                                id, e
+                            ))
+                        })?;
 
                     let elapsed = start.elapsed();
                     info!(
@@ -281,3 +475,76 @@ impl<R: Reader> WASMReadingContext<R> {
         self.encoding_versions.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use fff_core::nyi_err;
+    use fff_encoding::schemes::Encoder;
+
+    use super::*;
+    use crate::{decoder::encunit::EncUnitDecoder, registry::register_encoding};
+
+    #[derive(Debug)]
+    struct WasmPlugin;
+
+    impl EncodingPlugin for WasmPlugin {
+        fn id(&self) -> &str {
+            "test.context_wasm_plugin"
+        }
+
+        fn version(&self) -> Version {
+            Version::new(1, 0, 0)
+        }
+
+        fn create_encoder(
+            &self,
+            _data_type: &DataType,
+        ) -> fff_core::errors::Result<Rc<dyn Encoder>> {
+            nyi_err!("The test plugin does not encode")
+        }
+
+        fn create_decoder(
+            &self,
+            _data: Bytes,
+            _output_type: &DataType,
+            _num_rows: u64,
+        ) -> fff_core::errors::Result<Box<dyn EncUnitDecoder>> {
+            nyi_err!("The test plugin does not decode")
+        }
+
+        fn wasm_decoder(&self) -> Option<Vec<u8>> {
+            Some(b"plugin".to_vec())
+        }
+    }
+
+    #[test]
+    fn test_plugin_wasm_id_after_custom_wasms() {
+        let custom_wasm = |wasm: &[u8]| WasmLib::new(PathBuf::new(), wasm.to_vec());
+        let mut context = WASMWritingContext::with_custom_wasms(
+            HashMap::from([
+                (WASMId(0), custom_wasm(b"custom0")),
+                (WASMId(2), custom_wasm(b"custom2")),
+            ]),
+            HashMap::from([(DataType::Int32, WASMId(2))]),
+        );
+        register_encoding(Arc::new(WasmPlugin)).unwrap();
+        context.add_plugin(WasmPlugin.id()).unwrap();
+
+        let plugin_context = context
+            .with_column_encoder(
+                &DataType::Int64,
+                &CustomEncoderSelection::Plugin(WasmPlugin.id().to_string()),
+            )
+            .unwrap();
+        assert_eq!(
+            plugin_context.data_type_to_wasm_id(&DataType::Int64),
+            Some(WASMId(3))
+        );
+        // The custom Wasm with the id of the number of Wasms is kept.
+        assert_eq!(
+            context.get_sorted_wasms(),
+            vec![&b"custom0"[..], &b"custom2"[..], &b"plugin"[..]]
+        );
+    }
+}
@@ -1,26 +1,39 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
-use arrow_array::ArrayRef;
+use arrow::compute::concat;
+use arrow_array::{new_empty_array, ArrayRef};
 use arrow_schema::DataType;
 use bytes::Bytes;
 use fff_core::{
     errors::{Error, Result},
     general_error, non_nest_types, nyi_err,
-    util::buffer_to_array::primitive_array_from_arrow_buffers_iter,
+    util::{
+        buffer_to_array::{array_from_arrow_buffers_iter, num_rows_from_arrow_buffers},
+        kwargs::{kwargs_serialize, PPD, SPD},
+    },
 };
 use fff_encoding::schemes::{
     vortex::{VortexDecoder, VortexListDecoder, VortexListStructDecoder},
-    Decoder,
+    Decoder, EncUnitMetadata,
 };
 use fff_format::File::fff::flatbuf as fb;
-use fff_test_util::WASM_FUNC_GENERAL;
-use fff_ude_wasm::Runtime;
+use fff_ude_wasm::{EntryPoint, Instance, Runtime, ARROW_DECODE_FUNC};
 use log::debug;
+use roaring::RoaringBitmap;
 use vortex_sampling_compressor::ALL_ENCODINGS_CONTEXT;
 
 use crate::{
-    compression::decompress_data, context::WASMReadingContext,
-    file::footer::DEFAULT_ENCODING_VERSIONS, io::reader::Reader,
+    compression::decompress_data,
+    context::WASMReadingContext,
+    decoder::policy::DecoderPolicy,
+    file::footer::{is_encoding_version_compatible, version_from_fb, DEFAULT_ENCODING_VERSIONS},
+    io::reader::Reader,
+    registry::{compatible_encoding, EncodingPlugin},
 };
 
 /// Common API for decoding a EncUnit.
@@ -41,45 +54,137 @@ pub trait EncUnitDecoder {
     }
 }
 
-/// The optional Key-Word args for advanced features.
-type Key = String;
-type Word = String;
+/// The optional Key-Word args for advanced features, see format/kwargs.md.
+pub type Key = String;
+pub type Word = Vec<u8>;
 
+/// Decodes an EncUnit with the stateful API of a Wasm decoder, see [`EntryPoint::Stateful`].
+/// The decoder is initialized with the kwargs on creation, and each `decode_v2` outputs its next vector.
 pub struct WASMEncUnitDecoderV2 {
-    _data: Bytes,
-    _rt: Arc<Runtime>,
-    _output_type: DataType,
-    _num_rows: u64,
+    instance: Arc<Mutex<Instance>>,
+    output_type: DataType,
+    state: Mutex<DecodeState>,
+}
+
+struct DecodeState {
+    /// The decoder in the memory of the instance. The module frees it once exhausted or failed.
+    decoder: Option<u32>,
+    /// Rows not output yet.
+    remaining: u64,
 }
 
 impl WASMEncUnitDecoderV2 {
-    pub fn new(
+    pub fn try_new(
         data: Bytes,
         rt: Arc<Runtime>,
         output_type: DataType,
         num_rows: u64,
-        _kwargs: HashMap<Key, Word>,
-    ) -> Self {
-        // TODO: call check and init using kwargs.
-        Self {
-            _data: data,
-            _rt: rt,
-            _output_type: output_type,
-            _num_rows: num_rows,
-        }
+        kwargs: HashMap<Key, Word>,
+    ) -> Result<Self> {
+        // With ppd the decoder outputs the result of the predicate, and with spd only the selected rows.
+        let output_type = if kwargs.contains_key(PPD) {
+            DataType::Boolean
+        } else {
+            output_type
+        };
+        let num_rows = match kwargs.get(SPD) {
+            Some(word) => RoaringBitmap::deserialize_from(word.as_slice())
+                .map_err(|e| general_error!("Malformed spd", e))?
+                .len(),
+            None => num_rows,
+        };
+        let mut kwargs = kwargs
+            .iter()
+            .map(|(key, word)| (key.as_bytes(), word.as_slice()))
+            .collect::<Vec<_>>();
+        kwargs.sort();
+        // No bytes at all without kwargs.
+        let kwargs = if kwargs.is_empty() {
+            vec![]
+        } else {
+            kwargs_serialize(&kwargs)
+        };
+        let instance = Arc::new(Mutex::new(
+            rt.instantiate()
+                .map_err(|e| general_error!("Failed to instantiate WASM", e))?,
+        ));
+        let decoder = lock(&instance)?
+            .call_init(&data, &kwargs)
+            .map_err(|e| general_error!("WASM init failed", e))?
+            .ptr();
+        Ok(Self {
+            instance,
+            output_type,
+            state: Mutex::new(DecodeState {
+                decoder: Some(decoder),
+                remaining: num_rows,
+            }),
+        })
     }
 }
 
+fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|e| general_error!("WASM decoder lock poisoned", e))
+}
+
 impl EncUnitDecoder for WASMEncUnitDecoderV2 {
     fn decode_v2(&self) -> Result<Option<ArrayRef>> {
-        nyi_err!("WASMEncUnitDecoderV2::decode_v2")
+        let mut state = lock(&self.state)?;
+        let Some(decoder) = state.decoder else {
+            return Ok(None);
+        };
+        let next = lock(&self.instance)?.call_decode(decoder, self.instance.clone());
+        let buffers = match next {
+            Ok(Some(iter)) => iter.collect::<Vec<_>>(),
+            Ok(None) => {
+                state.decoder = None;
+                return Ok(None);
+            }
+            Err(e) => {
+                state.decoder = None;
+                return Err(general_error!("WASM decode failed", e));
+            }
+        };
+        // The decoder does not tell the rows of a vector, so count them from its buffers,
+        // or take all remaining rows if they do not tell.
+        let num_rows =
+            num_rows_from_arrow_buffers(&self.output_type, &buffers).unwrap_or(state.remaining);
+        if num_rows > state.remaining {
+            return Err(Error::General(format!(
+                "WASM decoder output {} rows, but only {} are left",
+                num_rows, state.remaining
+            )));
+        }
+        state.remaining -= num_rows;
+        Ok(Some(array_from_arrow_buffers_iter(
+            &self.output_type,
+            buffers.into_iter(),
+            num_rows,
+        )?))
+    }
+
+    /// Decode all the remaining vectors.
+    fn decode(&self) -> Result<ArrayRef> {
+        let mut arrays = vec![];
+        while let Some(array) = self.decode_v2()? {
+            arrays.push(array);
+        }
+        match arrays.len() {
+            0 => Ok(new_empty_array(&self.output_type)),
+            1 => Ok(arrays.remove(0)),
+            _ => Ok(concat(
+                &arrays.iter().map(|a| a.as_ref()).collect::<Vec<_>>(),
+            )?),
+        }
     }
 }
 
 pub struct WASMEncUnitDecoder<'a> {
     data: Bytes,
     rt: Arc<Runtime>,
-    func_name: &'a str,
+    func_name: Cow<'a, str>,
     output_type: DataType,
     num_rows: u64,
 }
@@ -88,14 +193,14 @@ impl<'a> WASMEncUnitDecoder<'a> {
     pub fn new(
         data: Bytes,
         rt: Arc<Runtime>,
-        func_name: &'a str,
+        func_name: impl Into<Cow<'a, str>>,
         output_type: DataType,
         num_rows: u64,
     ) -> Self {
         Self {
             data,
             rt,
-            func_name,
+            func_name: func_name.into(),
             output_type,
             num_rows,
         }
@@ -104,26 +209,85 @@ impl<'a> WASMEncUnitDecoder<'a> {
 
 impl EncUnitDecoder for WASMEncUnitDecoder<'_> {
     fn decode(&self) -> Result<ArrayRef> {
-        match &self.output_type {
-            non_nest_types!() => {
-                let res = self
-                    .rt
-                    .call_multi_buf(self.func_name, &self.data)
-                    .map_err(|e| general_error!("WASM call failed", e))?;
-                Ok(primitive_array_from_arrow_buffers_iter(
-                    &self.output_type,
-                    res,
-                    self.num_rows,
-                )?)
-            }
-            other => Err(Error::General(format!(
-                "WASM EncUnit decoding not implemented for type {:?}",
-                other
-            ))),
+        let res = self
+            .rt
+            .call_multi_buf(&self.func_name, &self.data)
+            .map_err(|e| general_error!("WASM call failed", e))?;
+        array_from_arrow_buffers_iter(&self.output_type, res, self.num_rows)
+    }
+}
+
+/// Decodes an EncUnit with a Wasm decoder outputting an Arrow array, see [`EntryPoint::Arrow`].
+/// The output references the memory of the instance instead of being copied out of it.
+pub struct WASMArrowEncUnitDecoder {
+    data: Bytes,
+    rt: Arc<Runtime>,
+    output_type: DataType,
+    num_rows: u64,
+}
+
+impl WASMArrowEncUnitDecoder {
+    pub fn new(data: Bytes, rt: Arc<Runtime>, output_type: DataType, num_rows: u64) -> Self {
+        Self {
+            data,
+            rt,
+            output_type,
+            num_rows,
         }
     }
 }
 
+impl EncUnitDecoder for WASMArrowEncUnitDecoder {
+    fn decode(&self) -> Result<ArrayRef> {
+        let res = self
+            .rt
+            .call_arrow(ARROW_DECODE_FUNC, &self.data, &self.output_type)
+            .map_err(|e| general_error!("WASM call failed", e))?;
+        if res.num_rows != self.num_rows {
+            return Err(Error::General(format!(
+                "WASM decoder output {} rows instead of {}",
+                res.num_rows, self.num_rows
+            )));
+        }
+        array_from_arrow_buffers_iter(&self.output_type, res.buffers.into_iter(), self.num_rows)
+    }
+}
+
+/// Create the decoder of an EncUnit running the Wasm decoder `rt`, with the stateful API if the module has it.
+pub fn create_wasm_decoder(
+    data: Bytes,
+    rt: Arc<Runtime>,
+    output_type: DataType,
+    num_rows: u64,
+    kwargs: HashMap<Key, Word>,
+) -> Result<Box<dyn EncUnitDecoder>> {
+    match rt
+        .entry_point()
+        .map_err(|e| general_error!("Unsupported WASM decoder", e))?
+    {
+        EntryPoint::Stateful => Ok(Box::new(WASMEncUnitDecoderV2::try_new(
+            data,
+            rt,
+            output_type,
+            num_rows,
+            kwargs,
+        )?)),
+        EntryPoint::Arrow => Ok(Box::new(WASMArrowEncUnitDecoder::new(
+            data,
+            rt,
+            output_type,
+            num_rows,
+        ))),
+        EntryPoint::General(func_name) => Ok(Box::new(WASMEncUnitDecoder::new(
+            data,
+            rt,
+            func_name,
+            output_type,
+            num_rows,
+        ))),
+    }
+}
+
 pub struct VortexEncUnitDecoder {
     data: Bytes,
     output_type: DataType,
@@ -218,7 +382,7 @@ impl EncUnitDecoder for VortexEncUnitDecoder {
                 // vortex_decoder.decode_all_as_array()?
             }
             ref other => {
-                return Err(Error::General(format!(
+                return Err(Error::NYI(format!(
                     "Vortex EncUnit slice not implemented for type {:?}",
                     other
                 )))
@@ -235,46 +399,204 @@ impl EncUnitDecoder for VortexEncUnitDecoder {
     }
 }
 
-pub fn create_encunit_decoder<R: Reader>(
-    encoding: fb::Encoding,
-    compression_type: fb::CompressionType,
-    mut data: Bytes,
-    num_rows: u64,
+/// Decoder for an EncUnit split into mini EncUnits.
+/// Each mini EncUnit has its own decoder, and only the ones covering the requested rows are decoded.
+pub struct MiniEncUnitDecoder {
+    /// Decoders of the mini EncUnits, along with their number of rows.
+    decoders: Vec<(usize, Box<dyn EncUnitDecoder>)>,
+}
+
+impl MiniEncUnitDecoder {
+    /// `data` holds the contiguous mini EncUnits in `mini_encunits`, which may be only a part of the EncUnit.
+    pub fn try_new<R: Reader>(
+        encunit: fb::EncUnit,
+        mini_encunits: Range<usize>,
+        mut data: Bytes,
+        output_type: DataType,
+        wasm_context: Option<Arc<WASMReadingContext<R>>>,
+    ) -> Result<Self> {
+        let sizes = encunit
+            .mini_encunit_sizes()
+            .ok_or_else(|| general_error!("Missing mini EncUnit sizes in EncUnit metadata"))?;
+        let metadata = EncUnitMetadata::with_mini_block_sizes(
+            encunit.num_rows(),
+            &sizes.iter().collect::<Vec<_>>(),
+        );
+        let mini_encunit_len = encunit.mini_encunit_len() as usize;
+        if mini_encunit_len == 0 {
+            return Err(general_error!("Invalid mini EncUnit length 0"));
+        }
+        let data_range = metadata
+            .mini_blocks_range(mini_encunits.clone())
+            .ok_or_else(|| Error::IndexOutOfBound(mini_encunits.end, metadata.num_mini_blocks()))?;
+        if data.len() != data_range.len() {
+            return Err(general_error!(format!(
+                "Mini EncUnits {:?} take {} bytes, got {}",
+                mini_encunits,
+                data_range.len(),
+                data.len()
+            )));
+        }
+        let encoding = encunit
+            .encoding()
+            .ok_or_else(|| general_error!("Missing encoding in EncUnit metadata"))?;
+        let decoders = mini_encunits
+            .map(|i| {
+                let num_rows = (metadata.num_values() as usize)
+                    .checked_sub(i * mini_encunit_len)
+                    .map(|rows| std::cmp::min(mini_encunit_len, rows))
+                    .ok_or_else(|| general_error!("Mini EncUnit out of the EncUnit rows"))?;
+                let mini_data = data.split_to(
+                    metadata
+                        .mini_blocks_range(i..i + 1)
+                        .ok_or_else(|| Error::IndexOutOfBound(i, metadata.num_mini_blocks()))?
+                        .len(),
+                );
+                Ok((
+                    num_rows,
+                    create_encunit_decoder(
+                        encoding,
+                        encunit.compression(),
+                        mini_data,
+                        num_rows as u64,
+                        output_type.clone(),
+                        wasm_context.as_ref().map(Arc::clone),
+                    )?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { decoders })
+    }
+}
+
+impl EncUnitDecoder for MiniEncUnitDecoder {
+    fn decode(&self) -> Result<ArrayRef> {
+        let arrays = self
+            .decoders
+            .iter()
+            .map(|(_, decoder)| decoder.decode())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(concat(
+            &arrays.iter().map(|a| a.as_ref()).collect::<Vec<_>>(),
+        )?)
+    }
+
+    fn slice(&self, start: usize, stop: usize) -> Result<ArrayRef> {
+        let mut arrays = vec![];
+        let mut cur = 0;
+        for (num_rows, decoder) in self.decoders.iter() {
+            let (mini_start, mini_stop) = (cur, cur + num_rows);
+            cur = mini_stop;
+            if mini_stop <= start {
+                continue;
+            }
+            if mini_start >= stop {
+                break;
+            }
+            let (from, to) = (
+                start.saturating_sub(mini_start),
+                std::cmp::min(stop, mini_stop) - mini_start,
+            );
+            // Only fall back to decoding the whole mini EncUnit if its encoding can not be sliced.
+            arrays.push(match decoder.slice(from, to) {
+                Err(Error::NYI(_)) => decoder.decode()?.slice(from, to - from),
+                res => res?,
+            });
+        }
+        Ok(concat(
+            &arrays.iter().map(|a| a.as_ref()).collect::<Vec<_>>(),
+        )?)
+    }
+}
+
+/// Create the decoder of an EncUnit from its metadata, which handles EncUnits split into mini EncUnits.
+pub fn create_encunit_decoder_from_fb<R: Reader>(
+    encunit: fb::EncUnit,
+    data: Bytes,
     output_type: DataType,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
 ) -> Result<Box<dyn EncUnitDecoder>> {
-    if compression_type != fb::CompressionType::Uncompressed {
-        data = decompress_data(data, compression_type)?;
-    }
-    let return_wasm_decoder = |data: Bytes,
-                               output_type: DataType,
-                               wasm_context: Arc<WASMReadingContext<R>>,
-                               num_rows: u64|
-     -> Result<Box<dyn EncUnitDecoder>> {
-        Ok(Box::new(WASMEncUnitDecoder::new(
+    match encunit.mini_encunit_sizes() {
+        Some(sizes) if !sizes.is_empty() => Ok(Box::new(MiniEncUnitDecoder::try_new(
+            encunit,
+            0..sizes.len(),
             data,
-            wasm_context.get_runtime(crate::context::WASMId(
-                encoding
-                    .wasm_encoding()
-                    .ok_or_else(|| {
-                        Error::General("not provided custom WASM in the file".to_string())
-                    })?
-                    .wasm_id(),
-            ))?,
-            WASM_FUNC_GENERAL, // FIXME: should get from wasm binary
             output_type,
-            num_rows,
-        )))
+            wasm_context,
+        )?)),
+        _ => create_encunit_decoder(
+            encunit
+                .encoding()
+                .ok_or_else(|| general_error!("Missing encoding in EncUnit metadata"))?,
+            encunit.compression(),
+            data,
+            encunit.num_rows() as u64,
+            output_type,
+            wasm_context,
+        ),
+    }
+}
+
+/// Create a decoder running the Wasm decoder carried in the file for an EncUnit, even if it also has a native one.
+/// Returns None if the file has no Wasm decoder for the EncUnit, or if the EncUnit is split into mini EncUnits.
+pub(crate) fn create_wasm_encunit_decoder_from_fb<R: Reader>(
+    encunit: fb::EncUnit,
+    mut data: Bytes,
+    output_type: DataType,
+    wasm_context: &WASMReadingContext<R>,
+) -> Result<Option<Box<dyn EncUnitDecoder>>> {
+    let encoding = encunit
+        .encoding()
+        .ok_or_else(|| general_error!("Missing encoding in EncUnit metadata"))?;
+    let Some(wasm_encoding) = encoding.wasm_encoding() else {
+        return Ok(None);
     };
+    if encunit
+        .mini_encunit_sizes()
+        .is_some_and(|sizes| !sizes.is_empty())
+    {
+        return Ok(None);
+    }
+    if encunit.compression() != fb::CompressionType::Uncompressed {
+        data = decompress_data(data, encunit.compression())?;
+    }
+    Ok(Some(create_wasm_decoder(
+        data,
+        wasm_context.get_runtime(crate::context::WASMId(wasm_encoding.wasm_id()))?,
+        output_type,
+        encunit.num_rows() as u64,
+        HashMap::new(),
+    )?))
+}
+
+/// Which decoder runs an EncUnit, negotiated between the encoding versions of the file and those
+/// of the reader, the registered plugins, and the [`DecoderPolicy`].
+pub(crate) enum DecoderChoice {
+    /// The built-in decoder, or the registered plugin if the encoding is PLUGIN.
+    Native(Option<Arc<dyn EncodingPlugin>>),
+    /// The Wasm decoder carried in the file.
+    Wasm,
+}
+
+/// Choose the decoder of an EncUnit with `encoding`, failing if it has none this reader can run.
+pub(crate) fn choose_decoder<R: Reader>(
+    encoding: fb::Encoding,
+    wasm_context: Option<&WASMReadingContext<R>>,
+) -> Result<DecoderChoice> {
+    let force_wasm =
+        wasm_context.is_some_and(|ctx| ctx.decoder_policy() == DecoderPolicy::ForceWasm);
+    let has_wasm = wasm_context.is_some() && encoding.wasm_encoding().is_some();
     Ok(match encoding.type_() {
         fb::EncodingType::CASCADE => {
-            let wasm_context =
-                wasm_context.ok_or_else(|| general_error!("WASM context not found"))?;
-            let encoding_versions = wasm_context
-                .get_encoding_versions()
-                .ok_or_else(|| general_error!("Encoding versions not found"))?;
-            let encoding_version = encoding_versions
-                .get(&encoding.type_())
+            // Recorded in the EncUnit, or once for the whole file in the footer.
+            let encoding_version = encoding
+                .version()
+                .map(version_from_fb)
+                .or_else(|| {
+                    wasm_context
+                        .and_then(|ctx| ctx.get_encoding_versions())
+                        .and_then(|versions| versions.get(&encoding.type_()).cloned())
+                })
                 .ok_or_else(|| general_error!("Encoding version not found"))?;
             let reader_version = DEFAULT_ENCODING_VERSIONS
                 .get(&encoding.type_())
@@ -284,21 +606,68 @@ pub fn create_encunit_decoder<R: Reader>(
                         encoding.type_()
                     ))
                 })?;
-            // if reader has less version than the file, and major version is different or major version is 0, then it is incompatible
-            if reader_version.cmp_precedence(encoding_version).is_lt()
-                && (reader_version.major != encoding_version.major || reader_version.major == 0)
-            {
-                return_wasm_decoder(data, output_type, wasm_context, num_rows)?
+            let compatible = is_encoding_version_compatible(reader_version, &encoding_version);
+            match (force_wasm || !compatible, has_wasm) {
+                (false, _) => DecoderChoice::Native(None),
+                (true, true) => DecoderChoice::Wasm,
+                (true, false) if compatible => {
+                    return Err(Error::General(format!(
+                        "Encoding {:?} {} has no Wasm decoder in the file",
+                        encoding.type_(),
+                        encoding_version
+                    )))
+                }
+                (true, false) => {
+                    return Err(Error::General(format!(
+                        "Encoding {:?} {} is newer than {} of the reader, and has no Wasm decoder in the file",
+                        encoding.type_(),
+                        encoding_version,
+                        reader_version
+                    )))
+                }
+            }
+        }
+        fb::EncodingType::PLUGIN => {
+            let plugin = encoding
+                .plugin()
+                .ok_or_else(|| general_error!("Missing plugin in EncUnit encoding"))?;
+            let plugin_version = version_from_fb(plugin.version());
+            let native = if force_wasm {
+                None
             } else {
-                Box::new(VortexEncUnitDecoder::new(data, output_type))
+                compatible_encoding(plugin.id(), &plugin_version)?
+            };
+            match native {
+                Some(native) => DecoderChoice::Native(Some(native)),
+                None if has_wasm => {
+                    debug!(
+                        "Decoding encoding {} {} with Wasm",
+                        plugin.id(),
+                        plugin_version
+                    );
+                    DecoderChoice::Wasm
+                }
+                None if force_wasm => {
+                    return Err(Error::General(format!(
+                        "Encoding {} {} has no Wasm decoder in the file",
+                        plugin.id(),
+                        plugin_version
+                    )))
+                }
+                None => {
+                    return Err(Error::General(format!(
+                        "Encoding {} {} is neither registered nor embedded as Wasm in the file",
+                        plugin.id(),
+                        plugin_version
+                    )))
+                }
             }
         }
         fb::EncodingType::CUSTOM_WASM => {
-            if let Some(wasm_context) = wasm_context {
-                return_wasm_decoder(data, output_type, wasm_context, num_rows)?
-            } else {
+            if wasm_context.is_none() {
                 return Err(general_error!("WASM context required for custom encoding"));
             }
+            DecoderChoice::Wasm
         }
         other => {
             return Err(Error::General(format!(
@@ -308,3 +677,40 @@ pub fn create_encunit_decoder<R: Reader>(
         }
     })
 }
+
+pub fn create_encunit_decoder<R: Reader>(
+    encoding: fb::Encoding,
+    compression_type: fb::CompressionType,
+    mut data: Bytes,
+    num_rows: u64,
+    output_type: DataType,
+    wasm_context: Option<Arc<WASMReadingContext<R>>>,
+) -> Result<Box<dyn EncUnitDecoder>> {
+    if compression_type != fb::CompressionType::Uncompressed {
+        data = decompress_data(data, compression_type)?;
+    }
+    Ok(match choose_decoder(encoding, wasm_context.as_deref())? {
+        DecoderChoice::Native(None) => Box::new(VortexEncUnitDecoder::new(data, output_type)),
+        DecoderChoice::Native(Some(plugin)) => {
+            plugin.create_decoder(data, &output_type, num_rows)?
+        }
+        DecoderChoice::Wasm => {
+            let wasm_context =
+                wasm_context.ok_or_else(|| general_error!("WASM context not found"))?;
+            create_wasm_decoder(
+                data,
+                wasm_context.get_runtime(crate::context::WASMId(
+                    encoding
+                        .wasm_encoding()
+                        .ok_or_else(|| {
+                            Error::General("not provided custom WASM in the file".to_string())
+                        })?
+                        .wasm_id(),
+                ))?,
+                output_type,
+                num_rows,
+                HashMap::new(),
+            )?
+        }
+    })
+}
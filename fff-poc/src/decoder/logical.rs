@@ -1,7 +1,8 @@
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
 
-use crate::common::checksum::{create_checksum, ChecksumType};
+use crate::common::checksum::{checksum_of, ChecksumType};
 use crate::dict::shared_dictionary_cache::SharedDictionaryCache;
+use crate::encryption::{module_aad, AesGcmCipher, ModuleType};
 use crate::io::reader::Reader;
 use crate::{common::ColumnIndexSequence, context::WASMReadingContext};
 use arrow::array::AsArray;
@@ -14,9 +15,11 @@ use fff_core::{
     general_error,
 };
 use fff_format::File::fff::flatbuf as fb;
-use flatbuffers::{ForwardsUOffset, VectorIter};
+use flatbuffers::{ForwardsUOffset, Vector, VectorIter};
 
-use super::physical::{create_physical_decoder, ChunkDecoder};
+use super::encunit::{EncUnitDecoder, MiniEncUnitDecoder};
+use super::physical::{create_physical_decoder, locate_encunits, ChunkDecoder, EncUnitRange};
+use super::policy::EncUnitLocation;
 use fff_core::non_nest_types;
 
 /// This maps to each logical column in the top level Arrow schema stored in file footer.
@@ -26,6 +29,9 @@ pub trait LogicalColDecoder {
     fn decode_batch(&mut self) -> Result<Vec<ArrayRef>>;
     /// Decode some rows out starting at row_id.
     fn decode_row_at(&mut self, row_id: usize, len: usize) -> Result<Vec<ArrayRef>>;
+    /// Decode the rows in the sorted `ranges` of the current row group, one `ArrayRef` per range.
+    /// Chunks without any of these rows are not read.
+    fn decode_ranges(&mut self, ranges: &[Range<usize>]) -> Result<Vec<ArrayRef>>;
 }
 
 /// A specific trait for testing select+proj performance of different nested implementation.
@@ -47,114 +53,326 @@ pub struct PrimitiveColDecoder<'a, R> {
     chunks_meta_iter: VectorIter<'a, ForwardsUOffset<fb::Chunk<'a>>>,
     primitive_type: DataType,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
-    shared_dictionary_cache: &'a SharedDictionaryCache,
+    shared_dictionary_cache: &'a SharedDictionaryCache<R>,
     /// if checksum is not None, we will verify the checksum of the chunk
     checksum_type: Option<ChecksumType>,
+    /// Decrypt the EncUnits with this cipher if the column is encrypted.
+    cipher: Option<Arc<AesGcmCipher>>,
+    /// Location of the first EncUnit of the next chunk.
+    location: EncUnitLocation,
 }
 
 impl<R: Reader> PrimitiveColDecoder<'_, R> {
-    /// Read a chunk from the reader
-    /// IO and compute are sequential in this case. Separation is left for future work.
-    fn read_chunk(&mut self, offset: u64, size: u32, checksum: Option<u64>) -> Result<BytesMut> {
-        let mut buf = BytesMut::zeroed(size as usize);
+    fn next_chunk_location(&mut self) -> EncUnitLocation {
+        let location = self.location;
+        self.location.chunk += 1;
+        location
+    }
+
+    fn read_range(&self, offset: u64, size: usize) -> Result<BytesMut> {
+        let mut buf = BytesMut::zeroed(size);
         self.r.read_exact_at(&mut buf, offset)?;
-        if let Some(checksum_type) = &mut self.checksum_type {
-            let checksum = checksum.ok_or_else(|| {
-                general_error!(format!(
-                    "No checksum in column meta for chunk at offset {}",
-                    offset
-                ))
-            })?;
-            let computed_checksum = {
-                let mut checksum = create_checksum(checksum_type);
-                checksum.update(&buf);
-                checksum.finalize()
-            };
-            if checksum != computed_checksum {
-                return Err(Error::General("Checksum verification failed".to_string()));
+        Ok(buf)
+    }
+
+    /// Read a chunk from the reader, verifying the checksum of the IOUnit if required.
+    /// Falls back to the checksums of its EncUnits if the IOUnit has none.
+    /// IO and compute are sequential in this case. Separation is left for future work.
+    fn read_chunk(&self, chunk_meta: fb::Chunk<'_>) -> Result<BytesMut> {
+        let offset = chunk_meta.offset();
+        let buf = self.read_range(offset, chunk_meta.size_() as usize)?;
+        if let Some(checksum_type) = &self.checksum_type {
+            match chunk_meta.checksum() {
+                Some(checksum) => {
+                    if checksum != checksum_of(checksum_type, &buf) {
+                        return Err(Error::General("Checksum verification failed".to_string()));
+                    }
+                }
+                None => {
+                    let encunits = chunk_meta
+                        .encunits()
+                        .filter(|encunits| has_encunit_checksums(*encunits))
+                        .ok_or_else(|| {
+                            general_error!(format!(
+                                "No checksum in column meta for chunk at offset {}",
+                                offset
+                            ))
+                        })?;
+                    self.verify_encunits(&buf, encunits, 0)?;
+                }
             }
         }
         Ok(buf)
     }
+
+    /// Verify the checksums of the EncUnits from `first_encunit` on that are inside `buf`.
+    fn verify_encunits(
+        &self,
+        buf: &[u8],
+        encunits: Vector<'_, ForwardsUOffset<fb::EncUnit<'_>>>,
+        first_encunit: usize,
+    ) -> Result<()> {
+        let Some(checksum_type) = &self.checksum_type else {
+            return Ok(());
+        };
+        let mut pos = 0;
+        for encunit in encunits.iter().skip(first_encunit) {
+            if pos >= buf.len() {
+                break;
+            }
+            let end = pos + encunit.size_() as usize;
+            let checksum = encunit
+                .checksum()
+                .ok_or_else(|| general_error!("No checksum in EncUnit metadata"))?;
+            let data = buf
+                .get(pos..end)
+                .ok_or_else(|| general_error!("EncUnit exceeds the read buffer"))?;
+            if checksum != checksum_of(checksum_type, data) {
+                return Err(Error::General(
+                    "EncUnit checksum verification failed".to_string(),
+                ));
+            }
+            pos = end;
+        }
+        Ok(())
+    }
+
+    /// Decrypt in place the EncUnits from `first_encunit` on that are inside `buf`,
+    /// which is read from file offset `offset`.
+    fn decrypt_encunits(
+        &self,
+        buf: &mut BytesMut,
+        offset: u64,
+        encunits: Vector<'_, ForwardsUOffset<fb::EncUnit<'_>>>,
+        first_encunit: usize,
+    ) -> Result<()> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(());
+        };
+        let mut pos = 0;
+        for encunit in encunits.iter().skip(first_encunit) {
+            if pos >= buf.len() {
+                break;
+            }
+            let end = pos + encunit.size_() as usize;
+            let params = encunit.encryption().ok_or_else(|| {
+                general_error!("EncUnit of an encrypted column has no encryption parameters")
+            })?;
+            cipher.decrypt_in_place_detached(
+                buf.get_mut(pos..end)
+                    .ok_or_else(|| general_error!("EncUnit exceeds the read buffer"))?,
+                &module_aad(ModuleType::EncUnit, offset + pos as u64),
+                params.nonce().map(|v| v.bytes()).unwrap_or_default(),
+                params.tag().map(|v| v.bytes()).unwrap_or_default(),
+            )?;
+            pos = end;
+        }
+        Ok(())
+    }
+}
+
+fn has_encunit_checksums(encunits: Vector<'_, ForwardsUOffset<fb::EncUnit<'_>>>) -> bool {
+    !encunits.is_empty() && encunits.iter().all(|encunit| encunit.checksum().is_some())
+}
+
+fn column_cipher(
+    column_ciphers: &[Option<Arc<AesGcmCipher>>],
+    column_index: u32,
+) -> Option<Arc<AesGcmCipher>> {
+    column_ciphers.get(column_index as usize).cloned().flatten()
+}
+
+impl<'a, R: Reader> PrimitiveColDecoder<'a, R> {
+    /// Read and decode a whole chunk, returning the arrays of its EncUnits.
+    fn decode_chunk(
+        &mut self,
+        chunk_meta: fb::Chunk<'a>,
+        location: EncUnitLocation,
+    ) -> Result<Vec<ArrayRef>> {
+        let encunits = chunk_meta
+            .encunits()
+            .ok_or_else(|| general_error!("No chunks in column meta"))?;
+        let mut encoded_chunk_buf = self.read_chunk(chunk_meta)?;
+        self.decrypt_encunits(&mut encoded_chunk_buf, chunk_meta.offset(), encunits, 0)?;
+        self.chunk_decoder = Some(create_physical_decoder::<R>(
+            encunits.iter(),
+            chunk_meta.encoding_type(),
+            chunk_meta.encoding_as_shared_dictionary(),
+            &self.primitive_type,
+            encoded_chunk_buf,
+            self.wasm_context.as_ref().map(Arc::clone),
+            Some(self.shared_dictionary_cache),
+            Some(location),
+        )?);
+        let mut arrays = vec![];
+        while let Some(array) = self
+            .chunk_decoder
+            .as_mut()
+            .ok_or_else(|| general_error!("Chunk decoder not initialized"))?
+            .decode_batch()?
+        {
+            arrays.push(array);
+        }
+        Ok(arrays)
+    }
 }
 
 impl<R: Reader> LogicalColDecoder for PrimitiveColDecoder<'_, R> {
     fn decode_batch(&mut self) -> Result<Vec<ArrayRef>> {
         let mut arrays = vec![];
         while let Some(chunk_meta) = self.chunks_meta_iter.next() {
-            let encoded_chunk_buf = self.read_chunk(
-                chunk_meta.offset(),
-                chunk_meta.size_(),
-                chunk_meta.checksum(),
-            )?;
-            self.chunk_decoder = Some(create_physical_decoder::<R>(
-                chunk_meta
-                    .encunits()
-                    .ok_or_else(|| general_error!("No chunks in column meta"))?
-                    .iter(),
-                chunk_meta.encoding_type(),
-                chunk_meta.encoding_as_shared_dictionary(),
-                &self.primitive_type,
-                encoded_chunk_buf,
-                self.wasm_context.as_ref().map(Arc::clone),
-                Some(self.shared_dictionary_cache),
-            )?);
-            while let Some(array) = self
-                .chunk_decoder
-                .as_mut()
-                .ok_or_else(|| general_error!("Chunk decoder not initialized"))?
-                .decode_batch()?
+            let location = self.next_chunk_location();
+            arrays.extend(self.decode_chunk(chunk_meta, location)?);
+        }
+        Ok(arrays)
+    }
+
+    fn decode_ranges(&mut self, ranges: &[Range<usize>]) -> Result<Vec<ArrayRef>> {
+        // The decoded arrays with their first row, only of the chunks overlapping the ranges.
+        let mut decoded: Vec<(usize, ArrayRef)> = vec![];
+        let mut chunk_start = 0;
+        while let Some(chunk_meta) = self.chunks_meta_iter.next() {
+            let location = self.next_chunk_location();
+            let chunk_end = chunk_start + chunk_meta.num_rows() as usize;
+            if ranges
+                .iter()
+                .any(|range| range.start < chunk_end && chunk_start < range.end)
             {
-                arrays.push(array);
+                let mut row = chunk_start;
+                for array in self.decode_chunk(chunk_meta, location)? {
+                    let len = array.len();
+                    decoded.push((row, array));
+                    row += len;
+                }
             }
+            chunk_start = chunk_end;
         }
-        Ok(arrays)
+        ranges
+            .iter()
+            .map(|range| {
+                let mut pieces = decoded
+                    .iter()
+                    .filter_map(|(start, array)| {
+                        let from = range.start.max(*start);
+                        let to = range.end.min(start + array.len());
+                        (from < to).then(|| array.slice(from - start, to - from))
+                    })
+                    .collect::<Vec<_>>();
+                match pieces.len() {
+                    0 => Err(general_error!(format!(
+                        "Rows {:?} not found in column chunks",
+                        range
+                    ))),
+                    1 => Ok(pieces.remove(0)),
+                    _ => Ok(arrow::compute::concat(
+                        &pieces.iter().map(|a| a.as_ref()).collect::<Vec<_>>(),
+                    )?),
+                }
+            })
+            .collect()
     }
+
     fn decode_row_at(&mut self, row_id: usize, len: usize) -> Result<Vec<ArrayRef>> {
         let mut arrays = vec![];
         let mut cur_row = 0; // FIXME: Not correct if we have muliple row groups
+        let mut next_row = row_id;
         let mut remaining = len;
         while let Some(chunk_meta) = self.chunks_meta_iter.next() {
+            let location = self.next_chunk_location();
             if remaining == 0 {
                 break;
             }
-            if cur_row + chunk_meta.num_rows() as usize <= row_id {
-                cur_row += chunk_meta.num_rows() as usize;
+            let chunk_num_rows = chunk_meta.num_rows() as usize;
+            if cur_row + chunk_num_rows <= next_row {
+                cur_row += chunk_num_rows;
                 continue;
             }
-            let mut to_decode = std::cmp::min(
-                chunk_meta.num_rows() as usize - (row_id - cur_row),
-                remaining,
-            );
-
-            let encoded_chunk_buf = self.read_chunk(
-                chunk_meta.offset(),
-                chunk_meta.size_(),
-                chunk_meta.checksum(),
-            )?;
-            // println!(
-            //     "read chunk at offset {} with size {}",
-            //     chunk_meta.offset(),
-            //     chunk_meta.size_()
-            // );
+            let row_id_in_chunk = next_row - cur_row;
+            let mut to_decode = std::cmp::min(chunk_num_rows - row_id_in_chunk, remaining);
+            let encunits = chunk_meta
+                .encunits()
+                .ok_or_else(|| general_error!("No chunks in column meta"))?;
+            // Fetch only the EncUnits covering the rows, unless the whole IOUnit is needed to verify its checksum.
+            // EncUnit checksums can verify a partial read, but not of mini EncUnits.
+            // Dictionary chunks still need their dictionary EncUnits, so they are read as a whole.
+            let verify_encunits = self.checksum_type.is_some();
+            let encunit_range = ((!verify_encunits || has_encunit_checksums(encunits))
+                && chunk_meta.encoding_type() == fb::DictionaryEncoding::NoDictionary)
+                .then(|| locate_encunits(encunits, row_id_in_chunk, to_decode, !verify_encunits))
+                .flatten();
+            if let Some(EncUnitRange {
+                first_encunit,
+                row_offset,
+                byte_range,
+                mini_encunits: Some(mini_encunits),
+            }) = &encunit_range
+            {
+                // The rows are inside a single EncUnit split into mini EncUnits, only decode the ones covering them.
+                let buf = self.read_range(
+                    chunk_meta.offset() + byte_range.start,
+                    (byte_range.end - byte_range.start) as usize,
+                )?;
+                let decoder = MiniEncUnitDecoder::try_new(
+                    encunits.get(*first_encunit),
+                    mini_encunits.clone(),
+                    buf.freeze(),
+                    self.primitive_type.clone(),
+                    self.wasm_context.as_ref().map(Arc::clone),
+                )?;
+                let row_id_in_buf = row_id_in_chunk - row_offset;
+                arrays.push(decoder.slice(row_id_in_buf, row_id_in_buf + to_decode)?);
+                remaining -= to_decode;
+                next_row += to_decode;
+                cur_row += chunk_num_rows;
+                continue;
+            }
+            let location = EncUnitLocation {
+                encunit: encunit_range
+                    .as_ref()
+                    .map_or(0, |range| range.first_encunit),
+                ..location
+            };
+            let (encoded_chunk_buf, encunit_iter, row_id_in_buf) = match encunit_range {
+                Some(range) => {
+                    let mut buf = self.read_range(
+                        chunk_meta.offset() + range.byte_range.start,
+                        (range.byte_range.end - range.byte_range.start) as usize,
+                    )?;
+                    self.verify_encunits(&buf, encunits, range.first_encunit)?;
+                    self.decrypt_encunits(
+                        &mut buf,
+                        chunk_meta.offset() + range.byte_range.start,
+                        encunits,
+                        range.first_encunit,
+                    )?;
+                    let mut encunit_iter = encunits.iter();
+                    if range.first_encunit > 0 {
+                        encunit_iter.nth(range.first_encunit - 1);
+                    }
+                    (buf, encunit_iter, row_id_in_chunk - range.row_offset)
+                }
+                None => {
+                    let mut buf = self.read_chunk(chunk_meta)?;
+                    self.decrypt_encunits(&mut buf, chunk_meta.offset(), encunits, 0)?;
+                    (buf, encunits.iter(), row_id_in_chunk)
+                }
+            };
             self.chunk_decoder = Some(create_physical_decoder::<R>(
-                chunk_meta
-                    .encunits()
-                    .ok_or_else(|| general_error!("No chunks in column meta"))?
-                    .iter(),
+                encunit_iter,
                 chunk_meta.encoding_type(),
                 chunk_meta.encoding_as_shared_dictionary(),
                 &self.primitive_type,
                 encoded_chunk_buf,
                 self.wasm_context.as_ref().map(Arc::clone),
                 Some(self.shared_dictionary_cache),
+                Some(location),
             )?);
             let mut decoded = 0;
             while let Some(array) = self
                 .chunk_decoder
                 .as_mut()
                 .ok_or_else(|| general_error!("Chunk decoder not initialized"))?
-                .decode_row_at(row_id - cur_row, to_decode)?
+                .decode_row_at(row_id_in_buf, to_decode)?
             {
                 to_decode -= array.len();
                 decoded += array.len();
@@ -164,6 +382,8 @@ impl<R: Reader> LogicalColDecoder for PrimitiveColDecoder<'_, R> {
                 }
             }
             remaining -= decoded;
+            next_row += decoded;
+            cur_row += chunk_num_rows;
         }
         Ok(arrays)
     }
@@ -226,6 +446,12 @@ impl<R: Reader> LogicalColDecoder for ListColDecoder<'_, R> {
             "Random access for ListColDecoder is not implemented yet".to_string(),
         ))
     }
+
+    fn decode_ranges(&mut self, _ranges: &[Range<usize>]) -> Result<Vec<ArrayRef>> {
+        Err(Error::General(
+            "Decoding row ranges for ListColDecoder is not implemented yet".to_string(),
+        ))
+    }
 }
 
 /// A custom experimental ListStruct(non_nest) decoder with Offsets pushdown for List.
@@ -454,17 +680,26 @@ impl<R: Reader> LogicalColDecoder for StructColDecoder<'_, R> {
             "Random access for StructColDecoder is not implemented yet".to_string(),
         ))
     }
+
+    fn decode_ranges(&mut self, _ranges: &[Range<usize>]) -> Result<Vec<ArrayRef>> {
+        Err(Error::General(
+            "Decoding row ranges for StructColDecoder is not implemented yet".to_string(),
+        ))
+    }
 }
 
 /// Create a LogicalListStructNonNestedColDecoder
 /// Whether it is OffsetPushdown or not depends on the feature flag "list-offsets-pushdown"
+#[allow(clippy::too_many_arguments)]
 pub fn create_list_struct_decoder<'a, R: Reader>(
     r: &'a R,
     field: FieldRef,
+    row_group: usize,
     column_metas: &Vec<fb::ColumnMetadata<'a>>,
     column_idx: &mut ColumnIndexSequence,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
-    shared_dictionary_cache: &'a SharedDictionaryCache,
+    shared_dictionary_cache: &'a SharedDictionaryCache<R>,
+    column_ciphers: &[Option<Arc<AesGcmCipher>>],
 ) -> Result<Box<dyn LogicalListStructNonNestedColDecoder + 'a>> {
     let mut column_index = column_idx.next_column_index();
     let mut column_meta = column_metas.get(column_index as usize).ok_or_else(|| {
@@ -524,6 +759,8 @@ pub fn create_list_struct_decoder<'a, R: Reader>(
                                 wasm_context: wasm_context.as_ref().map(Arc::clone),
                                 shared_dictionary_cache,
                                 checksum_type: None,
+                                cipher: column_cipher(column_ciphers, column_index),
+                                location: EncUnitLocation::column(row_group, column_index),
                             });
                             i += 1;
                             if i == fields.len() {
@@ -558,6 +795,8 @@ pub fn create_list_struct_decoder<'a, R: Reader>(
                             wasm_context: wasm_context.as_ref().map(Arc::clone),
                             shared_dictionary_cache,
                             checksum_type: None,
+                            cipher: column_cipher(column_ciphers, column_index),
+                            location: EncUnitLocation::column(row_group, column_index),
                         },
                         children: StructOfNonNestColDecoder {
                             fields: fields.clone(),
@@ -586,6 +825,8 @@ pub fn create_list_struct_decoder<'a, R: Reader>(
                                 wasm_context: wasm_context.as_ref().map(Arc::clone),
                                 shared_dictionary_cache,
                                 checksum_type: None,
+                                cipher: column_cipher(column_ciphers, column_index),
+                                location: EncUnitLocation::column(row_group, column_index),
                             },
                             children: fields
                                 .iter()
@@ -613,6 +854,8 @@ pub fn create_list_struct_decoder<'a, R: Reader>(
                                         wasm_context: wasm_context.as_ref().map(Arc::clone),
                                         shared_dictionary_cache,
                                         checksum_type: None,
+                                        cipher: column_cipher(column_ciphers, column_index),
+                                        location: EncUnitLocation::column(row_group, column_index),
                                     })
                                 })
                                 .collect::<Result<Vec<_>>>()?,
@@ -632,13 +875,16 @@ pub fn create_list_struct_decoder<'a, R: Reader>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_logical_decoder<'a, R: Reader>(
     r: &'a R,
     field: FieldRef,
+    row_group: usize,
     column_metas: &Vec<fb::ColumnMetadata<'a>>,
     column_idx: &mut ColumnIndexSequence,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
-    shared_dictionary_cache: &'a SharedDictionaryCache,
+    shared_dictionary_cache: &'a SharedDictionaryCache<R>,
+    column_ciphers: &[Option<Arc<AesGcmCipher>>],
     checksum_type: Option<ChecksumType>,
 ) -> Result<Box<dyn LogicalColDecoder + 'a>> {
     // match field.data_type() {
@@ -682,7 +928,8 @@ pub fn create_logical_decoder<'a, R: Reader>(
         .ok_or_else(|| Error::General("No chunks in column meta".to_string()))?
         .iter();
     match field.data_type() {
-        non_nest_types!() => {
+        // Dictionary fields are dictionary encoded columns decoded as DictionaryArrays.
+        non_nest_types!() | DataType::Dictionary(_, _) => {
             let data_type = field.data_type().clone();
             Ok(Box::new(PrimitiveColDecoder {
                 r,
@@ -692,6 +939,8 @@ pub fn create_logical_decoder<'a, R: Reader>(
                 wasm_context: wasm_context.map(|wasm_context| Arc::clone(&wasm_context)),
                 shared_dictionary_cache,
                 checksum_type,
+                cipher: column_cipher(column_ciphers, column_index),
+                location: EncUnitLocation::column(row_group, column_index),
             }))
         }
         DataType::List(child) | DataType::LargeList(child) => {
@@ -706,14 +955,18 @@ pub fn create_logical_decoder<'a, R: Reader>(
                     wasm_context: wasm_context.as_ref().map(Arc::clone),
                     shared_dictionary_cache,
                     checksum_type,
+                    cipher: column_cipher(column_ciphers, column_index),
+                    location: EncUnitLocation::column(row_group, column_index),
                 },
                 values_decoder: create_logical_decoder(
                     r,
                     Arc::clone(child),
+                    row_group,
                     column_metas,
                     column_idx,
                     wasm_context.map(|wasm_context| Arc::clone(&wasm_context)),
                     shared_dictionary_cache,
+                    column_ciphers,
                     checksum_type,
                 )?,
             }))
@@ -729,6 +982,8 @@ pub fn create_logical_decoder<'a, R: Reader>(
                 wasm_context: wasm_context.as_ref().map(Arc::clone),
                 shared_dictionary_cache,
                 checksum_type,
+                cipher: column_cipher(column_ciphers, column_index),
+                location: EncUnitLocation::column(row_group, column_index),
             },
             children: child_fields
                 .iter()
@@ -736,10 +991,12 @@ pub fn create_logical_decoder<'a, R: Reader>(
                     create_logical_decoder(
                         r,
                         Arc::clone(f),
+                        row_group,
                         column_metas,
                         column_idx,
                         wasm_context.as_ref().map(Arc::clone),
                         shared_dictionary_cache,
+                        column_ciphers,
                         checksum_type,
                     )
                 })
@@ -754,7 +1011,7 @@ pub fn create_logical_decoder<'a, R: Reader>(
 
 pub fn advance_column_index(field: FieldRef, column_idx: &mut ColumnIndexSequence) -> Result<()> {
     match field.data_type() {
-        non_nest_types!() => {
+        non_nest_types!() | DataType::Dictionary(_, _) => {
             let _column_index = column_idx.next_column_index();
             Ok(())
         }
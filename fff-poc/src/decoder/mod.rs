@@ -1,3 +1,4 @@
 pub mod encunit;
 pub mod logical;
 pub mod physical;
+pub mod policy;
@@ -4,14 +4,23 @@ use crate::{
     context::WASMReadingContext, dict::shared_dictionary_cache::SharedDictionaryCache,
     io::reader::Reader,
 };
-use arrow_array::{Array, ArrayRef, UInt16Array, UInt32Array, UInt64Array, UInt8Array};
+use arrow::compute::{cast, cast_with_options, CastOptions};
+use arrow_array::{make_array, Array, ArrayRef, UInt16Array, UInt32Array, UInt64Array, UInt8Array};
 use arrow_schema::{DataType, TimeUnit};
 use bytes::BytesMut;
-use fff_core::{errors::Result, general_error, non_nest_types, nyi_err};
+use fff_core::{
+    errors::{Error, Result},
+    general_error, non_nest_types, nyi_err,
+};
+use fff_encoding::schemes::EncUnitMetadata;
 use fff_format::File::fff::flatbuf as fb;
-use flatbuffers::{ForwardsUOffset, VectorIter};
+use flatbuffers::{ForwardsUOffset, Vector, VectorIter};
+use std::ops::Range;
 
-use super::encunit::create_encunit_decoder;
+use super::{
+    encunit::{create_encunit_decoder, create_encunit_decoder_from_fb},
+    policy::{verify_encunit, EncUnitLocation},
+};
 
 /// Stateful Chunk Decoder that will decode a EncUnit at a time.
 pub trait ChunkDecoder {
@@ -23,6 +32,111 @@ pub trait ChunkDecoder {
     fn decode_row_at(&mut self, row_id_in_chunk: usize, len: usize) -> Result<Option<ArrayRef>>;
 }
 
+/// Location of the EncUnits covering some rows inside a Chunk.
+pub(crate) struct EncUnitRange {
+    /// Index of the first EncUnit covering the rows.
+    pub(crate) first_encunit: usize,
+    /// Number of rows in the Chunk before the first EncUnit.
+    pub(crate) row_offset: usize,
+    /// Byte range relative to the start of the Chunk.
+    pub(crate) byte_range: Range<u64>,
+    /// If all rows are inside a single EncUnit split into mini EncUnits, the mini EncUnits covering them.
+    /// `row_offset` and `byte_range` are then narrowed down to these mini EncUnits.
+    pub(crate) mini_encunits: Option<Range<usize>>,
+}
+
+/// Locate the EncUnits covering rows `[row_id_in_chunk, row_id_in_chunk + len)` from the EncUnit sizes in metadata,
+/// so that only these EncUnits need to be fetched instead of the whole Chunk.
+/// Returns None if `row_id_in_chunk` is out of the Chunk.
+/// If `split_mini_encunits` is false, whole EncUnits are located even if they are split into mini EncUnits.
+pub(crate) fn locate_encunits(
+    encunits: Vector<'_, ForwardsUOffset<fb::EncUnit<'_>>>,
+    row_id_in_chunk: usize,
+    len: usize,
+    split_mini_encunits: bool,
+) -> Option<EncUnitRange> {
+    let mut first: Option<(usize, usize, u64)> = None;
+    let mut last = 0;
+    let mut cur_row = 0;
+    let mut cur_byte = 0;
+    for (i, encunit) in encunits.iter().enumerate() {
+        let end_row = cur_row + encunit.num_rows() as usize;
+        if first.is_none() && row_id_in_chunk < end_row {
+            first = Some((i, cur_row, cur_byte));
+        }
+        last = i;
+        cur_byte += encunit.size_() as u64;
+        if first.is_some() && row_id_in_chunk + len <= end_row {
+            break;
+        }
+        cur_row = end_row;
+    }
+    let (first_encunit, row_offset, start) = first?;
+    if first_encunit == last && split_mini_encunits {
+        if let Some(range) = locate_mini_encunits(
+            encunits.get(first_encunit),
+            row_id_in_chunk - row_offset,
+            len,
+        ) {
+            return Some(EncUnitRange {
+                first_encunit,
+                row_offset: row_offset + range.row_offset,
+                byte_range: start + range.byte_range.start..start + range.byte_range.end,
+                mini_encunits: Some(range.mini_encunits),
+            });
+        }
+    }
+    Some(EncUnitRange {
+        first_encunit,
+        row_offset,
+        byte_range: start..cur_byte,
+        mini_encunits: None,
+    })
+}
+
+/// Location of the mini EncUnits covering some rows inside an EncUnit.
+struct MiniEncUnitRange {
+    /// Indexes of the mini EncUnits covering the rows.
+    mini_encunits: Range<usize>,
+    /// Number of rows in the EncUnit before the first mini EncUnit.
+    row_offset: usize,
+    /// Byte range relative to the start of the EncUnit.
+    byte_range: Range<u64>,
+}
+
+/// Locate the mini EncUnits covering rows `[row_id_in_encunit, row_id_in_encunit + len)` inside an EncUnit,
+/// with offsets relative to the start of the EncUnit. Returns None if the EncUnit is not split,
+/// or if it is encrypted since it can only be authenticated as a whole.
+fn locate_mini_encunits(
+    encunit: fb::EncUnit<'_>,
+    row_id_in_encunit: usize,
+    len: usize,
+) -> Option<MiniEncUnitRange> {
+    if encunit.encryption().is_some() {
+        return None;
+    }
+    let sizes = encunit.mini_encunit_sizes()?;
+    let mini_encunit_len = encunit.mini_encunit_len() as usize;
+    if sizes.is_empty() || mini_encunit_len == 0 || len == 0 {
+        return None;
+    }
+    let metadata = EncUnitMetadata::with_mini_block_sizes(
+        encunit.num_rows(),
+        &sizes.iter().collect::<Vec<_>>(),
+    );
+    let first = row_id_in_encunit / mini_encunit_len;
+    let end = std::cmp::min(
+        (row_id_in_encunit + len).div_ceil(mini_encunit_len),
+        metadata.num_mini_blocks(),
+    );
+    let byte_range = metadata.mini_blocks_range(first..end)?;
+    Some(MiniEncUnitRange {
+        mini_encunits: first..end,
+        row_offset: first * mini_encunit_len,
+        byte_range: byte_range.start as u64..byte_range.end as u64,
+    })
+}
+
 /// The column data is not encoded in dictionary, but Plain.
 /// The scope of lifetime 'a is equal to the lifetime of metadata_owner in the reader.
 pub struct NoDictColDecoder<'a, R> {
@@ -33,6 +147,9 @@ pub struct NoDictColDecoder<'a, R> {
     /// The data type of the column.
     data_type: DataType,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
+    /// Location of the next EncUnit, None if it is not cross-checked with
+    /// [`DecoderPolicy::Verify`](super::policy::DecoderPolicy::Verify).
+    location: Option<EncUnitLocation>,
 }
 
 impl<'a, R: Reader> NoDictColDecoder<'a, R> {
@@ -41,14 +158,24 @@ impl<'a, R: Reader> NoDictColDecoder<'a, R> {
         encoded_chunk_buf: BytesMut,
         data_type: DataType,
         wasm_context: Option<Arc<WASMReadingContext<R>>>,
+        location: Option<EncUnitLocation>,
     ) -> Self {
         Self {
             encunit_iter,
             encoded_chunk_buf,
             data_type,
             wasm_context,
+            location,
         }
     }
+
+    fn next_location(&mut self) -> Option<EncUnitLocation> {
+        let location = self.location;
+        if let Some(next) = &mut self.location {
+            next.encunit += 1;
+        }
+        location
+    }
 }
 
 impl<R: Reader> ChunkDecoder for NoDictColDecoder<'_, R> {
@@ -58,20 +185,28 @@ impl<R: Reader> ChunkDecoder for NoDictColDecoder<'_, R> {
             None => return Ok(None),
         };
 
+        let location = self.next_location();
         let data = self
             .encoded_chunk_buf
-            .split_to(encblock_fb.size_() as usize);
-        let decoder = create_encunit_decoder(
-            encblock_fb
-                .encoding()
-                .ok_or_else(|| general_error!("Missing encoding in EncUnit metadata"))?,
-            encblock_fb.compression(),
-            data.freeze(),
-            encblock_fb.num_rows() as u64,
+            .split_to(encblock_fb.size_() as usize)
+            .freeze();
+        let decoder = create_encunit_decoder_from_fb(
+            encblock_fb,
+            data.clone(),
             self.data_type.clone(),
             self.wasm_context.as_ref().map(Arc::clone),
         )?;
-        decoder.decode().map(Some)
+        let array = decoder.decode()?;
+        verify_encunit(
+            self.wasm_context.as_deref(),
+            encblock_fb,
+            data,
+            self.data_type.clone(),
+            &array,
+            0,
+            location,
+        )?;
+        Ok(Some(array))
     }
 
     fn decode_row_at(&mut self, row_id_in_chunk: usize, len: usize) -> Result<Option<ArrayRef>> {
@@ -83,31 +218,39 @@ impl<R: Reader> ChunkDecoder for NoDictColDecoder<'_, R> {
                 Some(v) => v,
                 None => break,
             };
+            let location = self.next_location();
             let last_cur = cur;
             let enc_unit_num_rows = encblock_fb.num_rows() as usize;
             cur += enc_unit_num_rows;
-            if cur >= row_id_in_chunk {
-                let idx = row_id_in_chunk - last_cur;
+            if cur > row_id_in_chunk {
+                let idx = row_id_in_chunk.saturating_sub(last_cur);
                 let to_decode = std::cmp::min(remaining, enc_unit_num_rows - idx);
                 remaining -= to_decode;
                 let data = self
                     .encoded_chunk_buf
-                    .split_to(encblock_fb.size_() as usize);
-                let decoder = create_encunit_decoder(
-                    encblock_fb
-                        .encoding()
-                        .ok_or_else(|| general_error!("Missing encoding in EncUnit metadata"))?,
-                    encblock_fb.compression(),
-                    data.freeze(),
-                    enc_unit_num_rows as u64,
+                    .split_to(encblock_fb.size_() as usize)
+                    .freeze();
+                let decoder = create_encunit_decoder_from_fb(
+                    encblock_fb,
+                    data.clone(),
                     self.data_type.clone(),
                     self.wasm_context.as_ref().map(Arc::clone),
                 )?;
                 // Return the array with only one element at the given index.
+                // Fall back to a full decode if the EncUnit can not be sliced.
                 let array = match decoder.slice(idx, idx + to_decode) {
-                    Ok(res) => res,
-                    Err(_) => decoder.decode().map(|array| array.slice(idx, to_decode))?,
+                    Err(Error::NYI(_)) => decoder.decode()?.slice(idx, to_decode),
+                    res => res?,
                 };
+                verify_encunit(
+                    self.wasm_context.as_deref(),
+                    encblock_fb,
+                    data,
+                    self.data_type.clone(),
+                    &array,
+                    idx,
+                    location,
+                )?;
                 arrays.push(array);
             } else {
                 let _ = self
@@ -220,6 +363,9 @@ pub struct DictColDecoder<'a, R> {
     /// The data type of the column.
     data_type: DataType,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
+    /// Location of the next EncUnit, None if it is not cross-checked with
+    /// [`DecoderPolicy::Verify`](super::policy::DecoderPolicy::Verify).
+    location: Option<EncUnitLocation>,
 }
 
 impl<'a, R: Reader> DictColDecoder<'a, R> {
@@ -228,13 +374,23 @@ impl<'a, R: Reader> DictColDecoder<'a, R> {
         encoded_chunk_buf: BytesMut,
         data_type: DataType,
         wasm_context: Option<Arc<WASMReadingContext<R>>>,
+        location: Option<EncUnitLocation>,
     ) -> Self {
         Self {
             encunit_iter,
             encoded_chunk_buf,
             data_type,
             wasm_context,
+            location,
+        }
+    }
+
+    fn next_location(&mut self) -> Option<EncUnitLocation> {
+        let location = self.location;
+        if let Some(next) = &mut self.location {
+            next.encunit += 1;
         }
+        location
     }
 }
 
@@ -277,21 +433,34 @@ impl<R: Reader> ChunkDecoder for DictColDecoder<'_, R> {
             None => return Ok(None),
         };
 
-        let dict = self
+        let dict_location = self.next_location();
+        let dict_data = self
             .encoded_chunk_buf
-            .split_to(dict_encblock_fb.size_() as usize);
+            .split_to(dict_encblock_fb.size_() as usize)
+            .freeze();
+        let value_type = value_type(&self.data_type).clone();
         let dict_decoder = create_encunit_decoder(
             dict_encblock_fb
                 .encoding()
                 .ok_or_else(|| general_error!("Missing encoding in dict EncUnit metadata"))?,
             dict_encblock_fb.compression(),
-            dict.freeze(),
+            dict_data.clone(),
             dict_encblock_fb.num_rows() as u64,
-            self.data_type.clone(),
+            value_type.clone(),
             self.wasm_context.as_ref().map(Arc::clone),
         )?;
         let dict = if dict_encblock_fb.num_rows() > 0 {
-            dict_decoder.decode()?
+            let dict = dict_decoder.decode()?;
+            verify_encunit(
+                self.wasm_context.as_deref(),
+                dict_encblock_fb,
+                dict_data,
+                value_type,
+                &dict,
+                0,
+                dict_location,
+            )?;
+            dict
         } else {
             Arc::new(arrow_array::Int32Array::new_null(1))
         };
@@ -299,29 +468,39 @@ impl<R: Reader> ChunkDecoder for DictColDecoder<'_, R> {
             .encunit_iter
             .next()
             .ok_or_else(|| general_error!("Index EncUnit does not exist"))?;
+        let indices_location = self.next_location();
 
         let indices = self
             .encoded_chunk_buf
-            .split_to(index_encblock_fb.size_() as usize);
+            .split_to(index_encblock_fb.size_() as usize)
+            .freeze();
         let indices_decoder = create_encunit_decoder(
             index_encblock_fb
                 .encoding()
                 .ok_or_else(|| general_error!("Missing encoding in index EncUnit metadata"))?,
             index_encblock_fb.compression(),
-            indices.freeze(),
+            indices.clone(),
             index_encblock_fb.num_rows() as u64,
             DataType::Int64,
             self.wasm_context.as_ref().map(Arc::clone),
         )?;
         let indices_ref = indices_decoder.decode()?;
+        verify_encunit(
+            self.wasm_context.as_deref(),
+            index_encblock_fb,
+            indices,
+            DataType::Int64,
+            &indices_ref,
+            0,
+            indices_location,
+        )?;
+        if matches!(self.data_type, DataType::Dictionary(_, _)) {
+            return dictionary_array(&self.data_type, &dict, &indices_ref);
+        }
         let indices = indices_ref.as_any().downcast_ref::<UInt64Array>().ok_or(
             fff_core::errors::Error::General("Incorrect type of indices".to_owned()),
         )?;
         // Create an array of the same type as dict, then map
-        // TODO: use DictionaryArray with zero-copy
-        // DictionaryArray::<arrow::datatypes::Int64Type>::try_new(indices.clone(), dict)
-        //     .map_err(|err| fff_core::errors::Error::External(Box::new(err)))
-        //     .map(|arr| Some(Arc::new(arr) as ArrayRef))
         match *dict.data_type() {
             DataType::Int32 => {
                 dict_index_to_data!(arrow_array::Int32Array, dict, indices)
@@ -391,9 +570,12 @@ pub struct SharedDictColDecoder<'a, R> {
     /// The encoded chunk buffer is used to store the encoded chunk
     encoded_chunk_buf: BytesMut,
     /// The data type of the column.
-    _data_type: DataType,
+    data_type: DataType,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
     shared_dictionary: ArrayRef,
+    /// Location of the next EncUnit, None if it is not cross-checked with
+    /// [`DecoderPolicy::Verify`](super::policy::DecoderPolicy::Verify).
+    location: Option<EncUnitLocation>,
 }
 
 impl<'a, R: Reader> SharedDictColDecoder<'a, R> {
@@ -403,14 +585,24 @@ impl<'a, R: Reader> SharedDictColDecoder<'a, R> {
         data_type: DataType,
         wasm_context: Option<Arc<WASMReadingContext<R>>>,
         shared_dictionary: ArrayRef,
+        location: Option<EncUnitLocation>,
     ) -> Self {
         Self {
             encunit_iter,
             encoded_chunk_buf,
-            _data_type: data_type,
+            data_type,
             wasm_context,
             shared_dictionary,
+            location,
+        }
+    }
+
+    fn next_location(&mut self) -> Option<EncUnitLocation> {
+        let location = self.location;
+        if let Some(next) = &mut self.location {
+            next.encunit += 1;
         }
+        location
     }
 }
 
@@ -420,22 +612,36 @@ impl<R: Reader> ChunkDecoder for SharedDictColDecoder<'_, R> {
             Some(v) => v,
             None => return Ok(None),
         };
+        let location = self.next_location();
 
-        let indices = self
+        let data = self
             .encoded_chunk_buf
-            .split_to(index_encblock_fb.size_() as usize);
+            .split_to(index_encblock_fb.size_() as usize)
+            .freeze();
         let indices_decoder = create_encunit_decoder(
             index_encblock_fb
                 .encoding()
                 .ok_or_else(|| general_error!("Missing encoding in index EncUnit metadata"))?,
             index_encblock_fb.compression(),
-            indices.freeze(),
+            data.clone(),
             index_encblock_fb.num_rows() as u64,
             DataType::Int64,
             self.wasm_context.as_ref().map(Arc::clone),
         )?;
         let indices = indices_decoder.decode()?;
+        verify_encunit(
+            self.wasm_context.as_deref(),
+            index_encblock_fb,
+            data,
+            DataType::Int64,
+            &indices,
+            0,
+            location,
+        )?;
         let dict = &self.shared_dictionary;
+        if matches!(self.data_type, DataType::Dictionary(_, _)) {
+            return dictionary_array(&self.data_type, dict, &indices);
+        }
         // Create an array of the same type as dict, then map
         match dict.data_type() {
             DataType::Int32 => {
@@ -498,6 +704,51 @@ impl<R: Reader> ChunkDecoder for SharedDictColDecoder<'_, R> {
     }
 }
 
+/// The type of the values of a Dictionary column, or of other columns.
+fn value_type(data_type: &DataType) -> &DataType {
+    match data_type {
+        DataType::Dictionary(_, value_type) => value_type,
+        other => other,
+    }
+}
+
+/// A DictionaryArray of `data_type` whose keys are the decoded `indices` into `dict`,
+/// sharing the buffers of `dict` instead of copying its values for every row.
+fn dictionary_array(
+    data_type: &DataType,
+    dict: &ArrayRef,
+    indices: &ArrayRef,
+) -> Result<Option<ArrayRef>> {
+    let DataType::Dictionary(key_type, value_type) = data_type else {
+        return Err(general_error!(format!(
+            "Expected a dictionary type, got {data_type}"
+        )));
+    };
+    // Fail on indices that do not fit in the key type instead of nulling them.
+    let keys = cast_with_options(
+        indices,
+        key_type,
+        &CastOptions {
+            safe: false,
+            ..Default::default()
+        },
+    )?;
+    // Empty dictionaries are decoded as a null Int32 array.
+    let values = if dict.data_type() == value_type.as_ref() {
+        dict.clone()
+    } else {
+        cast(dict, value_type)?
+    };
+    let data = keys
+        .to_data()
+        .into_builder()
+        .data_type(data_type.clone())
+        .child_data(vec![values.to_data()])
+        .build()?;
+    Ok(Some(make_array(data)))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_physical_decoder<'a, R: Reader + 'a>(
     encunit_iter: VectorIter<'a, ForwardsUOffset<fb::EncUnit<'a>>>,
     dict_encoding_type: fb::DictionaryEncoding,
@@ -505,7 +756,8 @@ pub fn create_physical_decoder<'a, R: Reader + 'a>(
     data_type: &DataType,
     encoded_chunk_buf: BytesMut,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
-    shared_dictionary_cache: Option<&'a SharedDictionaryCache>,
+    shared_dictionary_cache: Option<&'a SharedDictionaryCache<R>>,
+    location: Option<EncUnitLocation>,
 ) -> Result<Box<dyn ChunkDecoder + 'a>> {
     if dict_encoding_type == fb::DictionaryEncoding::NoDictionary {
         match *data_type {
@@ -515,6 +767,7 @@ pub fn create_physical_decoder<'a, R: Reader + 'a>(
                     encoded_chunk_buf,
                     data_type.clone(),
                     wasm_context,
+                    location,
                 )))
             }
             _ => Err(general_error!(format!(
@@ -524,14 +777,16 @@ pub fn create_physical_decoder<'a, R: Reader + 'a>(
         }
     } else if dict_encoding_type == fb::DictionaryEncoding::LocalDictionary {
         match *data_type {
-            non_nest_types!() | DataType::List(_) | DataType::LargeList(_) => {
-                Ok(Box::new(DictColDecoder::new(
-                    encunit_iter,
-                    encoded_chunk_buf,
-                    data_type.clone(),
-                    wasm_context,
-                )))
-            }
+            non_nest_types!()
+            | DataType::List(_)
+            | DataType::LargeList(_)
+            | DataType::Dictionary(_, _) => Ok(Box::new(DictColDecoder::new(
+                encunit_iter,
+                encoded_chunk_buf,
+                data_type.clone(),
+                wasm_context,
+                location,
+            ))),
             _ => Err(general_error!(format!(
                 "Unsupported data type for LocalDictionary encoding: {:?}",
                 data_type
@@ -539,26 +794,27 @@ pub fn create_physical_decoder<'a, R: Reader + 'a>(
         }
     } else if dict_encoding_type == fb::DictionaryEncoding::SharedDictionary {
         match *data_type {
-            non_nest_types!() | DataType::List(_) | DataType::LargeList(_) => {
-                Ok(Box::new(SharedDictColDecoder::new(
-                    encunit_iter,
-                    encoded_chunk_buf,
-                    data_type.clone(),
-                    wasm_context,
-                    shared_dictionary_cache
-                        .ok_or_else(|| {
-                            general_error!(
-                                "Shared dictionary cache not found for a shared dictionary column"
-                            )
-                        })?
-                        .get_dict(
-                            shared_dictionary_id
-                                .ok_or_else(|| general_error!("Shared dictionary ID not found"))?
-                                .shared_dictionary_idx() as usize,
+            non_nest_types!()
+            | DataType::List(_)
+            | DataType::LargeList(_)
+            | DataType::Dictionary(_, _) => Ok(Box::new(SharedDictColDecoder::new(
+                encunit_iter,
+                encoded_chunk_buf,
+                data_type.clone(),
+                wasm_context,
+                shared_dictionary_cache
+                    .ok_or_else(|| {
+                        general_error!(
+                            "Shared dictionary cache not found for a shared dictionary column"
                         )
-                        .ok_or_else(|| general_error!("Shared dictionary not found in cache"))?,
-                )))
-            }
+                    })?
+                    .get_dict(
+                        shared_dictionary_id
+                            .ok_or_else(|| general_error!("Shared dictionary ID not found"))?
+                            .shared_dictionary_idx() as usize,
+                    )?,
+                location,
+            ))),
             _ => Err(general_error!(format!(
                 "Unsupported data type for SharedDictionary encoding: {:?}",
                 data_type
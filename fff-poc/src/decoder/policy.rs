@@ -0,0 +1,212 @@
+//! How EncUnits that have both a native decoder and a Wasm decoder in the file are decoded,
+//! and the cross-checking of the two with [`DecoderPolicy::Verify`].
+
+use arrow::compute::cast;
+use arrow_array::ArrayRef;
+use arrow_schema::DataType;
+use bytes::Bytes;
+use fff_core::errors::Result;
+use fff_format::File::fff::flatbuf as fb;
+
+use crate::{context::WASMReadingContext, io::reader::Reader};
+
+use super::encunit::create_wasm_encunit_decoder_from_fb;
+
+/// Which decoder the reader runs for EncUnits of built-in and plugin encodings that also carry
+/// a Wasm decoder. EncUnits without a native decoder are always decoded with Wasm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecoderPolicy {
+    /// Decode natively, unless the native decoder is missing or incompatible with the file.
+    #[default]
+    PreferNative,
+    /// Decode with the Wasm decoder in the file, and fail on EncUnits without one.
+    ForceWasm,
+    /// Decode natively, decode again with the Wasm decoder if the file has one, and record where
+    /// they disagree, see [`FileReaderV2::decoder_verification`](crate::reader::FileReaderV2::decoder_verification).
+    /// EncUnits split into mini EncUnits and EncUnits of shared dictionaries are not cross-checked.
+    Verify,
+}
+
+/// Where an EncUnit is in the file.
+/// Columns are physical columns, in the order of the ColumnMetadata of each row group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct EncUnitLocation {
+    pub row_group: usize,
+    pub column: usize,
+    pub chunk: usize,
+    pub encunit: usize,
+}
+
+impl EncUnitLocation {
+    /// The first EncUnit of the first chunk of a column.
+    pub(crate) fn column(row_group: usize, column: u32) -> Self {
+        Self {
+            row_group,
+            column: column as usize,
+            chunk: 0,
+            encunit: 0,
+        }
+    }
+}
+
+/// An EncUnit whose native and Wasm decoders disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecoderMismatch {
+    pub location: EncUnitLocation,
+    /// The first row of the EncUnit where the outputs differ, None if the Wasm decoder failed.
+    pub row: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for DecoderMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let EncUnitLocation {
+            row_group,
+            column,
+            chunk,
+            encunit,
+        } = self.location;
+        write!(
+            f,
+            "row group {row_group}, column {column}, chunk {chunk}, EncUnit {encunit}"
+        )?;
+        if let Some(row) = self.row {
+            write!(f, ", row {row}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// What [`DecoderPolicy::Verify`] found in the EncUnits decoded so far.
+#[derive(Debug, Clone, Default)]
+pub struct DecoderVerification {
+    /// EncUnits decoded both natively and with Wasm.
+    pub num_verified: usize,
+    pub mismatches: Vec<DecoderMismatch>,
+}
+
+impl DecoderVerification {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// With [`DecoderPolicy::Verify`], decode `encunit` again with its Wasm decoder and record where it
+/// disagrees with `native`, the natively decoded rows of the EncUnit from `row_offset` on.
+/// EncUnits without a location, i.e., of shared dictionaries, are not cross-checked.
+pub(crate) fn verify_encunit<R: Reader>(
+    wasm_context: Option<&WASMReadingContext<R>>,
+    encunit: fb::EncUnit,
+    data: Bytes,
+    output_type: DataType,
+    native: &ArrayRef,
+    row_offset: usize,
+    location: Option<EncUnitLocation>,
+) -> Result<()> {
+    let (Some(wasm_context), Some(location)) = (
+        wasm_context.filter(|ctx| ctx.decoder_policy() == DecoderPolicy::Verify),
+        location,
+    ) else {
+        return Ok(());
+    };
+    // Only these encodings have a native decoder the Wasm one stands in for.
+    if !encunit.encoding().is_some_and(|encoding| {
+        matches!(
+            encoding.type_(),
+            fb::EncodingType::CASCADE | fb::EncodingType::PLUGIN
+        )
+    }) {
+        return Ok(());
+    }
+    let wasm = create_wasm_encunit_decoder_from_fb(encunit, data, output_type, wasm_context)
+        .and_then(|decoder| decoder.map(|decoder| decoder.decode()).transpose());
+    let mismatch =
+        match wasm {
+            Ok(None) => return Ok(()),
+            Ok(Some(wasm)) if wasm.len() < row_offset + native.len() => Some(DecoderMismatch {
+                location,
+                row: Some(wasm.len()),
+                message: format!(
+                    "Wasm decoder output {} rows, the native one {}",
+                    wasm.len(),
+                    row_offset + native.len()
+                ),
+            }),
+            Ok(Some(wasm)) => first_difference(native, &wasm.slice(row_offset, native.len())).map(
+                |(row, message)| DecoderMismatch {
+                    location,
+                    row: Some(row_offset + row),
+                    message,
+                },
+            ),
+            Err(e) => Some(DecoderMismatch {
+                location,
+                row: None,
+                message: format!("Wasm decoding failed: {e}"),
+            }),
+        };
+    wasm_context.record_verification(mismatch)
+}
+
+/// The first row where `wasm`, cast to the type of `native`, differs from `native`, and how.
+/// Decoders may output the same values with different types, but output that can not be cast
+/// differs from the first row on.
+pub(crate) fn first_difference(native: &ArrayRef, wasm: &ArrayRef) -> Option<(usize, String)> {
+    let wasm = if native.data_type() == wasm.data_type() {
+        wasm.clone()
+    } else {
+        match cast(wasm, native.data_type()) {
+            Ok(wasm) => wasm,
+            Err(_) => {
+                return Some((
+                    0,
+                    format!(
+                        "Wasm decoder output {}, which can not be cast to {} of the native one",
+                        wasm.data_type(),
+                        native.data_type()
+                    ),
+                ))
+            }
+        }
+    };
+    if native.as_ref() == wasm.as_ref() {
+        return None;
+    }
+    let shorter = native.len().min(wasm.len());
+    let row = (0..shorter)
+        .find(|&i| native.slice(i, 1).as_ref() != wasm.slice(i, 1).as_ref())
+        .unwrap_or(shorter);
+    Some((row, "Native and Wasm decoders disagree".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{types::Int32Type, Int32Array, Int64Array, ListArray};
+
+    use super::*;
+
+    #[test]
+    fn test_first_difference() {
+        let native: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3]));
+        let same_values: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 3]));
+        assert_eq!(first_difference(&native, &same_values), None);
+
+        let different: ArrayRef = Arc::new(Int64Array::from(vec![1, 5, 3]));
+        assert_eq!(
+            first_difference(&native, &different).map(|(row, _)| row),
+            Some(1)
+        );
+
+        let list: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(
+            (1..=3).map(|i| Some(vec![Some(i)])),
+        ));
+        let (row, message) = first_difference(&native, &list).unwrap();
+        assert_eq!(row, 0);
+        assert!(
+            message.contains("Int32") && message.contains("List"),
+            "{message}"
+        );
+    }
+}
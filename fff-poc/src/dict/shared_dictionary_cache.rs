@@ -1,30 +1,66 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use arrow::compute::concat;
-use arrow_array::ArrayRef;
+use arrow_array::{Array, ArrayRef};
 use arrow_ipc::{convert::fb_to_schema, root_as_message};
-use bytes::BytesMut;
-use fff_core::{errors::Error, nyi_err};
-use fff_format::File::fff::flatbuf as fb;
+use arrow_schema::Schema;
+use bytes::{Bytes, BytesMut};
+use fff_core::{errors::Error, general_error, nyi_err};
+use fff_format::File::fff::flatbuf::{self as fb, root_as_footer};
 
 use crate::{
     context::WASMReadingContext, decoder::physical::create_physical_decoder, io::reader::Reader,
 };
 
-pub struct SharedDictionaryCache {
-    // shared_dictionary_table: fb::SharedDictionaryTable<'a>,
-    dictionaries: Vec<Option<ArrayRef>>,
+/// The shared dictionaries of a file. A dictionary is read and decoded the first time a chunk
+/// references it, and is then shared by the chunks of all row groups.
+pub struct SharedDictionaryCache<R> {
+    reader: Option<R>,
+    /// The Footer flatbuffer, which holds the SharedDictionaryTable.
+    footer: Bytes,
+    wasm_context: Option<Arc<WASMReadingContext<R>>>,
+    dict_schema: Schema,
     dictionary_compressed_sizes: Vec<usize>,
     dictionary_chunk_sizes: Vec<usize>,
     dictionary_chunk_references: Vec<Vec<usize>>,
+    /// Evict the least recently used dictionaries once the decoded ones take more memory.
+    max_memory_size: Option<usize>,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    dictionaries: Vec<Option<ArrayRef>>,
+    last_access: Vec<u64>,
+    clock: u64,
+    memory_size: usize,
 }
 
-impl SharedDictionaryCache {
-    pub fn try_new_read_all<R: Reader>(
+impl<R> Default for SharedDictionaryCache<R> {
+    fn default() -> Self {
+        Self {
+            reader: None,
+            footer: Bytes::new(),
+            wasm_context: None,
+            dict_schema: Schema::empty(),
+            dictionary_compressed_sizes: vec![],
+            dictionary_chunk_sizes: vec![],
+            dictionary_chunk_references: vec![],
+            max_memory_size: None,
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl<R: Reader> SharedDictionaryCache<R> {
+    /// Read the layout of the shared dictionaries from `footer`, without decoding any of them.
+    pub fn try_new(
         reader: R,
-        shared_dictionary_table: fb::SharedDictionaryTable,
+        footer: Bytes,
         wasm_context: Option<Arc<WASMReadingContext<R>>>,
+        max_memory_size: Option<usize>,
     ) -> Result<Self, Error> {
+        let shared_dictionary_table = shared_dictionary_table(&footer)?;
         let positions = shared_dictionary_table
             .dictionary_positions()
             .ok_or_else(|| Error::ParseError("Dictionary positions not found".to_string()))?
@@ -42,6 +78,22 @@ impl SharedDictionaryCache {
             .iter()
             .map(|chunk_meta| chunk_meta.size_() as usize)
             .collect::<Vec<_>>();
+        let dictionary_compressed_sizes = positions
+            .iter()
+            .map(|chunk_ids| {
+                chunk_ids
+                    .iter()
+                    .map(|&chunk_id| {
+                        dictionary_chunk_sizes
+                            .get(chunk_id)
+                            .copied()
+                            .ok_or_else(|| {
+                                Error::IndexOutOfBound(chunk_id, dictionary_chunk_sizes.len())
+                            })
+                    })
+                    .sum::<Result<usize, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         let dict_schema = shared_dictionary_table
             .dictionary_schema()
             .ok_or_else(|| Error::ParseError("Shared dictionary schema not found".to_string()))?;
@@ -51,74 +103,84 @@ impl SharedDictionaryCache {
             .header_as_schema()
             .ok_or_else(|| Error::ParseError("Unable to read IPC message as schema".to_string()))?;
         let dict_schema = fb_to_schema(ipc_schema);
-        let mut dict_sizes = vec![]; // TODO: do not use mutable vec to modify
-        let dictionaries = positions
-            .iter()
-            .enumerate()
-            .map(|(i, chunk_ids)| -> Result<Option<ArrayRef>, Error> {
-                let datatype = dict_schema.field(i).data_type();
-                let mut dict_size = 0;
-                let dict_arrs = chunk_ids
-                    .iter()
-                    .map(|chunk_id| {
-                        let chunk_meta = chunks.get(*chunk_id);
-                        dict_size += chunk_meta.size_() as usize;
-                        let mut encoded_chunk_buf = BytesMut::zeroed(chunk_meta.size_() as usize);
-                        reader.read_exact_at(&mut encoded_chunk_buf, chunk_meta.offset())?;
-                        let mut decoder = create_physical_decoder::<R>(
-                            chunk_meta
-                                .encunits()
-                                .ok_or_else(|| {
-                                    Error::General("No chunks in column meta".to_string())
-                                })?
-                                .iter(),
-                            chunk_meta.encoding_type(),
-                            None,
-                            datatype,
-                            encoded_chunk_buf,
-                            wasm_context
-                                .as_ref()
-                                .map(Arc::clone),
-                            None,
-                        )?;
-                        let mut arrays = vec![];
-                        if chunk_meta.num_rows() == 0 {
-                            arrays.push(Arc::new(arrow_array::Int32Array::new_null(1)) as ArrayRef);
-                        } else {
-                            while let Some(array) = decoder.decode_batch()? {
-                                arrays.push(array);
-                            }
-                        }
-                        if arrays.len() != 1 {
-                            nyi_err!(
-                            "Now we only handle the case where each dictionary chunk has a single EncUnit"
-                        )
-                        } else {
-                            Ok(arrays[0].clone())
-                        }
-                    })
-                    .collect::<Result<Vec<_>, Error>>()?;
-                dict_sizes.push(dict_size);
-                if dict_arrs.len() == 1 {
-                    Ok(Some(dict_arrs[0].clone()))
-                } else if dict_arrs.len() == 2 {
-                    assert_eq!(dict_arrs[0].data_type(), dict_arrs[1].data_type());
-                    Ok(Some(concat(&[&dict_arrs[0], &dict_arrs[1]])?))
-                } else {
-                    Err(Error::General("Now we only handle the case where each dictionary has <=2 chunks".to_owned()))
-                }
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        let num_dictionaries = positions.len();
         Ok(Self {
-            dictionaries,
-            dictionary_compressed_sizes: dict_sizes,
+            reader: Some(reader),
+            footer,
+            wasm_context,
+            dict_schema,
+            dictionary_compressed_sizes,
             dictionary_chunk_sizes,
             dictionary_chunk_references: positions,
+            max_memory_size,
+            state: Mutex::new(CacheState {
+                dictionaries: vec![None; num_dictionaries],
+                last_access: vec![0; num_dictionaries],
+                ..Default::default()
+            }),
         })
     }
 
-    pub fn get_dict(&self, index: usize) -> Option<ArrayRef> {
-        self.dictionaries.get(index).cloned().flatten()
+    /// Read and decode all the dictionaries, e.g., to check that they are not corrupted.
+    pub fn read_all(&self) -> Result<(), Error> {
+        for index in 0..self.dictionary_chunk_references.len() {
+            self.get_dict(index)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_dict(&self, index: usize) -> Result<ArrayRef, Error> {
+        {
+            let mut state = self.lock_state()?;
+            if index >= state.dictionaries.len() {
+                return Err(general_error!(format!(
+                    "Shared dictionary {index} not found, the file has {}",
+                    state.dictionaries.len()
+                )));
+            }
+            state.clock += 1;
+            state.last_access[index] = state.clock;
+            if let Some(dict) = &state.dictionaries[index] {
+                return Ok(dict.clone());
+            }
+        }
+        // Read without holding the lock, so that cached dictionaries are served meanwhile.
+        let dict = self.read_dict(index)?;
+        let mut state = self.lock_state()?;
+        if let Some(dict) = &state.dictionaries[index] {
+            // Another reader loaded it concurrently, keep a single copy.
+            return Ok(dict.clone());
+        }
+        state.memory_size += dict.get_array_memory_size();
+        state.dictionaries[index] = Some(dict.clone());
+        if let Some(max_memory_size) = self.max_memory_size {
+            while state.memory_size > max_memory_size {
+                let Some(lru) = (0..state.dictionaries.len())
+                    .filter(|&i| i != index && state.dictionaries[i].is_some())
+                    .min_by_key(|&i| state.last_access[i])
+                else {
+                    break;
+                };
+                if let Some(evicted) = state.dictionaries[lru].take() {
+                    state.memory_size -= evicted.get_array_memory_size();
+                }
+            }
+        }
+        Ok(dict)
+    }
+
+    /// The memory taken by the decoded dictionaries in the cache.
+    pub fn memory_size(&self) -> usize {
+        self.lock_state().map_or(0, |state| state.memory_size)
+    }
+
+    /// The indexes of the dictionaries that are decoded and in the cache.
+    pub fn loaded_dictionaries(&self) -> Vec<usize> {
+        self.lock_state().map_or(vec![], |state| {
+            (0..state.dictionaries.len())
+                .filter(|&i| state.dictionaries[i].is_some())
+                .collect()
+        })
     }
 
     pub fn get_dict_size(&self, index: usize) -> Option<usize> {
@@ -132,4 +194,79 @@ impl SharedDictionaryCache {
     pub fn get_dict_references(&self) -> &Vec<Vec<usize>> {
         &self.dictionary_chunk_references
     }
+
+    fn lock_state(&self) -> Result<MutexGuard<'_, CacheState>, Error> {
+        self.state
+            .lock()
+            .map_err(|_| general_error!("Shared dictionary cache lock poisoned"))
+    }
+
+    fn read_dict(&self, index: usize) -> Result<ArrayRef, Error> {
+        let reader = self
+            .reader
+            .as_ref()
+            .ok_or_else(|| general_error!("Shared dictionary cache has no reader"))?;
+        let chunks = shared_dictionary_table(&self.footer)?
+            .dictionary_chunks()
+            .ok_or_else(|| Error::ParseError("Dictionary chunks not found".to_string()))?;
+        let datatype = self
+            .dict_schema
+            .fields()
+            .get(index)
+            .ok_or_else(|| Error::IndexOutOfBound(index, self.dict_schema.fields().len()))?
+            .data_type();
+        let dict_arrs = self.dictionary_chunk_references[index]
+            .iter()
+            .map(|chunk_id| {
+                let chunk_meta = chunks.get(*chunk_id);
+                let mut encoded_chunk_buf = BytesMut::zeroed(chunk_meta.size_() as usize);
+                reader.read_exact_at(&mut encoded_chunk_buf, chunk_meta.offset())?;
+                let mut decoder = create_physical_decoder::<R>(
+                    chunk_meta
+                        .encunits()
+                        .ok_or_else(|| Error::General("No chunks in column meta".to_string()))?
+                        .iter(),
+                    chunk_meta.encoding_type(),
+                    None,
+                    datatype,
+                    encoded_chunk_buf,
+                    self.wasm_context.as_ref().map(Arc::clone),
+                    None,
+                    None,
+                )?;
+                let mut arrays = vec![];
+                if chunk_meta.num_rows() == 0 {
+                    arrays.push(Arc::new(arrow_array::Int32Array::new_null(1)) as ArrayRef);
+                } else {
+                    while let Some(array) = decoder.decode_batch()? {
+                        arrays.push(array);
+                    }
+                }
+                if arrays.len() != 1 {
+                    nyi_err!(
+                        "Now we only handle the case where each dictionary chunk has a single EncUnit"
+                    )
+                } else {
+                    Ok(arrays[0].clone())
+                }
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        if dict_arrs.len() == 1 {
+            Ok(dict_arrs[0].clone())
+        } else if dict_arrs.len() == 2 {
+            assert_eq!(dict_arrs[0].data_type(), dict_arrs[1].data_type());
+            Ok(concat(&[&dict_arrs[0], &dict_arrs[1]])?)
+        } else {
+            Err(Error::General(
+                "Now we only handle the case where each dictionary has <=2 chunks".to_owned(),
+            ))
+        }
+    }
+}
+
+fn shared_dictionary_table(footer: &[u8]) -> Result<fb::SharedDictionaryTable<'_>, Error> {
+    root_as_footer(footer)
+        .map_err(|e| Error::ParseError(format!("Unable to get root as footer: {e:?}")))?
+        .shared_dictionary_table()
+        .ok_or_else(|| Error::ParseError("Shared dictionary table not found".to_string()))
 }
@@ -13,7 +13,7 @@ use crate::{
         encoded_column_chunk::{EncodedColumnChunk, SerializedEncUnit},
         encunit::create_encunit_encoder,
     },
-    file::footer::{self, WASMEncoding},
+    file::footer,
     options::{DEFAULT_ENCODING_UNIT_LEN, DEFAULT_IOUNIT_SIZE},
 };
 
@@ -219,28 +219,15 @@ impl SharedDictionaryContext {
                         wasm_context.clone(),
                         dict.data_type().clone(),
                         false,
+                        &[],
                     )?;
                     let write_slice = |slice, slice_len| -> Result<SerializedEncUnit, Error> {
                         let encoded_bytes = encode_to_bytes(dict_encoder.clone(), slice);
                         Ok(SerializedEncUnit::new(
                             encoded_bytes,
                             slice_len as u32,
-                            {
-                                let encoding_type = dict_encoder.encoding_type();
-                                footer::Encoding::try_new(
-                                    if wasm_context.always_set_custom_wasm_for_built_in() {
-                                        fb::EncodingType::CUSTOM_WASM
-                                    } else {
-                                        encoding_type.to_fbs_encoding()
-                                    },
-                                    if wasm_context.always_set_custom_wasm_for_built_in() {
-                                        wasm_context.builtin_wasm_id()
-                                    } else {
-                                        wasm_context.data_type_to_wasm_id(&dict_dtype)
-                                    }
-                                    .map(|id| WASMEncoding::new(id.0, Vec::new())),
-                                )?
-                            },
+                            wasm_context
+                                .encunit_encoding(dict_encoder.encoding_type(), &dict_dtype)?,
                             self.compression_type,
                         ))
                     };
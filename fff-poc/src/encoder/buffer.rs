@@ -0,0 +1,102 @@
+use arrow::compute::concat;
+use arrow_array::{Array, ArrayRef};
+use fff_core::errors::Result;
+
+/// Accumulates the rows of a root-level column until they fill an EncUnit,
+/// so that EncUnits do not depend on how callers chunk their `RecordBatch`es.
+pub(crate) struct EncUnitBuffer {
+    /// Number of rows of an EncUnit.
+    encunit_len: usize,
+    /// Target size of an EncUnit in bytes, estimated from the in-memory size of the rows.
+    encunit_size: Option<u64>,
+    arrays: Vec<ArrayRef>,
+    len: usize,
+    memory_size: usize,
+}
+
+impl EncUnitBuffer {
+    pub(crate) fn new(encunit_len: usize, encunit_size: Option<u64>) -> Self {
+        Self {
+            encunit_len: encunit_len.max(1),
+            encunit_size,
+            arrays: vec![],
+            len: 0,
+            memory_size: 0,
+        }
+    }
+
+    /// Number of rows of the next EncUnit, at most `encunit_len`.
+    /// The byte size target uses the average row size of `array`.
+    fn next_encunit_len(&self, array: &dyn Array) -> Result<usize> {
+        let Some(encunit_size) = self.encunit_size else {
+            return Ok(self.encunit_len);
+        };
+        let array_size = array.to_data().get_slice_memory_size()? as u64;
+        if array.is_empty() || array_size == 0 {
+            return Ok(self.encunit_len);
+        }
+        let rows = encunit_size * array.len() as u64 / array_size;
+        Ok((rows as usize).clamp(1, self.encunit_len))
+    }
+
+    /// Number of leading rows of `array` that complete the next EncUnit, at most `array.len()`.
+    pub(crate) fn rows_to_next_encunit(&self, array: &dyn Array) -> Result<usize> {
+        let encunit_len = self.next_encunit_len(array)?;
+        Ok(encunit_len
+            .saturating_sub(self.len)
+            .clamp(1, array.len().max(1)))
+    }
+
+    /// Buffer `array` and return the arrays of the EncUnits it completes.
+    pub(crate) fn push(&mut self, array: ArrayRef) -> Result<Vec<ArrayRef>> {
+        let encunit_len = self.next_encunit_len(array.as_ref())?;
+        let mut res = vec![];
+        let mut offset = 0;
+        if self.len > 0 {
+            // Complete the buffered EncUnit first.
+            let len = encunit_len.saturating_sub(self.len).min(array.len());
+            self.buffer(array.slice(0, len))?;
+            offset = len;
+            if self.len < encunit_len {
+                return Ok(res);
+            }
+            res.extend(self.take()?);
+        }
+        // Full EncUnits are sliced out of `array` without copying.
+        while array.len() - offset >= encunit_len {
+            res.push(array.slice(offset, encunit_len));
+            offset += encunit_len;
+        }
+        if offset < array.len() {
+            self.buffer(array.slice(offset, array.len() - offset))?;
+        }
+        Ok(res)
+    }
+
+    fn buffer(&mut self, array: ArrayRef) -> Result<()> {
+        self.len += array.len();
+        self.memory_size += array.to_data().get_slice_memory_size()?;
+        self.arrays.push(array);
+        Ok(())
+    }
+
+    /// Take the buffered rows as a single array, e.g., at the end of a row group.
+    pub(crate) fn take(&mut self) -> Result<Option<ArrayRef>> {
+        let arrays = std::mem::take(&mut self.arrays);
+        self.len = 0;
+        self.memory_size = 0;
+        match arrays.len() {
+            0 => Ok(None),
+            1 => Ok(arrays.into_iter().next()),
+            _ => {
+                let arrays = arrays.iter().map(|a| a.as_ref()).collect::<Vec<_>>();
+                Ok(Some(concat(&arrays)?))
+            }
+        }
+    }
+
+    /// In-memory size of the buffered rows.
+    pub(crate) fn memory_size(&self) -> usize {
+        self.memory_size
+    }
+}
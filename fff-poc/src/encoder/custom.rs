@@ -4,7 +4,11 @@ use std::result::Result;
 use std::sync::Arc;
 
 use arrow::ffi::{to_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{Field, Schema};
 use bytes::Bytes;
+use fff_ude_wasm::Runtime;
 use libloading::Library;
 use uniffi_core::RustBuffer;
 
@@ -12,6 +16,9 @@ use fff_encoding::enc_unit::EncUnit;
 
 use fff_encoding::schemes::Encoder;
 
+/// Function exported by sandboxed encoders, see `fff_ude::ffi::encode_wrapper` for its ABI.
+pub(crate) const WASM_ENCODE_FUNC: &str = "encode_ffi";
+
 type EncodeFunc =
     unsafe extern "C" fn(input: FFI_ArrowArray, schema: FFI_ArrowSchema) -> RustBuffer;
 
@@ -60,6 +67,44 @@ impl Encoder for CustomEncoder {
     }
 }
 
+/// Custom encoder running in the Wasm sandbox.
+/// The array is passed in as a single-column Arrow IPC stream, and the encoded bytes are copied out.
+pub struct WasmEncoder {
+    runtime: Arc<Runtime>,
+}
+
+impl WasmEncoder {
+    pub fn new(runtime: Arc<Runtime>) -> Self {
+        Self { runtime }
+    }
+}
+
+impl Encoder for WasmEncoder {
+    fn encode(&self, arr: arrow_array::ArrayRef) -> fff_core::errors::Result<EncUnit> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "",
+            arr.data_type().clone(),
+            true,
+        )]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![arr])?;
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema)?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        let encoded = self
+            .runtime
+            .call_encode(WASM_ENCODE_FUNC, &writer.into_inner()?)?;
+        Ok(EncUnit::new(
+            vec![Bytes::from(encoded)],
+            fff_encoding::enc_unit::Encoding::Custom,
+            vec![],
+        ))
+    }
+
+    fn encoding_type(&self) -> fff_encoding::enc_unit::Encoding {
+        fff_encoding::enc_unit::Encoding::Custom
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use arrow_array::ArrayRef;
@@ -10,6 +10,9 @@ pub struct SerializedEncUnit {
     num_rows: u32,
     encoding: footer::Encoding,
     compression_type: CompressionType,
+    /// Number of rows in each mini EncUnit, 0 if not split.
+    mini_encunit_len: u32,
+    mini_encunit_sizes: Vec<u32>,
 }
 
 impl SerializedEncUnit {
@@ -24,9 +27,21 @@ impl SerializedEncUnit {
             num_rows,
             encoding,
             compression_type,
+            mini_encunit_len: 0,
+            mini_encunit_sizes: vec![],
         }
     }
 
+    pub fn with_mini_encunits(
+        mut self,
+        mini_encunit_len: u32,
+        mini_encunit_sizes: Vec<u32>,
+    ) -> Self {
+        self.mini_encunit_len = mini_encunit_len;
+        self.mini_encunit_sizes = mini_encunit_sizes;
+        self
+    }
+
     pub fn bytes(&self) -> Bytes {
         self.bytes.clone()
     }
@@ -42,6 +57,14 @@ impl SerializedEncUnit {
     pub fn compression_type(&self) -> CompressionType {
         self.compression_type
     }
+
+    pub fn mini_encunit_len(&self) -> u32 {
+        self.mini_encunit_len
+    }
+
+    pub fn mini_encunit_sizes(&self) -> &[u32] {
+        &self.mini_encunit_sizes
+    }
 }
 
 /// An encoded ColumnChunk, serves as an IO unit.
@@ -1,29 +1,49 @@
 use std::{rc::Rc, sync::Arc};
 
+use arrow_array::ArrayRef;
 use arrow_schema::DataType;
-use fff_encoding::schemes::{vortex::VortexEncoder, Encoder};
+use bytes::{Bytes, BytesMut};
+use fff_encoding::schemes::{encode_mini_blocks, encode_to_bytes, vortex::VortexEncoder, Encoder};
+use fff_format::File::fff::flatbuf as fb;
 
-use crate::context::WASMWritingContext;
+use crate::{
+    compression::{compress_blocks, compress_data, BlockCompression},
+    context::{EncoderLib, WASMWritingContext},
+};
 
-use super::custom::CustomEncoder;
+use super::custom::{CustomEncoder, WasmEncoder};
 
 /// Strategy to map physical DataType to EncUnit Encoder.
 /// List is using our custom ones since Vortex does not support it.
 /// List appears here because we encode offsets as a List of dummy values.
+/// Encoding plugins selected for the column take precedence over custom Wasm encodings.
+/// Vortex compressors with ids in `excluded_compressors` are not tried.
 pub fn create_encunit_encoder(
     wasm_context: Arc<WASMWritingContext>,
     data_type: DataType,
     enable_dict: bool,
+    excluded_compressors: &[String],
 ) -> fff_core::errors::Result<Rc<dyn Encoder>> {
-    if let Some(lib) = wasm_context.data_type_to_wasm_lib(&data_type) {
-        // FIXME: function name is fixed as "encode"
+    if let Some(plugin) = wasm_context.data_type_to_plugin(&data_type) {
+        plugin.create_encoder(&data_type)
+    } else if let Some(lib) = wasm_context.data_type_to_wasm_lib(&data_type) {
+        match lib.encoder() {
+            // FIXME: function name is fixed as "encode"
+            EncoderLib::Native(path) => Ok(Rc::new(
+                CustomEncoder::try_new(path.clone(), "encode").map_err(|e| {
+                    fff_core::errors::Error::General(format!(
+                        "Failed to create custom encoder: {}",
+                        e
+                    ))
+                })?,
+            )),
+            EncoderLib::Wasm(runtime) => Ok(Rc::new(WasmEncoder::new(runtime.clone()))),
+        }
+    } else {
         Ok(Rc::new(
-            CustomEncoder::try_new(lib.encode_lib_path(), "encode").map_err(|e| {
-                fff_core::errors::Error::General(format!("Failed to create custom encoder: {}", e))
-            })?,
+            VortexEncoder::new(enable_dict)
+                .with_excluded_compressors(excluded_compressors.to_vec()),
         ))
-    } else {
-        Ok(Rc::new(VortexEncoder::new(enable_dict)))
     }
     // match data_type {
     //     DataType::List(_) | DataType::LargeList(_) => {
@@ -46,3 +66,38 @@ pub fn create_encunit_encoder(
     //     Rc::new(PlainEncoder {})
     // }
 }
+
+/// Encode and compress `array` as a single EncUnit, returning the compression type that was applied.
+/// If `mini_encunit_len` is set, the array is split into mini EncUnits of that many rows,
+/// each encoded and compressed on its own, and their sizes are returned along with the bytes.
+/// The mini EncUnits share the compression type of the EncUnit, so they are either all compressed or none is.
+pub(crate) fn encode_encunit(
+    encoder: Rc<dyn Encoder>,
+    array: &ArrayRef,
+    compression: BlockCompression,
+    mini_encunit_len: Option<u64>,
+) -> fff_core::errors::Result<(Bytes, Vec<u32>, fb::CompressionType)> {
+    match mini_encunit_len {
+        Some(mini_encunit_len) if mini_encunit_len > 0 && array.len() as u64 > mini_encunit_len => {
+            let (mini_encunits, _) =
+                encode_mini_blocks(encoder, array.clone(), mini_encunit_len as usize)?;
+            let (mini_encunits, compression_type) = compress_blocks(mini_encunits, compression)?;
+            // The sizes after compression are stored in the EncUnit, from which readers rebuild
+            // the offsets of the mini EncUnits.
+            let sizes = mini_encunits
+                .iter()
+                .map(|mini_encunit| mini_encunit.len() as u32)
+                .collect();
+            let mut buf = BytesMut::new();
+            for mini_encunit in mini_encunits {
+                buf.extend_from_slice(&mini_encunit);
+            }
+            Ok((buf.freeze(), sizes, compression_type))
+        }
+        _ => {
+            let (data, compression_type) =
+                compress_data(encode_to_bytes(encoder, array.clone()), compression)?;
+            Ok((data, vec![], compression_type))
+        }
+    }
+}
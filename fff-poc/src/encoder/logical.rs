@@ -1,4 +1,4 @@
-use std::{ops::Not, sync::Arc};
+use std::{collections::HashMap, ops::Not, sync::Arc};
 
 use super::{
     encoded_column_chunk::EncodedColumnChunk,
@@ -8,7 +8,8 @@ use crate::{
     common::ColumnIndexSequence,
     context::WASMWritingContext,
     counter::EncodingCounter,
-    dict::{shared_dictionary_context::SharedDictionaryContext, DictionaryTypeOptions},
+    dict::shared_dictionary_context::SharedDictionaryContext,
+    options::{ColumnEncodingConfig, ColumnEncodingPolicy},
 };
 use arrow_array::cast::AsArray;
 use arrow_array::Array;
@@ -82,6 +83,11 @@ impl LogicalColEncoder for FlatColEncoder {
         counter: &mut EncodingCounter,
         shared_dict_ctx: &mut SharedDictionaryContext,
     ) -> Result<Option<Vec<EncodedColumnChunk>>> {
+        let array = if self.data_encoder.accepts_dictionary_arrays() {
+            array
+        } else {
+            physical::unpack_dictionary(array)?
+        };
         let mut res = vec![];
         for data_chunk in self.data_encoder.encode(array, counter, shared_dict_ctx)? {
             res.push(data_chunk.update_column_index(self.column_index));
@@ -311,16 +317,20 @@ impl LogicalColEncoder for StructColEncoder {
     }
 }
 
-#[allow(clippy::only_used_in_recursion)]
+/// `field_path` is the column path of `field`, and `config` its resolved encoding config.
+/// The configs of nested columns are resolved from `column_policies` by their paths.
+#[allow(clippy::only_used_in_recursion, clippy::too_many_arguments)]
 pub fn create_logical_encoder(
     field: FieldRef,
     field_id: i32,
+    field_path: &str,
     max_chunk_size: u64,
     column_idx: &mut ColumnIndexSequence,
     wasm_context: Arc<WASMWritingContext>,
-    dictionary_type: DictionaryTypeOptions,
-    compression_type: fb::CompressionType,
+    config: &ColumnEncodingConfig,
+    column_policies: &HashMap<String, ColumnEncodingPolicy>,
 ) -> Result<(Box<dyn LogicalColEncoder>, LogicalTree)> {
+    let child_path = |child: &FieldRef| format!("{}.{}", field_path, child.name());
     match field.data_type() {
         non_nest_types!() => Ok((
             Box::new(FlatColEncoder {
@@ -328,9 +338,13 @@ pub fn create_logical_encoder(
                     field.data_type(),
                     max_chunk_size,
                     field.is_nullable(),
-                    wasm_context,
-                    dictionary_type,
-                    compression_type,
+                    match &config.encoder {
+                        Some(encoder) => {
+                            Arc::new(wasm_context.with_column_encoder(field.data_type(), encoder)?)
+                        }
+                        None => wasm_context,
+                    },
+                    config,
                 )?,
                 column_index: column_idx.next_column_index(),
             }),
@@ -354,7 +368,7 @@ pub fn create_logical_encoder(
                                     physical::ListOfStructColEncoder::new(
                                         max_chunk_size,
                                         wasm_context.clone(),
-                                        compression_type,
+                                        config.compression,
                                     )
                                 })
                                 .collect(),
@@ -373,17 +387,18 @@ pub fn create_logical_encoder(
                         max_chunk_size,
                         field.is_nullable(),
                         wasm_context.clone(),
-                        dictionary_type,
-                        compression_type,
+                        config,
                     )?;
+                    let child_path = child_path(child);
                     let (values_encoder, child_tree) = create_logical_encoder(
                         Arc::clone(child),
                         field_id,
+                        &child_path,
                         max_chunk_size,
                         column_idx,
                         wasm_context,
-                        dictionary_type,
-                        compression_type,
+                        &config.child(column_policies.get(&child_path)),
+                        column_policies,
                     )?;
                     Ok((
                         Box::new(ListColEncoder {
@@ -401,14 +416,16 @@ pub fn create_logical_encoder(
             let mut fields_encoders = vec![];
             let mut child_trees = vec![];
             for child_field in child_fields.iter() {
+                let child_path = child_path(child_field);
                 let (enc, child_tree) = create_logical_encoder(
                     Arc::clone(child_field),
                     field_id,
+                    &child_path,
                     max_chunk_size,
                     column_idx,
                     wasm_context.clone(),
-                    dictionary_type,
-                    compression_type,
+                    &config.child(column_policies.get(&child_path)),
+                    column_policies,
                 )?;
                 fields_encoders.push(enc);
                 child_trees.push(child_tree);
@@ -420,8 +437,7 @@ pub fn create_logical_encoder(
                         max_chunk_size,
                         false,
                         wasm_context.clone(),
-                        dictionary_type,
-                        compression_type,
+                        config,
                     )?,
                     column_index: validity_index,
                     fields_encoders,
@@ -503,6 +519,7 @@ mod tests {
         use arrow_array::RecordBatch;
 
         use super::*;
+        use crate::options::{DictionaryTypeOptions, FileWriterOptions};
         use arrow_schema::Field;
         use arrow_schema::Schema;
         use std::sync::Arc;
@@ -522,11 +539,16 @@ mod tests {
         let mut encoder = create_logical_encoder(
             Arc::new(input_batch.schema_ref().field(0).clone()),
             0,
+            "a",
             1024 * 1024,
             &mut ColumnIndexSequence::default(),
             Arc::new(WASMWritingContext::empty()),
-            DictionaryTypeOptions::EncoderDictionary,
-            fb::CompressionType::Uncompressed,
+            &ColumnEncodingConfig::new(
+                &FileWriterOptions::builder()
+                    .set_dictionary_type(DictionaryTypeOptions::EncoderDictionary)
+                    .build(),
+            ),
+            &HashMap::new(),
         )
         .unwrap()
         .0;
@@ -1,4 +1,5 @@
-mod custom;
+pub(crate) mod buffer;
+pub(crate) mod custom;
 pub mod encoded_column_chunk;
 pub(super) mod encunit;
 pub mod logical;
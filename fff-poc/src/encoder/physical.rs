@@ -1,24 +1,28 @@
 use std::{io::Cursor, sync::Arc};
 
 use crate::{
-    compression::compress_data,
+    compression::{compress_data, BlockCompression},
     context::WASMWritingContext,
     counter::EncodingCounter,
     dict::{shared_dictionary_context::SharedDictionaryContext, Dictionary, DictionaryTypeOptions},
-    file::footer::{self, WASMEncoding},
+    file::footer,
+    options::ColumnEncodingConfig,
 };
-use arrow::{array::AsArray, datatypes::UInt64Type};
-use arrow_array::{array::ArrayRef, Array, UInt16Array, UInt32Array, UInt8Array};
+use arrow::{
+    array::AsArray,
+    compute::{cast, take},
+    datatypes::UInt64Type,
+};
+use arrow_array::{array::ArrayRef, Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array};
 use arrow_schema::DataType;
 use bytes::Bytes;
 use fff_core::{errors::Result, non_nest_types};
-use fff_format::File::fff::flatbuf as fb;
 use itertools::Itertools;
 use rand::seq::IteratorRandom;
 
 use super::{
     encoded_column_chunk::{EncodedColumnChunk, SerializedEncUnit},
-    encunit::create_encunit_encoder,
+    encunit::{create_encunit_encoder, encode_encunit},
 };
 
 use fff_encoding::schemes::{encode_to_bytes, vortex::VortexEncoder, Encoder};
@@ -43,6 +47,36 @@ pub trait PhysicalColEncoder {
     ) -> Result<Vec<EncodedColumnChunk>>;
 
     fn submit_dict(&mut self, shared_dict_ctx: &mut SharedDictionaryContext) -> Result<()>;
+
+    /// Whether `encode` takes DictionaryArrays as is. Other encoders are given their values.
+    fn accepts_dictionary_arrays(&self) -> bool {
+        false
+    }
+}
+
+/// The values of a DictionaryArray as a plain array. Other arrays are returned as is.
+pub(crate) fn unpack_dictionary(array: ArrayRef) -> Result<ArrayRef> {
+    match array.data_type() {
+        DataType::Dictionary(_, value_type) => Ok(cast(&array, value_type)?),
+        _ => Ok(array),
+    }
+}
+
+/// The dictionary of a DictionaryArray and the UInt64 indices of its rows into it, so that the
+/// dictionary is hashed instead of every row. Indices are null for null keys and null values.
+/// None for other arrays, and if the dictionary has more values than the array has rows.
+fn reusable_dictionary(array: &ArrayRef) -> Option<(ArrayRef, ArrayRef)> {
+    let dictionary = array.as_any_dictionary_opt()?;
+    if dictionary.values().len() > array.len() {
+        return None;
+    }
+    let keys = dictionary
+        .normalized_keys()
+        .into_iter()
+        .map(|key| key as u64)
+        .collect::<Vec<_>>();
+    let indices = UInt64Array::new(keys.into(), array.logical_nulls());
+    Some((dictionary.values().clone(), Arc::new(indices)))
 }
 
 /// A specific experimental encoder for testing List of Struct of non nest types.
@@ -53,14 +87,14 @@ pub struct ListOfStructColEncoder {
     /// The desired encoded column chunk size, should match I/O unit size (e.g., 8MB on S3)
     column_chunk_size: u64,
     wasm_context: Arc<WASMWritingContext>,
-    compression_type: fb::CompressionType,
+    compression: BlockCompression,
 }
 
 impl ListOfStructColEncoder {
     pub fn new(
         column_chunk_size: u64,
         wasm_context: Arc<WASMWritingContext>,
-        compression_type: fb::CompressionType,
+        compression: BlockCompression,
     ) -> Self {
         Self {
             accumulated_chunk: EncodedColumnChunk::builder()
@@ -69,7 +103,7 @@ impl ListOfStructColEncoder {
             accumulated_size: 0,
             column_chunk_size,
             wasm_context,
-            compression_type,
+            compression,
         }
     }
 
@@ -93,31 +127,16 @@ impl ListOfStructColEncoder {
         };
 
         // Compress the data if compression is enabled
-        let compressed_enc_unit = compress_data(enc_unit, self.compression_type)?;
+        let (compressed_enc_unit, compression_type) = compress_data(enc_unit, self.compression)?;
         let compressed_size = compressed_enc_unit.len() as u64;
 
         self.accumulated_size += compressed_size;
         self.accumulated_chunk.encunits.push(SerializedEncUnit::new(
             compressed_enc_unit,
             list_len as u32,
-            {
-                let encoding_type = encoder.encoding_type();
-                footer::Encoding::try_new(
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        fb::EncodingType::CUSTOM_WASM
-                    } else {
-                        encoding_type.to_fbs_encoding()
-                    },
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        self.wasm_context.builtin_wasm_id()
-                    } else {
-                        self.wasm_context
-                            .data_type_to_wasm_id(list_array.data_type())
-                    }
-                    .map(|id| WASMEncoding::new(id.0, Vec::new())),
-                )?
-            },
-            self.compression_type,
+            self.wasm_context
+                .encunit_encoding(encoder.encoding_type(), list_array.data_type())?,
+            compression_type,
         ));
         self.accumulated_chunk.num_rows += list_len;
         if self.accumulated_size > self.column_chunk_size {
@@ -146,7 +165,11 @@ pub struct EncoderDictColEncoder {
     column_chunk_size: u64,
     wasm_context: Arc<WASMWritingContext>,
     enable_dict: bool,
-    compression_type: fb::CompressionType,
+    compression: BlockCompression,
+    /// Ids of the Vortex compressors not to try.
+    excluded_compressors: Vec<String>,
+    /// Split each EncUnit into mini EncUnits of this many rows.
+    mini_encunit_len: Option<u64>,
 }
 
 impl EncoderDictColEncoder {
@@ -154,7 +177,7 @@ impl EncoderDictColEncoder {
         column_chunk_size: u64,
         wasm_context: Arc<WASMWritingContext>,
         enable_dict: bool,
-        compression_type: fb::CompressionType,
+        compression: BlockCompression,
     ) -> Self {
         Self {
             accumulated_chunk: EncodedColumnChunk::builder()
@@ -164,9 +187,21 @@ impl EncoderDictColEncoder {
             column_chunk_size,
             wasm_context,
             enable_dict,
-            compression_type,
+            compression,
+            excluded_compressors: vec![],
+            mini_encunit_len: None,
         }
     }
+
+    pub fn with_excluded_compressors(mut self, excluded_compressors: Vec<String>) -> Self {
+        self.excluded_compressors = excluded_compressors;
+        self
+    }
+
+    pub fn with_mini_encunit_len(mut self, mini_encunit_len: Option<u64>) -> Self {
+        self.mini_encunit_len = mini_encunit_len;
+        self
+    }
 }
 
 impl PhysicalColEncoder for EncoderDictColEncoder {
@@ -180,38 +215,37 @@ impl PhysicalColEncoder for EncoderDictColEncoder {
             self.wasm_context.clone(),
             array.data_type().clone(),
             self.enable_dict,
+            &self.excluded_compressors,
         )?;
-        let enc_unit = encode_to_bytes(encoder.clone(), array.clone());
-
         // Compress the data if compression is enabled
-        let compressed_enc_unit = compress_data(enc_unit, self.compression_type)?;
+        let (compressed_enc_unit, mini_encunit_sizes, compression_type) = encode_encunit(
+            encoder.clone(),
+            &array,
+            self.compression,
+            self.mini_encunit_len,
+        )?;
         let compressed_size = compressed_enc_unit.len() as u64;
 
         // Update accumulated size with compressed size
         self.accumulated_size += compressed_size;
         counter.index_size += compressed_enc_unit.len();
 
-        self.accumulated_chunk.encunits.push(SerializedEncUnit::new(
-            compressed_enc_unit,
-            array.len() as u32,
-            {
-                let encoding_type = encoder.encoding_type();
-                footer::Encoding::try_new(
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        fb::EncodingType::CUSTOM_WASM
-                    } else {
-                        encoding_type.to_fbs_encoding()
-                    },
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        self.wasm_context.builtin_wasm_id()
-                    } else {
-                        self.wasm_context.data_type_to_wasm_id(array.data_type())
-                    }
-                    .map(|id| WASMEncoding::new(id.0, Vec::new())),
-                )?
-            },
-            self.compression_type,
-        ));
+        self.accumulated_chunk.encunits.push(
+            SerializedEncUnit::new(
+                compressed_enc_unit,
+                array.len() as u32,
+                self.wasm_context
+                    .encunit_encoding(encoder.encoding_type(), array.data_type())?,
+                compression_type,
+            )
+            .with_mini_encunits(
+                match mini_encunit_sizes.is_empty() {
+                    true => 0,
+                    false => self.mini_encunit_len.unwrap_or_default() as u32,
+                },
+                mini_encunit_sizes,
+            ),
+        );
         self.accumulated_chunk.num_rows += array.len();
         if self.accumulated_size > self.column_chunk_size {
             let chunk = std::mem::take(&mut self.accumulated_chunk);
@@ -358,14 +392,16 @@ pub struct DictColEncoder {
     /// The desired encoded column chunk size, should match I/O unit size (e.g., 8MB on S3)
     column_chunk_size: u64,
     wasm_context: Arc<WASMWritingContext>,
-    compression_type: fb::CompressionType,
+    compression: BlockCompression,
+    /// Ids of the Vortex compressors not to try.
+    excluded_compressors: Vec<String>,
 }
 
 impl DictColEncoder {
     pub fn new(
         column_chunk_size: u64,
         wasm_context: Arc<WASMWritingContext>,
-        compression_type: fb::CompressionType,
+        compression: BlockCompression,
     ) -> Self {
         Self {
             accumulated_chunk: EncodedColumnChunk::builder()
@@ -379,9 +415,15 @@ impl DictColEncoder {
             accumulated_size: 0,
             column_chunk_size,
             wasm_context,
-            compression_type,
+            compression,
+            excluded_compressors: vec![],
         }
     }
+
+    pub fn with_excluded_compressors(mut self, excluded_compressors: Vec<String>) -> Self {
+        self.excluded_compressors = excluded_compressors;
+        self
+    }
 }
 
 impl PhysicalColEncoder for DictColEncoder {
@@ -391,25 +433,38 @@ impl PhysicalColEncoder for DictColEncoder {
         counter: &mut EncodingCounter,
         _shared_dict_ctx: &mut SharedDictionaryContext,
     ) -> Result<Vec<EncodedColumnChunk>> {
-        let dtype = array.data_type().clone();
-        let mut dict = Dictionary::try_new(dtype.clone())?;
-        dict.extend(array)?;
-        let (dict, indices) = dict.finish()?;
+        let (dict, indices) = match reusable_dictionary(&array) {
+            Some(dict_and_indices) => dict_and_indices,
+            None => {
+                let array = unpack_dictionary(array)?;
+                let mut dict = Dictionary::try_new(array.data_type().clone())?;
+                dict.extend(array)?;
+                dict.finish()?
+            }
+        };
+        let dtype = dict.data_type().clone();
         let indices = cast_index_dtype(indices, dict.len());
         let indices_dtype = indices.data_type().clone();
-        let dict_encoder =
-            create_encunit_encoder(self.wasm_context.clone(), dict.data_type().clone(), false)?;
+        let dict_encoder = create_encunit_encoder(
+            self.wasm_context.clone(),
+            dict.data_type().clone(),
+            false,
+            &self.excluded_compressors,
+        )?;
         let dict_enc_unit = encode_to_bytes(dict_encoder.clone(), dict.clone());
         let indices_encoder = create_encunit_encoder(
             self.wasm_context.clone(),
             indices.data_type().clone(),
             false,
+            &self.excluded_compressors,
         )?;
         let indices_enc_unit = encode_to_bytes(indices_encoder.clone(), indices.clone());
 
         // Compress the dictionary data if compression is enabled
-        let compressed_dict_enc_unit = compress_data(dict_enc_unit, self.compression_type)?;
-        let compressed_indices_enc_unit = compress_data(indices_enc_unit, self.compression_type)?;
+        let (compressed_dict_enc_unit, dict_compression_type) =
+            compress_data(dict_enc_unit, self.compression)?;
+        let (compressed_indices_enc_unit, indices_compression_type) =
+            compress_data(indices_enc_unit, self.compression)?;
 
         let dict_compressed_size = compressed_dict_enc_unit.len() as u64;
         let indices_compressed_size = compressed_indices_enc_unit.len() as u64;
@@ -423,44 +478,16 @@ impl PhysicalColEncoder for DictColEncoder {
             compressed_dict_enc_unit,
             // TODO: be careful this num_rows does not correspond to the original table
             dict.len() as u32,
-            {
-                let encoding_type = dict_encoder.encoding_type();
-                footer::Encoding::try_new(
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        fb::EncodingType::CUSTOM_WASM
-                    } else {
-                        encoding_type.to_fbs_encoding()
-                    },
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        self.wasm_context.builtin_wasm_id()
-                    } else {
-                        self.wasm_context.data_type_to_wasm_id(&dtype)
-                    }
-                    .map(|id| WASMEncoding::new(id.0, Vec::new())),
-                )?
-            },
-            self.compression_type,
+            self.wasm_context
+                .encunit_encoding(dict_encoder.encoding_type(), &dtype)?,
+            dict_compression_type,
         ));
         self.accumulated_chunk.encunits.push(SerializedEncUnit::new(
             compressed_indices_enc_unit,
             indices.len() as u32,
-            {
-                let encoding_type = indices_encoder.encoding_type();
-                footer::Encoding::try_new(
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        fb::EncodingType::CUSTOM_WASM
-                    } else {
-                        encoding_type.to_fbs_encoding()
-                    },
-                    if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                        self.wasm_context.builtin_wasm_id()
-                    } else {
-                        self.wasm_context.data_type_to_wasm_id(&indices_dtype)
-                    }
-                    .map(|id| WASMEncoding::new(id.0, Vec::new())),
-                )?
-            },
-            self.compression_type,
+            self.wasm_context
+                .encunit_encoding(indices_encoder.encoding_type(), &indices_dtype)?,
+            indices_compression_type,
         ));
         self.accumulated_chunk.num_rows += indices.len() as usize;
         if self.accumulated_size > self.column_chunk_size {
@@ -492,6 +519,10 @@ impl PhysicalColEncoder for DictColEncoder {
     fn submit_dict(&mut self, _shared_dict_ctx: &mut SharedDictionaryContext) -> Result<()> {
         Ok(())
     }
+
+    fn accepts_dictionary_arrays(&self) -> bool {
+        true
+    }
 }
 
 /// Shared dictionaries are used.
@@ -504,7 +535,9 @@ pub struct SharedDictColEncoder {
     column_chunk_size: u64,
     wasm_context: Arc<WASMWritingContext>,
     submitted_dict_idx: Option<u32>,
-    compression_type: fb::CompressionType,
+    compression: BlockCompression,
+    /// Ids of the Vortex compressors not to try.
+    excluded_compressors: Vec<String>,
 }
 
 impl SharedDictColEncoder {
@@ -512,7 +545,7 @@ impl SharedDictColEncoder {
         fixed_dict_scope: u64,
         column_chunk_size: u64,
         wasm_context: Arc<WASMWritingContext>,
-        compression_type: fb::CompressionType,
+        compression: BlockCompression,
     ) -> Self {
         Self {
             fixed_dict_scope,
@@ -522,10 +555,16 @@ impl SharedDictColEncoder {
             column_chunk_size,
             wasm_context,
             submitted_dict_idx: None,
-            compression_type,
+            compression,
+            excluded_compressors: vec![],
         }
     }
 
+    pub fn with_excluded_compressors(mut self, excluded_compressors: Vec<String>) -> Self {
+        self.excluded_compressors = excluded_compressors;
+        self
+    }
+
     fn encode_dict_scope(
         &mut self,
         counter: &mut EncodingCounter,
@@ -542,12 +581,18 @@ impl SharedDictColEncoder {
                         "Cannot encode empty buffer in SharedDictColEncoder".to_string(),
                     )
                 })?;
-                shared_dict_ctx.new_dictionary(first_arr.data_type().clone())?
+                shared_dict_ctx.new_dictionary(value_type(first_arr.data_type()).clone())?
             }
         };
         let indices_arrs = buffered_arrs
             .into_iter()
-            .map(|arr| shared_dict_ctx.extend_and_get_index(dict_idx, arr))
+            .map(|arr| match reusable_dictionary(&arr) {
+                Some((dict, indices)) => {
+                    let dict_indices = shared_dict_ctx.extend_and_get_index(dict_idx, dict)?;
+                    Ok(take(dict_indices.as_ref(), indices.as_ref(), None)?)
+                }
+                None => shared_dict_ctx.extend_and_get_index(dict_idx, unpack_dictionary(arr)?),
+            })
             .collect::<Result<Vec<_>>>()?;
         let dict_len = shared_dict_ctx.dict_len(dict_idx)?;
         let indices_arrs = indices_arrs
@@ -560,12 +605,17 @@ impl SharedDictColEncoder {
             .build();
         let mut accumulated_size = 0;
         for arr in indices_arrs {
-            let encoder =
-                create_encunit_encoder(self.wasm_context.clone(), arr.data_type().clone(), false)?;
+            let encoder = create_encunit_encoder(
+                self.wasm_context.clone(),
+                arr.data_type().clone(),
+                false,
+                &self.excluded_compressors,
+            )?;
             let enc_unit = encode_to_bytes(encoder.clone(), arr.clone());
 
             // Compress the data if compression is enabled
-            let compressed_enc_unit = compress_data(enc_unit, self.compression_type)?;
+            let (compressed_enc_unit, compression_type) =
+                compress_data(enc_unit, self.compression)?;
             let compressed_size = compressed_enc_unit.len() as u64;
 
             accumulated_size += compressed_size;
@@ -573,23 +623,9 @@ impl SharedDictColEncoder {
             accumulated_chunk.encunits.push(SerializedEncUnit::new(
                 compressed_enc_unit,
                 arr.len() as u32,
-                {
-                    let encoding_type = encoder.encoding_type();
-                    footer::Encoding::try_new(
-                        if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                            fb::EncodingType::CUSTOM_WASM
-                        } else {
-                            encoding_type.to_fbs_encoding()
-                        },
-                        if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                            self.wasm_context.builtin_wasm_id()
-                        } else {
-                            self.wasm_context.data_type_to_wasm_id(arr.data_type())
-                        }
-                        .map(|id| WASMEncoding::new(id.0, Vec::new())),
-                    )?
-                },
-                self.compression_type,
+                self.wasm_context
+                    .encunit_encoding(encoder.encoding_type(), arr.data_type())?,
+                compression_type,
             ));
             accumulated_chunk.num_rows += arr.len();
             if accumulated_size > self.column_chunk_size {
@@ -659,17 +695,36 @@ impl PhysicalColEncoder for SharedDictColEncoder {
                         "Cannot submit dict with empty buffer in SharedDictColEncoder".to_string(),
                     )
                 })?;
-                let idx = shared_dict_ctx.new_dictionary(first_arr.data_type().clone())?;
+                let idx =
+                    shared_dict_ctx.new_dictionary(value_type(first_arr.data_type()).clone())?;
                 self.submitted_dict_idx = Some(idx);
                 idx
             }
         };
         self.buffered_arrays
             .iter()
-            .map(|arr| shared_dict_ctx.submit_values(dict_idx, arr.clone()))
+            .map(|arr| {
+                let values = match reusable_dictionary(arr) {
+                    Some((dict, _)) => dict,
+                    None => unpack_dictionary(arr.clone())?,
+                };
+                shared_dict_ctx.submit_values(dict_idx, values)
+            })
             .collect::<Result<Vec<_>>>()?;
         Ok(())
     }
+
+    fn accepts_dictionary_arrays(&self) -> bool {
+        true
+    }
+}
+
+/// The type of the values of a DictionaryArray, or of other arrays.
+fn value_type(data_type: &DataType) -> &DataType {
+    match data_type {
+        DataType::Dictionary(_, value_type) => value_type,
+        other => other,
+    }
 }
 
 /// Best of global/local dictionaries is used (may use sampling to estimate).
@@ -681,7 +736,9 @@ pub struct GLBestEncoder {
     /// The desired encoded column chunk size, should match I/O unit size (e.g., 8MB on S3)
     column_chunk_size: u64,
     wasm_context: Arc<WASMWritingContext>,
-    compression_type: fb::CompressionType,
+    compression: BlockCompression,
+    /// Ids of the Vortex compressors not to try.
+    excluded_compressors: Vec<String>,
 }
 
 impl GLBestEncoder {
@@ -689,7 +746,7 @@ impl GLBestEncoder {
         sample_size: Option<(f64, usize)>,
         column_chunk_size: u64,
         wasm_context: Arc<WASMWritingContext>,
-        compression_type: fb::CompressionType,
+        compression: BlockCompression,
     ) -> Self {
         Self {
             sample_size,
@@ -698,10 +755,16 @@ impl GLBestEncoder {
             buffered_array_mem_size: 0,
             column_chunk_size,
             wasm_context,
-            compression_type,
+            compression,
+            excluded_compressors: vec![],
         }
     }
 
+    pub fn with_excluded_compressors(mut self, excluded_compressors: Vec<String>) -> Self {
+        self.excluded_compressors = excluded_compressors;
+        self
+    }
+
     fn estimate_arrays_encoded_size(
         &self,
         arrs: &[ArrayRef],
@@ -764,12 +827,17 @@ impl GLBestEncoder {
             .build();
         let mut accumulated_size = 0;
         for arr in arrs {
-            let encoder =
-                create_encunit_encoder(self.wasm_context.clone(), arr.data_type().clone(), false)?;
+            let encoder = create_encunit_encoder(
+                self.wasm_context.clone(),
+                arr.data_type().clone(),
+                false,
+                &self.excluded_compressors,
+            )?;
             let enc_unit = encode_to_bytes(encoder.clone(), arr.clone());
 
             // Compress the data if compression is enabled
-            let compressed_enc_unit = compress_data(enc_unit, self.compression_type)?;
+            let (compressed_enc_unit, compression_type) =
+                compress_data(enc_unit, self.compression)?;
             let compressed_size = compressed_enc_unit.len() as u64;
 
             accumulated_size += compressed_size;
@@ -781,23 +849,9 @@ impl GLBestEncoder {
             accumulated_chunk.encunits.push(SerializedEncUnit::new(
                 compressed_enc_unit,
                 arr.len() as u32,
-                {
-                    let encoding_type = encoder.encoding_type();
-                    footer::Encoding::try_new(
-                        if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                            fb::EncodingType::CUSTOM_WASM
-                        } else {
-                            encoding_type.to_fbs_encoding()
-                        },
-                        if self.wasm_context.always_set_custom_wasm_for_built_in() {
-                            self.wasm_context.builtin_wasm_id()
-                        } else {
-                            self.wasm_context.data_type_to_wasm_id(arr.data_type())
-                        }
-                        .map(|id| WASMEncoding::new(id.0, Vec::new())),
-                    )?
-                },
-                self.compression_type,
+                self.wasm_context
+                    .encunit_encoding(encoder.encoding_type(), arr.data_type())?,
+                compression_type,
             ));
             accumulated_chunk.num_rows += arr.len();
             // Only split to multiple chunks for indices
@@ -872,8 +926,9 @@ impl PhysicalColEncoder for GLBestEncoder {
                         self.column_chunk_size,
                         self.wasm_context.clone(),
                         true,
-                        fb::CompressionType::Uncompressed,
-                    );
+                        BlockCompression::default(),
+                    )
+                    .with_excluded_compressors(self.excluded_compressors.clone());
                     let mut local_counter = EncodingCounter::default();
                     let local_chunks = arrs
                         .iter()
@@ -1060,48 +1115,44 @@ pub fn create_physical_encoder(
     max_chunk_size: u64,
     _nullable: bool, // We use Vortex and its null info is embedded in the EncUnit.
     wasm_context: Arc<WASMWritingContext>,
-    dictionary_type: DictionaryTypeOptions,
-    compression_type: fb::CompressionType,
+    config: &ColumnEncodingConfig,
 ) -> Result<Box<dyn PhysicalColEncoder>> {
+    let compression = config.compression;
+    let excluded = config.excluded_vortex_compressors.clone();
     match data_type {
-        non_nest_types!() => match dictionary_type {
-            DictionaryTypeOptions::NoDictionary => Ok(Box::new(EncoderDictColEncoder::new(
-                max_chunk_size,
-                wasm_context,
-                false,
-                compression_type,
-            ))),
-            DictionaryTypeOptions::EncoderDictionary => Ok(Box::new(EncoderDictColEncoder::new(
-                max_chunk_size,
-                wasm_context,
-                true,
-                compression_type,
-            ))),
-            DictionaryTypeOptions::LocalDictionary => Ok(Box::new(DictColEncoder::new(
-                max_chunk_size,
-                wasm_context,
-                compression_type,
-            ))),
+        non_nest_types!() => match config.dictionary_type {
+            DictionaryTypeOptions::NoDictionary => Ok(Box::new(
+                EncoderDictColEncoder::new(max_chunk_size, wasm_context, false, compression)
+                    .with_excluded_compressors(excluded)
+                    .with_mini_encunit_len(config.mini_encunit_len),
+            )),
+            DictionaryTypeOptions::EncoderDictionary => Ok(Box::new(
+                EncoderDictColEncoder::new(max_chunk_size, wasm_context, true, compression)
+                    .with_excluded_compressors(excluded)
+                    .with_mini_encunit_len(config.mini_encunit_len),
+            )),
+            DictionaryTypeOptions::LocalDictionary => Ok(Box::new(
+                DictColEncoder::new(max_chunk_size, wasm_context, compression)
+                    .with_excluded_compressors(excluded),
+            )),
             DictionaryTypeOptions::GlobalDictionary
             | DictionaryTypeOptions::GlobalDictionaryMultiColSharing => Ok(Box::new(
-                SharedDictColEncoder::new(u64::MAX, max_chunk_size, wasm_context, compression_type),
+                SharedDictColEncoder::new(u64::MAX, max_chunk_size, wasm_context, compression)
+                    .with_excluded_compressors(excluded),
             )),
             DictionaryTypeOptions::FixedScopeDictionary(scope) => Ok(Box::new(
-                SharedDictColEncoder::new(scope, max_chunk_size, wasm_context, compression_type),
+                SharedDictColEncoder::new(scope, max_chunk_size, wasm_context, compression)
+                    .with_excluded_compressors(excluded),
+            )),
+            DictionaryTypeOptions::GLBest(sample_size) => Ok(Box::new(
+                GLBestEncoder::new(sample_size, max_chunk_size, wasm_context, compression)
+                    .with_excluded_compressors(excluded),
             )),
-            DictionaryTypeOptions::GLBest(sample_size) => Ok(Box::new(GLBestEncoder::new(
-                sample_size,
-                max_chunk_size,
-                wasm_context,
-                compression_type,
-            ))),
         },
-        DataType::List(_) | DataType::LargeList(_) => Ok(Box::new(EncoderDictColEncoder::new(
-            max_chunk_size,
-            wasm_context,
-            true,
-            compression_type,
-        ))),
+        DataType::List(_) | DataType::LargeList(_) => Ok(Box::new(
+            EncoderDictColEncoder::new(max_chunk_size, wasm_context, true, compression)
+                .with_excluded_compressors(excluded),
+        )),
         other => {
             return Err(fff_core::errors::Error::General(format!(
                 "Physical encoder not supported for data type {:?}",
@@ -1133,7 +1184,7 @@ mod tests {
             DEFAULT_IOUNIT_SIZE,
             WASMWritingContext::empty().into(),
             true,
-            fb::CompressionType::Uncompressed,
+            fb::CompressionType::Uncompressed.into(),
         );
         let a =
             Arc::new(arrow_array::Int32Array::from(vec![Some(1), None, Some(3)])) as Arc<dyn Array>;
@@ -1173,7 +1224,7 @@ mod tests {
         let mut encoder = super::DictColEncoder::new(
             DEFAULT_IOUNIT_SIZE,
             WASMWritingContext::empty().into(),
-            fb::CompressionType::Uncompressed,
+            fb::CompressionType::Uncompressed.into(),
         );
         let a = Arc::new(arrow_array::Int32Array::from(vec![
             Some(1),
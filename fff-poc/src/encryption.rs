@@ -0,0 +1,393 @@
+//! Column-level modular encryption with AES-GCM, in the spirit of Parquet modular encryption.
+//!
+//! Each encrypted root-level column has its own key. Its EncUnits are encrypted in place (the tag is kept
+//! in the EncUnit metadata), and its ColumnMetadata is stored as nonce || ciphertext || tag.
+//! Optionally, the footer is encrypted with a footer key, otherwise it stays readable and only records
+//! the key ids of the encrypted columns.
+//!
+//! Every module is authenticated with its type and file offset as additional data,
+//! so modules cannot be swapped inside or across files without being detected.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{AeadCore, AeadInPlace, KeyInit, OsRng};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use byteorder::{ByteOrder, LittleEndian};
+use fff_core::errors::{Error, Result};
+use fff_format::File::fff::flatbuf as fb;
+use fff_format::ToFlatBuffer;
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
+
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+/// Provides the keys of encrypted files to the reader.
+pub trait KeyRetriever: Send + Sync {
+    fn retrieve_key(&self, key_id: &str) -> Result<Vec<u8>>;
+}
+
+/// Keys held in memory, mostly for tests.
+#[derive(Clone, Default)]
+pub struct InMemoryKeyRetriever {
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl InMemoryKeyRetriever {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(mut self, key_id: impl Into<String>, key: impl Into<Vec<u8>>) -> Self {
+        self.keys.insert(key_id.into(), key.into());
+        self
+    }
+}
+
+impl KeyRetriever for InMemoryKeyRetriever {
+    fn retrieve_key(&self, key_id: &str) -> Result<Vec<u8>> {
+        self.keys
+            .get(key_id)
+            .cloned()
+            .ok_or_else(|| Error::General(format!("Key {key_id} not found")))
+    }
+}
+
+/// Keys stored as raw bytes in `<dir>/<key_id>.key`.
+#[derive(Clone)]
+pub struct FileKeyRetriever {
+    dir: PathBuf,
+}
+
+impl FileKeyRetriever {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl KeyRetriever for FileKeyRetriever {
+    fn retrieve_key(&self, key_id: &str) -> Result<Vec<u8>> {
+        if key_id.contains(['/', '\\']) || key_id == ".." {
+            return Err(Error::General(format!("Invalid key id {key_id}")));
+        }
+        std::fs::read(self.dir.join(format!("{key_id}.key")))
+            .map_err(|e| Error::General(format!("Unable to read key {key_id}: {e}")))
+    }
+}
+
+/// A key and the id under which the reader can retrieve it.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    key_id: String,
+    key: Vec<u8>,
+}
+
+impl EncryptionKey {
+    /// The key must be 16 (AES-128) or 32 (AES-256) bytes.
+    pub fn new(key_id: impl Into<String>, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key_id: key_id.into(),
+            key: key.into(),
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+/// Which columns to encrypt with which keys, and whether to encrypt the footer.
+#[derive(Clone, Default)]
+pub struct FileEncryptionOptions {
+    /// Mapping between root-level column id and its key.
+    column_keys: HashMap<usize, EncryptionKey>,
+    /// Encrypt the footer with this key. The footer is plaintext if None.
+    footer_key: Option<EncryptionKey>,
+}
+
+impl FileEncryptionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_column_key(mut self, field_id: usize, key: EncryptionKey) -> Self {
+        self.column_keys.insert(field_id, key);
+        self
+    }
+
+    pub fn with_footer_key(mut self, key: EncryptionKey) -> Self {
+        self.footer_key = Some(key);
+        self
+    }
+
+    pub fn column_keys(&self) -> &HashMap<usize, EncryptionKey> {
+        &self.column_keys
+    }
+
+    pub fn footer_key(&self) -> Option<&EncryptionKey> {
+        self.footer_key.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.column_keys.is_empty() && self.footer_key.is_none()
+    }
+}
+
+/// Kind of the module being encrypted, part of the additional authenticated data.
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub(crate) enum ModuleType {
+    EncUnit = 0,
+    ColumnMetadata = 1,
+    Footer = 2,
+}
+
+/// Additional authenticated data of a module: its type and its offset in the file.
+pub(crate) fn module_aad(module_type: ModuleType, offset: u64) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[0] = module_type as u8;
+    LittleEndian::write_u64(&mut aad[1..], offset);
+    aad
+}
+
+enum CipherImpl {
+    Aes128(Box<Aes128Gcm>),
+    Aes256(Box<Aes256Gcm>),
+}
+
+/// AES-GCM cipher of a single key.
+pub struct AesGcmCipher {
+    inner: CipherImpl,
+}
+
+impl AesGcmCipher {
+    pub fn try_new(key: &[u8]) -> Result<Self> {
+        let inner = match key.len() {
+            16 => CipherImpl::Aes128(Box::new(Aes128Gcm::new(GenericArray::from_slice(key)))),
+            32 => CipherImpl::Aes256(Box::new(Aes256Gcm::new(GenericArray::from_slice(key)))),
+            len => {
+                return Err(Error::General(format!(
+                    "AES-GCM key must be 16 or 32 bytes, got {len}"
+                )))
+            }
+        };
+        Ok(Self { inner })
+    }
+
+    /// Encrypt `buf` in place with a random nonce, returning the nonce and the tag.
+    pub(crate) fn encrypt_in_place_detached(
+        &self,
+        buf: &mut [u8],
+        aad: &[u8],
+    ) -> Result<AesGcmParams> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let tag = match &self.inner {
+            CipherImpl::Aes128(cipher) => cipher.encrypt_in_place_detached(&nonce, aad, buf),
+            CipherImpl::Aes256(cipher) => cipher.encrypt_in_place_detached(&nonce, aad, buf),
+        }
+        .map_err(|_| Error::General("AES-GCM encryption failed".to_string()))?;
+        let mut params = AesGcmParams {
+            nonce: [0; NONCE_SIZE],
+            tag: [0; TAG_SIZE],
+        };
+        params.nonce.copy_from_slice(&nonce);
+        params.tag.copy_from_slice(&tag);
+        Ok(params)
+    }
+
+    pub(crate) fn decrypt_in_place_detached(
+        &self,
+        buf: &mut [u8],
+        aad: &[u8],
+        nonce: &[u8],
+        tag: &[u8],
+    ) -> Result<()> {
+        if nonce.len() != NONCE_SIZE || tag.len() != TAG_SIZE {
+            return Err(Error::ParseError(format!(
+                "Invalid AES-GCM nonce or tag size: {} and {}",
+                nonce.len(),
+                tag.len()
+            )));
+        }
+        let nonce = GenericArray::from_slice(nonce);
+        let tag = GenericArray::from_slice(tag);
+        match &self.inner {
+            CipherImpl::Aes128(cipher) => cipher.decrypt_in_place_detached(nonce, aad, buf, tag),
+            CipherImpl::Aes256(cipher) => cipher.decrypt_in_place_detached(nonce, aad, buf, tag),
+        }
+        .map_err(|_| {
+            Error::General("AES-GCM decryption failed: wrong key or corrupted data".to_string())
+        })
+    }
+
+    /// Returns nonce || ciphertext || tag.
+    pub(crate) fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(NONCE_SIZE + plaintext.len() + TAG_SIZE);
+        buf.extend_from_slice(&[0; NONCE_SIZE]);
+        buf.extend_from_slice(plaintext);
+        let params = self.encrypt_in_place_detached(&mut buf[NONCE_SIZE..], aad)?;
+        buf[..NONCE_SIZE].copy_from_slice(&params.nonce);
+        buf.extend_from_slice(&params.tag);
+        Ok(buf)
+    }
+
+    /// Decrypt nonce || ciphertext || tag.
+    pub(crate) fn decrypt(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_SIZE + TAG_SIZE {
+            return Err(Error::ParseError(format!(
+                "Encrypted module of {} bytes is too short",
+                data.len()
+            )));
+        }
+        let (nonce, rest) = data.split_at(NONCE_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+        let mut buf = ciphertext.to_vec();
+        self.decrypt_in_place_detached(&mut buf, aad, nonce, tag)?;
+        Ok(buf)
+    }
+}
+
+/// Nonce and tag of an encrypted EncUnit.
+#[derive(Clone, Debug)]
+pub struct AesGcmParams {
+    nonce: [u8; NONCE_SIZE],
+    tag: [u8; TAG_SIZE],
+}
+
+impl ToFlatBuffer for AesGcmParams {
+    type Target<'a> = fb::AesGcmParams<'a>;
+
+    fn to_fb<'fb>(&self, fbb: &mut FlatBufferBuilder<'fb>) -> WIPOffset<Self::Target<'fb>> {
+        let nonce = fbb.create_vector(&self.nonce);
+        let tag = fbb.create_vector(&self.tag);
+        fb::AesGcmParams::create(
+            fbb,
+            &fb::AesGcmParamsArgs {
+                nonce: Some(nonce),
+                tag: Some(tag),
+            },
+        )
+    }
+}
+
+/// Key id of an encrypted root-level column, recorded in the footer.
+#[derive(Clone)]
+pub(crate) struct ColumnEncryption {
+    pub(crate) field_id: u32,
+    pub(crate) column_start: u32,
+    pub(crate) column_end: u32,
+    pub(crate) key_id: String,
+}
+
+impl ToFlatBuffer for ColumnEncryption {
+    type Target<'a> = fb::ColumnEncryption<'a>;
+
+    fn to_fb<'fb>(&self, fbb: &mut FlatBufferBuilder<'fb>) -> WIPOffset<Self::Target<'fb>> {
+        let key_id = fbb.create_string(&self.key_id);
+        fb::ColumnEncryption::create(
+            fbb,
+            &fb::ColumnEncryptionArgs {
+                field_id: self.field_id,
+                column_start: self.column_start,
+                column_end: self.column_end,
+                key_id: Some(key_id),
+            },
+        )
+    }
+}
+
+/// Encrypt the serialized footer, which starts at `offset` in the file.
+pub(crate) fn encrypt_footer(footer: &[u8], key: &EncryptionKey, offset: u64) -> Result<Vec<u8>> {
+    let cipher = AesGcmCipher::try_new(key.key())?;
+    let key_id_len = u16::try_from(key.key_id().len())
+        .map_err(|_| Error::General("Footer key id is too long".to_string()))?;
+    let mut res = key_id_len.to_le_bytes().to_vec();
+    res.extend_from_slice(key.key_id().as_bytes());
+    res.extend(cipher.encrypt(footer, &module_aad(ModuleType::Footer, offset))?);
+    Ok(res)
+}
+
+/// Decrypt the footer section starting at `offset` in the file.
+pub(crate) fn decrypt_footer(
+    buf: &[u8],
+    key_retriever: Option<&dyn KeyRetriever>,
+    offset: u64,
+) -> Result<Vec<u8>> {
+    let key_retriever = key_retriever.ok_or_else(|| {
+        Error::General("The footer is encrypted but no KeyRetriever is provided".to_string())
+    })?;
+    if buf.len() < 2 {
+        return Err(Error::ParseError(
+            "Encrypted footer is too short".to_string(),
+        ));
+    }
+    let key_id_len = LittleEndian::read_u16(&buf[..2]) as usize;
+    let key_id = buf
+        .get(2..2 + key_id_len)
+        .and_then(|key_id| std::str::from_utf8(key_id).ok())
+        .ok_or_else(|| Error::ParseError("Invalid footer key id".to_string()))?;
+    let cipher = AesGcmCipher::try_new(&key_retriever.retrieve_key(key_id)?)?;
+    cipher.decrypt(
+        &buf[2 + key_id_len..],
+        &module_aad(ModuleType::Footer, offset),
+    )
+}
+
+/// Resolves the ciphers of encrypted physical columns on the reader side.
+/// Keys are only retrieved for the columns actually read.
+pub(crate) struct FileDecryptor {
+    /// Key id of each encrypted physical column.
+    column_key_ids: HashMap<usize, String>,
+    key_retriever: Option<Arc<dyn KeyRetriever>>,
+    ciphers: HashMap<String, Arc<AesGcmCipher>>,
+}
+
+impl FileDecryptor {
+    pub(crate) fn try_new(
+        encryption: Option<fb::FileEncryption<'_>>,
+        key_retriever: Option<Arc<dyn KeyRetriever>>,
+    ) -> Result<Self> {
+        let mut column_key_ids = HashMap::new();
+        for column_key in encryption
+            .and_then(|encryption| encryption.column_keys())
+            .into_iter()
+            .flatten()
+        {
+            let key_id = column_key
+                .key_id()
+                .ok_or_else(|| Error::ParseError("Column key id not found".to_string()))?;
+            for column_idx in column_key.column_start()..column_key.column_end() {
+                column_key_ids.insert(column_idx as usize, key_id.to_string());
+            }
+        }
+        Ok(Self {
+            column_key_ids,
+            key_retriever,
+            ciphers: HashMap::new(),
+        })
+    }
+
+    /// Returns None if the physical column is not encrypted.
+    pub(crate) fn column_cipher(&mut self, column_idx: usize) -> Result<Option<Arc<AesGcmCipher>>> {
+        let Some(key_id) = self.column_key_ids.get(&column_idx) else {
+            return Ok(None);
+        };
+        if let Some(cipher) = self.ciphers.get(key_id) {
+            return Ok(Some(Arc::clone(cipher)));
+        }
+        let key_retriever = self.key_retriever.as_ref().ok_or_else(|| {
+            Error::General(format!(
+                "Column {column_idx} is encrypted with key {key_id}, but no KeyRetriever is provided"
+            ))
+        })?;
+        let cipher = Arc::new(AesGcmCipher::try_new(&key_retriever.retrieve_key(key_id)?)?);
+        self.ciphers.insert(key_id.clone(), Arc::clone(&cipher));
+        Ok(Some(cipher))
+    }
+}
@@ -4,9 +4,8 @@ use fff_format::POSTSCRIPT_SIZE;
 use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use semver::Version;
 use std::collections::HashMap;
-use std::io::Seek;
 use std::io::Write;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 
 use arrow_ipc::convert::fb_to_schema;
 use arrow_ipc::root_as_message;
@@ -15,7 +14,9 @@ use arrow_schema::SchemaRef;
 use fff_format::File::fff::flatbuf as fb;
 
 use crate::common::checksum::Checksum;
-use crate::common::checksum::ChecksumType;
+use crate::common::checksum::{checksum_of, ChecksumType};
+use crate::encryption::{module_aad, AesGcmCipher, AesGcmParams, ModuleType};
+use crate::io::writer::PositionedWriter;
 use crate::reader::RowGroupCntNPointer;
 use fff_core::errors::{Error, Result};
 
@@ -36,6 +37,16 @@ pub(crate) static DEFAULT_ENCODING_VERSIONS: LazyLock<HashMap<fb::EncodingType,
         ])
     });
 
+/// Whether a reader implementing `reader_version` of an encoding can decode data written with `file_version`.
+/// A reader older than the file is incompatible if the major versions differ, or in the 0.x range.
+pub(crate) fn is_encoding_version_compatible(
+    reader_version: &Version,
+    file_version: &Version,
+) -> bool {
+    !(reader_version.cmp_precedence(file_version).is_lt()
+        && (reader_version.major != file_version.major || reader_version.major == 0))
+}
+
 pub struct PostScript {
     pub metadata_size: u32,
     pub footer_size: u32,
@@ -46,6 +57,8 @@ pub struct PostScript {
     pub schema_checksum: u64,
     pub major_version: u16,
     pub minor_version: u16,
+    /// Whether the footer is encrypted, according to the magic.
+    pub encrypted_footer: bool,
 }
 
 /// Maps an encoding type to its semantic version
@@ -68,14 +81,7 @@ impl ToFlatBuffer for EncodingVersion {
     type Target<'a> = fb::EncodingVersion<'a>;
 
     fn to_fb<'fb>(&self, fbb: &mut FlatBufferBuilder<'fb>) -> WIPOffset<Self::Target<'fb>> {
-        let sem_ver = fb::SemVer::create(
-            fbb,
-            &fb::SemVerArgs {
-                major: self.version.major,
-                minor: self.version.minor,
-                patch: self.version.patch,
-            },
-        );
+        let sem_ver = version_to_fb(fbb, &self.version);
         fb::EncodingVersion::create(
             fbb,
             &fb::EncodingVersionArgs {
@@ -86,15 +92,37 @@ impl ToFlatBuffer for EncodingVersion {
     }
 }
 
-/// Helper function to create encoding versions for all supported encoding types
-pub(crate) fn create_default_encoding_versions() -> Result<Vec<EncodingVersion>> {
-    let mut encoding_versions = Vec::new();
+/// The versions the writer implements of `encoding_types`, for the footer.
+/// PLUGIN is left out, as each EncUnit records the version of its plugin.
+pub(crate) fn create_encoding_versions<'a>(
+    encoding_types: impl IntoIterator<Item = &'a fb::EncodingType>,
+) -> Vec<EncodingVersion> {
+    encoding_types
+        .into_iter()
+        .filter_map(|encoding_type| {
+            DEFAULT_ENCODING_VERSIONS
+                .get(encoding_type)
+                .map(|version| EncodingVersion::new(*encoding_type, version.clone()))
+        })
+        .collect()
+}
 
-    for (&encoding_type, version) in DEFAULT_ENCODING_VERSIONS.iter() {
-        encoding_versions.push(EncodingVersion::new(encoding_type, version.clone()));
-    }
+pub(crate) fn version_from_fb(version: fb::SemVer) -> Version {
+    Version::new(version.major(), version.minor(), version.patch())
+}
 
-    Ok(encoding_versions)
+fn version_to_fb<'fb>(
+    fbb: &mut FlatBufferBuilder<'fb>,
+    version: &Version,
+) -> WIPOffset<fb::SemVer<'fb>> {
+    fb::SemVer::create(
+        fbb,
+        &fb::SemVerArgs {
+            major: version.major,
+            minor: version.minor,
+            patch: version.patch,
+        },
+    )
 }
 
 #[derive(Clone, Default)]
@@ -331,6 +359,56 @@ impl ToFlatBuffer for WASMEncoding {
     }
 }
 
+/// PluginEncoding for writer to use.
+/// Reader should use fff_format::File::fff::flatbuf::PluginEncoding directly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PluginEncoding {
+    id: String,
+    version: Version,
+}
+
+impl PluginEncoding {
+    pub fn new(id: impl Into<String>, version: Version) -> Self {
+        Self {
+            id: id.into(),
+            version,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+}
+
+impl From<&fb::PluginEncoding<'_>> for PluginEncoding {
+    fn from(fb: &fb::PluginEncoding) -> Self {
+        Self {
+            id: fb.id().to_string(),
+            version: version_from_fb(fb.version()),
+        }
+    }
+}
+
+impl ToFlatBuffer for PluginEncoding {
+    type Target<'a> = fb::PluginEncoding<'a>;
+
+    fn to_fb<'fb>(&self, fbb: &mut FlatBufferBuilder<'fb>) -> WIPOffset<Self::Target<'fb>> {
+        let id = fbb.create_string(&self.id);
+        let version = version_to_fb(fbb, &self.version);
+        fb::PluginEncoding::create(
+            fbb,
+            &fb::PluginEncodingArgs {
+                id: Some(id),
+                version: Some(version),
+            },
+        )
+    }
+}
+
 /// Encoding for writer to use.
 /// Reader should use fff_format::File::fff::flatbuf::Encoding directly.
 #[derive(Clone)]
@@ -338,6 +416,10 @@ pub struct Encoding {
     encoding_type: fb::EncodingType,
     /// WASM binary location and minipage sizes
     wasm_encoding: Option<WASMEncoding>,
+    /// Set if `encoding_type` is PLUGIN
+    plugin: Option<PluginEncoding>,
+    /// Version of a built-in `encoding_type`, if recorded per EncUnit.
+    version: Option<Version>,
 }
 
 // impl From<fff_encoding::enc_unit::Encoding> for Encoding {
@@ -360,6 +442,8 @@ impl Default for Encoding {
         Self {
             encoding_type: fb::EncodingType::CASCADE,
             wasm_encoding: None,
+            plugin: None,
+            version: None,
         }
     }
 }
@@ -371,6 +455,10 @@ impl From<&fb::Encoding<'_>> for Encoding {
             wasm_encoding: fb
                 .wasm_encoding()
                 .map(|fb_wasm_encoding| WASMEncoding::from(&fb_wasm_encoding)),
+            plugin: fb
+                .plugin()
+                .map(|fb_plugin| PluginEncoding::from(&fb_plugin)),
+            version: fb.version().map(version_from_fb),
         }
     }
 }
@@ -383,9 +471,18 @@ impl Encoding {
         Ok(Self {
             encoding_type,
             wasm_encoding,
+            plugin: None,
+            version: None,
         })
     }
 
+    /// Encoding of the plugin registered under the id of `plugin`.
+    pub fn with_plugin(mut self, plugin: PluginEncoding) -> Self {
+        self.encoding_type = fb::EncodingType::PLUGIN;
+        self.plugin = Some(plugin);
+        self
+    }
+
     pub fn encoding_type(&self) -> fb::EncodingType {
         self.encoding_type
     }
@@ -393,6 +490,20 @@ impl Encoding {
     pub fn wasm_encoding(&self) -> Option<&WASMEncoding> {
         self.wasm_encoding.as_ref()
     }
+
+    pub fn plugin(&self) -> Option<&PluginEncoding> {
+        self.plugin.as_ref()
+    }
+
+    /// Record the version of the built-in encoding in the EncUnit.
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
 }
 
 impl ToFlatBuffer for Encoding {
@@ -403,11 +514,18 @@ impl ToFlatBuffer for Encoding {
             .wasm_encoding
             .as_ref()
             .map(|wasm_encoding| wasm_encoding.to_fb(fbb));
+        let plugin = self.plugin.as_ref().map(|plugin| plugin.to_fb(fbb));
+        let version = self
+            .version
+            .as_ref()
+            .map(|version| version_to_fb(fbb, version));
         fb::Encoding::create(
             fbb,
             &fb::EncodingArgs {
                 type_: self.encoding_type,
                 wasm_encoding,
+                plugin,
+                version,
             },
         )
     }
@@ -421,6 +539,10 @@ pub struct EncUnit {
     num_rows: u32,
     encoding: Encoding,
     compression: fb::CompressionType,
+    mini_encunit_len: u32,
+    mini_encunit_sizes: Vec<u32>,
+    encryption: Option<AesGcmParams>,
+    checksum: Option<u64>,
 }
 
 // impl From<&fb::EncBlock<'_>> for EncBlock {
@@ -444,8 +566,35 @@ impl EncUnit {
             num_rows,
             encoding,
             compression,
+            mini_encunit_len: 0,
+            mini_encunit_sizes: vec![],
+            encryption: None,
+            checksum: None,
         }
     }
+
+    /// Record that the EncUnit is split into mini EncUnits of `mini_encunit_len` rows.
+    pub fn with_mini_encunits(
+        mut self,
+        mini_encunit_len: u32,
+        mini_encunit_sizes: Vec<u32>,
+    ) -> Self {
+        self.mini_encunit_len = mini_encunit_len;
+        self.mini_encunit_sizes = mini_encunit_sizes;
+        self
+    }
+
+    /// Record the nonce and tag of the encrypted EncUnit.
+    pub(crate) fn with_encryption(mut self, encryption: Option<AesGcmParams>) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Record the checksum of the EncUnit as written to the file.
+    pub fn with_checksum(mut self, checksum: Option<u64>) -> Self {
+        self.checksum = checksum;
+        self
+    }
 }
 
 impl ToFlatBuffer for EncUnit {
@@ -453,6 +602,9 @@ impl ToFlatBuffer for EncUnit {
 
     fn to_fb<'fb>(&self, fbb: &mut FlatBufferBuilder<'fb>) -> WIPOffset<Self::Target<'fb>> {
         let encoding = self.encoding.to_fb(fbb);
+        let mini_encunit_sizes = (!self.mini_encunit_sizes.is_empty())
+            .then(|| fbb.create_vector(&self.mini_encunit_sizes));
+        let encryption = self.encryption.as_ref().map(|params| params.to_fb(fbb));
         fb::EncUnit::create(
             fbb,
             &fb::EncUnitArgs {
@@ -460,6 +612,10 @@ impl ToFlatBuffer for EncUnit {
                 num_rows: self.num_rows,
                 encoding: Some(encoding),
                 compression: self.compression,
+                mini_encunit_len: self.mini_encunit_len,
+                mini_encunit_sizes,
+                encryption,
+                checksum: self.checksum,
             },
         )
     }
@@ -499,16 +655,46 @@ impl ToFlatBuffer for MetadataSection {
     }
 }
 
+/// Look up an optional metadata section by its name.
+pub(crate) fn find_optional_section(
+    sections: &fb::OptionalMetadataSections,
+    name: &str,
+) -> Result<Option<MetadataSection>> {
+    let names = sections
+        .names()
+        .ok_or_else(|| Error::ParseError("Optional section names not found".to_string()))?;
+    let Some(pos) = names.iter().position(|v| v == name) else {
+        return Ok(None);
+    };
+    let offsets = sections
+        .offsets()
+        .ok_or_else(|| Error::ParseError("Optional section offsets not found".to_string()))?;
+    let sizes = sections
+        .sizes()
+        .ok_or_else(|| Error::ParseError("Optional section sizes not found".to_string()))?;
+    let compression_types = sections.compression_types().ok_or_else(|| {
+        Error::ParseError("Optional section compression types not found".to_string())
+    })?;
+    Ok(Some(MetadataSection {
+        offset: offsets.get(pos),
+        size: sizes.get(pos),
+        compression_type: compression_types.get(pos),
+    }))
+}
+
 /// Row group metadata storing indirect column metadata sections for writer.
 /// Reader should use [RowGroupMetadataFBS](fff_format::File::fff::flatbuf::RowGroupMetadata) directly.
 #[derive(Default)]
 pub struct IndirectRowGroupMetadata {
     col_metadatas: Vec<MetadataSection>,
+    /// Checksum of each ColumnMetadata, empty if not computed.
+    col_metadata_checksums: Vec<u64>,
 }
 
 impl IndirectRowGroupMetadata {
-    pub fn add_col_meta(&mut self, col_meta: MetadataSection) {
+    pub fn add_col_meta(&mut self, col_meta: MetadataSection, checksum: u64) {
         self.col_metadatas.push(col_meta);
+        self.col_metadata_checksums.push(checksum);
     }
 }
 
@@ -539,6 +725,11 @@ impl From<&fb::RowGroupMetadata<'_>> for IndirectRowGroupMetadata {
                 .flatten()
                 .map(|x| MetadataSection::from(&x))
                 .collect(),
+            col_metadata_checksums: row_group_metadata
+                .col_metadata_checksums()
+                .into_iter()
+                .flatten()
+                .collect(),
         }
     }
 }
@@ -547,6 +738,7 @@ impl IndirectRowGroupMetadata {
     pub fn new(metadata_sec: Vec<MetadataSection>) -> Self {
         Self {
             col_metadatas: metadata_sec,
+            col_metadata_checksums: vec![],
         }
     }
 }
@@ -561,10 +753,13 @@ impl ToFlatBuffer for IndirectRowGroupMetadata {
             .map(|x: &MetadataSection| x.to_fb(fbb))
             .collect::<Vec<_>>();
         let col_metadatas = fbb.create_vector(&col_metadatas);
+        let col_metadata_checksums = (!self.col_metadata_checksums.is_empty())
+            .then(|| fbb.create_vector(&self.col_metadata_checksums));
         fb::RowGroupMetadata::create(
             fbb,
             &fb::RowGroupMetadataArgs {
                 col_metadatas: Some(col_metadatas),
+                col_metadata_checksums,
             },
         )
     }
@@ -612,28 +807,45 @@ impl RowGroupsTable {
 
     /// Write ColumnMetadata as FBS to file and update indirect_row_group_metadata
     /// Returns the start offset of the very first ColumnMetadata
-    pub fn to_indirect_and_flush<W: Write + Seek>(
+    /// ColumnMetadata of physical columns with a cipher in `column_ciphers` is encrypted.
+    /// Each ColumnMetadata is also checksummed with `checksum_type` so readers can verify it on open.
+    pub fn to_indirect_and_flush<W: Write>(
         &mut self,
-        writer: &mut W,
+        writer: &mut PositionedWriter<W>,
         checksum: &mut dyn Checksum,
+        checksum_type: ChecksumType,
+        column_ciphers: &[Option<Arc<AesGcmCipher>>],
     ) -> Result<u64> {
-        let start_offset = writer.stream_position()?;
+        let start_offset = writer.position();
         for row_group in &self.row_group_metadata {
             let mut indirect_row_group_metadata = IndirectRowGroupMetadata::default();
-            for col_meta in row_group.col_metadatas() {
+            for (col_meta, cipher) in row_group.col_metadatas().iter().zip(column_ciphers) {
                 let mut fbb = FlatBufferBuilder::new();
                 let fbs = col_meta.to_fb(&mut fbb);
                 fbb.finish(fbs, None);
-                let data = fbb.finished_data();
-                let offset = writer.stream_position()?;
+                let offset = writer.position();
+                let encrypted;
+                let data = match cipher {
+                    Some(cipher) => {
+                        encrypted = cipher.encrypt(
+                            fbb.finished_data(),
+                            &module_aad(ModuleType::ColumnMetadata, offset),
+                        )?;
+                        &encrypted
+                    }
+                    None => fbb.finished_data(),
+                };
                 writer.write_all(data)?;
                 checksum.update(data);
                 let size = data.len() as u32;
-                indirect_row_group_metadata.add_col_meta(MetadataSection {
-                    offset,
-                    size,
-                    compression_type: fb::CompressionType::Uncompressed,
-                });
+                indirect_row_group_metadata.add_col_meta(
+                    MetadataSection {
+                        offset,
+                        size,
+                        compression_type: fb::CompressionType::Uncompressed,
+                    },
+                    checksum_of(&checksum_type, data),
+                );
             }
             self.indirect_row_group_metadata
                 .push(indirect_row_group_metadata);
@@ -696,10 +908,20 @@ impl<'a> Footer<'a> {
     /// This function reads the whole footer from the file, without column projection.
     /// buf is the preallocated buffer according to postscript
     pub fn try_new(buf: &'a [u8], file_size: usize, post_script: &PostScript) -> Result<Self> {
+        if post_script.encrypted_footer {
+            return Err(Error::NYI(
+                "Encrypted files can only be read by FileReaderV2".to_string(),
+            ));
+        }
         let data_size = file_size - POSTSCRIPT_SIZE as usize - post_script.metadata_size as usize;
         let footer_fbs =
             root_as_footer(&buf[(post_script.metadata_size - post_script.footer_size) as usize..])
                 .map_err(|e| Error::ParseError(format!("Unable to get root as footer: {e:?}")))?;
+        if footer_fbs.encryption().is_some() {
+            return Err(Error::NYI(
+                "Encrypted files can only be read by FileReaderV2".to_string(),
+            ));
+        }
         // FIXME: use logical tree to know which logical encoding to use.
         let (schema, _logical_tree, row_groups_pointer, _shared_dict, _, _) =
             parse_footer(&footer_fbs)?;
@@ -798,18 +1020,10 @@ pub fn parse_footer<'a>(
 
     // Parse encoding versions if present
     let encoding_versions = footer_fbs.encoding_versions().map(|versions| {
-        let mut map = HashMap::new();
-        for version in versions.iter() {
-            map.insert(
-                version.encoding_type(),
-                Version::new(
-                    version.version().major(),
-                    version.version().minor(),
-                    version.version().patch(),
-                ),
-            );
-        }
-        map
+        versions
+            .iter()
+            .map(|version| (version.encoding_type(), version_from_fb(version.version())))
+            .collect::<HashMap<_, _>>()
     });
 
     Ok((
@@ -1 +1,2 @@
 pub mod reader;
+pub mod writer;
@@ -6,12 +6,13 @@ use object_store::path::Path;
 use object_store::ObjectStore;
 use parquet::file::reader::{ChunkReader, Length};
 use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, OnceLock};
 use std::{fs::File, os::unix::fs::FileExt};
 use tracing::{debug, error, instrument};
 
 lazy_static! {
-    static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Runtime::new()
+    pub(crate) static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Runtime::new()
         .expect("Failed to create tokio runtime for object store operations. This is a critical initialization failure.");
 }
 
@@ -76,6 +77,40 @@ impl Reader for [u8] {
     }
 }
 
+/// Reader counting the bytes read through it, e.g., to check how much of a file a read fetches.
+/// Clones share the count.
+#[derive(Clone)]
+pub struct CountingReader<R> {
+    inner: R,
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            bytes_read: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of bytes read so far, by this reader and its clones.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+}
+
+impl<R: Reader> Reader for CountingReader<R> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        self.bytes_read
+            .fetch_add(buf.len() as u64, Ordering::Relaxed);
+        self.inner.read_exact_at(buf, offset)
+    }
+
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
 #[derive(Clone)]
 pub struct ObjectStoreReadAt {
     object_store: Arc<dyn ObjectStore>,
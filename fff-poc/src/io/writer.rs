@@ -0,0 +1,229 @@
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use fff_core::errors::{Error, Result};
+use futures::executor::block_on;
+use object_store::path::Path;
+use object_store::{MultipartUpload, ObjectStore};
+use tokio::task::JoinSet;
+use tracing::{debug, instrument, warn};
+
+use super::reader::RUNTIME;
+use crate::counter::EncodingCounter;
+use crate::options::FileWriterOptions;
+use crate::writer::FileWriter;
+
+/// S3 rejects multipart parts smaller than 5 MiB, except for the last one.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// Maximum number of parts being uploaded concurrently.
+const MAX_CONCURRENT_PARTS: usize = 8;
+
+/// Buffered writer that keeps track of the number of bytes written,
+/// so that the file writer does not need the sink to be `Seek`.
+pub struct PositionedWriter<W: Write> {
+    inner: BufWriter<W>,
+    position: u64,
+}
+
+impl<W: Write> PositionedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner: BufWriter::new(inner),
+            position: 0,
+        }
+    }
+
+    /// Number of bytes written so far, i.e., the offset of the next write.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Flush the buffer and return the underlying sink.
+    pub fn into_inner(self) -> Result<W> {
+        self.inner.into_inner().map_err(|e| e.into_error().into())
+    }
+}
+
+impl<W: Write> Write for PositionedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Write` sink that streams the bytes to an object store as multipart upload parts.
+///
+/// The upload is only made visible by [`MultipartSink::complete`];
+/// dropping the sink before that, or a failure in `complete`, aborts the upload.
+pub struct MultipartSink {
+    upload: Option<Box<dyn MultipartUpload>>,
+    /// Bytes not yet sent as a part.
+    buffer: Vec<u8>,
+    part_size: usize,
+    /// Parts being uploaded.
+    tasks: JoinSet<object_store::Result<()>>,
+    location: Path,
+}
+
+impl MultipartSink {
+    pub fn try_new(
+        object_store: Arc<dyn ObjectStore>,
+        location: Path,
+        part_size: usize,
+    ) -> Result<Self> {
+        let store_location = location.clone();
+        let upload = block_on(async move {
+            RUNTIME
+                .spawn(async move { object_store.put_multipart(&store_location).await })
+                .await
+        })
+        .map_err(|e| Error::General(format!("Task join error: {}", e)))?
+        .map_err(Error::ObjectStore)?;
+        let part_size = part_size.max(MIN_PART_SIZE);
+        Ok(Self {
+            upload: Some(upload),
+            buffer: Vec::with_capacity(part_size),
+            part_size,
+            tasks: JoinSet::new(),
+            location,
+        })
+    }
+
+    fn upload(&mut self) -> Result<&mut Box<dyn MultipartUpload>> {
+        self.upload
+            .as_mut()
+            .ok_or_else(|| Error::General("Multipart upload is already completed".to_owned()))
+    }
+
+    /// Start uploading `part`, waiting for a slot if too many parts are in flight.
+    fn put_part(&mut self, part: Vec<u8>) -> Result<()> {
+        // Apply backpressure instead of buffering the whole file in memory.
+        while self.tasks.len() >= MAX_CONCURRENT_PARTS {
+            self.join_next_part()?;
+        }
+        let upload = self.upload()?.put_part(part.into());
+        // The part uploads are spawned onto the IO runtime.
+        let _guard = RUNTIME.enter();
+        self.tasks.spawn(upload);
+        Ok(())
+    }
+
+    /// Wait for one in-flight part upload to finish, if any.
+    fn join_next_part(&mut self) -> Result<()> {
+        let _guard = RUNTIME.enter();
+        if let Some(res) = block_on(self.tasks.join_next()) {
+            res.map_err(|e| Error::General(format!("Task join error: {}", e)))?
+                .map_err(Error::ObjectStore)?;
+        }
+        Ok(())
+    }
+
+    /// Upload the remaining parts and complete the upload.
+    fn finish_parts(&mut self) -> Result<()> {
+        if !self.buffer.is_empty() {
+            let part = std::mem::take(&mut self.buffer);
+            self.put_part(part)?;
+        }
+        while !self.tasks.is_empty() {
+            self.join_next_part()?;
+        }
+        let upload = self.upload()?;
+        let _guard = RUNTIME.enter();
+        block_on(upload.complete()).map_err(Error::ObjectStore)?;
+        Ok(())
+    }
+
+    /// Upload the remaining bytes and complete the upload atomically.
+    ///
+    /// If any part, or the completion itself, fails, the upload is aborted.
+    #[instrument(skip(self), fields(location = %self.location))]
+    pub fn complete(mut self) -> Result<()> {
+        self.upload()?;
+        if let Err(e) = self.finish_parts() {
+            self.abort();
+            return Err(e);
+        }
+        self.upload = None;
+        debug!("Multipart upload completed");
+        Ok(())
+    }
+
+    /// Cancel the in-flight parts and abort the upload, if it is not finished yet.
+    fn abort(&mut self) {
+        if let Some(mut upload) = self.upload.take() {
+            warn!(location = %self.location, "Aborting unfinished multipart upload");
+            let _guard = RUNTIME.enter();
+            block_on(self.tasks.shutdown());
+            if let Err(e) = block_on(upload.abort()) {
+                warn!(error = %e, "Failed to abort multipart upload");
+            }
+        }
+    }
+}
+
+impl Write for MultipartSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.upload()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= self.part_size {
+            let rest = self.buffer.split_off(self.part_size);
+            let part = std::mem::replace(&mut self.buffer, rest);
+            self.put_part(part)
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // Parts are uploaded once they reach the part size, so there is nothing to flush.
+        self.upload()
+            .map(|_| ())
+            .map_err(|e| std::io::Error::other(e.to_string()))
+    }
+}
+
+impl Drop for MultipartSink {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
+
+/// Writes a file directly to an object store, one IOUnit-sized part at a time.
+///
+/// The object only becomes visible after a successful [`ObjectStoreWriter::finish`].
+/// Dropping the writer, or an error in `finish`, aborts the upload.
+pub struct ObjectStoreWriter {
+    inner: FileWriter<MultipartSink>,
+}
+
+impl ObjectStoreWriter {
+    pub fn try_new(
+        schema: SchemaRef,
+        object_store: Arc<dyn ObjectStore>,
+        location: Path,
+        options: FileWriterOptions,
+    ) -> Result<Self> {
+        let sink = MultipartSink::try_new(object_store, location, options.iounit_size() as usize)?;
+        Ok(Self {
+            inner: FileWriter::try_new(schema, sink, options)?,
+        })
+    }
+
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.inner.write_batch(batch)
+    }
+
+    pub fn finish(self) -> Result<Vec<EncodingCounter>> {
+        let (counters, sink) = self.inner.finish_into_inner()?;
+        sink.complete()?;
+        Ok(counters)
+    }
+}
@@ -1,6 +1,7 @@
 #![feature(new_range_api)]
 use mimalloc::MiMalloc;
 
+pub mod bloom_filter;
 pub mod common;
 mod compression;
 pub mod counter;
@@ -8,12 +9,14 @@ pub mod file;
 pub mod io;
 pub mod options;
 pub mod reader;
+pub mod registry;
 pub mod writer;
 
 pub mod context;
 pub mod decoder;
 mod dict;
 pub(crate) mod encoder;
+pub mod encryption;
 
 /// Initialize tracing subscriber for structured logging.
 ///
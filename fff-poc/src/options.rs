@@ -1,12 +1,16 @@
 use std::collections::HashMap;
 
 use arrow_schema::DataType;
+use fff_core::{errors::Result, general_error};
 use fff_format::File::fff::flatbuf::CompressionType;
 
+pub use crate::compression::BlockCompression;
 pub use crate::dict::DictionaryTypeOptions;
 use crate::{
+    bloom_filter::BloomFilterOptions,
     common::checksum::ChecksumType,
     context::{WASMId, WASMWritingContext, WasmLib},
+    encryption::FileEncryptionOptions,
 };
 
 pub const DEFAULT_IOUNIT_SIZE: u64 = 8 * 1024 * 1024; // in bytes
@@ -17,9 +21,11 @@ pub const DEFAULT_CHECKSUM_TYPE: ChecksumType = ChecksumType::XxHash;
 pub struct FileWriterOptions {
     /// The size of an IOUnit in bytes. 8MB by default.
     iounit_size: u64,
-    /// The length of an encoding unit in dictionary. 64Ki rows by default.
+    /// The length of an encoding unit, also the scope of a shared dictionary. 64Ki rows by default.
     encoding_unit_len: u64,
-    /// The type of the checksum for data and schema. xxhash by defalt.
+    /// The target size of an encoding unit in bytes. Unset by default.
+    encoding_unit_size: Option<u64>,
+    /// The type of the checksum for data, schema, metadata, IOUnits and EncUnits. xxhash by defalt.
     checksum_type: ChecksumType,
     /// Always set the encoding of EncUnit metadata tobe CUSTOM_WASM. Write built-in Wasm to the file.
     /// In the meantime, disallow extension Wasms.
@@ -28,6 +34,8 @@ pub struct FileWriterOptions {
     custom_encunit_len: HashMap<usize, usize>,
     /// The size of a row group in number of rows. Infinite by default.
     row_group_size: u64,
+    /// The maximum size of a row group in bytes. Infinite by default.
+    max_row_group_bytes: u64,
     /// Custom encoding options, include the encoder dylib and decoder wasm lib
     /// FIXME: cannot be used together with write_built_in_wasm
     custom_encoding_options: CustomEncodingOptions,
@@ -35,8 +43,28 @@ pub struct FileWriterOptions {
     dictionary_type: DictionaryTypeOptions,
     /// Enable per-IOUnit checksum
     enable_io_unit_checksum: bool,
+    /// Enable per-EncUnit checksum, which allows verifying partial reads of an IOUnit.
+    enable_encunit_checksum: bool,
+    /// Record the encoding version in the metadata of each EncUnit, besides once per encoding type
+    /// in the footer.
+    enable_encunit_encoding_versions: bool,
     /// The type of compression to use for EncUnits
     compression_type: CompressionType,
+    /// Level of `compression_type`, the codec default if None
+    compression_level: Option<i32>,
+    /// Store an EncUnit compressed only if compression saves at least this percentage of its size.
+    /// Always compress if None
+    min_compression_savings: Option<u8>,
+    /// Mapping between root-level column id and its Bloom filter options.
+    /// Only non-nested columns are supported.
+    bloom_filter_columns: HashMap<usize, BloomFilterOptions>,
+    /// Split EncUnits of non-nested columns into independently decodable mini EncUnits of this
+    /// many rows (e.g., 1-2Ki), so point accesses only fetch and decode a mini EncUnit. Disabled by default.
+    mini_encunit_len: Option<u64>,
+    /// Column keys and footer key for modular encryption. Nothing is encrypted by default.
+    encryption: FileEncryptionOptions,
+    /// Mapping between column path and its encoding policy.
+    column_policies: HashMap<String, ColumnEncodingPolicy>,
 }
 
 impl Default for FileWriterOptions {
@@ -62,6 +90,10 @@ impl FileWriterOptions {
         self.encoding_unit_len
     }
 
+    pub fn encoding_unit_size(&self) -> Option<u64> {
+        self.encoding_unit_size
+    }
+
     pub fn checksum_type(&self) -> ChecksumType {
         self.checksum_type
     }
@@ -78,6 +110,10 @@ impl FileWriterOptions {
         self.row_group_size
     }
 
+    pub fn max_row_group_bytes(&self) -> u64 {
+        self.max_row_group_bytes
+    }
+
     pub fn custom_encoding_options(&self) -> &CustomEncodingOptions {
         &self.custom_encoding_options
     }
@@ -94,37 +130,124 @@ impl FileWriterOptions {
         self.enable_io_unit_checksum
     }
 
+    pub fn enable_encunit_checksum(&self) -> bool {
+        self.enable_encunit_checksum
+    }
+
+    pub fn enable_encunit_encoding_versions(&self) -> bool {
+        self.enable_encunit_encoding_versions
+    }
+
     pub fn compression_type(&self) -> CompressionType {
         self.compression_type
     }
+
+    pub fn compression_level(&self) -> Option<i32> {
+        self.compression_level
+    }
+
+    pub fn min_compression_savings(&self) -> Option<u8> {
+        self.min_compression_savings
+    }
+
+    pub fn bloom_filter_columns(&self) -> &HashMap<usize, BloomFilterOptions> {
+        &self.bloom_filter_columns
+    }
+
+    pub fn mini_encunit_len(&self) -> Option<u64> {
+        self.mini_encunit_len
+    }
+
+    pub fn encryption(&self) -> &FileEncryptionOptions {
+        &self.encryption
+    }
+
+    pub fn column_policies(&self) -> &HashMap<String, ColumnEncodingPolicy> {
+        &self.column_policies
+    }
+
+    /// Reject invalid values, which the builder accepts as is.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.row_group_size == 0 {
+            return Err(general_error!("row_group_size must be positive"));
+        }
+        let savings = std::iter::once(self.min_compression_savings)
+            .chain(
+                self.column_policies
+                    .values()
+                    .map(|p| p.min_compression_savings),
+            )
+            .flatten();
+        for percent in savings {
+            if percent > 100 {
+                return Err(general_error!(
+                    "min_compression_savings is a percentage",
+                    percent
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 pub struct FileWriterOptionsBuilder {
     /// The size of an IOUnit in bytes. 8MB by default.
     iounit_size: u64,
-    /// The length of an encoding unit in dictionary. 64Ki rows by default.
+    /// The length of an encoding unit, also the scope of a shared dictionary. 64Ki rows by default.
+    /// Rows are buffered per column, so EncUnits have this length regardless of the batch sizes,
+    /// except for the last EncUnit of a row group.
     encoding_unit_len: u64,
-    /// The type of the checksum for data and schema. xxhash by defalt.
+    /// The target size of an encoding unit in bytes. Unset by default.
+    /// EncUnits are cut earlier than `encoding_unit_len` rows once the in-memory size of their rows
+    /// reaches this size, which bounds the encoded size of EncUnits of wide values, e.g., long strings.
+    encoding_unit_size: Option<u64>,
+    /// The type of the checksum for data, schema, metadata, IOUnits and EncUnits. xxhash by defalt.
     checksum_type: ChecksumType,
     /// Always set the encoding of EncUnit metadata to be CUSTOM_WASM. Write built-in Wasm to the file.
     /// In the meantime, disallow extension Wasms.
     write_built_in_wasm: bool,
-    /// Mapping between root-level column id and custom encunit len (num of rows)
-    /// TODO: not correctly implement yet. Check FileWriter::write_batch
+    /// Mapping between root-level column id and custom encunit len (num of rows),
+    /// overriding `encoding_unit_len` for the column.
     custom_encunit_len: HashMap<usize, usize>,
     /// The size of a row group in number of rows. Infinite by default.
-    /// This is a threshold. E.g., if row_group_size is 1000 and we already wrote 900 rows,
-    /// and then we write a batch of 200 rows, the row group will be 1100 rows.
-    /// Check FileWriter::write_batch
+    /// Incoming batches are sliced so that every row group but the last has exactly this many rows.
+    /// E.g., if row_group_size is 1000 and we already wrote 900 rows,
+    /// and then we write a batch of 200 rows, the row group will be 1000 rows and the next starts with 100.
     row_group_size: u64,
+    /// The maximum size of a row group in bytes, counting the bytes already written to the file
+    /// and the bytes buffered in the encoders (`FileWriter::memory_size`). Infinite by default.
+    /// This is checked whenever an EncUnit is encoded, so a row group can exceed it by one EncUnit per column.
+    max_row_group_bytes: u64,
     /// Custom encoding options, include the encoder dylib and decoder wasm lib
     /// FIXME: cannot be used together with write_built_in_wasm
     custom_encoding_options: CustomEncodingOptions,
     dictionary_type: DictionaryTypeOptions,
     /// Enable per-IOUnit checksum
     enable_io_unit_checksum: bool,
+    /// Enable per-EncUnit checksum, which allows verifying partial reads of an IOUnit.
+    enable_encunit_checksum: bool,
+    /// Record the encoding version in the metadata of each EncUnit, besides once per encoding type
+    /// in the footer.
+    enable_encunit_encoding_versions: bool,
     /// The type of compression to use for EncUnits
     compression_type: CompressionType,
+    /// Level of `compression_type`, the codec default if None
+    compression_level: Option<i32>,
+    /// Store an EncUnit compressed only if compression saves at least this percentage of its size.
+    /// Always compress if None
+    min_compression_savings: Option<u8>,
+    /// Mapping between root-level column id and its Bloom filter options.
+    /// Only non-nested columns are supported.
+    bloom_filter_columns: HashMap<usize, BloomFilterOptions>,
+    /// Split EncUnits of non-nested columns into independently decodable mini EncUnits of this
+    /// many rows (e.g., 1-2Ki), so point accesses only fetch and decode a mini EncUnit. Disabled by default.
+    mini_encunit_len: Option<u64>,
+    /// Column keys and footer key for modular encryption. Nothing is encrypted by default.
+    encryption: FileEncryptionOptions,
+    /// Mapping between column path and its encoding policy, overriding the options above for that column.
+    /// A column path is the field name of a root-level column, or the field names from the root joined
+    /// by "." for a nested column, e.g., "address.city", or "tags.item" for the values of a List.
+    column_policies: HashMap<String, ColumnEncodingPolicy>,
 }
 
 impl FileWriterOptionsBuilder {
@@ -133,14 +256,24 @@ impl FileWriterOptionsBuilder {
         Self {
             iounit_size: DEFAULT_IOUNIT_SIZE,
             encoding_unit_len: DEFAULT_ENCODING_UNIT_LEN,
+            encoding_unit_size: None,
             checksum_type: DEFAULT_CHECKSUM_TYPE,
             write_built_in_wasm: false,
             custom_encunit_len: Default::default(),
             row_group_size: u64::MAX, // By default, only one row group per file.
+            max_row_group_bytes: u64::MAX,
             custom_encoding_options: Default::default(),
             dictionary_type: DictionaryTypeOptions::EncoderDictionary,
             enable_io_unit_checksum: false,
+            enable_encunit_checksum: false,
+            enable_encunit_encoding_versions: false,
             compression_type: CompressionType::Uncompressed,
+            compression_level: None,
+            min_compression_savings: None,
+            bloom_filter_columns: Default::default(),
+            mini_encunit_len: None,
+            encryption: Default::default(),
+            column_policies: Default::default(),
         }
     }
 
@@ -151,14 +284,24 @@ impl FileWriterOptionsBuilder {
         FileWriterOptions {
             iounit_size: self.iounit_size,
             encoding_unit_len: self.encoding_unit_len,
+            encoding_unit_size: self.encoding_unit_size,
             checksum_type: self.checksum_type,
             write_built_in_wasm: self.write_built_in_wasm,
             custom_encunit_len: self.custom_encunit_len,
             row_group_size: self.row_group_size,
+            max_row_group_bytes: self.max_row_group_bytes,
             custom_encoding_options: self.custom_encoding_options,
             dictionary_type: self.dictionary_type,
             enable_io_unit_checksum: self.enable_io_unit_checksum,
+            enable_encunit_checksum: self.enable_encunit_checksum,
+            enable_encunit_encoding_versions: self.enable_encunit_encoding_versions,
             compression_type: self.compression_type,
+            compression_level: self.compression_level,
+            min_compression_savings: self.min_compression_savings,
+            bloom_filter_columns: self.bloom_filter_columns,
+            mini_encunit_len: self.mini_encunit_len,
+            encryption: self.encryption,
+            column_policies: self.column_policies,
         }
     }
 
@@ -172,6 +315,11 @@ impl FileWriterOptionsBuilder {
         self
     }
 
+    pub fn set_encoding_unit_size(mut self, encoding_unit_size: Option<u64>) -> Self {
+        self.encoding_unit_size = encoding_unit_size;
+        self
+    }
+
     pub fn set_checksum_type(mut self, checksum_type: ChecksumType) -> Self {
         self.checksum_type = checksum_type;
         self
@@ -192,6 +340,11 @@ impl FileWriterOptionsBuilder {
         self
     }
 
+    pub fn set_max_row_group_bytes(mut self, max_row_group_bytes: u64) -> Self {
+        self.max_row_group_bytes = max_row_group_bytes;
+        self
+    }
+
     pub fn set_custom_encoding_options(
         mut self,
         custom_encoding_options: CustomEncodingOptions,
@@ -210,10 +363,210 @@ impl FileWriterOptionsBuilder {
         self
     }
 
+    pub fn enable_encunit_checksum(mut self, enable_encunit_checksum: bool) -> Self {
+        self.enable_encunit_checksum = enable_encunit_checksum;
+        self
+    }
+
+    pub fn enable_encunit_encoding_versions(mut self, enable: bool) -> Self {
+        self.enable_encunit_encoding_versions = enable;
+        self
+    }
+
     pub fn set_compression_type(mut self, compression_type: CompressionType) -> Self {
         self.compression_type = compression_type;
         self
     }
+
+    pub fn set_compression_level(mut self, compression_level: i32) -> Self {
+        self.compression_level = Some(compression_level);
+        self
+    }
+
+    pub fn set_min_compression_savings(mut self, percent: u8) -> Self {
+        self.min_compression_savings = Some(percent);
+        self
+    }
+
+    pub fn set_bloom_filter_columns(
+        mut self,
+        bloom_filter_columns: HashMap<usize, BloomFilterOptions>,
+    ) -> Self {
+        self.bloom_filter_columns = bloom_filter_columns;
+        self
+    }
+
+    pub fn set_mini_encunit_len(mut self, mini_encunit_len: u64) -> Self {
+        self.mini_encunit_len = Some(mini_encunit_len);
+        self
+    }
+
+    pub fn set_encryption(mut self, encryption: FileEncryptionOptions) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    pub fn set_column_policy(
+        mut self,
+        column_path: impl Into<String>,
+        policy: ColumnEncodingPolicy,
+    ) -> Self {
+        self.column_policies.insert(column_path.into(), policy);
+        self
+    }
+}
+
+/// Which encoder a column uses for its EncUnits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CustomEncoderSelection {
+    /// The custom encoding registered with this id in [`CustomEncodingOptions`].
+    Wasm(WASMId),
+    /// The latest version of the native encoding registered with this id in the [`crate::registry`].
+    Plugin(String),
+    /// The built-in encoding, even if a custom encoding is registered for the data type.
+    BuiltIn,
+}
+
+/// Encoding choices of a column. Unset choices fall back to the policy of the parent column,
+/// and then to the file-wide options.
+/// EncUnit length only applies to root-level columns, and the encoder selection is not inherited
+/// by nested columns, since their data types differ.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnEncodingPolicy {
+    dictionary_type: Option<DictionaryTypeOptions>,
+    compression_type: Option<CompressionType>,
+    /// Only applies to `compression_type` of the same policy if set, since levels are codec specific.
+    compression_level: Option<i32>,
+    min_compression_savings: Option<u8>,
+    /// Ids of the Vortex compressors not to try, e.g., "vortex.dict".
+    excluded_vortex_compressors: Option<Vec<String>>,
+    encoding_unit_len: Option<u64>,
+    encoder: Option<CustomEncoderSelection>,
+}
+
+impl ColumnEncodingPolicy {
+    pub fn with_dictionary_type(mut self, dictionary_type: DictionaryTypeOptions) -> Self {
+        self.dictionary_type = Some(dictionary_type);
+        self
+    }
+
+    pub fn with_compression_type(mut self, compression_type: CompressionType) -> Self {
+        self.compression_type = Some(compression_type);
+        self
+    }
+
+    pub fn with_compression_level(mut self, compression_level: i32) -> Self {
+        self.compression_level = Some(compression_level);
+        self
+    }
+
+    pub fn with_min_compression_savings(mut self, percent: u8) -> Self {
+        self.min_compression_savings = Some(percent);
+        self
+    }
+
+    pub fn with_excluded_vortex_compressors<S: Into<String>>(
+        mut self,
+        compressors: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.excluded_vortex_compressors = Some(compressors.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_encoding_unit_len(mut self, encoding_unit_len: u64) -> Self {
+        self.encoding_unit_len = Some(encoding_unit_len);
+        self
+    }
+
+    pub fn with_encoder(mut self, encoder: CustomEncoderSelection) -> Self {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    pub fn dictionary_type(&self) -> Option<DictionaryTypeOptions> {
+        self.dictionary_type
+    }
+
+    pub fn compression_type(&self) -> Option<CompressionType> {
+        self.compression_type
+    }
+
+    pub fn compression_level(&self) -> Option<i32> {
+        self.compression_level
+    }
+
+    pub fn min_compression_savings(&self) -> Option<u8> {
+        self.min_compression_savings
+    }
+
+    pub fn excluded_vortex_compressors(&self) -> Option<&[String]> {
+        self.excluded_vortex_compressors.as_deref()
+    }
+
+    pub fn encoding_unit_len(&self) -> Option<u64> {
+        self.encoding_unit_len
+    }
+
+    pub fn encoder(&self) -> Option<&CustomEncoderSelection> {
+        self.encoder.as_ref()
+    }
+}
+
+/// Encoding choices of a physical column, resolved from the file-wide options
+/// and the [`ColumnEncodingPolicy`]s along its column path.
+#[derive(Clone, Debug)]
+pub(crate) struct ColumnEncodingConfig {
+    pub(crate) dictionary_type: DictionaryTypeOptions,
+    pub(crate) compression: BlockCompression,
+    pub(crate) excluded_vortex_compressors: Vec<String>,
+    pub(crate) encoder: Option<CustomEncoderSelection>,
+    pub(crate) mini_encunit_len: Option<u64>,
+}
+
+impl ColumnEncodingConfig {
+    pub(crate) fn new(options: &FileWriterOptions) -> Self {
+        Self {
+            dictionary_type: options.dictionary_type(),
+            compression: BlockCompression::new(options.compression_type())
+                .with_level(options.compression_level())
+                .with_min_savings_percent(options.min_compression_savings()),
+            excluded_vortex_compressors: vec![],
+            encoder: None,
+            mini_encunit_len: options.mini_encunit_len(),
+        }
+    }
+
+    /// The config of a child column, or of a root-level column if `self` is the file-wide config.
+    pub(crate) fn child(&self, policy: Option<&ColumnEncodingPolicy>) -> Self {
+        let Some(policy) = policy else {
+            return Self {
+                encoder: None,
+                ..self.clone()
+            };
+        };
+        Self {
+            dictionary_type: policy.dictionary_type.unwrap_or(self.dictionary_type),
+            compression: match policy.compression_type {
+                Some(compression_type) => {
+                    BlockCompression::new(compression_type).with_level(policy.compression_level)
+                }
+                None => self
+                    .compression
+                    .with_level(policy.compression_level.or(self.compression.level())),
+            }
+            .with_min_savings_percent(
+                policy
+                    .min_compression_savings
+                    .or(self.compression.min_savings_percent()),
+            ),
+            excluded_vortex_compressors: policy
+                .excluded_vortex_compressors
+                .clone()
+                .unwrap_or_else(|| self.excluded_vortex_compressors.clone()),
+            encoder: policy.encoder.clone(),
+            mini_encunit_len: self.mini_encunit_len,
+        }
+    }
 }
 
 #[derive(Clone, Default)]
@@ -1,19 +1,29 @@
 use crate::{
-    common::checksum::{create_checksum, ChecksumType},
+    bloom_filter::{
+        prune_row_groups, BloomFilterPredicate, RowGroupMatch, BLOOM_FILTERS_SECTION_NAME,
+    },
+    common::checksum::{checksum_of, create_checksum, ChecksumType},
     context::{WASMId, WASMReadingContext},
+    decoder::policy::DecoderPolicy,
     dict::shared_dictionary_cache::SharedDictionaryCache,
-    file::footer::{parse_footer, MetadataSection},
+    encryption::{decrypt_footer, module_aad, FileDecryptor, KeyRetriever, ModuleType},
+    file::footer::{find_optional_section, parse_footer, MetadataSection},
     io::reader::Reader,
     options::DEFAULT_IOUNIT_SIZE,
-    reader::{read_postscript, RowGroupCntNPointer},
+    reader::{read_postscript, RowGroupCntNPointer, RowsToRead},
 };
 use arrow_buffer::MutableBuffer;
+use arrow_schema::{DataType, Schema};
 use bytes::Bytes;
-use fff_core::errors::{Error, Result};
-use fff_format::File::fff::flatbuf::root_as_footer;
+use fff_core::{
+    errors::{Error, Result},
+    non_nest_types,
+};
+use fff_format::File::fff::flatbuf::{self as fb, root_as_footer};
 use fff_format::POSTSCRIPT_SIZE;
 use fff_ude_wasm::Runtime;
-use std::{collections::HashMap, sync::Arc};
+use semver::Version;
+use std::{collections::HashMap, ops::Range, sync::Arc};
 
 use crate::reader::{FileReaderV2, Projection, Selection};
 
@@ -28,6 +38,18 @@ pub struct FileReaderV2Builder<R: Reader + Clone> {
     verify_io_unit_checksum: bool,
     /// Whether we verify the file checksum.
     verify_file_checksum: bool,
+    /// Whether we verify the schema and column metadata checksums. Enabled by default.
+    verify_metadata_checksum: bool,
+    /// Skip the row groups and IOUnits whose Bloom filters rule out the predicate.
+    bloom_filter_predicate: Option<BloomFilterPredicate>,
+    /// Provides the keys of encrypted columns and of an encrypted footer.
+    key_retriever: Option<Arc<dyn KeyRetriever>>,
+    /// Whether EncUnits are decoded natively or with the Wasm decoders in the file.
+    decoder_policy: DecoderPolicy,
+    /// Bound on the memory of the decoded shared dictionaries, None for no bound.
+    max_shared_dictionary_memory: Option<usize>,
+    /// Key type of the DictionaryArrays of dictionary encoded columns, None to decode their values.
+    dictionary_key_type: Option<DataType>,
 }
 
 impl<R: Reader + Clone> FileReaderV2Builder<R> {
@@ -40,6 +62,12 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
             wasm_rts: None,
             verify_io_unit_checksum: false,
             verify_file_checksum: false,
+            verify_metadata_checksum: true,
+            bloom_filter_predicate: None,
+            key_retriever: None,
+            decoder_policy: DecoderPolicy::default(),
+            max_shared_dictionary_memory: None,
+            dictionary_key_type: None,
         }
     }
 
@@ -83,6 +111,56 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
         self
     }
 
+    /// Whether we verify the schema checksum in the postscript and the checksum of each column metadata read.
+    /// Files written without column metadata checksums are only checked for the schema.
+    pub fn with_verify_metadata_checksum(mut self, verify_metadata_checksum: bool) -> Self {
+        self.verify_metadata_checksum = verify_metadata_checksum;
+        self
+    }
+
+    /// Skip the row groups that can not contain any value of the predicate, according to the Bloom filters in the file.
+    /// With per-IOUnit filters, the rows of the IOUnits that can not contain any value are skipped too,
+    /// and the IOUnits of the other columns only covering those rows are not fetched.
+    /// Row groups are kept as is if the column has no Bloom filter.
+    pub fn with_bloom_filter_predicate(mut self, predicate: BloomFilterPredicate) -> Self {
+        self.bloom_filter_predicate = Some(predicate);
+        self
+    }
+
+    /// Retrieve the keys to decrypt encrypted columns and footer.
+    /// Without it, only files with a plaintext footer can be opened, and encrypted columns can not be projected.
+    pub fn with_key_retriever(mut self, key_retriever: Arc<dyn KeyRetriever>) -> Self {
+        self.key_retriever = Some(key_retriever);
+        self
+    }
+
+    /// Choose between the native decoders and the Wasm decoders carried in the file,
+    /// e.g., to check with [`DecoderPolicy::Verify`] that the Wasm decoders reproduce the native results.
+    pub fn with_decoder_policy(mut self, decoder_policy: DecoderPolicy) -> Self {
+        self.decoder_policy = decoder_policy;
+        self
+    }
+
+    /// Evict the least recently used shared dictionaries once the decoded ones take more than
+    /// `max_memory` bytes. Evicted dictionaries are read and decoded again when referenced.
+    pub fn with_max_shared_dictionary_memory(mut self, max_memory: usize) -> Self {
+        self.max_shared_dictionary_memory = Some(max_memory);
+        self
+    }
+
+    /// Return the non-nested columns whose chunks all use local or shared dictionaries as
+    /// DictionaryArrays with `key_type` keys, instead of copying the dictionary values of every row.
+    /// The arrays of chunks referencing the same shared dictionary share its values.
+    pub fn with_dictionary_arrays(mut self, key_type: DataType) -> Result<Self> {
+        if !key_type.is_dictionary_key_type() {
+            return Err(Error::General(format!(
+                "{key_type} is not a dictionary key type"
+            )));
+        }
+        self.dictionary_key_type = Some(key_type);
+        Ok(self)
+    }
+
     fn verify_file_checksum(
         &self,
         file_size: u64,
@@ -132,7 +210,8 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
             )?;
         }
         let mut footer_buffer = MutableBuffer::from_len_zeroed(post_script.footer_size as usize);
-        let footer_fbs = if self.read_ahead {
+        let footer_offset = file_size - POSTSCRIPT_SIZE - post_script.footer_size as u64;
+        let footer_bytes = if self.read_ahead {
             if post_script.footer_size >= (DEFAULT_IOUNIT_SIZE - 32) as u32 {
                 return Err(Error::General(format!(
                     "Footer size {} exceeds read-ahead buffer capacity (max {})",
@@ -140,21 +219,25 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
                     (DEFAULT_IOUNIT_SIZE - 32) as u32
                 )));
             }
-            root_as_footer(
-                &read_ahead_buffer.as_slice()[read_ahead_buffer.len()
-                    - POSTSCRIPT_SIZE as usize
-                    - post_script.footer_size as usize
-                    ..read_ahead_buffer.len() - POSTSCRIPT_SIZE as usize],
-            )
-            .map_err(|e| Error::ParseError(format!("Unable to get root as footer: {e:?}")))?
+            &read_ahead_buffer.as_slice()[read_ahead_buffer.len()
+                - POSTSCRIPT_SIZE as usize
+                - post_script.footer_size as usize
+                ..read_ahead_buffer.len() - POSTSCRIPT_SIZE as usize]
         } else {
-            self.reader.read_exact_at(
-                footer_buffer.as_slice_mut(),
-                file_size - POSTSCRIPT_SIZE - post_script.footer_size as u64,
-            )?;
-            root_as_footer(&footer_buffer)
-                .map_err(|e| Error::ParseError(format!("Unable to get root as footer: {e:?}")))?
+            self.reader
+                .read_exact_at(footer_buffer.as_slice_mut(), footer_offset)?;
+            footer_buffer.as_slice()
         };
+        let decrypted_footer;
+        let footer_bytes = if post_script.encrypted_footer {
+            decrypted_footer =
+                decrypt_footer(footer_bytes, self.key_retriever.as_deref(), footer_offset)?;
+            decrypted_footer.as_slice()
+        } else {
+            footer_bytes
+        };
+        let footer_fbs = root_as_footer(footer_bytes)
+            .map_err(|e| Error::ParseError(format!("Unable to get root as footer: {e:?}")))?;
         // FIXME: use logical tree to know which logical encoding to use.
         let (
             schema,
@@ -164,6 +247,19 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
             optional_sections,
             encoding_versions,
         ) = parse_footer(&footer_fbs)?;
+        // The schema checksum is zeroed if the footer is encrypted.
+        if self.verify_metadata_checksum && !post_script.encrypted_footer {
+            let schema_bytes = footer_fbs
+                .schema()
+                .ok_or_else(|| Error::ParseError("Schema not found".to_string()))?;
+            if post_script.schema_checksum
+                != checksum_of(&post_script.checksum_type, schema_bytes.bytes())
+            {
+                return Err(Error::General(
+                    "Schema checksum verification failed".to_string(),
+                ));
+            }
+        }
         // Depending on the ratio between number of projected columns and total columns,
         // we fetch them all or do one by one fetch.
         let rg_metadatas = row_groups_pointer.row_group_metadatas().ok_or_else(|| {
@@ -185,7 +281,7 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
         let sizes = row_groups_pointer.sizes().ok_or_else(|| {
             Error::ParseError("Sizes not found in row groups pointer".to_string())
         })?;
-        let row_group_cnt_n_pointers =
+        let row_group_cnt_n_pointers: Vec<RowGroupCntNPointer> =
             itertools::izip!(row_counts.iter(), offsets.iter(), sizes.iter())
                 .map(|(row_count, offset, size)| RowGroupCntNPointer {
                     row_count,
@@ -193,6 +289,21 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
                     _size: size,
                 })
                 .collect();
+        let row_group_matches = match (&self.bloom_filter_predicate, optional_sections) {
+            (Some(predicate), Some(sections)) => {
+                match find_optional_section(&sections, BLOOM_FILTERS_SECTION_NAME)? {
+                    Some(section) => prune_row_groups(
+                        &self.reader,
+                        &section,
+                        &schema,
+                        predicate,
+                        row_group_cnt_n_pointers.len(),
+                    )?,
+                    None => None,
+                }
+            }
+            _ => None,
+        };
         let ratio = match &self.projections {
             Projection::All => 1.0,
             Projection::LeafColumnIndexes(projections) => {
@@ -223,98 +334,124 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
         let row_group_metadata_fbs = row_groups_pointer
             .row_group_metadatas()
             .ok_or_else(|| Error::ParseError("Row group metadatas not found".to_string()))?;
+        // Keys are only retrieved for the encrypted columns being read.
+        let mut file_decryptor =
+            FileDecryptor::try_new(footer_fbs.encryption(), self.key_retriever.clone())?;
+        let projected_columns = match &self.projections {
+            Projection::All => (0..total_columns).collect::<Vec<_>>(),
+            Projection::LeafColumnIndexes(projections) => projections.clone(),
+        };
+        let column_ciphers = projected_columns
+            .iter()
+            .map(|column_idx| file_decryptor.column_cipher(*column_idx))
+            .collect::<Result<Vec<_>>>()?;
+        let read_column_meta_buffer = |column_meta_pointer: &fb::MetadataSection| -> Result<Bytes> {
+            Ok(match all_metadata_buffer {
+                None => {
+                    // read each column meta one by one
+                    let column_meta_size = column_meta_pointer.size_() as usize;
+                    let mut column_meta_buffer: Vec<u8> = vec![0; column_meta_size];
+                    self.reader
+                        .read_exact_at(&mut column_meta_buffer, column_meta_pointer.offset())?;
+                    column_meta_buffer.into()
+                }
+                Some(ref buf) => {
+                    // column metas are already read at once
+                    let data_size = file_size as usize
+                        - POSTSCRIPT_SIZE as usize
+                        - post_script.metadata_size as usize;
+                    buf.slice(
+                        column_meta_pointer.offset() as usize - data_size
+                            ..column_meta_pointer.offset() as usize - data_size
+                                + column_meta_pointer.size_() as usize,
+                    )
+                }
+            })
+        };
         let mut grouped_column_metadata_buffers: Vec<Vec<Bytes>> = vec![];
         for rg_meta_fbs in row_group_metadata_fbs.iter() {
             let mut column_metadata_buffers: Vec<Bytes> = vec![];
             let col_metadatas = rg_meta_fbs.col_metadatas().ok_or_else(|| {
                 Error::ParseError("Column metadatas not found in row group".to_string())
             })?;
-            let column_meta_ptrs = match self.projections {
-                Projection::All => col_metadatas.into_iter().collect(),
-                Projection::LeafColumnIndexes(ref projections) => {
-                    let mut column_meta_offsets = vec![];
-                    for i in projections {
-                        column_meta_offsets.push(col_metadatas.get(*i));
-                    }
-                    column_meta_offsets
-                }
-            };
-            for column_meta_pointer in column_meta_ptrs {
-                match all_metadata_buffer {
-                    None => {
-                        // read each column meta one by one
-                        let column_meta_size = column_meta_pointer.size_() as usize;
-                        let mut column_meta_buffer: Vec<u8> = vec![0; column_meta_size];
-                        self.reader
-                            .read_exact_at(&mut column_meta_buffer, column_meta_pointer.offset())?;
-                        column_metadata_buffers.push(column_meta_buffer.into());
-                    }
-                    Some(ref buf) => {
-                        // column metas are already read at once
-                        let data_size = file_size as usize
-                            - POSTSCRIPT_SIZE as usize
-                            - post_script.metadata_size as usize;
-                        column_metadata_buffers.push(buf.slice(
-                            column_meta_pointer.offset() as usize - data_size
-                                ..column_meta_pointer.offset() as usize - data_size
-                                    + column_meta_pointer.size_() as usize,
-                        ))
+            let col_metadata_checksums = rg_meta_fbs
+                .col_metadata_checksums()
+                .filter(|_| self.verify_metadata_checksum);
+            for (&column_idx, cipher) in projected_columns.iter().zip(&column_ciphers) {
+                let column_meta_pointer = col_metadatas.get(column_idx);
+                let column_meta_buffer = read_column_meta_buffer(column_meta_pointer)?;
+                if let Some(checksums) = col_metadata_checksums {
+                    if checksums.get(column_idx)
+                        != checksum_of(&post_script.checksum_type, &column_meta_buffer)
+                    {
+                        return Err(Error::General(format!(
+                            "Checksum verification failed for metadata of column {column_idx}"
+                        )));
                     }
                 }
+                column_metadata_buffers.push(match cipher {
+                    Some(cipher) => cipher
+                        .decrypt(
+                            &column_meta_buffer,
+                            &module_aad(ModuleType::ColumnMetadata, column_meta_pointer.offset()),
+                        )?
+                        .into(),
+                    None => column_meta_buffer,
+                });
             }
             grouped_column_metadata_buffers.push(column_metadata_buffers);
         }
-        let wasm_context = if let Some(wasm_rts) = self.wasm_rts {
-            Some(WASMReadingContext::new_with_rt_and_versions(wasm_rts, encoding_versions).into())
-        } else {
-            match optional_sections {
-                Some(sections) => {
-                    let names = sections.names().ok_or_else(|| {
-                        Error::ParseError("Optional section names not found".to_string())
-                    })?;
-                    let pos = names
-                        .iter()
-                        .position(|v| v == "WASMBinaries")
-                        .ok_or_else(|| {
-                            Error::General(
-                                "WASMBinaries section not found in optional sections".to_string(),
-                            )
-                        })?;
-                    let offsets = sections.offsets().ok_or_else(|| {
-                        Error::ParseError("Optional section offsets not found".to_string())
-                    })?;
-                    let sizes = sections.sizes().ok_or_else(|| {
-                        Error::ParseError("Optional section sizes not found".to_string())
-                    })?;
-                    let compression_types = sections.compression_types().ok_or_else(|| {
-                        Error::ParseError(
-                            "Optional section compression types not found".to_string(),
-                        )
-                    })?;
-                    Some(
-                        WASMReadingContext::new_with_versions(
-                            MetadataSection {
-                                offset: offsets.get(pos),
-                                size: sizes.get(pos),
-                                compression_type: compression_types.get(pos),
-                            },
-                            self.reader.clone(),
-                            encoding_versions,
-                        )
-                        .into(),
-                    )
-                }
-                None => None,
-            }
-        };
+        let rows_to_read = row_group_matches
+            .map(|matches| {
+                matches
+                    .into_iter()
+                    .zip(row_group_metadata_fbs.iter())
+                    .map(|(row_group_match, rg_meta_fbs)| {
+                        Ok(match row_group_match {
+                            RowGroupMatch::None => RowsToRead::Ranges(vec![]),
+                            RowGroupMatch::All => RowsToRead::All,
+                            RowGroupMatch::IOUnits { column_idx, mask } => {
+                                let col_metadatas =
+                                    rg_meta_fbs.col_metadatas().ok_or_else(|| {
+                                        Error::ParseError(
+                                            "Column metadatas not found in row group".to_string(),
+                                        )
+                                    })?;
+                                let column_meta_buffer =
+                                    read_column_meta_buffer(col_metadatas.get(column_idx))?;
+                                iounit_row_ranges(&column_meta_buffer, &mask)?
+                            }
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+        let wasm_context = create_wasm_context(
+            &self.reader,
+            self.wasm_rts,
+            optional_sections,
+            encoding_versions,
+            self.decoder_policy,
+        )?;
+        // Dictionaries are decoded when the projected columns first reference them.
         let shared_dictionary_cache = match shared_dict_table {
-            Some(shared_dict_table) => Some(SharedDictionaryCache::try_new_read_all(
+            Some(_) => Some(SharedDictionaryCache::try_new(
                 self.reader.clone(),
-                shared_dict_table,
+                Bytes::copy_from_slice(footer_bytes),
                 wasm_context.clone(),
+                self.max_shared_dictionary_memory,
             )?),
             None => None,
         };
+        let schema = match &self.dictionary_key_type {
+            Some(key_type) => schema_with_dictionaries(
+                schema,
+                key_type,
+                &self.projections,
+                &grouped_column_metadata_buffers,
+            )?,
+            None => schema,
+        };
         Ok(FileReaderV2 {
             reader: self.reader,
             schema: schema.into(),
@@ -324,9 +461,173 @@ impl<R: Reader + Clone> FileReaderV2Builder<R> {
             row_group_cnt_n_pointers,
             wasm_context,
             shared_dictionary_cache,
+            rows_to_read,
+            column_ciphers,
             checksum_type: self
                 .verify_io_unit_checksum
                 .then_some(post_script.checksum_type),
         })
     }
 }
+
+/// The row ranges of the IOUnits of a column kept by `mask`, merging adjacent ones.
+/// Reads the whole row group if the chunks of the column do not match the IOUnit filters.
+fn iounit_row_ranges(column_meta_buffer: &[u8], mask: &[bool]) -> Result<RowsToRead> {
+    let column_meta = flatbuffers::root::<fb::ColumnMetadata>(column_meta_buffer)
+        .map_err(|e| Error::ParseError(format!("Invalid ColumnMetadata flatbuffer: {:?}", e)))?;
+    let chunks = column_meta
+        .column_chunks()
+        .ok_or_else(|| Error::General("No chunks in column meta".to_string()))?;
+    if chunks.len() != mask.len() {
+        return Ok(RowsToRead::All);
+    }
+    let mut ranges: Vec<Range<usize>> = vec![];
+    let mut start = 0;
+    for (chunk, keep) in chunks.iter().zip(mask) {
+        let end = start + chunk.num_rows() as usize;
+        if *keep {
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+        start = end;
+    }
+    Ok(RowsToRead::Ranges(ranges))
+}
+
+/// Turn the non-nested fields whose chunks all use local or shared dictionaries, in every row group
+/// read, into Dictionary fields with `key_type` keys.
+/// Like the column metadata read, `Projection::LeafColumnIndexes` are physical column indexes.
+fn schema_with_dictionaries(
+    schema: Schema,
+    key_type: &DataType,
+    projections: &Projection,
+    column_metadata_buffers: &[Vec<Bytes>],
+) -> Result<Schema> {
+    // The first physical column of each field.
+    let mut first_columns = Vec::with_capacity(schema.fields().len());
+    let mut column = 0;
+    for field in schema.fields() {
+        first_columns.push(column);
+        column += num_physical_columns(field.data_type());
+    }
+    // Each field read, with the position of its first physical column among the read ones.
+    // Leaf columns of nested fields do not start a field, and are skipped.
+    let field_columns = match projections {
+        Projection::All => first_columns.into_iter().enumerate().collect::<Vec<_>>(),
+        Projection::LeafColumnIndexes(columns) => columns
+            .iter()
+            .enumerate()
+            .filter_map(|(position, column)| {
+                let field_idx = first_columns.binary_search(column).ok()?;
+                Some((field_idx, position))
+            })
+            .collect(),
+    };
+    let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+    for (field_idx, column) in field_columns {
+        let field = &fields[field_idx];
+        if !matches!(field.data_type(), non_nest_types!()) {
+            continue;
+        }
+        let mut is_dictionary_encoded = true;
+        let mut num_dictionary_chunks = 0;
+        for row_group in column_metadata_buffers {
+            let buffer = row_group
+                .get(column)
+                .ok_or_else(|| Error::IndexOutOfBound(column, row_group.len()))?;
+            let column_meta = flatbuffers::root::<fb::ColumnMetadata>(buffer).map_err(|e| {
+                Error::ParseError(format!("Invalid ColumnMetadata flatbuffer: {:?}", e))
+            })?;
+            for chunk in column_meta.column_chunks().into_iter().flatten() {
+                if chunk.encoding_type() == fb::DictionaryEncoding::NoDictionary {
+                    is_dictionary_encoded = false;
+                } else {
+                    num_dictionary_chunks += 1;
+                }
+            }
+        }
+        // A column without any chunk is not known to be dictionary encoded.
+        if is_dictionary_encoded && num_dictionary_chunks > 0 {
+            let data_type = DataType::Dictionary(
+                Box::new(key_type.clone()),
+                Box::new(field.data_type().clone()),
+            );
+            fields[field_idx] = Arc::new(field.as_ref().clone().with_data_type(data_type));
+        }
+    }
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Number of physical columns of a field, in the order the logical decoders read them.
+fn num_physical_columns(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::List(child) | DataType::LargeList(child) => {
+            1 + num_physical_columns(child.data_type())
+        }
+        DataType::Struct(children) => {
+            1 + children
+                .iter()
+                .map(|child| num_physical_columns(child.data_type()))
+                .sum::<usize>()
+        }
+        _ => 1,
+    }
+}
+
+/// Create the context to run the Wasm decoders of the file, from the given runtimes or the WASMBinaries section.
+pub(super) fn create_wasm_context<R: Reader + Clone>(
+    reader: &R,
+    wasm_rts: Option<HashMap<WASMId, Arc<Runtime>>>,
+    optional_sections: Option<fb::OptionalMetadataSections<'_>>,
+    encoding_versions: Option<HashMap<fb::EncodingType, Version>>,
+    decoder_policy: DecoderPolicy,
+) -> Result<Option<Arc<WASMReadingContext<R>>>> {
+    Ok(if let Some(wasm_rts) = wasm_rts {
+        Some(
+            WASMReadingContext::new_with_rt_and_versions(wasm_rts, encoding_versions)
+                .with_decoder_policy(decoder_policy)
+                .into(),
+        )
+    } else {
+        match optional_sections {
+            Some(sections) => {
+                let names = sections.names().ok_or_else(|| {
+                    Error::ParseError("Optional section names not found".to_string())
+                })?;
+                let pos = names
+                    .iter()
+                    .position(|v| v == "WASMBinaries")
+                    .ok_or_else(|| {
+                        Error::General(
+                            "WASMBinaries section not found in optional sections".to_string(),
+                        )
+                    })?;
+                let offsets = sections.offsets().ok_or_else(|| {
+                    Error::ParseError("Optional section offsets not found".to_string())
+                })?;
+                let sizes = sections.sizes().ok_or_else(|| {
+                    Error::ParseError("Optional section sizes not found".to_string())
+                })?;
+                let compression_types = sections.compression_types().ok_or_else(|| {
+                    Error::ParseError("Optional section compression types not found".to_string())
+                })?;
+                Some(
+                    WASMReadingContext::new_with_versions(
+                        MetadataSection {
+                            offset: offsets.get(pos),
+                            size: sizes.get(pos),
+                            compression_type: compression_types.get(pos),
+                        },
+                        reader.clone(),
+                        encoding_versions,
+                    )
+                    .with_decoder_policy(decoder_policy)
+                    .into(),
+                )
+            }
+            None => None,
+        }
+    })
+}
@@ -0,0 +1,152 @@
+//! Which columns of a file this reader decodes natively, and which need the Wasm decoders in the file.
+
+use std::fmt::{Display, Formatter};
+
+use bytes::Bytes;
+use fff_core::{
+    errors::{Error, Result},
+    general_error,
+};
+use fff_format::File::fff::flatbuf as fb;
+
+use crate::{
+    context::WASMReadingContext,
+    decoder::encunit::{choose_decoder, DecoderChoice},
+    io::reader::Reader,
+};
+
+/// How the EncUnits of a physical column are decoded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnCompatibility {
+    /// The physical column, in the order of the ColumnMetadata of each row group.
+    pub column: usize,
+    pub num_native: usize,
+    /// EncUnits decoded with the Wasm decoder in the file, e.g., of encodings newer than the reader.
+    pub num_wasm: usize,
+    /// Why EncUnits can not be decoded at all, once per distinct reason.
+    pub errors: Vec<String>,
+}
+
+impl ColumnCompatibility {
+    pub fn needs_wasm(&self) -> bool {
+        self.num_wasm > 0
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// See [`FileReaderV2::compatibility_report`](super::FileReaderV2::compatibility_report).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatibilityReport {
+    pub columns: Vec<ColumnCompatibility>,
+}
+
+impl CompatibilityReport {
+    /// Whether every EncUnit is decoded natively.
+    pub fn is_native(&self) -> bool {
+        self.columns
+            .iter()
+            .all(|column| !column.needs_wasm() && column.is_readable())
+    }
+
+    /// The columns that need the Wasm decoders in the file.
+    pub fn wasm_columns(&self) -> Vec<usize> {
+        self.columns
+            .iter()
+            .filter(|column| column.needs_wasm())
+            .map(|column| column.column)
+            .collect()
+    }
+
+    /// The columns with EncUnits that can not be decoded.
+    pub fn unreadable_columns(&self) -> Vec<usize> {
+        self.columns
+            .iter()
+            .filter(|column| !column.is_readable())
+            .map(|column| column.column)
+            .collect()
+    }
+}
+
+impl Display for CompatibilityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let list = |columns: Vec<usize>| {
+            columns
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if self.is_native() {
+            return write!(f, "All columns are decoded natively");
+        }
+        let mut sep = "";
+        if !self.wasm_columns().is_empty() {
+            write!(
+                f,
+                "Needs Wasm fallback for columns {}",
+                list(self.wasm_columns())
+            )?;
+            sep = "; ";
+        }
+        for column in self.columns.iter().filter(|column| !column.is_readable()) {
+            write!(
+                f,
+                "{sep}Can not decode column {}: {}",
+                column.column,
+                column.errors.join(", ")
+            )?;
+            sep = "; ";
+        }
+        Ok(())
+    }
+}
+
+/// Negotiate the decoder of every EncUnit in `column_metadata_buffers`, the ColumnMetadata of
+/// `columns` in each row group.
+pub(super) fn check_compatibility<R: Reader>(
+    columns: &[usize],
+    column_metadata_buffers: &[Vec<Bytes>],
+    wasm_context: Option<&WASMReadingContext<R>>,
+) -> Result<CompatibilityReport> {
+    let mut report = CompatibilityReport {
+        columns: columns
+            .iter()
+            .map(|&column| ColumnCompatibility {
+                column,
+                ..Default::default()
+            })
+            .collect(),
+    };
+    for row_group in column_metadata_buffers {
+        for (buffer, compatibility) in row_group.iter().zip(&mut report.columns) {
+            let column_meta = flatbuffers::root::<fb::ColumnMetadata>(buffer).map_err(|e| {
+                Error::ParseError(format!("Invalid ColumnMetadata flatbuffer: {:?}", e))
+            })?;
+            let encodings = column_meta
+                .column_chunks()
+                .into_iter()
+                .flatten()
+                .flat_map(|chunk| chunk.encunits().into_iter().flatten())
+                .map(|encunit| encunit.encoding());
+            for encoding in encodings {
+                let choice = encoding
+                    .ok_or_else(|| general_error!("Missing encoding in EncUnit metadata"))
+                    .and_then(|encoding| choose_decoder(encoding, wasm_context));
+                match choice {
+                    Ok(DecoderChoice::Native(_)) => compatibility.num_native += 1,
+                    Ok(DecoderChoice::Wasm) => compatibility.num_wasm += 1,
+                    Err(e) => {
+                        let e = e.to_string();
+                        if !compatibility.errors.contains(&e) {
+                            compatibility.errors.push(e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(report)
+}
@@ -48,6 +48,8 @@ impl<R: Reader> FileReader<R> {
             None,
             None,
             None,
+            &[],
+            None,
         )
     }
 
@@ -3,7 +3,7 @@ use crate::{
     context::WASMReadingContext,
     counter::EncodingCounter,
     decoder::logical::{create_list_struct_decoder, create_logical_decoder},
-    dict::shared_dictionary_cache::SharedDictionaryCache,
+    encryption::AesGcmCipher,
     file::footer::{Footer, GroupedColumnMetadata, PostScript},
     io::reader::Reader,
 };
@@ -18,8 +18,8 @@ use fff_core::{
     non_nest_types,
 };
 use fff_format::File::fff::flatbuf::{self as fb, CompressionType};
-use fff_format::{MAGIC, POSTSCRIPT_SIZE};
-use std::sync::Arc;
+use fff_format::{ENCRYPTED_FOOTER_MAGIC, MAGIC, MAJOR_VERSION, POSTSCRIPT_SIZE};
+use std::{ops::Range, sync::Arc};
 use tracing::{debug, info, instrument};
 
 mod projection;
@@ -33,6 +33,20 @@ pub use legacy::FileReader;
 mod builder;
 pub use builder::FileReaderV2Builder;
 
+pub use crate::decoder::policy::{
+    DecoderMismatch, DecoderPolicy, DecoderVerification, EncUnitLocation,
+};
+pub use crate::dict::shared_dictionary_cache::SharedDictionaryCache;
+
+mod compat;
+pub use compat::{ColumnCompatibility, CompatibilityReport};
+
+mod verify;
+pub use verify::{
+    salvage_file, verify_file, CorruptionKind, CorruptionLocation, FileVerifier, Finding,
+    SalvagedFile, SalvagedRowGroup, VerificationReport,
+};
+
 /// Utility function to get the max size of a Chunk in this FFF file.
 pub fn get_max_chunk_size<R: Reader + Clone>(reader: R) -> Result<usize> {
     let file_size = reader.size()?;
@@ -90,6 +104,14 @@ pub(crate) struct RowGroupCntNPointer {
     pub(crate) _size: u32,
 }
 
+/// Rows of a row group left to read by a Bloom filter predicate.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum RowsToRead {
+    All,
+    /// Sorted and disjoint row ranges, the row group is skipped if there are none.
+    Ranges(Vec<Range<usize>>),
+}
+
 pub struct FileReaderV2<R> {
     reader: R,
     schema: SchemaRef,
@@ -100,7 +122,11 @@ pub struct FileReaderV2<R> {
     row_group_cnt_n_pointers: Vec<RowGroupCntNPointer>,
     /// TODO: remove this Option wrapping when removing V1 reader.
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
-    shared_dictionary_cache: Option<SharedDictionaryCache>,
+    shared_dictionary_cache: Option<SharedDictionaryCache<R>>,
+    /// Rows of each row group that may match the Bloom filter predicate. None means reading all of them.
+    rows_to_read: Option<Vec<RowsToRead>>,
+    /// Cipher of each projected physical column, None if the column is not encrypted.
+    column_ciphers: Vec<Option<Arc<AesGcmCipher>>>,
     /// Whether we verify the IOUnit checksum.
     checksum_type: Option<ChecksumType>,
 }
@@ -136,6 +162,8 @@ impl<R: Reader> FileReaderV2<R> {
             &self.selection,
             self.wasm_context.clone(),
             self.shared_dictionary_cache.as_ref(),
+            self.rows_to_read.as_deref(),
+            &self.column_ciphers,
             self.checksum_type,
         );
 
@@ -155,6 +183,44 @@ impl<R: Reader> FileReaderV2<R> {
         result
     }
 
+    /// What [`DecoderPolicy::Verify`] found in the EncUnits read so far.
+    pub fn decoder_verification(&self) -> DecoderVerification {
+        let mut verification = self
+            .wasm_context
+            .as_ref()
+            .map(|ctx| ctx.decoder_verification())
+            .unwrap_or_default();
+        // Decoders only see the projected columns.
+        if let Projection::LeafColumnIndexes(columns) = &self.projections {
+            for mismatch in &mut verification.mismatches {
+                if let Some(&column) = columns.get(mismatch.location.column) {
+                    mismatch.location.column = column;
+                }
+            }
+        }
+        verification
+    }
+
+    /// Which of the projected columns need the Wasm decoders in the file, or can not be decoded,
+    /// under the [`DecoderPolicy`] of the reader. Shared dictionaries are decoded, or fail to, when
+    /// a chunk first references them.
+    pub fn compatibility_report(&self) -> Result<CompatibilityReport> {
+        let columns = match &self.projections {
+            Projection::All => (0..self.column_ciphers.len()).collect(),
+            Projection::LeafColumnIndexes(columns) => columns.clone(),
+        };
+        compat::check_compatibility(
+            &columns,
+            &self.grouped_column_metadata_buffers,
+            self.wasm_context.as_deref(),
+        )
+    }
+
+    /// The shared dictionaries of the file, None if it has none.
+    pub fn shared_dictionary_cache(&self) -> Option<&SharedDictionaryCache<R>> {
+        self.shared_dictionary_cache.as_ref()
+    }
+
     #[allow(clippy::type_complexity)]
     pub fn get_shared_dict_sizes(
         &mut self,
@@ -209,6 +275,7 @@ impl<R: Reader> FileReaderV2<R> {
             row_id,
             self.wasm_context.clone(),
             self.shared_dictionary_cache.as_ref(),
+            &self.column_ciphers,
         )
     }
 }
@@ -228,13 +295,16 @@ fn get_metadata_buffer<R: Reader>(reader: &R, post_script: &PostScript) -> Resul
     Ok(buffer)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn read_file_based_on_footer<R: Reader>(
     reader: &mut R,
     footer: Footer,
     projections: &Projection,
     selection: &Selection,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
-    shared_dictionary_cache: Option<&SharedDictionaryCache>,
+    shared_dictionary_cache: Option<&SharedDictionaryCache<R>>,
+    rows_to_read: Option<&[RowsToRead]>,
+    column_ciphers: &[Option<Arc<AesGcmCipher>>],
     checksum_type: Option<ChecksumType>,
 ) -> Result<Vec<RecordBatch>> {
     let shared_dictionary_cache = shared_dictionary_cache.ok_or_else(|| {
@@ -243,46 +313,67 @@ fn read_file_based_on_footer<R: Reader>(
     let mut record_batches = vec![];
     let rg_metas = footer.row_group_metadatas();
     // let projections = projections.map(|vec| vec.iter().map(|v| *v).collect::<HashSet<usize>>());
+    // TODO: needs some magic to handle nested data. Basically needs to go over the schema recursively
+    // and figure out which leaf nodes to fetch. Currently projection is only tested on flat data.
+    let fields = match projections {
+        Projection::LeafColumnIndexes(projected_indices) => projected_indices
+            .iter()
+            .map(|&v| {
+                footer
+                    .schema()
+                    .fields()
+                    .get(v)
+                    .ok_or_else(|| Error::IndexOutOfBound(v, footer.schema().fields().len()))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        Projection::All => footer.schema().fields().iter().collect(),
+    };
+    // Only non-nested columns decode row ranges, otherwise row groups are read as a whole.
+    let ranges_supported = fields.iter().all(|field| {
+        matches!(
+            field.data_type(),
+            non_nest_types!() | DataType::Dictionary(_, _)
+        )
+    });
     let selected_rg_metas = process_selection(selection, rg_metas);
-    for (rg_meta, selection_in_rg) in selected_rg_metas {
+    for (rg_idx, rg_meta, selection_in_rg) in selected_rg_metas {
+        let row_ranges = match rows_to_read.and_then(|rows_to_read| rows_to_read.get(rg_idx)) {
+            Some(RowsToRead::Ranges(ranges)) => match &selection_in_rg {
+                Selection::RowIndexes(row_indexes)
+                    if !row_indexes
+                        .iter()
+                        .any(|row| ranges.iter().any(|range| range.contains(&(*row as usize)))) =>
+                {
+                    continue
+                }
+                Selection::All if ranges.is_empty() => continue,
+                Selection::All if ranges_supported => Some(ranges.as_slice()),
+                _ => None,
+            },
+            _ => None,
+        };
         let mut column_idx = ColumnIndexSequence::default();
         let mut columns = vec![];
-        let mut decode_col = |field: &Arc<Field>| -> Result<()> {
+        for &field in fields.iter() {
             let mut col_decoder = create_logical_decoder(
                 reader,
                 Arc::clone(field),
+                rg_idx,
                 &rg_meta.column_metadatas,
                 &mut column_idx,
                 wasm_context.as_ref().map(Arc::clone),
                 shared_dictionary_cache,
+                column_ciphers,
                 checksum_type,
             )?;
             let arrays = if let Selection::RowIndexes(row_indexes) = &selection_in_rg {
                 col_decoder.decode_row_at(row_indexes[0] as usize, 1)?
+            } else if let Some(row_ranges) = row_ranges {
+                col_decoder.decode_ranges(row_ranges)?
             } else {
                 col_decoder.decode_batch()?
             };
             columns.push(arrays);
-            Ok(())
-        };
-        // TODO: needs some magic to handle nested data. Basically needs to go over the schema recursively
-        // and figure out which leaf nodes to fetch. Currently projection is only tested on flat data.
-        match projections {
-            Projection::LeafColumnIndexes(projected_indices) => {
-                for &v in projected_indices.iter() {
-                    let field =
-                        footer.schema().fields().get(v).ok_or_else(|| {
-                            Error::IndexOutOfBound(v, footer.schema().fields().len())
-                        })?;
-                    decode_col(field)?;
-                }
-            }
-            Projection::All => {
-                for field in footer.schema().fields().iter() {
-                    // println!("decode col {field_id}");
-                    decode_col(field)?;
-                }
-            }
         }
         // TODO: vortex may not round-trip out the input Arrow type. https://github.com/spiraldb/vortex/issues/1021
         for i in 0..columns[0].len() {
@@ -305,9 +396,9 @@ fn read_file_based_on_footer<R: Reader>(
 }
 
 #[allow(clippy::type_complexity)]
-fn get_shared_dict_size_based_on_footer(
+fn get_shared_dict_size_based_on_footer<R: Reader>(
     footer: Footer,
-    shared_dictionary_cache: &SharedDictionaryCache,
+    shared_dictionary_cache: &SharedDictionaryCache<R>,
 ) -> Result<(Vec<EncodingCounter>, Vec<Vec<(usize, usize)>>)> {
     let rg_metas = footer.row_group_metadatas();
     let mut referenced_dicts: Vec<std::collections::HashSet<u32>> =
@@ -373,6 +464,7 @@ fn get_shared_dict_size_based_on_footer(
 }
 
 /// Access single row id from a leaf column, in a file with schema List(Struct(_)) where _ is non_nest_type!().
+#[allow(clippy::too_many_arguments)]
 fn point_access_list_struct<R: Reader>(
     reader: &mut R,
     footer: Footer,
@@ -381,7 +473,8 @@ fn point_access_list_struct<R: Reader>(
     top_col_field: FieldRef,
     row_id: usize,
     wasm_context: Option<Arc<WASMReadingContext<R>>>,
-    shared_dictionary_cache: Option<&SharedDictionaryCache>,
+    shared_dictionary_cache: Option<&SharedDictionaryCache<R>>,
+    column_ciphers: &[Option<Arc<AesGcmCipher>>],
 ) -> Result<Vec<RecordBatch>> {
     let mut record_batches = vec![];
     let shared_dictionary_cache = shared_dictionary_cache.ok_or_else(|| {
@@ -389,17 +482,19 @@ fn point_access_list_struct<R: Reader>(
     })?;
     let rg_metas = footer.row_group_metadatas();
     // let projections = projections.map(|vec| vec.iter().map(|v| *v).collect::<HashSet<usize>>());
-    for rg_meta in rg_metas {
+    for (rg_idx, rg_meta) in rg_metas.iter().enumerate() {
         let mut column_idx = ColumnIndexSequence::default();
         let mut columns = vec![];
         // This col_decoder is unfortunately the decoder of List(Struct(_)) type.
         let mut col_decoder = create_list_struct_decoder(
             reader,
             Arc::clone(&top_col_field),
+            rg_idx,
             &rg_meta.column_metadatas,
             &mut column_idx,
             wasm_context.as_ref().map(Arc::clone),
             shared_dictionary_cache,
+            column_ciphers,
         )?;
         let arrays = col_decoder.decode_batch_at_with_proj(col_leaf_id as usize, row_id, 1)?;
         let concatenated = concat(
@@ -502,18 +597,20 @@ pub fn collect_stats(footer: Footer) -> Result<()> {
 ///
 /// # Returns
 /// A vector of tuples where each tuple contains:
+/// * The index of the row group
 /// * The row group metadata
 /// * An adjusted Selection specific to that row group
 pub fn process_selection<'a>(
     selection: &Selection,
     grouped_metadata: &'a [GroupedColumnMetadata<'a>],
-) -> Vec<(&'a GroupedColumnMetadata<'a>, Selection)> {
+) -> Vec<(usize, &'a GroupedColumnMetadata<'a>, Selection)> {
     match selection {
         Selection::All => {
             // When selecting all rows, simply include all row groups with Selection::All
             grouped_metadata
                 .iter()
-                .map(|metadata| (metadata, Selection::All))
+                .enumerate()
+                .map(|(rg_idx, metadata)| (rg_idx, metadata, Selection::All))
                 .collect()
         }
         Selection::RowIndexes(row_indexes) => {
@@ -531,7 +628,7 @@ pub fn process_selection<'a>(
             let mut current_idx_pos = 0; // Position in the sorted_indexes array
 
             // Process each group once, advancing through the sorted indexes
-            for metadata in grouped_metadata {
+            for (rg_idx, metadata) in grouped_metadata.iter().enumerate() {
                 let row_count = metadata.row_count as u64;
                 let start_row = cumulative_row_count;
                 let end_row = start_row + row_count;
@@ -556,7 +653,7 @@ pub fn process_selection<'a>(
 
                 // Only include this group if it contains at least one selected row
                 if !group_indexes.is_empty() {
-                    result.push((metadata, Selection::RowIndexes(group_indexes)));
+                    result.push((rg_idx, metadata, Selection::RowIndexes(group_indexes)));
                 }
 
                 cumulative_row_count = end_row;
@@ -576,7 +673,9 @@ fn read_postscript<R: Reader + ?Sized>(reader: &R, file_size: u64) -> Result<Pos
     // read postscript from file
     let mut postscript_buffer: [u8; POSTSCRIPT_SIZE as usize] = [0; POSTSCRIPT_SIZE as usize];
     reader.read_exact_at(&mut postscript_buffer, file_size - POSTSCRIPT_SIZE)?;
-    if postscript_buffer[postscript_buffer.len() - 2..] != *MAGIC {
+    let magic = &postscript_buffer[postscript_buffer.len() - 2..];
+    let encrypted_footer = magic == ENCRYPTED_FOOTER_MAGIC;
+    if magic != MAGIC && !encrypted_footer {
         return Err(Error::General("Magic number incorrect".to_string()));
     }
     let metadata_size = LittleEndian::read_u32(&postscript_buffer[0..4]);
@@ -587,6 +686,13 @@ fn read_postscript<R: Reader + ?Sized>(reader: &R, file_size: u64) -> Result<Pos
     let schema_checksum = LittleEndian::read_u64(&postscript_buffer[18..26]);
     let major_version = LittleEndian::read_u16(&postscript_buffer[26..28]);
     let minor_version = LittleEndian::read_u16(&postscript_buffer[28..30]);
+    // Minor versions only add to the format, which older readers can skip.
+    if major_version != MAJOR_VERSION {
+        return Err(Error::General(format!(
+            "File format version {major_version}.{minor_version} is not supported, \
+             this reader reads version {MAJOR_VERSION}.x"
+        )));
+    }
 
     // Convert checksum type with proper error handling
     let checksum_type = ChecksumType::try_from(checksum_type_byte)?;
@@ -600,6 +706,7 @@ fn read_postscript<R: Reader + ?Sized>(reader: &R, file_size: u64) -> Result<Pos
         schema_checksum,
         major_version,
         minor_version,
+        encrypted_footer,
     })
 }
 
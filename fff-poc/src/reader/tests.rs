@@ -1,9 +1,11 @@
-use arrow_array::{Int32Array, RecordBatch};
+use arrow::compute::concat_batches;
+use arrow_array::{Array, Int32Array, Int64Array, RecordBatch};
 use arrow_ipc::writer::{DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
 use arrow_schema::{DataType, Field, Schema};
 use fff_format::{File::fff::flatbuf::CompressionType, MAJOR_VERSION, MINOR_VERSION};
 use xxhash_rust::xxh64;
 
+use crate::io::reader::CountingReader;
 use crate::reader::legacy::FileReader;
 use crate::writer::FileWriter;
 use crate::{common::checksum::ChecksumType, options::FileWriterOptions};
@@ -38,6 +40,24 @@ fn test_read_postscript() {
     assert_eq!(postscript.minor_version, MINOR_VERSION);
 }
 
+#[test]
+#[rustfmt::skip]
+fn test_read_postscript_unsupported_major_version() {
+    let postscript = [
+        42,0,0,0, /* metadata size */
+        23,0,0,0, /* footer size */
+        CompressionType::Uncompressed.into(), /* compression type */
+        ChecksumType::XxHash as u8,
+        17,0,0,0,0,0,0,0, /* data checksum */
+        19,0,0,0,0,0,0,0, /* schema checksum */
+        MAJOR_VERSION as u8 + 1, 0, /* major version */
+        MINOR_VERSION as u8 + 1, 0, /* minor version */
+        b'F',  b'3', /* magic */
+    ];
+    let err = read_postscript(postscript.as_slice(), postscript.len() as u64).unwrap_err();
+    assert!(err.to_string().contains("not supported"), "{err}");
+}
+
 #[test]
 fn test_footer_roundtrip() {
     let schema = Schema::new(vec![
@@ -132,3 +152,106 @@ fn test_version_incompatibility() {
     let mut reader = FileReaderV2Builder::new(Arc::new(file)).build().unwrap();
     let _output_batches = reader.read_file().unwrap();
 }
+
+/// Size of the EncUnit of the first column holding `row_id`.
+fn encunit_size_of_row<R>(reader: &FileReaderV2<R>, row_id: usize) -> u64 {
+    let mut first_row = 0;
+    for buffers in &reader.grouped_column_metadata_buffers {
+        let column_meta = flatbuffers::root::<fb::ColumnMetadata>(&buffers[0]).unwrap();
+        for chunk in column_meta.column_chunks().unwrap() {
+            for encunit in chunk.encunits().unwrap() {
+                let num_rows = encunit.num_rows() as usize;
+                if row_id < first_row + num_rows {
+                    return encunit.size_() as u64;
+                }
+                first_row += num_rows;
+            }
+        }
+    }
+    panic!("Row {row_id} not found in the file");
+}
+
+#[test]
+fn test_point_access_reads_single_encunit() {
+    const NUM_ROWS: usize = 4 * 64 * 1024;
+    let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+    // Scramble the values so that the EncUnits are not compressed to nothing.
+    let values = Int64Array::from_iter_values(
+        (0..NUM_ROWS as i64).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15u64 as i64)),
+    );
+    let file = Arc::new(tempfile::tempfile().unwrap());
+    {
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(values.clone())]).unwrap();
+        let options = FileWriterOptions::builder()
+            .set_dictionary_type(crate::options::DictionaryTypeOptions::NoDictionary)
+            .build();
+        let mut writer = FileWriter::try_new(schema, file.clone(), options).unwrap();
+        writer.write_batch(&batch).unwrap();
+        writer.finish().unwrap();
+    }
+    for row_id in [0, 64 * 1024 - 1, 64 * 1024, 3 * 64 * 1024 + 5, NUM_ROWS - 1] {
+        let counting_reader = CountingReader::new(file.clone());
+        let mut reader = FileReaderV2Builder::new(counting_reader.clone())
+            .with_selection(Selection::RowIndexes(vec![row_id as u64]))
+            .unwrap()
+            .build()
+            .unwrap();
+        // The postscript, footer and column metadata are read when building the reader.
+        let metadata_bytes_read = counting_reader.bytes_read();
+        let encunit_size = encunit_size_of_row(&reader, row_id);
+        let batches = reader.read_file().unwrap();
+        let output = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output.value(0), values.value(row_id));
+        // Only the EncUnit holding the row should be fetched.
+        let data_bytes_read = counting_reader.bytes_read() - metadata_bytes_read;
+        assert!(data_bytes_read > 0 && data_bytes_read <= encunit_size);
+    }
+}
+
+#[test]
+fn test_mini_encunit_point_access() {
+    const NUM_ROWS: usize = 2 * 64 * 1024;
+    let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+    let values = Int64Array::from_iter(
+        (0..NUM_ROWS as i64)
+            .map(|i| (i % 11 != 0).then(|| i.wrapping_mul(0x9E37_79B9_7F4A_7C15u64 as i64))),
+    );
+    let file = Arc::new(tempfile::tempfile().unwrap());
+    {
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(values.clone())]).unwrap();
+        let options = FileWriterOptions::builder()
+            .set_dictionary_type(crate::options::DictionaryTypeOptions::NoDictionary)
+            .set_mini_encunit_len(1024)
+            .build();
+        let mut writer = FileWriter::try_new(schema, file.clone(), options).unwrap();
+        writer.write_batch(&batch).unwrap();
+        writer.finish().unwrap();
+    }
+    // Mini EncUnits are transparent to full reads.
+    let mut reader = FileReaderV2Builder::new(file.clone()).build().unwrap();
+    let batches = reader.read_file().unwrap();
+    let output = concat_batches(&batches[0].schema(), &batches).unwrap();
+    assert_eq!(output.column(0).as_ref(), &values as &dyn Array);
+
+    let file_size = file.metadata().unwrap().len();
+    for row_id in [0, 1023, 1024, 64 * 1024 + 77, NUM_ROWS - 1] {
+        let counting_reader = CountingReader::new(file.clone());
+        let mut reader = FileReaderV2Builder::new(counting_reader.clone())
+            .with_selection(Selection::RowIndexes(vec![row_id as u64]))
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.read_file().unwrap();
+        assert_eq!(
+            batches[0].column(0).as_ref(),
+            &values.slice(row_id, 1) as &dyn Array
+        );
+        // Only a 1Ki-row mini EncUnit (plus metadata) should be fetched.
+        assert!(counting_reader.bytes_read() < file_size / 16);
+    }
+}
@@ -0,0 +1,1101 @@
+//! Integrity checking of a file, and salvaging of the intact parts of a corrupted file.
+
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use arrow::compute::concat;
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use bytes::{Bytes, BytesMut};
+use fff_core::errors::{Error, Result};
+use fff_format::File::fff::flatbuf::{self as fb, root_as_footer};
+use fff_format::POSTSCRIPT_SIZE;
+use fff_ude_wasm::Runtime;
+use flatbuffers::FlatBufferBuilder;
+
+use crate::{
+    common::{
+        checksum::{checksum_of, create_checksum, ChecksumType},
+        ColumnIndexSequence,
+    },
+    context::{WASMId, WASMReadingContext},
+    decoder::{
+        encunit::{create_encunit_decoder_from_fb, create_wasm_encunit_decoder_from_fb},
+        logical::create_logical_decoder,
+        physical::create_physical_decoder,
+        policy::{first_difference, DecoderPolicy, EncUnitLocation},
+    },
+    dict::shared_dictionary_cache::SharedDictionaryCache,
+    encryption::{
+        decrypt_footer, module_aad, AesGcmCipher, FileDecryptor, KeyRetriever, ModuleType,
+    },
+    file::footer::parse_footer,
+    io::reader::Reader,
+    options::DEFAULT_IOUNIT_SIZE,
+};
+
+use super::{builder::create_wasm_context, read_postscript};
+
+/// Where a problem is found in the file.
+/// Columns are physical columns, in the order of the ColumnMetadata of each row group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorruptionLocation {
+    /// The file as a whole, e.g. for the file level checksum.
+    File,
+    PostScript,
+    Footer,
+    Schema,
+    OptionalSection(String),
+    SharedDictionaries,
+    SharedDictionaryChunk {
+        chunk: usize,
+    },
+    RowGroup {
+        row_group: usize,
+    },
+    ColumnMetadata {
+        row_group: usize,
+        column: usize,
+    },
+    Chunk {
+        row_group: usize,
+        column: usize,
+        chunk: usize,
+    },
+    EncUnit {
+        row_group: usize,
+        column: usize,
+        chunk: usize,
+        encunit: usize,
+    },
+}
+
+impl CorruptionLocation {
+    /// The row group and physical column of the location, if it is inside a column.
+    fn column(&self) -> Option<(usize, usize)> {
+        match *self {
+            Self::ColumnMetadata { row_group, column }
+            | Self::Chunk {
+                row_group, column, ..
+            }
+            | Self::EncUnit {
+                row_group, column, ..
+            } => Some((row_group, column)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// The bytes can not be read from the file.
+    Unreadable,
+    /// The metadata can not be parsed, or is inconsistent.
+    Malformed,
+    /// An offset and size point outside of the region they belong to.
+    OutOfBounds,
+    /// Two sections of the file overlap.
+    Overlap,
+    ChecksumMismatch,
+    /// The key is missing or wrong, or the encrypted bytes were tampered with.
+    DecryptionFailure,
+    /// The data can not be decoded, or does not decode to what the metadata describes.
+    DecodeFailure,
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub location: CorruptionLocation,
+    pub kind: CorruptionKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} at {:?}: {}",
+            self.kind, self.location, self.message
+        )
+    }
+}
+
+/// Findings of a [FileVerifier]. Checking stops early if the postscript or the footer are unusable.
+#[derive(Debug, Default)]
+pub struct VerificationReport {
+    pub file_size: u64,
+    pub num_row_groups: usize,
+    /// Number of physical columns.
+    pub num_columns: usize,
+    /// Number of chunks and EncUnits that were read and checked.
+    pub num_chunks: usize,
+    pub num_encunits: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl VerificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Whether no problem is found in the metadata or data of the physical column in the row group.
+    pub fn is_column_intact(&self, row_group: usize, column: usize) -> bool {
+        !self
+            .findings
+            .iter()
+            .any(|finding| finding.location.column() == Some((row_group, column)))
+    }
+
+    fn add(
+        &mut self,
+        location: CorruptionLocation,
+        kind: CorruptionKind,
+        message: impl Into<String>,
+    ) {
+        self.findings.push(Finding {
+            location,
+            kind,
+            message: message.into(),
+        });
+    }
+}
+
+/// The fields of a row group whose physical columns are all intact.
+#[derive(Debug)]
+pub struct SalvagedRowGroup {
+    pub row_group: usize,
+    pub batch: RecordBatch,
+    /// Indexes of the top-level fields left out of `batch` in the schema of the file.
+    pub missing_fields: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct SalvagedFile {
+    pub report: VerificationReport,
+    /// Row groups without any intact field are left out.
+    pub row_groups: Vec<SalvagedRowGroup>,
+}
+
+/// Walks through the postscript, footer, row group and column metadata of a file,
+/// checks that every section lies inside its region without overlapping others, verifies all the checksums,
+/// and test-decodes each EncUnit, with both the native and the Wasm decoder if the file carries one.
+pub struct FileVerifier<R: Reader + Clone> {
+    reader: R,
+    key_retriever: Option<Arc<dyn KeyRetriever>>,
+    wasm_rts: Option<HashMap<WASMId, Arc<Runtime>>>,
+    /// Whether we test-decode every EncUnit. Enabled by default.
+    decode: bool,
+}
+
+/// Verify the file with the default options, see [FileVerifier].
+pub fn verify_file<R: Reader + Clone>(reader: R) -> VerificationReport {
+    FileVerifier::new(reader).verify()
+}
+
+/// Read all the intact row groups and columns of a possibly corrupted file, see [FileVerifier::salvage].
+pub fn salvage_file<R: Reader + Clone>(reader: R) -> SalvagedFile {
+    FileVerifier::new(reader).salvage()
+}
+
+/// What the checks of all the chunks in the file share.
+struct ChunkContext<'a, R> {
+    data_size: u64,
+    checksum_type: ChecksumType,
+    wasm_context: Option<Arc<WASMReadingContext<R>>>,
+    shared_dictionary_cache: &'a SharedDictionaryCache<R>,
+}
+
+impl<R: Reader + Clone> FileVerifier<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            key_retriever: None,
+            wasm_rts: None,
+            decode: true,
+        }
+    }
+
+    /// Retrieve the keys to decrypt encrypted columns and footer.
+    /// Encrypted parts of the file whose key can not be retrieved are reported as corrupted.
+    pub fn with_key_retriever(mut self, key_retriever: Arc<dyn KeyRetriever>) -> Self {
+        self.key_retriever = Some(key_retriever);
+        self
+    }
+
+    /// Use the existing Wasm Runtime provided, instead of compiling from the Wasm in the file.
+    pub fn with_existing_runtimes(mut self, wasm_rts: HashMap<WASMId, Arc<Runtime>>) -> Self {
+        self.wasm_rts = Some(wasm_rts);
+        self
+    }
+
+    /// Whether we test-decode every EncUnit, on top of the structural and checksum checks.
+    pub fn with_decode(mut self, decode: bool) -> Self {
+        self.decode = decode;
+        self
+    }
+
+    pub fn verify(&self) -> VerificationReport {
+        let mut report = VerificationReport::default();
+        self.walk(&mut report, None);
+        report
+    }
+
+    /// Verify the file, then decode every top-level field of each row group whose physical columns are all intact.
+    /// Fields that still fail to decode are left out and reported.
+    pub fn salvage(&self) -> SalvagedFile {
+        let mut report = VerificationReport::default();
+        let mut row_groups = vec![];
+        self.walk(&mut report, Some(&mut row_groups));
+        SalvagedFile { report, row_groups }
+    }
+
+    fn walk(
+        &self,
+        report: &mut VerificationReport,
+        mut salvaged: Option<&mut Vec<SalvagedRowGroup>>,
+    ) {
+        use CorruptionLocation as Loc;
+
+        let file_size = match self.reader.size() {
+            Ok(file_size) => file_size,
+            Err(e) => return report.add(Loc::File, CorruptionKind::Unreadable, e.to_string()),
+        };
+        report.file_size = file_size;
+        if file_size < POSTSCRIPT_SIZE {
+            return report.add(
+                Loc::PostScript,
+                CorruptionKind::OutOfBounds,
+                format!("File of {file_size} bytes is smaller than the postscript"),
+            );
+        }
+        let post_script = match read_postscript(&self.reader, file_size) {
+            Ok(post_script) => post_script,
+            Err(e) => return report.add(Loc::PostScript, CorruptionKind::Malformed, e.to_string()),
+        };
+        if post_script.footer_size > post_script.metadata_size
+            || post_script.metadata_size as u64 > file_size - POSTSCRIPT_SIZE
+        {
+            return report.add(
+                Loc::PostScript,
+                CorruptionKind::OutOfBounds,
+                format!(
+                    "Metadata of {} bytes with a footer of {} bytes does not fit in a file of {} bytes",
+                    post_script.metadata_size, post_script.footer_size, file_size
+                ),
+            );
+        }
+        let data_size = file_size - POSTSCRIPT_SIZE - post_script.metadata_size as u64;
+        let footer_offset = file_size - POSTSCRIPT_SIZE - post_script.footer_size as u64;
+        match self.data_checksum(&post_script.checksum_type, file_size - POSTSCRIPT_SIZE) {
+            Ok(checksum) if checksum == post_script.data_checksum => {}
+            Ok(_) => report.add(
+                Loc::File,
+                CorruptionKind::ChecksumMismatch,
+                "File level Checksum verification failed",
+            ),
+            Err(e) => report.add(Loc::File, CorruptionKind::Unreadable, e.to_string()),
+        }
+
+        let mut footer_buf = vec![0; post_script.footer_size as usize];
+        if let Err(e) = self.reader.read_exact_at(&mut footer_buf, footer_offset) {
+            return report.add(Loc::Footer, CorruptionKind::Unreadable, e.to_string());
+        }
+        if post_script.encrypted_footer {
+            footer_buf =
+                match decrypt_footer(&footer_buf, self.key_retriever.as_deref(), footer_offset) {
+                    Ok(footer_buf) => footer_buf,
+                    Err(e) => {
+                        return report.add(
+                            Loc::Footer,
+                            CorruptionKind::DecryptionFailure,
+                            e.to_string(),
+                        )
+                    }
+                };
+        }
+        let footer_fbs = match root_as_footer(&footer_buf) {
+            Ok(footer_fbs) => footer_fbs,
+            Err(e) => {
+                return report.add(
+                    Loc::Footer,
+                    CorruptionKind::Malformed,
+                    format!("Unable to get root as footer: {e:?}"),
+                )
+            }
+        };
+        // The schema checksum is zeroed if the footer is encrypted.
+        if !post_script.encrypted_footer
+            && footer_fbs
+                .schema()
+                .map(|schema| checksum_of(&post_script.checksum_type, schema.bytes()))
+                != Some(post_script.schema_checksum)
+        {
+            report.add(
+                Loc::Schema,
+                CorruptionKind::ChecksumMismatch,
+                "Schema checksum verification failed",
+            );
+        }
+        let (
+            schema,
+            _logical_tree,
+            row_groups_pointer,
+            shared_dict_table,
+            optional_sections,
+            encoding_versions,
+        ) = match parse_footer(&footer_fbs) {
+            Ok(parsed) => parsed,
+            Err(e) => return report.add(Loc::Footer, CorruptionKind::Malformed, e.to_string()),
+        };
+
+        // Sections in the data region and in the metadata region, to find overlaps between them.
+        let data_region = 0..data_size;
+        let metadata_region = data_size..footer_offset;
+        let mut data_extents = vec![];
+        let mut metadata_extents = vec![];
+        let mut row_group_extents = vec![];
+        if let Some(sections) = optional_sections {
+            match (sections.names(), sections.offsets(), sections.sizes()) {
+                (Some(names), Some(offsets), Some(sizes))
+                    if names.len() == offsets.len() && names.len() == sizes.len() =>
+                {
+                    for i in 0..names.len() {
+                        check_extent(
+                            report,
+                            &mut data_extents,
+                            extent(offsets.get(i), sizes.get(i)),
+                            &data_region,
+                            Loc::OptionalSection(names.get(i).to_string()),
+                        );
+                    }
+                }
+                _ => report.add(
+                    Loc::Footer,
+                    CorruptionKind::Malformed,
+                    "Incomplete optional metadata sections",
+                ),
+            }
+        }
+        let wasm_context = create_wasm_context(
+            &self.reader,
+            self.wasm_rts.clone(),
+            optional_sections,
+            encoding_versions,
+            DecoderPolicy::PreferNative,
+        )
+        .unwrap_or_else(|e| {
+            report.add(
+                Loc::OptionalSection("WASMBinaries".to_string()),
+                CorruptionKind::Malformed,
+                e.to_string(),
+            );
+            None
+        });
+        let shared_dictionary_cache = match shared_dict_table {
+            Some(shared_dict_table) => {
+                for (i, chunk) in shared_dict_table
+                    .dictionary_chunks()
+                    .into_iter()
+                    .flatten()
+                    .enumerate()
+                {
+                    check_extent(
+                        report,
+                        &mut data_extents,
+                        extent(chunk.offset(), chunk.size_()),
+                        &data_region,
+                        Loc::SharedDictionaryChunk { chunk: i },
+                    );
+                }
+                // Chunks of the dictionaries that fail to decode fail again when checked.
+                SharedDictionaryCache::try_new(
+                    self.reader.clone(),
+                    Bytes::copy_from_slice(&footer_buf),
+                    wasm_context.clone(),
+                    None,
+                )
+                .map(|cache| {
+                    if let Err(e) = cache.read_all() {
+                        report.add(
+                            Loc::SharedDictionaries,
+                            CorruptionKind::DecodeFailure,
+                            e.to_string(),
+                        );
+                    }
+                    cache
+                })
+                .unwrap_or_else(|e| {
+                    report.add(
+                        Loc::SharedDictionaries,
+                        CorruptionKind::DecodeFailure,
+                        e.to_string(),
+                    );
+                    SharedDictionaryCache::default()
+                })
+            }
+            None => SharedDictionaryCache::default(),
+        };
+        let ctx = ChunkContext {
+            data_size,
+            checksum_type: post_script.checksum_type,
+            wasm_context,
+            shared_dictionary_cache: &shared_dictionary_cache,
+        };
+
+        let (Some(row_counts), Some(offsets), Some(sizes), Some(rg_metas)) = (
+            row_groups_pointer.row_counts(),
+            row_groups_pointer.offsets(),
+            row_groups_pointer.sizes(),
+            row_groups_pointer.row_group_metadatas(),
+        ) else {
+            return report.add(
+                Loc::Footer,
+                CorruptionKind::Malformed,
+                "Incomplete row groups table",
+            );
+        };
+        if [row_counts.len(), offsets.len(), sizes.len()]
+            .iter()
+            .any(|len| *len != rg_metas.len())
+        {
+            return report.add(
+                Loc::Footer,
+                CorruptionKind::Malformed,
+                "Row groups table has vectors of different lengths",
+            );
+        }
+        report.num_row_groups = rg_metas.len();
+        // Data type decoded from each physical column of each top-level field, as in create_logical_decoder.
+        let field_columns = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let mut types = vec![];
+                physical_column_types(field, &mut types);
+                types
+            })
+            .collect::<Vec<_>>();
+        let column_types = field_columns.concat();
+        let first_columns = field_columns
+            .iter()
+            .scan(0, |start, types| {
+                let first = *start;
+                *start += types.len();
+                Some(first)
+            })
+            .collect::<Vec<_>>();
+        report.num_columns = column_types.len();
+        let mut file_decryptor =
+            match FileDecryptor::try_new(footer_fbs.encryption(), self.key_retriever.clone()) {
+                Ok(file_decryptor) => file_decryptor,
+                Err(e) => return report.add(Loc::Footer, CorruptionKind::Malformed, e.to_string()),
+            };
+        let column_ciphers = (0..column_types.len())
+            .map(|column| file_decryptor.column_cipher(column))
+            .collect::<Vec<_>>();
+        let usable_ciphers = column_ciphers
+            .iter()
+            .map(|cipher| cipher.as_ref().ok().cloned().flatten())
+            .collect::<Vec<_>>();
+        // Stands for the unusable ColumnMetadata, whose columns are never decoded.
+        let empty_column_metadata = {
+            let mut fbb = FlatBufferBuilder::new();
+            let column_metadata = fb::ColumnMetadata::create(&mut fbb, &Default::default());
+            fbb.finish(column_metadata, None);
+            fbb.finished_data().to_vec()
+        };
+
+        for (rg_idx, rg_meta) in rg_metas.iter().enumerate() {
+            check_extent(
+                report,
+                &mut row_group_extents,
+                extent(offsets.get(rg_idx), sizes.get(rg_idx)),
+                &data_region,
+                Loc::RowGroup { row_group: rg_idx },
+            );
+            let Some(col_metadatas) = rg_meta.col_metadatas() else {
+                report.add(
+                    Loc::RowGroup { row_group: rg_idx },
+                    CorruptionKind::Malformed,
+                    "Column metadatas not found in row group",
+                );
+                continue;
+            };
+            if col_metadatas.len() != column_types.len() {
+                report.add(
+                    Loc::RowGroup { row_group: rg_idx },
+                    CorruptionKind::Malformed,
+                    format!(
+                        "{} column metadatas for {} physical columns in the schema",
+                        col_metadatas.len(),
+                        column_types.len()
+                    ),
+                );
+                continue;
+            }
+            let checksums = rg_meta.col_metadata_checksums();
+            if checksums.is_some_and(|checksums| checksums.len() != col_metadatas.len()) {
+                report.add(
+                    Loc::RowGroup { row_group: rg_idx },
+                    CorruptionKind::Malformed,
+                    "Column metadata checksums do not match the column metadatas",
+                );
+                continue;
+            }
+            let mut column_meta_buffers = vec![];
+            for (column, column_meta_pointer) in col_metadatas.iter().enumerate() {
+                let location = Loc::ColumnMetadata {
+                    row_group: rg_idx,
+                    column,
+                };
+                let buf = check_extent(
+                    report,
+                    &mut metadata_extents,
+                    extent(column_meta_pointer.offset(), column_meta_pointer.size_()),
+                    &metadata_region,
+                    location.clone(),
+                )
+                .then(|| {
+                    self.read_column_metadata(
+                        report,
+                        location,
+                        column_meta_pointer.offset(),
+                        column_meta_pointer.size_(),
+                        checksums.map(|checksums| checksums.get(column)),
+                        ctx.checksum_type,
+                        &column_ciphers[column],
+                    )
+                })
+                .flatten();
+                column_meta_buffers.push(buf);
+            }
+            let column_metas = column_meta_buffers
+                .iter()
+                .map(|buf| {
+                    flatbuffers::root::<fb::ColumnMetadata>(
+                        buf.as_deref().unwrap_or(&empty_column_metadata),
+                    )
+                })
+                .collect::<std::result::Result<Vec<_>, _>>();
+            let Ok(column_metas) = column_metas else {
+                report.add(
+                    Loc::RowGroup { row_group: rg_idx },
+                    CorruptionKind::Malformed,
+                    "Invalid ColumnMetadata flatbuffer",
+                );
+                continue;
+            };
+            for (column, column_meta) in column_metas.iter().enumerate() {
+                if column_meta_buffers[column].is_none() {
+                    continue;
+                }
+                // Top-level fields have as many rows as the row group in their first physical column.
+                let expected_rows = first_columns
+                    .contains(&column)
+                    .then(|| row_counts.get(rg_idx) as u64);
+                self.verify_column(
+                    report,
+                    &ctx,
+                    &mut data_extents,
+                    (rg_idx, column),
+                    *column_meta,
+                    &column_types[column],
+                    usable_ciphers[column].as_deref(),
+                    expected_rows,
+                );
+            }
+            if let Some(salvaged) = salvaged.as_deref_mut() {
+                salvaged.extend(self.salvage_row_group(
+                    report,
+                    &ctx,
+                    rg_idx,
+                    &schema,
+                    &field_columns,
+                    &column_metas,
+                    &usable_ciphers,
+                ));
+            }
+        }
+        check_overlaps(report, data_extents);
+        check_overlaps(report, metadata_extents);
+        check_overlaps(report, row_group_extents);
+    }
+
+    fn data_checksum(&self, checksum_type: &ChecksumType, len: u64) -> Result<u64> {
+        let mut checksum = create_checksum(checksum_type);
+        let mut buf = vec![0; std::cmp::min(len, DEFAULT_IOUNIT_SIZE) as usize];
+        let mut offset = 0;
+        while offset < len {
+            let size = std::cmp::min(len - offset, buf.len() as u64) as usize;
+            self.reader.read_exact_at(&mut buf[..size], offset)?;
+            checksum.update(&buf[..size]);
+            offset += size as u64;
+        }
+        Ok(checksum.finalize())
+    }
+
+    /// Read, verify and decrypt a ColumnMetadata. Returns None if it is unusable.
+    #[allow(clippy::too_many_arguments)]
+    fn read_column_metadata(
+        &self,
+        report: &mut VerificationReport,
+        location: CorruptionLocation,
+        offset: u64,
+        size: u32,
+        checksum: Option<u64>,
+        checksum_type: ChecksumType,
+        cipher: &Result<Option<Arc<AesGcmCipher>>>,
+    ) -> Option<Vec<u8>> {
+        let mut buf = vec![0; size as usize];
+        if let Err(e) = self.reader.read_exact_at(&mut buf, offset) {
+            report.add(location, CorruptionKind::Unreadable, e.to_string());
+            return None;
+        }
+        if checksum.is_some_and(|checksum| checksum != checksum_of(&checksum_type, &buf)) {
+            report.add(
+                location,
+                CorruptionKind::ChecksumMismatch,
+                "Checksum verification failed for column metadata",
+            );
+            return None;
+        }
+        let buf = match cipher {
+            Ok(None) => buf,
+            Ok(Some(cipher)) => {
+                match cipher.decrypt(&buf, &module_aad(ModuleType::ColumnMetadata, offset)) {
+                    Ok(buf) => buf,
+                    Err(e) => {
+                        report.add(location, CorruptionKind::DecryptionFailure, e.to_string());
+                        return None;
+                    }
+                }
+            }
+            Err(e) => {
+                report.add(location, CorruptionKind::DecryptionFailure, e.to_string());
+                return None;
+            }
+        };
+        if let Err(e) = flatbuffers::root::<fb::ColumnMetadata>(&buf) {
+            report.add(
+                location,
+                CorruptionKind::Malformed,
+                format!("Invalid ColumnMetadata flatbuffer: {e:?}"),
+            );
+            return None;
+        }
+        Some(buf)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn verify_column(
+        &self,
+        report: &mut VerificationReport,
+        ctx: &ChunkContext<'_, R>,
+        data_extents: &mut Vec<(Range<u64>, CorruptionLocation)>,
+        (row_group, column): (usize, usize),
+        column_meta: fb::ColumnMetadata<'_>,
+        data_type: &DataType,
+        cipher: Option<&AesGcmCipher>,
+        expected_rows: Option<u64>,
+    ) {
+        let Some(chunks) = column_meta.column_chunks() else {
+            return report.add(
+                CorruptionLocation::ColumnMetadata { row_group, column },
+                CorruptionKind::Malformed,
+                "No chunks in column meta",
+            );
+        };
+        for (chunk_idx, chunk) in chunks.iter().enumerate() {
+            self.verify_chunk(
+                report,
+                ctx,
+                data_extents,
+                (row_group, column, chunk_idx),
+                chunk,
+                data_type,
+                cipher,
+            );
+        }
+        let num_rows: u64 = chunks.iter().map(|chunk| chunk.num_rows()).sum();
+        if let Some(expected_rows) = expected_rows.filter(|rows| *rows != num_rows) {
+            report.add(
+                CorruptionLocation::ColumnMetadata { row_group, column },
+                CorruptionKind::Malformed,
+                format!("Chunks have {num_rows} rows in a row group of {expected_rows} rows"),
+            );
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn verify_chunk(
+        &self,
+        report: &mut VerificationReport,
+        ctx: &ChunkContext<'_, R>,
+        data_extents: &mut Vec<(Range<u64>, CorruptionLocation)>,
+        (row_group, column, chunk_idx): (usize, usize, usize),
+        chunk: fb::Chunk<'_>,
+        data_type: &DataType,
+        cipher: Option<&AesGcmCipher>,
+    ) {
+        let location = CorruptionLocation::Chunk {
+            row_group,
+            column,
+            chunk: chunk_idx,
+        };
+        let encunit_location = |encunit| CorruptionLocation::EncUnit {
+            row_group,
+            column,
+            chunk: chunk_idx,
+            encunit,
+        };
+        if !check_extent(
+            report,
+            data_extents,
+            extent(chunk.offset(), chunk.size_()),
+            &(0..ctx.data_size),
+            location.clone(),
+        ) {
+            return;
+        }
+        let Some(encunits) = chunk.encunits() else {
+            return report.add(location, CorruptionKind::Malformed, "No EncUnits in chunk");
+        };
+        let encunits_size: u64 = encunits.iter().map(|encunit| encunit.size_() as u64).sum();
+        if encunits_size != chunk.size_() as u64 {
+            return report.add(
+                location,
+                CorruptionKind::Malformed,
+                format!(
+                    "EncUnits take {encunits_size} bytes in a chunk of {} bytes",
+                    chunk.size_()
+                ),
+            );
+        }
+        let mut buf = BytesMut::zeroed(chunk.size_() as usize);
+        if let Err(e) = self.reader.read_exact_at(&mut buf, chunk.offset()) {
+            return report.add(location, CorruptionKind::Unreadable, e.to_string());
+        }
+        report.num_chunks += 1;
+        report.num_encunits += encunits.len();
+
+        let num_findings = report.findings.len();
+        if chunk
+            .checksum()
+            .is_some_and(|checksum| checksum != checksum_of(&ctx.checksum_type, &buf))
+        {
+            report.add(
+                location.clone(),
+                CorruptionKind::ChecksumMismatch,
+                "Checksum verification failed",
+            );
+        }
+        let mut pos = 0;
+        for (i, encunit) in encunits.iter().enumerate() {
+            let end = pos + encunit.size_() as usize;
+            if encunit
+                .checksum()
+                .is_some_and(|checksum| checksum != checksum_of(&ctx.checksum_type, &buf[pos..end]))
+            {
+                report.add(
+                    encunit_location(i),
+                    CorruptionKind::ChecksumMismatch,
+                    "EncUnit checksum verification failed",
+                );
+            }
+            if let Some(cipher) = cipher {
+                let decrypted = encunit
+                    .encryption()
+                    .ok_or_else(|| {
+                        Error::General(
+                            "EncUnit of an encrypted column has no encryption parameters"
+                                .to_string(),
+                        )
+                    })
+                    .and_then(|params| {
+                        cipher.decrypt_in_place_detached(
+                            &mut buf[pos..end],
+                            &module_aad(ModuleType::EncUnit, chunk.offset() + pos as u64),
+                            params.nonce().map(|v| v.bytes()).unwrap_or_default(),
+                            params.tag().map(|v| v.bytes()).unwrap_or_default(),
+                        )
+                    });
+                if let Err(e) = decrypted {
+                    report.add(
+                        encunit_location(i),
+                        CorruptionKind::DecryptionFailure,
+                        e.to_string(),
+                    );
+                }
+            }
+            pos = end;
+        }
+        // Corrupted bytes may crash the decoders in unexpected ways, so they are not decoded.
+        if !self.decode || report.findings.len() > num_findings {
+            return;
+        }
+
+        if chunk.encoding_type() != fb::DictionaryEncoding::NoDictionary {
+            // EncUnits of dictionary chunks can only be decoded along with their dictionary.
+            let num_rows = create_physical_decoder::<R>(
+                encunits.iter(),
+                chunk.encoding_type(),
+                chunk.encoding_as_shared_dictionary(),
+                data_type,
+                buf,
+                ctx.wasm_context.clone(),
+                Some(ctx.shared_dictionary_cache),
+                Some(EncUnitLocation {
+                    row_group,
+                    column,
+                    chunk: chunk_idx,
+                    encunit: 0,
+                }),
+            )
+            .and_then(|mut decoder| {
+                let mut num_rows = 0;
+                while let Some(array) = decoder.decode_batch()? {
+                    num_rows += array.len() as u64;
+                }
+                Ok(num_rows)
+            });
+            match num_rows {
+                Ok(num_rows) if num_rows == chunk.num_rows() => {}
+                Ok(num_rows) => report.add(
+                    location,
+                    CorruptionKind::DecodeFailure,
+                    format!(
+                        "Decoded {num_rows} rows from a chunk of {} rows",
+                        chunk.num_rows()
+                    ),
+                ),
+                Err(e) => report.add(location, CorruptionKind::DecodeFailure, e.to_string()),
+            }
+            return;
+        }
+        let mut data = buf.freeze();
+        for (i, encunit) in encunits.iter().enumerate() {
+            let encunit_data = data.split_to(encunit.size_() as usize);
+            let native = create_encunit_decoder_from_fb(
+                encunit,
+                encunit_data.clone(),
+                data_type.clone(),
+                ctx.wasm_context.clone(),
+            )
+            .and_then(|decoder| decoder.decode());
+            let native = match native {
+                Ok(native) if native.len() == encunit.num_rows() as usize => native,
+                Ok(native) => {
+                    report.add(
+                        encunit_location(i),
+                        CorruptionKind::DecodeFailure,
+                        format!(
+                            "Decoded {} rows from an EncUnit of {} rows",
+                            native.len(),
+                            encunit.num_rows()
+                        ),
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    report.add(
+                        encunit_location(i),
+                        CorruptionKind::DecodeFailure,
+                        e.to_string(),
+                    );
+                    continue;
+                }
+            };
+            // Cross-check the Wasm decoder carried in the file against the native one it stands in for.
+            let cross_check = encunit.encoding().is_some_and(|encoding| {
+                matches!(
+                    encoding.type_(),
+                    fb::EncodingType::CASCADE | fb::EncodingType::PLUGIN
+                )
+            });
+            let Some(wasm_context) = ctx.wasm_context.as_deref().filter(|_| cross_check) else {
+                continue;
+            };
+            let wasm = create_wasm_encunit_decoder_from_fb(
+                encunit,
+                encunit_data,
+                data_type.clone(),
+                wasm_context,
+            )
+            .and_then(|decoder| decoder.map(|decoder| decoder.decode()).transpose());
+            match wasm {
+                Ok(Some(wasm)) => {
+                    if let Some((row, message)) = first_difference(&native, &wasm) {
+                        report.add(
+                            encunit_location(i),
+                            CorruptionKind::DecodeFailure,
+                            format!("{message} at row {row}"),
+                        )
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => report.add(
+                    encunit_location(i),
+                    CorruptionKind::DecodeFailure,
+                    format!("Wasm decoding failed: {e}"),
+                ),
+            }
+        }
+    }
+
+    /// Decode the top-level fields of a row group whose physical columns are all intact.
+    #[allow(clippy::too_many_arguments)]
+    fn salvage_row_group(
+        &self,
+        report: &mut VerificationReport,
+        ctx: &ChunkContext<'_, R>,
+        row_group: usize,
+        schema: &Schema,
+        field_columns: &[Vec<DataType>],
+        column_metas: &Vec<fb::ColumnMetadata<'_>>,
+        column_ciphers: &[Option<Arc<AesGcmCipher>>],
+    ) -> Option<SalvagedRowGroup> {
+        let mut fields = vec![];
+        let mut columns = vec![];
+        let mut missing_fields = vec![];
+        let mut first_column = 0;
+        for (field_idx, (field, types)) in schema.fields().iter().zip(field_columns).enumerate() {
+            let physical_columns = first_column..first_column + types.len();
+            first_column = physical_columns.end;
+            if !physical_columns
+                .clone()
+                .all(|column| report.is_column_intact(row_group, column))
+            {
+                missing_fields.push(field_idx);
+                continue;
+            }
+            let array = create_logical_decoder(
+                &self.reader,
+                Arc::clone(field),
+                row_group,
+                column_metas,
+                &mut ColumnIndexSequence::new_start_from(physical_columns.start as u32),
+                ctx.wasm_context.clone(),
+                ctx.shared_dictionary_cache,
+                column_ciphers,
+                None,
+            )
+            .and_then(|mut decoder| decoder.decode_batch())
+            .and_then(|arrays| {
+                Ok(concat(
+                    &arrays
+                        .iter()
+                        .map(|array| array.as_ref())
+                        .collect::<Vec<_>>(),
+                )?)
+            });
+            match array {
+                Ok(array) => {
+                    fields.push(Field::new(
+                        field.name(),
+                        array.data_type().clone(),
+                        field.is_nullable(),
+                    ));
+                    columns.push(array);
+                }
+                Err(e) => {
+                    report.add(
+                        CorruptionLocation::ColumnMetadata {
+                            row_group,
+                            column: physical_columns.start,
+                        },
+                        CorruptionKind::DecodeFailure,
+                        format!("Decoding field {} failed: {e}", field.name()),
+                    );
+                    missing_fields.push(field_idx);
+                }
+            }
+        }
+        if columns.is_empty() {
+            return None;
+        }
+        match RecordBatch::try_new(Arc::new(Schema::new(fields)), columns) {
+            Ok(batch) => Some(SalvagedRowGroup {
+                row_group,
+                batch,
+                missing_fields,
+            }),
+            Err(e) => {
+                report.add(
+                    CorruptionLocation::RowGroup { row_group },
+                    CorruptionKind::DecodeFailure,
+                    e.to_string(),
+                );
+                None
+            }
+        }
+    }
+}
+
+fn extent(offset: u64, size: u32) -> Range<u64> {
+    offset..offset.saturating_add(size as u64)
+}
+
+/// Record the extent of a section if it lies inside its region. Returns whether it does.
+fn check_extent(
+    report: &mut VerificationReport,
+    extents: &mut Vec<(Range<u64>, CorruptionLocation)>,
+    extent: Range<u64>,
+    region: &Range<u64>,
+    location: CorruptionLocation,
+) -> bool {
+    if extent.start < region.start || extent.end > region.end {
+        report.add(
+            location,
+            CorruptionKind::OutOfBounds,
+            format!("Bytes {extent:?} are outside of {region:?}"),
+        );
+        return false;
+    }
+    if !extent.is_empty() {
+        extents.push((extent, location));
+    }
+    true
+}
+
+fn check_overlaps(
+    report: &mut VerificationReport,
+    mut extents: Vec<(Range<u64>, CorruptionLocation)>,
+) {
+    extents.sort_by_key(|(extent, _)| (extent.start, extent.end));
+    let mut furthest: Option<&(Range<u64>, CorruptionLocation)> = None;
+    for cur in extents.iter() {
+        match furthest {
+            Some((prev, prev_location)) if cur.0.start < prev.end => {
+                report.add(
+                    cur.1.clone(),
+                    CorruptionKind::Overlap,
+                    format!(
+                        "Bytes {:?} overlap with bytes {prev:?} of {prev_location:?}",
+                        cur.0
+                    ),
+                );
+                if cur.0.end > prev.end {
+                    furthest = Some(cur);
+                }
+            }
+            _ => furthest = Some(cur),
+        }
+    }
+}
+
+/// Data type of each physical column of a field, in the order create_logical_decoder reads them.
+fn physical_column_types(field: &Field, types: &mut Vec<DataType>) {
+    match field.data_type() {
+        DataType::List(child) | DataType::LargeList(child) => {
+            // Validity and offsets are decoded as the List type.
+            types.push(field.data_type().clone());
+            physical_column_types(child, types);
+        }
+        DataType::Struct(children) => {
+            types.push(DataType::Boolean);
+            for child in children {
+                physical_column_types(child, types);
+            }
+        }
+        other => types.push(other.clone()),
+    }
+}
@@ -0,0 +1,217 @@
+//! Registry of natively implemented encodings, consulted by both the writer and the reader.
+//!
+//! Applications register their in-house encodings once per process with [`register_encoding`],
+//! and select them per column with [`CustomEncoderSelection::Plugin`](crate::options::CustomEncoderSelection::Plugin).
+//! Files written with a plugin also carry its Wasm decoder, if it provides one, so that readers
+//! without a compatible native implementation can still decode them.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    rc::Rc,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use arrow_schema::DataType;
+use bytes::Bytes;
+use fff_core::errors::{Error, Result};
+use fff_encoding::schemes::Encoder;
+use semver::Version;
+use tracing::info;
+
+use crate::{decoder::encunit::EncUnitDecoder, file::footer::is_encoding_version_compatible};
+
+static ENCODING_REGISTRY: LazyLock<RwLock<EncodingRegistry>> =
+    LazyLock::new(|| RwLock::new(EncodingRegistry::default()));
+
+/// A natively implemented encoding.
+pub trait EncodingPlugin: Debug + Send + Sync {
+    /// Unique id of the encoding, e.g., "acme.delta".
+    /// Prefix it with the name of your organization to avoid collisions.
+    fn id(&self) -> &str;
+
+    /// Version of the encoding, recorded in every EncUnit written with it.
+    /// Readers with a different major version (or any older 0.x version) do not use this implementation.
+    fn version(&self) -> Version;
+
+    /// Encoder of the EncUnits of `data_type`.
+    fn create_encoder(&self, data_type: &DataType) -> Result<Rc<dyn Encoder>>;
+
+    /// Decoder of an uncompressed EncUnit of `num_rows` rows written by the encoder of `output_type`.
+    fn create_decoder(
+        &self,
+        data: Bytes,
+        output_type: &DataType,
+        num_rows: u64,
+    ) -> Result<Box<dyn EncUnitDecoder>>;
+
+    /// Wasm binary exporting the same decode function as the built-in Wasm decoder,
+    /// embedded in the files written with this encoding.
+    fn wasm_decoder(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Encoding plugins by id, with possibly multiple versions per id.
+#[derive(Default)]
+pub struct EncodingRegistry {
+    plugins: HashMap<String, Vec<Arc<dyn EncodingPlugin>>>,
+}
+
+impl EncodingRegistry {
+    pub fn register(&mut self, plugin: Arc<dyn EncodingPlugin>) -> Result<()> {
+        let versions = self.plugins.entry(plugin.id().to_string()).or_default();
+        if versions.iter().any(|p| p.version() == plugin.version()) {
+            return Err(Error::General(format!(
+                "Encoding {} {} is already registered",
+                plugin.id(),
+                plugin.version()
+            )));
+        }
+        info!(id = plugin.id(), version = %plugin.version(), "Registered encoding plugin");
+        versions.push(plugin);
+        Ok(())
+    }
+
+    /// Returns whether the version was registered.
+    pub fn unregister(&mut self, id: &str, version: &Version) -> bool {
+        let Some(versions) = self.plugins.get_mut(id) else {
+            return false;
+        };
+        let len = versions.len();
+        versions.retain(|p| &p.version() != version);
+        let removed = versions.len() < len;
+        if versions.is_empty() {
+            self.plugins.remove(id);
+        }
+        removed
+    }
+
+    /// The latest registered version of `id`, which is used for writing.
+    pub fn latest(&self, id: &str) -> Option<Arc<dyn EncodingPlugin>> {
+        self.plugins
+            .get(id)?
+            .iter()
+            .max_by(|a, b| a.version().cmp_precedence(&b.version()))
+            .cloned()
+    }
+
+    /// The registered version of `id` that decodes data written with `version`:
+    /// the latest one with the same major version, or else the latest compatible one.
+    pub fn compatible(&self, id: &str, version: &Version) -> Option<Arc<dyn EncodingPlugin>> {
+        self.plugins
+            .get(id)?
+            .iter()
+            .filter(|p| is_encoding_version_compatible(&p.version(), version))
+            .max_by(|a, b| {
+                let (a, b) = (a.version(), b.version());
+                (a.major == version.major)
+                    .cmp(&(b.major == version.major))
+                    .then_with(|| a.cmp_precedence(&b))
+            })
+            .cloned()
+    }
+}
+
+/// Register `plugin` in the process-wide registry.
+pub fn register_encoding(plugin: Arc<dyn EncodingPlugin>) -> Result<()> {
+    ENCODING_REGISTRY
+        .write()
+        .map_err(|e| Error::General(format!("Encoding registry poisoned: {}", e)))?
+        .register(plugin)
+}
+
+/// Remove a version of an encoding from the process-wide registry.
+/// Files written with it are then decoded with their embedded Wasm decoder.
+pub fn unregister_encoding(id: &str, version: &Version) -> Result<bool> {
+    Ok(ENCODING_REGISTRY
+        .write()
+        .map_err(|e| Error::General(format!("Encoding registry poisoned: {}", e)))?
+        .unregister(id, version))
+}
+
+pub(crate) fn latest_encoding(id: &str) -> Result<Option<Arc<dyn EncodingPlugin>>> {
+    Ok(ENCODING_REGISTRY
+        .read()
+        .map_err(|e| Error::General(format!("Encoding registry poisoned: {}", e)))?
+        .latest(id))
+}
+
+pub(crate) fn compatible_encoding(
+    id: &str,
+    version: &Version,
+) -> Result<Option<Arc<dyn EncodingPlugin>>> {
+    Ok(ENCODING_REGISTRY
+        .read()
+        .map_err(|e| Error::General(format!("Encoding registry poisoned: {}", e)))?
+        .compatible(id, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use fff_core::nyi_err;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct VersionedPlugin(Version);
+
+    impl EncodingPlugin for VersionedPlugin {
+        fn id(&self) -> &str {
+            "test.versioned"
+        }
+
+        fn version(&self) -> Version {
+            self.0.clone()
+        }
+
+        fn create_encoder(&self, _data_type: &DataType) -> Result<Rc<dyn Encoder>> {
+            nyi_err!("The test plugin does not encode")
+        }
+
+        fn create_decoder(
+            &self,
+            _data: Bytes,
+            _output_type: &DataType,
+            _num_rows: u64,
+        ) -> Result<Box<dyn EncUnitDecoder>> {
+            nyi_err!("The test plugin does not decode")
+        }
+    }
+
+    #[test]
+    fn test_version_resolution() {
+        let mut registry = EncodingRegistry::default();
+        for version in ["1.2.0", "1.0.0", "2.0.0"] {
+            registry
+                .register(Arc::new(VersionedPlugin(Version::parse(version).unwrap())))
+                .unwrap();
+        }
+        assert!(registry
+            .register(Arc::new(VersionedPlugin(Version::new(1, 0, 0))))
+            .is_err());
+        let version_of = |p: Option<Arc<dyn EncodingPlugin>>| p.map(|p| p.version());
+
+        assert_eq!(
+            version_of(registry.latest("test.versioned")),
+            Some(Version::new(2, 0, 0))
+        );
+        assert_eq!(
+            version_of(registry.compatible("test.versioned", &Version::new(1, 1, 0))),
+            Some(Version::new(1, 2, 0))
+        );
+        assert!(registry.unregister("test.versioned", &Version::new(1, 2, 0)));
+        assert!(registry.unregister("test.versioned", &Version::new(1, 0, 0)));
+        assert!(!registry.unregister("test.versioned", &Version::new(1, 0, 0)));
+        // A newer major version still reads older files.
+        assert_eq!(
+            version_of(registry.compatible("test.versioned", &Version::new(1, 1, 0))),
+            Some(Version::new(2, 0, 0))
+        );
+        assert_eq!(
+            version_of(registry.compatible("test.versioned", &Version::new(3, 0, 0))),
+            None
+        );
+        assert!(registry.latest("test.unknown").is_none());
+    }
+}
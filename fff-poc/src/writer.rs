@@ -1,57 +1,82 @@
-use std::collections::HashMap;
-use std::io::{BufWriter, Seek, Write};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
 use std::iter::once;
 use std::sync::Arc;
 
-use arrow_array::RecordBatch;
+use arrow_array::{ArrayRef, RecordBatch};
 use arrow_ipc::writer::IpcWriteOptions;
 use arrow_ipc::writer::{DictionaryTracker, IpcDataGenerator};
-use arrow_schema::Schema;
 use arrow_schema::SchemaRef;
+use arrow_schema::{DataType, FieldRef, Schema};
+use fff_encoding::schemes::vortex::VortexEncoder;
 use fff_format::File::fff::flatbuf as fb;
 use fff_format::ToFlatBuffer;
-use fff_format::{File::fff::flatbuf::CompressionType, MAGIC, MAJOR_VERSION, MINOR_VERSION};
+use fff_format::{
+    File::fff::flatbuf::CompressionType, ENCRYPTED_FOOTER_MAGIC, MAGIC, MAJOR_VERSION,
+    MINOR_VERSION,
+};
 use flatbuffers::FlatBufferBuilder;
 use tracing::{debug, info, instrument};
 
+use crate::bloom_filter::{
+    BloomFilterWriter, ColumnBloomFilterLocation, Sbbf, BLOOM_FILTERS_SECTION_NAME,
+};
 use crate::common::checksum::create_checksum;
 use crate::common::checksum::Checksum;
-use crate::common::checksum::ChecksumType;
+use crate::common::checksum::{checksum_of, ChecksumType};
 use crate::common::ColumnIndexSequence;
 use crate::context::WASMWritingContext;
 use crate::counter::EncodingCounter;
 use crate::dict::shared_dictionary::SharedDictionaryTable;
 use crate::dict::shared_dictionary_context::SharedDictionaryContext;
 use crate::dict::DictionaryTypeOptions;
+use crate::encoder::buffer::EncUnitBuffer;
 use crate::encoder::encoded_column_chunk::EncodedColumnChunk;
 use crate::encoder::logical::LogicalColEncoder;
 use crate::encoder::logical::{create_logical_encoder, LogicalTree};
-use crate::file::footer::create_default_encoding_versions;
-use crate::file::footer::{self, Chunk, ColumnMetadata, RowGroupMetadata, RowGroupsTable};
-use crate::options::FileWriterOptions;
-
-use fff_core::{errors::Result, nyi_err};
-
-struct FileWriteState<W: Write + Seek> {
-    writer: BufWriter<W>,
+use crate::encryption::{
+    encrypt_footer, module_aad, AesGcmCipher, ColumnEncryption, EncryptionKey, ModuleType,
+};
+use crate::file::footer::{
+    self, Chunk, ColumnMetadata, MetadataSection, RowGroupMetadata, RowGroupsTable,
+};
+use crate::file::footer::{create_encoding_versions, DEFAULT_ENCODING_VERSIONS};
+use crate::io::writer::PositionedWriter;
+use crate::options::{ColumnEncodingConfig, CustomEncoderSelection, FileWriterOptions};
+
+use fff_core::{errors::Result, general_error, non_nest_types, nyi_err};
+
+struct FileWriteState<W: Write> {
+    writer: PositionedWriter<W>,
     row_groups_table: RowGroupsTable,
-    num_rows_in_file: u32,
+    num_rows_in_file: u64,
     num_physical_columns: usize,
     data_checksum: Box<dyn Checksum>,
     column_counters: Vec<EncodingCounter>,
     enable_io_unit_checksum: bool,
+    enable_encunit_checksum: bool,
+    enable_encunit_encoding_versions: bool,
+    /// The encoding types of all the EncUnits written so far, whose versions go to the footer.
+    encoding_types: BTreeSet<fb::EncodingType>,
+    /// The type of all checksums in the file.
+    checksum_type: ChecksumType,
     /// Metadata for the current row group.
     column_metadatas_in_cur_row_group: Vec<ColumnMetadata>,
     start_offset_of_cur_row_group: u64,
-    num_rows_in_cur_row_group: u32,
+    num_rows_in_cur_row_group: u64,
+    bloom_filters: BloomFilterWriter,
+    /// Cipher of each physical column, None if the column is not encrypted.
+    column_ciphers: Vec<Option<Arc<AesGcmCipher>>>,
 }
 
 impl<W> FileWriteState<W>
 where
-    W: Write + Seek,
+    W: Write,
 {
     pub fn flush_chunk(&mut self, chunk: EncodedColumnChunk) -> Result<()> {
         let column_index = chunk.column_index;
+        self.bloom_filters
+            .finish_iounit(column_index, chunk.num_rows)?;
         let chunk_meta = self.flush_chunk_and_get_metadata(chunk)?;
         // use chunk.column_index to let the metadata knows which physical column does this chunk belong to
         self.column_metadatas_in_cur_row_group[column_index as usize].add_chunk(chunk_meta);
@@ -66,28 +91,55 @@ where
 
     pub fn flush_chunk_and_get_metadata(&mut self, chunk: EncodedColumnChunk) -> Result<Chunk> {
         // println!("flush chunk with index {}", chunk.column_index);
-        let offset = self.writer.stream_position()?;
+        let offset = self.writer.position();
         let mut iounit_checksum = self
             .enable_io_unit_checksum
-            .then_some(create_checksum(&ChecksumType::XxHash));
+            .then(|| create_checksum(&self.checksum_type));
+        let cipher = self
+            .column_ciphers
+            .get(chunk.column_index as usize)
+            .cloned()
+            .flatten();
         let encunit_metas = chunk
             .encunits
             .into_iter()
             .map(|unit| {
-                let buf = unit.bytes();
+                let (buf, encryption) = match &cipher {
+                    Some(cipher) => {
+                        let mut buf = unit.bytes().to_vec();
+                        let aad = module_aad(ModuleType::EncUnit, self.writer.position());
+                        let params = cipher.encrypt_in_place_detached(&mut buf, &aad)?;
+                        (buf.into(), Some(params))
+                    }
+                    None => (unit.bytes(), None),
+                };
                 self.write_and_update_file_level_checksum(buf.as_ref())?;
                 if let Some(checksum) = &mut iounit_checksum {
                     checksum.update(buf.as_ref());
                 }
+                let encunit_checksum = self
+                    .enable_encunit_checksum
+                    .then(|| checksum_of(&self.checksum_type, buf.as_ref()));
+                let mut encoding = unit.encoding().clone();
+                self.encoding_types.insert(encoding.encoding_type());
+                if self.enable_encunit_encoding_versions {
+                    if let Some(version) = DEFAULT_ENCODING_VERSIONS.get(&encoding.encoding_type())
+                    {
+                        encoding = encoding.with_version(version.clone());
+                    }
+                }
                 Ok(footer::EncUnit::new(
                     buf.len() as u32,
                     unit.num_rows(),
-                    unit.encoding().clone(),
+                    encoding,
                     unit.compression_type(),
-                ))
+                )
+                .with_mini_encunits(unit.mini_encunit_len(), unit.mini_encunit_sizes().to_vec())
+                .with_encryption(encryption)
+                .with_checksum(encunit_checksum))
             })
             .collect::<Result<Vec<_>>>()?;
-        let size: u64 = self.writer.stream_position()? - offset;
+        let size: u64 = self.writer.position() - offset;
         // use chunk.column_index to let the metadata knows which physical column does this chunk belong to
         Ok(Chunk::new(
             offset,
@@ -103,10 +155,16 @@ where
     #[instrument(skip(self), fields(num_rows = self.num_rows_in_cur_row_group, num_columns = self.num_physical_columns))]
     pub fn finish_row_group(&mut self) -> Result<()> {
         debug!("Finishing row group");
-        let size = (self.writer.stream_position()? - self.start_offset_of_cur_row_group) as u32;
+        let size = u32::try_from(self.writer.position() - self.start_offset_of_cur_row_group)
+            .map_err(|_| {
+                general_error!("Row group exceeds 4GiB, consider a smaller row group size")
+            })?;
+        let num_rows = u32::try_from(self.num_rows_in_cur_row_group).map_err(|_| {
+            general_error!("Row group exceeds u32::MAX rows, consider a smaller row group size")
+        })?;
 
         self.row_groups_table.add_meta(
-            self.num_rows_in_cur_row_group,
+            num_rows,
             self.start_offset_of_cur_row_group,
             size,
             RowGroupMetadata::new(std::mem::replace(
@@ -115,15 +173,73 @@ where
             )),
         );
 
+        self.bloom_filters.finish_row_group()?;
+
         debug!(size_bytes = size, "Row group finished");
         self.num_rows_in_cur_row_group = 0;
-        self.start_offset_of_cur_row_group = self.writer.stream_position()?;
+        self.start_offset_of_cur_row_group = self.writer.position();
         Ok(())
     }
 
+    fn write_bloom_filter_bitset(&mut self, filter: &Sbbf) -> Result<MetadataSection> {
+        let offset = self.writer.position();
+        let bitset = filter.to_bytes();
+        self.write_and_update_file_level_checksum(&bitset)?;
+        Ok(MetadataSection {
+            offset,
+            size: bitset.len() as u32,
+            compression_type: CompressionType::Uncompressed,
+        })
+    }
+
+    /// Write the bitsets of all Bloom filters, followed by the BloomFilters flatbuffer pointing to them.
+    /// Returns the location of the flatbuffer, or None if no Bloom filter is configured.
+    pub fn flush_bloom_filters(&mut self) -> Result<Option<MetadataSection>> {
+        let filters = self.bloom_filters.take_finished();
+        if filters.is_empty() {
+            return Ok(None);
+        }
+        let locations = filters
+            .iter()
+            .map(|filter| {
+                Ok(ColumnBloomFilterLocation {
+                    row_group_idx: filter.row_group_idx,
+                    field_id: filter.field_id,
+                    column_idx: filter.column_idx,
+                    filter: self.write_bloom_filter_bitset(&filter.filter)?,
+                    iounit_filters: filter
+                        .iounit_filters
+                        .iter()
+                        .map(|iounit_filter| self.write_bloom_filter_bitset(iounit_filter))
+                        .collect::<Result<Vec<_>>>()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut fbb = FlatBufferBuilder::new();
+        let column_filters = locations
+            .iter()
+            .map(|x| x.to_fb(&mut fbb))
+            .collect::<Vec<_>>();
+        let column_filters = fbb.create_vector(&column_filters);
+        let bloom_filters = fb::BloomFilters::create(
+            &mut fbb,
+            &fb::BloomFiltersArgs {
+                column_filters: Some(column_filters),
+            },
+        );
+        fbb.finish(bloom_filters, None);
+        let offset = self.writer.position();
+        self.write_and_update_file_level_checksum(fbb.finished_data())?;
+        Ok(Some(MetadataSection {
+            offset,
+            size: fbb.finished_data().len() as u32,
+            compression_type: CompressionType::Uncompressed,
+        }))
+    }
+
     // Deprecated flush logic with null info
     // pub fn flush_chunk(&mut self, chunk: EncodedColumnChunk) -> Result<()> {
-    //     let offset = self.writer.stream_position()?;
+    //     let offset = self.writer.position();
     //     let blocks = chunk
     //         .encunits
     //         .into_iter()
@@ -161,7 +277,7 @@ where
     //             footer::Block::new(block.num_rows, null_info, data_blocks)
     //         })
     //         .collect();
-    //     let size: u64 = self.writer.stream_position()? - offset;
+    //     let size: u64 = self.writer.position() - offset;
     //     // use chunk.column_index to let the metadata knows which physical column does this chunk belong to
     //     self.column_metadatas[chunk.column_index as usize].add_chunk(Chunk::new(
     //         offset,
@@ -175,59 +291,125 @@ where
 }
 
 #[allow(clippy::arc_with_non_send_sync)]
-pub struct FileWriter<W: Write + Seek> {
+pub struct FileWriter<W: Write> {
     schema: Schema,
     column_encoders: Vec<Box<dyn LogicalColEncoder>>,
     logical_tree: LogicalTree,
     state: FileWriteState<W>,
     schema_checksum: Box<dyn Checksum>,
     wasm_context: Arc<WASMWritingContext>,
-    custom_encunit_len: HashMap<usize, usize>,
+    /// Rows of each root-level column waiting to fill an EncUnit.
+    encunit_buffers: Vec<EncUnitBuffer>,
     row_group_size: u64,
+    max_row_group_bytes: u64,
     shared_dictionary_context: SharedDictionaryContext,
+    /// Key ids of the encrypted columns, recorded in the footer.
+    column_encryptions: Vec<ColumnEncryption>,
+    footer_key: Option<EncryptionKey>,
 }
 
-impl<W: Write + Seek> FileWriter<W> {
+impl<W: Write> FileWriter<W> {
     #[allow(clippy::arc_with_non_send_sync)]
     pub fn try_new(schema: SchemaRef, writer: W, mut options: FileWriterOptions) -> Result<Self> {
+        options.validate()?;
+        let schema = schema_without_dictionaries(&schema);
         let checksum_type = options.checksum_type();
         let mut column_idx = ColumnIndexSequence::default();
-        let wasm_context = Arc::new(
-            match (
-                options.write_built_in_wasm(),
-                !options.custom_encoding_options().is_empty(),
-            ) {
-                (true, false) => WASMWritingContext::default_with_always_set_custom_wasm()?,
-                (false, true) => options.take_custom_encoding_options().into_context(),
-                (false, false) => WASMWritingContext::empty(),
-                (true, true) => {
-                    return Err(fff_core::errors::Error::General(
-                        "Cannot combine write_built_in_wasm with custom encoding options"
-                            .to_string(),
-                    ));
-                }
-            },
-        );
+        let mut wasm_context = match (
+            options.write_built_in_wasm(),
+            !options.custom_encoding_options().is_empty(),
+        ) {
+            (true, false) => WASMWritingContext::default_with_always_set_custom_wasm()?,
+            (false, true) => options.take_custom_encoding_options().into_context(),
+            (false, false) => WASMWritingContext::empty(),
+            (true, true) => {
+                return Err(fff_core::errors::Error::General(
+                    "Cannot combine write_built_in_wasm with custom encoding options".to_string(),
+                ));
+            }
+        };
+        // Sorted, so that the Wasm decoders of the plugins are written in a deterministic order.
+        let plugin_ids = options
+            .column_policies()
+            .values()
+            .filter_map(|policy| match policy.encoder() {
+                Some(CustomEncoderSelection::Plugin(id)) => Some(id.as_str()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        for id in plugin_ids {
+            wasm_context.add_plugin(id)?;
+        }
+        let wasm_context = Arc::new(wasm_context);
         let mut column_encoders = vec![];
         let mut child_trees = vec![];
+        let mut bloom_filter_columns = HashMap::new();
         let shared_dictionary_context = SharedDictionaryContext::new(
             options.encoding_unit_len(),
             options.iounit_size(),
             options.dictionary_type() == DictionaryTypeOptions::GlobalDictionaryMultiColSharing,
             options.compression_type(),
         );
+        let encryption = options.encryption().clone();
+        if !encryption.column_keys().is_empty()
+            && !matches!(
+                options.dictionary_type(),
+                DictionaryTypeOptions::NoDictionary
+                    | DictionaryTypeOptions::EncoderDictionary
+                    | DictionaryTypeOptions::LocalDictionary
+            )
+        {
+            // Shared dictionaries would store values of encrypted columns in plaintext.
+            return nyi_err!("Shared dictionaries with encrypted columns");
+        }
+        validate_column_policies(&schema, &options)?;
+        let file_config = ColumnEncodingConfig::new(&options);
+        let mut column_ciphers = vec![];
+        let mut column_encryptions = vec![];
         for (field_id, field) in schema.fields().iter().enumerate() {
+            let column_start = column_idx.get_current_index();
+            let column_key = encryption.column_keys().get(&field_id);
+            if column_key.is_some() && options.bloom_filter_columns().contains_key(&field_id) {
+                return Err(fff_core::errors::Error::General(format!(
+                    "Bloom filter on encrypted column {} would leak its values",
+                    field.name()
+                )));
+            }
+            if let Some(bloom_filter_options) = options.bloom_filter_columns().get(&field_id) {
+                if !matches!(field.data_type(), non_nest_types!()) {
+                    return nyi_err!(format!("Bloom filter for nested column {}", field.name()));
+                }
+                bloom_filter_columns.insert(
+                    field_id,
+                    (column_idx.get_current_index(), *bloom_filter_options),
+                );
+            }
             let (encoder, child_tree) = create_logical_encoder(
                 Arc::clone(field),
                 field_id as i32,
+                field.name(),
                 options.iounit_size(),
                 &mut column_idx,
                 wasm_context.clone(),
-                options.dictionary_type(),
-                options.compression_type(),
+                &file_config.child(options.column_policies().get(field.name())),
+                options.column_policies(),
             )?;
             column_encoders.push(encoder);
             child_trees.push(child_tree);
+            let column_end = column_idx.get_current_index();
+            let cipher = match column_key {
+                Some(key) => {
+                    column_encryptions.push(ColumnEncryption {
+                        field_id: field_id as u32,
+                        column_start,
+                        column_end,
+                        key_id: key.key_id().to_string(),
+                    });
+                    Some(Arc::new(AesGcmCipher::try_new(key.key())?))
+                }
+                None => None,
+            };
+            column_ciphers.extend((column_start..column_end).map(|_| cipher.clone()));
         }
         let num_physical_columns = column_idx.get_current_index() as usize;
         Ok(Self {
@@ -235,7 +417,7 @@ impl<W: Write + Seek> FileWriter<W> {
             column_encoders,
             logical_tree: LogicalTree::new(fb::LogicalId::STRUCT, child_trees),
             state: FileWriteState {
-                writer: BufWriter::new(writer),
+                writer: PositionedWriter::new(writer),
                 column_metadatas_in_cur_row_group: vec![
                     ColumnMetadata::default();
                     num_physical_columns
@@ -248,62 +430,70 @@ impl<W: Write + Seek> FileWriter<W> {
                 data_checksum: create_checksum(&checksum_type),
                 column_counters: vec![EncodingCounter::default(); num_physical_columns],
                 enable_io_unit_checksum: options.enable_io_unit_checksum(),
+                enable_encunit_checksum: options.enable_encunit_checksum(),
+                enable_encunit_encoding_versions: options.enable_encunit_encoding_versions(),
+                encoding_types: BTreeSet::new(),
+                checksum_type,
+                bloom_filters: BloomFilterWriter::try_new(bloom_filter_columns)?,
+                column_ciphers,
             },
             schema_checksum: create_checksum(&checksum_type),
             wasm_context,
-            custom_encunit_len: options.custom_encunit_len().clone(),
+            encunit_buffers: schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let policy_len = options
+                        .column_policies()
+                        .get(field.name())
+                        .and_then(|policy| policy.encoding_unit_len());
+                    EncUnitBuffer::new(
+                        policy_len
+                            .map(|len| len as usize)
+                            .or_else(|| options.custom_encunit_len().get(&i).copied())
+                            .unwrap_or(options.encoding_unit_len() as usize),
+                        options.encoding_unit_size(),
+                    )
+                })
+                .collect(),
             row_group_size: options.row_group_size(),
+            max_row_group_bytes: options.max_row_group_bytes(),
             shared_dictionary_context,
+            column_encryptions,
+            footer_key: encryption.footer_key().cloned(),
         })
     }
 
+    /// Write a batch, slicing it so that row groups close exactly at `row_group_size` rows,
+    /// or after the first EncUnit that makes them reach `max_row_group_bytes`.
     #[instrument(skip(self, batch), fields(num_rows = batch.num_rows(), num_columns = batch.num_columns()))]
     pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
         debug!("Writing batch");
-        // push each array into the column writer
-        // the logic of metadata should also be in the column writer
-        for (i, col) in batch.columns().iter().enumerate() {
-            let encoder = self.column_encoders[i].as_mut();
-            // TODO: currently this is for research experiments.
-            // A detailed API similar to Parquet's write_batch with correct internal buffer should be added.
-            // Currently requires the input batch size to be the multiple of the custom encunit size.
-            if let Some(encunit_len) = self.custom_encunit_len.get(&i) {
-                if batch.num_rows() < *encunit_len {
-                    return nyi_err!("Batch size should be larger than the custom encunit size");
-                }
-                for j in 0..(batch.num_rows() / encunit_len) {
-                    // slice col to the correct range, then encode
-                    let col_sliced = col.slice(
-                        j * encunit_len,
-                        std::cmp::min(*encunit_len, col.len() - j * encunit_len),
-                    );
-                    if let Some(res) = encoder.encode(
-                        col_sliced.clone(),
-                        &mut self.state.column_counters[i],
-                        &mut self.shared_dictionary_context,
-                    )? {
-                        res.into_iter()
-                            .try_for_each(|chunk| self.state.flush_chunk(chunk))?;
-                    };
-                }
-            } else if let Some(res) = encoder.encode(
-                col.clone(),
-                &mut self.state.column_counters[i],
-                &mut self.shared_dictionary_context,
-            )? {
-                res.into_iter()
-                    .try_for_each(|chunk| self.state.flush_chunk(chunk))?;
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let rows_left_in_group = self.row_group_size - self.state.num_rows_in_cur_row_group;
+            let mut len = usize::try_from(rows_left_in_group)
+                .unwrap_or(usize::MAX)
+                .min(batch.num_rows() - offset);
+            if self.max_row_group_bytes < u64::MAX {
+                // Check the byte limit whenever an EncUnit is encoded.
+                len = self.rows_to_next_encunit(&batch.slice(offset, len))?;
+            }
+            self.write_columns(&batch.slice(offset, len))?;
+            offset += len;
+            self.state.num_rows_in_file += len as u64;
+            self.state.num_rows_in_cur_row_group += len as u64;
+            if self.state.num_rows_in_cur_row_group >= self.row_group_size
+                || self.cur_row_group_bytes() >= self.max_row_group_bytes
+            {
+                debug!(
+                    rows_in_group = self.state.num_rows_in_cur_row_group,
+                    "Flushing full row group"
+                );
+                self.flush_pending_chunks()?;
+                self.state.finish_row_group()?;
             }
-        }
-        self.state.num_rows_in_file += batch.num_rows() as u32;
-        self.state.num_rows_in_cur_row_group += batch.num_rows() as u32;
-        if self.state.num_rows_in_cur_row_group as u64 >= self.row_group_size {
-            debug!(
-                rows_in_group = self.state.num_rows_in_cur_row_group,
-                "Flushing full row group"
-            );
-            self.flush_pending_chunks()?;
-            self.state.finish_row_group()?;
         }
         debug!(
             total_rows_written = self.state.num_rows_in_file,
@@ -312,12 +502,67 @@ impl<W: Write + Seek> FileWriter<W> {
         Ok(())
     }
 
+    /// Number of leading rows of `batch` after which some column completes an EncUnit.
+    fn rows_to_next_encunit(&self, batch: &RecordBatch) -> Result<usize> {
+        batch
+            .columns()
+            .iter()
+            .zip(&self.encunit_buffers)
+            .try_fold(batch.num_rows(), |len, (col, buffer)| {
+                Ok(len.min(buffer.rows_to_next_encunit(col.as_ref())?))
+            })
+    }
+
+    fn write_columns(&mut self, batch: &RecordBatch) -> Result<()> {
+        for (i, col) in batch.columns().iter().enumerate() {
+            self.state.bloom_filters.insert(i, col.as_ref())?;
+            for encunit in self.encunit_buffers[i].push(col.clone())? {
+                self.encode_column(i, encunit)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode the rows of an EncUnit of root-level column `i`, flushing the chunks that are full.
+    fn encode_column(&mut self, i: usize, array: ArrayRef) -> Result<()> {
+        if let Some(res) = self.column_encoders[i].encode(
+            array,
+            &mut self.state.column_counters[i],
+            &mut self.shared_dictionary_context,
+        )? {
+            res.into_iter()
+                .try_for_each(|chunk| self.state.flush_chunk(chunk))?;
+        }
+        Ok(())
+    }
+
+    /// Encode the remaining buffered rows as a shorter EncUnit, e.g., at the end of a row group.
+    fn encode_buffered_rows(&mut self) -> Result<()> {
+        for i in 0..self.encunit_buffers.len() {
+            if let Some(array) = self.encunit_buffers[i].take()? {
+                self.encode_column(i, array)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes of the current row group, both flushed to the file and buffered in the encoders.
+    fn cur_row_group_bytes(&self) -> u64 {
+        self.state.writer.position() - self.state.start_offset_of_cur_row_group
+            + self.memory_size() as u64
+    }
+
     pub fn memory_size(&self) -> usize {
-        self.column_encoders.iter().map(|e| e.memory_size()).sum()
+        self.column_encoders
+            .iter()
+            .map(|e| e.memory_size())
+            .chain(self.encunit_buffers.iter().map(|b| b.memory_size()))
+            .sum()
     }
 
     /// For testing memory usage if we correctly implement row groups
     pub fn flush_pending_chunks(&mut self) -> Result<()> {
+        self.encode_buffered_rows()?;
         for (i, encoder) in self.column_encoders.iter_mut().enumerate() {
             if let Some(res) = encoder.finish(
                 &mut self.state.column_counters[i],
@@ -330,11 +575,18 @@ impl<W: Write + Seek> FileWriter<W> {
         Ok(())
     }
 
+    pub fn finish(self) -> Result<Vec<EncodingCounter>> {
+        self.finish_into_inner().map(|(counters, _)| counters)
+    }
+
+    /// Finish the file and give back the underlying writer, e.g. to complete an upload.
     #[instrument(skip(self), fields(total_rows = self.state.num_rows_in_file, num_row_groups = self.state.row_groups_table.row_counts().len()))]
-    pub fn finish(mut self) -> Result<Vec<EncodingCounter>> {
+    pub fn finish_into_inner(mut self) -> Result<(Vec<EncodingCounter>, W)> {
         info!("Finishing file write");
         let start = std::time::Instant::now();
 
+        // Buffered rows must reach the encoders before their values are submitted to dictionaries.
+        self.encode_buffered_rows()?;
         // if dictionary mode is global with sharing, first submit all values to dictionary context
         if self.shared_dictionary_context.is_multi_col_sharing() {
             for encoder in self.column_encoders.iter_mut() {
@@ -346,8 +598,13 @@ impl<W: Write + Seek> FileWriter<W> {
         // flush pendding data in encoders
         self.flush_pending_chunks()?;
 
-        // Make sure flushed pending data added to row group metadata
-        self.state.finish_row_group()?;
+        // Make sure flushed pending data added to row group metadata.
+        // Skip it if the last batch exactly filled a row group, except for an empty file.
+        if self.state.num_rows_in_cur_row_group > 0
+            || self.state.row_groups_table.row_counts().is_empty()
+        {
+            self.state.finish_row_group()?;
+        }
 
         // flush shared dictionary
         let (dict_chunks, merge_peers, dict_dtypes) = self
@@ -370,9 +627,9 @@ impl<W: Write + Seek> FileWriter<W> {
             .get_sorted_wasms()
             .into_iter()
             .map(|wasm| {
-                let offset = self.state.writer.stream_position()?;
+                let offset = self.state.writer.position();
                 self.state.write_and_update_file_level_checksum(wasm)?;
-                let size = self.state.writer.stream_position()? - offset;
+                let size = self.state.writer.position() - offset;
                 let mut b = fb::MetadataSectionBuilder::new(&mut fbb);
                 b.add_offset(offset);
                 b.add_size_(size as u32);
@@ -387,15 +644,20 @@ impl<W: Write + Seek> FileWriter<W> {
         let wasms = wasm_b_builder.finish();
         fbb.finish(wasms, None);
         let wasms = fbb.finished_data();
-        let wasm_meta_start = self.state.writer.stream_position()?;
+        let wasm_meta_start = self.state.writer.position();
         self.state.write_and_update_file_level_checksum(wasms)?;
-        let wasm_meta_size = self.state.writer.stream_position()? - wasm_meta_start;
+        let wasm_meta_size = self.state.writer.position() - wasm_meta_start;
+
+        // write Bloom filters, if any
+        let bloom_filters_section = self.state.flush_bloom_filters()?;
 
         // write ColumnMetadata and update indirect_row_group_metadata
-        let metadata_start = self
-            .state
-            .row_groups_table
-            .to_indirect_and_flush(&mut self.state.writer, self.state.data_checksum.as_mut())?;
+        let metadata_start = self.state.row_groups_table.to_indirect_and_flush(
+            &mut self.state.writer,
+            self.state.data_checksum.as_mut(),
+            self.state.checksum_type,
+            &self.state.column_ciphers,
+        )?;
 
         // write RowGroups fbs table to file
         let mut fbb = FlatBufferBuilder::new();
@@ -473,11 +735,40 @@ impl<W: Write + Seek> FileWriter<W> {
         let logical_tree = self.logical_tree.to_fb(&mut fbb);
 
         let optional_metadata_section = {
-            let name = fbb.create_string("WASMBinaries");
-            let names = fbb.create_vector(&[name]);
-            let offsets = fbb.create_vector(&[wasm_meta_start]);
-            let sizes = fbb.create_vector(&[wasm_meta_size as u32]);
-            let compression_types = fbb.create_vector(&[CompressionType::Uncompressed]);
+            let mut sections = vec![(
+                "WASMBinaries",
+                MetadataSection {
+                    offset: wasm_meta_start,
+                    size: wasm_meta_size as u32,
+                    compression_type: CompressionType::Uncompressed,
+                },
+            )];
+            if let Some(section) = bloom_filters_section {
+                sections.push((BLOOM_FILTERS_SECTION_NAME, section));
+            }
+            let names = sections
+                .iter()
+                .map(|(name, _)| fbb.create_string(name))
+                .collect::<Vec<_>>();
+            let names = fbb.create_vector(&names);
+            let offsets = fbb.create_vector(
+                &sections
+                    .iter()
+                    .map(|(_, section)| section.offset)
+                    .collect::<Vec<_>>(),
+            );
+            let sizes = fbb.create_vector(
+                &sections
+                    .iter()
+                    .map(|(_, section)| section.size)
+                    .collect::<Vec<_>>(),
+            );
+            let compression_types = fbb.create_vector(
+                &sections
+                    .iter()
+                    .map(|(_, section)| section.compression_type)
+                    .collect::<Vec<_>>(),
+            );
             let mut builder = fb::OptionalMetadataSectionsBuilder::new(&mut fbb);
             builder.add_names(names);
             builder.add_offsets(offsets);
@@ -486,14 +777,29 @@ impl<W: Write + Seek> FileWriter<W> {
             builder.finish()
         };
 
-        // Create encoding versions
-        let encoding_versions = create_default_encoding_versions()?;
+        // Versions of the encodings used in the file
+        let encoding_versions = create_encoding_versions(&self.state.encoding_types);
         let encoding_versions_fb = encoding_versions
             .iter()
             .map(|ev| ev.to_fb(&mut fbb))
             .collect::<Vec<_>>();
         let encoding_versions_fb = fbb.create_vector(&encoding_versions_fb);
 
+        let encryption = (!self.column_encryptions.is_empty()).then(|| {
+            let column_keys = self
+                .column_encryptions
+                .iter()
+                .map(|x| x.to_fb(&mut fbb))
+                .collect::<Vec<_>>();
+            let column_keys = fbb.create_vector(&column_keys);
+            fb::FileEncryption::create(
+                &mut fbb,
+                &fb::FileEncryptionArgs {
+                    column_keys: Some(column_keys),
+                },
+            )
+        });
+
         let footer = {
             let mut footer_builder = fb::FooterBuilder::new(&mut fbb);
             footer_builder.add_schema(schema);
@@ -502,31 +808,47 @@ impl<W: Write + Seek> FileWriter<W> {
             footer_builder.add_optional_sections(optional_metadata_section);
             footer_builder.add_shared_dictionary_table(shared_dict_table);
             footer_builder.add_encoding_versions(encoding_versions_fb);
+            if let Some(encryption) = encryption {
+                footer_builder.add_encryption(encryption);
+            }
             footer_builder.finish()
         };
         fbb.finish(footer, None);
-        let footer_data = fbb.finished_data();
+        let footer_data = match &self.footer_key {
+            Some(key) => encrypt_footer(fbb.finished_data(), key, self.state.writer.position())?,
+            None => fbb.finished_data().to_vec(),
+        };
         self.state
-            .write_and_update_file_level_checksum(footer_data)?;
+            .write_and_update_file_level_checksum(&footer_data)?;
 
         // write postscript to file
         let writer = &mut self.state.writer;
-        let metadata_size = (writer.stream_position()? - metadata_start) as u32;
+        let metadata_size = (writer.position() - metadata_start) as u32;
         writer.write_all(metadata_size.to_le_bytes().as_ref())?;
         let footer_size = footer_data.len() as u32;
         writer.write_all(footer_size.to_le_bytes().as_ref())?;
         let footer_compression = CompressionType::Uncompressed;
         writer.write_all(u8::from(footer_compression).to_le_bytes().as_ref())?;
-        writer.write_all((ChecksumType::XxHash as u8).to_le_bytes().as_ref())?;
+        writer.write_all((self.state.checksum_type as u8).to_le_bytes().as_ref())?;
         writer.write_all(self.state.data_checksum.finalize().to_le_bytes().as_ref())?;
+        // The schema checksum would allow guessing the encrypted schema.
+        let schema_checksum = if self.footer_key.is_some() {
+            0
+        } else {
+            schema_checksum
+        };
         writer.write_all(schema_checksum.to_le_bytes().as_ref())?;
         writer.write_all(MAJOR_VERSION.to_le_bytes().as_ref())?;
         writer.write_all(MINOR_VERSION.to_le_bytes().as_ref())?;
-        writer.write_all(MAGIC)?;
+        writer.write_all(if self.footer_key.is_some() {
+            ENCRYPTED_FOOTER_MAGIC
+        } else {
+            MAGIC
+        })?;
         writer.flush()?;
 
         let elapsed = start.elapsed();
-        let file_position = writer.stream_position()?;
+        let file_position = writer.position();
         info!(
             elapsed_ms = elapsed.as_millis(),
             file_size_bytes = file_position,
@@ -534,6 +856,101 @@ impl<W: Write + Seek> FileWriter<W> {
             "File write completed successfully"
         );
 
-        Ok(self.state.column_counters)
+        Ok((self.state.column_counters, self.state.writer.into_inner()?))
+    }
+}
+
+/// Paths of `field` and all its nested columns, see [`FileWriterOptions::column_policies`].
+fn collect_column_paths(field: &FieldRef, path: String, paths: &mut Vec<String>) {
+    match field.data_type() {
+        DataType::List(child) | DataType::LargeList(child) => {
+            collect_column_paths(child, format!("{}.{}", path, child.name()), paths)
+        }
+        DataType::Struct(children) => {
+            for child in children.iter() {
+                collect_column_paths(child, format!("{}.{}", path, child.name()), paths);
+            }
+        }
+        _ => {}
+    }
+    paths.push(path);
+}
+
+/// Columns of DictionaryArrays are stored as their values, with the dictionary encoding of the
+/// options. Dictionary encoders reuse the dictionaries of the input instead of hashing every row.
+fn schema_without_dictionaries(schema: &Schema) -> SchemaRef {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::Dictionary(_, value_type)
+                if matches!(value_type.as_ref(), non_nest_types!()) =>
+            {
+                Arc::new(
+                    field
+                        .as_ref()
+                        .clone()
+                        .with_data_type(value_type.as_ref().clone()),
+                )
+            }
+            _ => Arc::clone(field),
+        })
+        .collect::<Vec<_>>();
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+fn validate_column_policies(schema: &Schema, options: &FileWriterOptions) -> Result<()> {
+    if options.column_policies().is_empty() {
+        return Ok(());
+    }
+    let mut paths = vec![];
+    let mut encrypted_paths = vec![];
+    for (field_id, field) in schema.fields().iter().enumerate() {
+        let mut field_paths = vec![];
+        collect_column_paths(field, field.name().clone(), &mut field_paths);
+        if options.encryption().column_keys().contains_key(&field_id) {
+            encrypted_paths.extend(field_paths.iter().cloned());
+        }
+        paths.extend(field_paths);
+    }
+    for (path, policy) in options.column_policies() {
+        if !paths.contains(path) {
+            return Err(fff_core::errors::Error::General(format!(
+                "Encoding policy for unknown column {}",
+                path
+            )));
+        }
+        if let Some(id) = policy
+            .excluded_vortex_compressors()
+            .into_iter()
+            .flatten()
+            .find(|id| !VortexEncoder::is_known_compressor(id))
+        {
+            return Err(fff_core::errors::Error::General(format!(
+                "Unknown Vortex compressor {} in the encoding policy for column {}",
+                id, path
+            )));
+        }
+        match policy.dictionary_type() {
+            None
+            | Some(DictionaryTypeOptions::NoDictionary)
+            | Some(DictionaryTypeOptions::EncoderDictionary)
+            | Some(DictionaryTypeOptions::LocalDictionary) => {}
+            Some(_) if encrypted_paths.contains(path) => {
+                return nyi_err!(format!("Shared dictionary for encrypted column {}", path));
+            }
+            Some(DictionaryTypeOptions::GlobalDictionaryMultiColSharing)
+                if options.dictionary_type()
+                    != DictionaryTypeOptions::GlobalDictionaryMultiColSharing =>
+            {
+                // Sharing dictionaries across columns is decided for the whole file.
+                return Err(fff_core::errors::Error::General(format!(
+                    "Multi-column dictionary sharing for column {} requires it file-wide",
+                    path
+                )));
+            }
+            Some(_) => {}
+        }
     }
+    Ok(())
 }
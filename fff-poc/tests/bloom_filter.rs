@@ -0,0 +1,179 @@
+use std::{collections::HashMap, sync::Arc};
+
+use arrow::{array::AsArray, datatypes::Int64Type};
+use arrow_array::{ArrayRef, Int32Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use fff_poc::{
+    bloom_filter::{BloomFilterOptions, BloomFilterPredicate},
+    io::reader::CountingReader,
+    options::{DictionaryTypeOptions, FileWriterOptions},
+    reader::FileReaderV2Builder,
+    writer::FileWriter,
+};
+
+const ROWS_PER_GROUP: usize = 64 * 1024;
+const NUM_GROUPS: usize = 4;
+
+fn prepare_test_file(
+    bloom_filter_columns: HashMap<usize, BloomFilterOptions>,
+) -> Arc<std::fs::File> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+    ]));
+    let temp_file = Arc::new(tempfile::tempfile().unwrap());
+    let options = FileWriterOptions::builder()
+        .set_row_group_size(ROWS_PER_GROUP as u64)
+        .set_bloom_filter_columns(bloom_filter_columns)
+        .build();
+    let mut writer = FileWriter::try_new(schema.clone(), temp_file.clone(), options).unwrap();
+    for g in 0..NUM_GROUPS {
+        let ids = (g * ROWS_PER_GROUP..(g + 1) * ROWS_PER_GROUP).map(|i| i as i64);
+        let names = ids
+            .clone()
+            .map(|i| (i % 7 != 0).then(|| format!("user_{i}")));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(ids)) as ArrayRef,
+                Arc::new(StringArray::from_iter(names)) as ArrayRef,
+            ],
+        )
+        .unwrap();
+        writer.write_batch(&batch).unwrap();
+    }
+    writer.finish().unwrap();
+    temp_file
+}
+
+fn read_with_predicate(file: Arc<std::fs::File>, predicate: BloomFilterPredicate) -> usize {
+    let mut reader = FileReaderV2Builder::new(file)
+        .with_bloom_filter_predicate(predicate)
+        .build()
+        .unwrap();
+    reader
+        .read_file()
+        .unwrap()
+        .iter()
+        .map(|b| b.num_rows())
+        .sum()
+}
+
+#[test]
+fn test_bloom_filter_equal() {
+    let file = prepare_test_file(HashMap::from([(0, BloomFilterOptions::default())]));
+    let predicate =
+        BloomFilterPredicate::equal(0, Arc::new(Int64Array::from(vec![70000]))).unwrap();
+    assert_eq!(read_with_predicate(file.clone(), predicate), ROWS_PER_GROUP);
+    let predicate = BloomFilterPredicate::equal(0, Arc::new(Int64Array::from(vec![-1]))).unwrap();
+    assert_eq!(read_with_predicate(file, predicate), 0);
+}
+
+#[test]
+fn test_bloom_filter_in_list_with_cast() {
+    let file = prepare_test_file(HashMap::from([(0, BloomFilterOptions::default())]));
+    // Values are cast to the column type before probing.
+    let predicate =
+        BloomFilterPredicate::in_list(0, Arc::new(Int32Array::from(vec![5, 200000, -3])));
+    assert_eq!(read_with_predicate(file, predicate), 2 * ROWS_PER_GROUP);
+}
+
+#[test]
+fn test_bloom_filter_per_iounit() {
+    let file = prepare_test_file(HashMap::from([(
+        1,
+        BloomFilterOptions::new(1000, 0.001).with_per_iounit(true),
+    )]));
+    let predicate = BloomFilterPredicate::in_list(
+        1,
+        Arc::new(StringArray::from(vec![
+            Some("user_3"),
+            None,
+            Some("user_200001"),
+        ])),
+    );
+    assert_eq!(
+        read_with_predicate(file.clone(), predicate),
+        2 * ROWS_PER_GROUP
+    );
+    // Nulls never match.
+    let predicate =
+        BloomFilterPredicate::in_list(1, Arc::new(StringArray::from(vec![None::<&str>])));
+    assert_eq!(read_with_predicate(file, predicate), 0);
+}
+
+#[test]
+fn test_bloom_filter_missing_column() {
+    // No Bloom filter on the predicate's column, nothing is skipped.
+    let file = prepare_test_file(HashMap::from([(0, BloomFilterOptions::default())]));
+    let predicate = BloomFilterPredicate::equal(1, Arc::new(StringArray::from(vec!["x"]))).unwrap();
+    assert_eq!(
+        read_with_predicate(file, predicate),
+        NUM_GROUPS * ROWS_PER_GROUP
+    );
+}
+
+#[test]
+fn test_bloom_filter_skips_iounits() {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+    ]));
+    // Scramble the ids so that the EncUnits are not compressed to nothing.
+    let ids = Int64Array::from_iter_values(
+        (0..(NUM_GROUPS * ROWS_PER_GROUP) as i64)
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15u64 as i64)),
+    );
+    let names = StringArray::from_iter_values(
+        (0..NUM_GROUPS * ROWS_PER_GROUP).map(|i| format!("user_{i}")),
+    );
+    let file = Arc::new(tempfile::tempfile().unwrap());
+    let options = FileWriterOptions::builder()
+        .set_row_group_size(ROWS_PER_GROUP as u64)
+        .set_encoding_unit_len(4096)
+        .set_iounit_size(16 * 1024)
+        .set_dictionary_type(DictionaryTypeOptions::NoDictionary)
+        // The row group filters are saturated, only the IOUnit filters rule out rows.
+        .set_bloom_filter_columns(HashMap::from([(
+            0,
+            BloomFilterOptions::new(1000, 0.01).with_per_iounit(true),
+        )]))
+        .build();
+    let mut writer = FileWriter::try_new(schema.clone(), file.clone(), options).unwrap();
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(ids.clone()) as ArrayRef,
+            Arc::new(names) as ArrayRef,
+        ],
+    )
+    .unwrap();
+    writer.write_batch(&batch).unwrap();
+    writer.finish().unwrap();
+
+    let read = |predicate: Option<BloomFilterPredicate>| {
+        let counting_reader = CountingReader::new(file.clone());
+        let mut builder = FileReaderV2Builder::new(counting_reader.clone());
+        if let Some(predicate) = predicate {
+            builder = builder.with_bloom_filter_predicate(predicate);
+        }
+        let batches = builder.build().unwrap().read_file().unwrap();
+        (batches, counting_reader.bytes_read())
+    };
+    let (_, bytes_read_all) = read(None);
+    let row_id = 2 * ROWS_PER_GROUP + 12345;
+    let predicate = BloomFilterPredicate::equal(0, Arc::new(ids.slice(row_id, 1))).unwrap();
+    let (batches, bytes_read) = read(Some(predicate));
+    let num_rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+    assert!(num_rows > 0 && num_rows < ROWS_PER_GROUP / 4);
+    // The matching row is kept, aligned with the other column.
+    let expected_name = format!("user_{row_id}");
+    assert!(batches.iter().any(|batch| {
+        let ids_out = batch.column(0).as_primitive::<Int64Type>();
+        let names_out = batch.column(1).as_string::<i32>();
+        (0..batch.num_rows())
+            .any(|i| ids_out.value(i) == ids.value(row_id) && names_out.value(i) == expected_name)
+    }));
+    // The IOUnits of both columns outside of the matching rows are not fetched.
+    assert!(bytes_read < bytes_read_all / 8);
+}
@@ -4,11 +4,15 @@ use std::{
     sync::Arc,
 };
 
-use arrow_array::RecordBatch;
+use arrow_array::{ArrayRef, Int64Array, RecordBatch};
 use fff_core::errors::Error;
 use fff_poc::{
+    common::checksum::ChecksumType,
     options::FileWriterOptions,
-    reader::{FileReaderV2Builder, Selection},
+    reader::{
+        salvage_file, verify_file, CorruptionKind, CorruptionLocation, FileReaderV2Builder,
+        Selection,
+    },
     writer::FileWriter,
 };
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
@@ -57,6 +61,52 @@ fn corrupted_iounit() {
     ));
 }
 
+#[test]
+fn corrupted_encunit() {
+    for checksum_type in [
+        ChecksumType::Crc32c,
+        ChecksumType::Xxh3,
+        ChecksumType::Blake3,
+    ] {
+        let options = FileWriterOptions::builder()
+            .set_checksum_type(checksum_type)
+            .enable_io_unit_checksum(true)
+            .enable_encunit_checksum(true)
+            .build();
+        // Random values so that the first EncUnit spans the corrupted bytes.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let values = Int64Array::from_iter_values((0..65536).map(|_| rng.gen()));
+        let batch = RecordBatch::try_from_iter([("v", Arc::new(values) as ArrayRef)]).unwrap();
+        let temp_file = Arc::new(tempfile::tempfile().unwrap());
+        let mut writer = FileWriter::try_new(batch.schema(), temp_file.clone(), options).unwrap();
+        writer.write_batch(&batch).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = FileReaderV2Builder::new(temp_file.clone())
+            .with_verify_io_unit_checksum(true)
+            .with_selection(Selection::RowIndexes(vec![5]))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(reader.read_file().is_ok());
+
+        let mut file = temp_file.clone();
+        file.seek(SeekFrom::Start(100)).unwrap();
+        file.write_all(&[0; 100]).unwrap();
+
+        let mut reader = FileReaderV2Builder::new(temp_file)
+            .with_verify_io_unit_checksum(true)
+            .with_selection(Selection::RowIndexes(vec![5]))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(matches!(
+            reader.read_file(),
+            Err(Error::General(e)) if e.eq("EncUnit checksum verification failed")
+        ));
+    }
+}
+
 #[test]
 fn corrupted_flatbuffer() {
     let options = FileWriterOptions::builder().build();
@@ -97,6 +147,86 @@ fn verify_file_checksum() {
     ));
 }
 
+#[test]
+fn verify_intact_file() {
+    let options = FileWriterOptions::builder()
+        .enable_io_unit_checksum(true)
+        .build();
+    let temp_file = prepare_test_file(options);
+
+    let report = verify_file(temp_file);
+    assert!(report.is_ok(), "{:?}", report.findings);
+    assert!(report.num_row_groups > 0);
+    assert!(report.num_encunits >= report.num_chunks);
+}
+
+#[test]
+fn verify_and_salvage_corrupted_iounit() {
+    let options = FileWriterOptions::builder()
+        .enable_io_unit_checksum(true)
+        .build();
+    let temp_file = prepare_test_file(options);
+
+    let mut file = temp_file.clone();
+    file.seek(SeekFrom::Start(100)).unwrap();
+    file.write_all(&[0; 100]).unwrap();
+
+    let report = verify_file(temp_file.clone());
+    assert!(report
+        .findings
+        .iter()
+        .any(|f| f.location == CorruptionLocation::File
+            && f.kind == CorruptionKind::ChecksumMismatch));
+    let corrupted_columns = report
+        .findings
+        .iter()
+        .filter(|f| f.kind == CorruptionKind::ChecksumMismatch)
+        .filter_map(|f| match f.location {
+            CorruptionLocation::Chunk {
+                row_group, column, ..
+            } => Some((row_group, column)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(!corrupted_columns.is_empty());
+    assert!(corrupted_columns
+        .iter()
+        .all(|(row_group, _)| *row_group == 0));
+
+    let salvaged = salvage_file(temp_file);
+    assert_eq!(salvaged.row_groups.len(), report.num_row_groups);
+    // The taxi data is flat, so that physical columns are the fields.
+    for row_group in salvaged.row_groups.iter() {
+        let mut expected_missing = corrupted_columns
+            .iter()
+            .filter(|(rg, _)| *rg == row_group.row_group)
+            .map(|(_, column)| *column)
+            .collect::<Vec<_>>();
+        expected_missing.dedup();
+        assert_eq!(row_group.missing_fields, expected_missing);
+        assert!(row_group.batch.num_rows() > 0);
+    }
+    assert!(!salvaged.row_groups[0].missing_fields.is_empty());
+}
+
+#[test]
+fn verify_corrupted_footer() {
+    let options = FileWriterOptions::builder().build();
+    let temp_file = prepare_test_file(options);
+
+    let mut file = temp_file.clone();
+    file.seek(SeekFrom::End(-32 - 64)).unwrap();
+    file.write_all(&[42; 50]).unwrap();
+
+    let salvaged = salvage_file(temp_file);
+    assert!(salvaged
+        .report
+        .findings
+        .iter()
+        .any(|f| f.location == CorruptionLocation::Footer && f.kind == CorruptionKind::Malformed));
+    assert!(salvaged.row_groups.is_empty());
+}
+
 #[test]
 fn fuzz_test() {
     let options = FileWriterOptions::builder()
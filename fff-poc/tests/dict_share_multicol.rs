@@ -2,7 +2,11 @@ use std::sync::Arc;
 
 use arrow_array::{ArrayRef, Int32Array, RecordBatch};
 use arrow_schema::{Field, Schema};
-use fff_poc::{options::FileWriterOptions, reader::FileReaderV2Builder, writer::FileWriter};
+use fff_poc::{
+    options::FileWriterOptions,
+    reader::{FileReaderV2Builder, Projection},
+    writer::FileWriter,
+};
 
 #[test]
 fn test_multi_col_share_dict() {
@@ -62,3 +66,67 @@ fn test_multi_col_share_dict() {
     eprintln!("Shared counters: {:?}", counters);
     eprintln!("Sharing peers: {:?}", sharing_peers);
 }
+
+#[test]
+fn test_lazy_shared_dictionaries() {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("a", arrow_schema::DataType::Int32, false),
+        Field::new("b", arrow_schema::DataType::Int32, false),
+        Field::new("c", arrow_schema::DataType::Int32, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from_iter((0..20000).map(|i| i % 1000))) as ArrayRef,
+            Arc::new(Int32Array::from_iter((0..20000).map(|i| 50000 + i % 1500))) as ArrayRef,
+            Arc::new(Int32Array::from_iter((0..20000).map(|i| 90000 + i % 2000))) as ArrayRef,
+        ],
+    )
+    .unwrap();
+    let temp_file = Arc::new(tempfile::tempfile().unwrap());
+    let options = FileWriterOptions::builder()
+        .set_dictionary_type(
+            fff_poc::options::DictionaryTypeOptions::GlobalDictionaryMultiColSharing,
+        )
+        .set_row_group_size(5000)
+        .build();
+    let mut fff_writer = FileWriter::try_new(schema, temp_file.clone(), options).unwrap();
+    fff_writer.write_batch(&batch).unwrap();
+    fff_writer.finish().unwrap();
+
+    // Nothing is decoded when opening the file, and only the dictionaries of the projected column
+    // when reading it.
+    let mut reader = FileReaderV2Builder::new(temp_file.clone())
+        .with_projections(Projection::LeafColumnIndexes(vec![1]))
+        .build()
+        .unwrap();
+    let cache = reader.shared_dictionary_cache().unwrap();
+    assert!(cache.loaded_dictionaries().is_empty());
+    assert_eq!(cache.memory_size(), 0);
+    let batches = reader.read_file().unwrap();
+    assert!(batches.len() > 1);
+    let output = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+    assert_eq!(output.column(0).as_ref(), batch.column(1).as_ref());
+    let cache = reader.shared_dictionary_cache().unwrap();
+    let loaded = cache.loaded_dictionaries();
+    assert!(!loaded.is_empty());
+    assert!(loaded.len() < cache.get_dict_references().len());
+    assert!(cache.memory_size() > 0);
+
+    // With a tiny bound, only the last dictionary read stays decoded.
+    let mut reader = FileReaderV2Builder::new(temp_file)
+        .with_max_shared_dictionary_memory(1)
+        .build()
+        .unwrap();
+    let batches = reader.read_file().unwrap();
+    let output = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+    assert_eq!(output, batch);
+    assert_eq!(
+        reader
+            .shared_dictionary_cache()
+            .unwrap()
+            .loaded_dictionaries()
+            .len(),
+        1
+    );
+}
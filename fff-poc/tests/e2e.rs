@@ -13,16 +13,25 @@ use arrow::{
     array::{Int32Builder, ListBuilder},
     compute::concat_batches,
 };
-use arrow_array::{Array, ArrayRef, GenericByteViewArray, Int32Array, RecordBatch, UInt64Array};
+use arrow_array::{
+    Array, ArrayRef, GenericByteViewArray, Int32Array, Int64Array, RecordBatch, UInt64Array,
+};
 use arrow_schema::{ArrowError, DataType, Field, Schema};
 use fff_poc::{
     context::{WASMId, WasmLib},
-    io::reader::{ObjectStoreReadAt, Reader},
-    options::{CustomEncodingOptions, FileWriterOptions, FileWriterOptionsBuilder},
-    reader::{FileReaderV2Builder, Projection, Selection},
+    io::{
+        reader::{ObjectStoreReadAt, Reader},
+        writer::ObjectStoreWriter,
+    },
+    options::{
+        CustomEncodingOptions, DictionaryTypeOptions, FileWriterOptions, FileWriterOptionsBuilder,
+    },
+    reader::{
+        salvage_file, verify_file, DecoderPolicy, FileReaderV2Builder, Projection, Selection,
+    },
     writer::FileWriter,
 };
-use object_store::{aws::AmazonS3Builder, ObjectStore};
+use object_store::{aws::AmazonS3Builder, memory::InMemory, ObjectStore};
 
 fn read_parquet_file(file_path: impl AsRef<Path>, batch_size: usize) -> Vec<RecordBatch> {
     let parquet = std::fs::File::open(file_path).unwrap();
@@ -138,10 +147,13 @@ fn test_read_file_roundtrip(
     test_read(Arc::new(file), input_batches, proj, selection);
 }
 
+/// With `true`, every EncUnit is decoded by the built-in Wasm decoder carried in the file,
+/// which has to be built first, see `fff_test_util::BUILTIN_WASM_PATH`.
 #[rstest_reuse::template]
 #[rstest]
-// #[case(true)]
 #[case(false)]
+#[case(true)]
+#[ignore]
 fn enable_built_in_wasm(#[case] a: bool) {}
 
 #[apply(enable_built_in_wasm)]
@@ -236,8 +248,8 @@ fn test_64k_data(#[case] enable_built_in_wasm: bool) {
     );
 }
 
-#[test]
-fn test_basic_list() {
+#[apply(enable_built_in_wasm)]
+fn test_basic_list(#[case] enable_built_in_wasm: bool) {
     let schema = Arc::new(Schema::new(vec![Field::new(
         "a",
         DataType::List(Arc::new(Field::new("item", DataType::Int32, true))),
@@ -253,13 +265,15 @@ fn test_basic_list() {
     test_read_file_roundtrip(
         &[input_batch],
         Projection::default(),
-        FileWriterOptions::default(),
+        FileWriterOptionsBuilder::with_defaults()
+            .write_built_in_wasm(enable_built_in_wasm)
+            .build(),
         Selection::default(),
     );
 }
 
-#[test]
-fn test_complex_list_roundtrip() {
+#[apply(enable_built_in_wasm)]
+fn test_complex_list_roundtrip(#[case] enable_built_in_wasm: bool) {
     use lance_datagen::{array, gen, BatchCount, RowCount};
     let non_nullable_list = DataType::List(Arc::new(Field::new("a", DataType::Int32, false)));
     let nullable_list = DataType::List(Arc::new(Field::new("b", DataType::Int32, true)));
@@ -275,13 +289,15 @@ fn test_complex_list_roundtrip() {
     test_read_file_roundtrip(
         &input_batchs,
         Projection::default(),
-        FileWriterOptions::default(),
+        FileWriterOptionsBuilder::with_defaults()
+            .write_built_in_wasm(enable_built_in_wasm)
+            .build(),
         Selection::default(),
     );
 }
 
-#[test]
-fn test_complex_list_roundtrip2() {
+#[apply(enable_built_in_wasm)]
+fn test_complex_list_roundtrip2(#[case] enable_built_in_wasm: bool) {
     use lance_datagen::{array, gen, BatchCount, RowCount};
     let list_of_list = DataType::List(Arc::new(Field::new(
         "a",
@@ -299,7 +315,9 @@ fn test_complex_list_roundtrip2() {
     test_read_file_roundtrip(
         &input_batchs,
         Projection::default(),
-        FileWriterOptions::default(),
+        FileWriterOptionsBuilder::with_defaults()
+            .write_built_in_wasm(enable_built_in_wasm)
+            .build(),
         Selection::default(),
     );
 }
@@ -553,6 +571,89 @@ async fn test_s3() {
     );
 }
 
+fn multipart_test_batches() -> Vec<RecordBatch> {
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    // Random values so that the file spans several 5 MiB upload parts.
+    (0..4)
+        .map(|_| {
+            let a = Int64Array::from_iter_values((0..1 << 18).map(|_| rng.gen()));
+            let b = Int32Array::from_iter((0..1 << 18).map(|i| (i % 3 != 0).then_some(i)));
+            RecordBatch::try_from_iter([
+                ("a", Arc::new(a) as ArrayRef),
+                ("b", Arc::new(b) as ArrayRef),
+            ])
+            .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn test_non_seekable_writer() {
+    let input_batches = multipart_test_batches();
+    let mut writer = FileWriter::try_new(
+        input_batches[0].schema(),
+        Vec::new(),
+        FileWriterOptions::default(),
+    )
+    .unwrap();
+    for batch in input_batches.iter() {
+        writer.write_batch(batch).unwrap();
+    }
+    let (_, buf) = writer.finish_into_inner().unwrap();
+
+    let mut file = tempfile::tempfile().unwrap();
+    std::io::Write::write_all(&mut file, &buf).unwrap();
+    test_read(
+        Arc::new(file),
+        &input_batches,
+        Projection::All,
+        Selection::default(),
+    );
+}
+
+#[test]
+fn test_object_store_writer() {
+    let input_batches = multipart_test_batches();
+    let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let location = object_store::path::Path::from("multipart.fff");
+    let mut writer = ObjectStoreWriter::try_new(
+        input_batches[0].schema(),
+        store.clone(),
+        location.clone(),
+        FileWriterOptions::default(),
+    )
+    .unwrap();
+    for batch in input_batches.iter() {
+        writer.write_batch(batch).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let file = ObjectStoreReadAt::new(store, location.into());
+    test_read(file, &input_batches, Projection::All, Selection::default());
+}
+
+#[test]
+fn test_object_store_writer_abort_on_drop() {
+    let input_batches = multipart_test_batches();
+    let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let location = object_store::path::Path::from("aborted.fff");
+    let mut writer = ObjectStoreWriter::try_new(
+        input_batches[0].schema(),
+        store.clone(),
+        location.clone(),
+        FileWriterOptions::default(),
+    )
+    .unwrap();
+    for batch in input_batches.iter() {
+        writer.write_batch(batch).unwrap();
+    }
+    drop(writer);
+
+    let head = futures::executor::block_on(store.head(&location));
+    assert!(matches!(head, Err(object_store::Error::NotFound { .. })));
+}
+
 #[apply(enable_built_in_wasm)]
 #[ignore]
 fn test_taxi(#[case] enable_built_in_wasm: bool) {
@@ -669,6 +770,413 @@ fn test_multi_row_group_projection(#[case] enable_built_in_wasm: bool) {
     test(1024 * 1024);
 }
 
+#[test]
+fn test_exact_row_group_size() {
+    let batches = (0..5)
+        .map(|i| {
+            let a = Int64Array::from_iter_values((0..1000).map(|j| i * 1000 + j));
+            RecordBatch::try_from_iter([("a", Arc::new(a) as ArrayRef)]).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let row_group_rows = |options: FileWriterOptions| {
+        let mut file = tempfile::tempfile().unwrap();
+        write_batches(&mut file, &batches, options);
+        let file = Arc::new(file);
+        test_read(
+            file.clone(),
+            &batches,
+            Projection::default(),
+            Selection::default(),
+        );
+        salvage_file(file)
+            .row_groups
+            .iter()
+            .map(|rg| rg.batch.num_rows())
+            .collect::<Vec<_>>()
+    };
+
+    let rows = row_group_rows(
+        FileWriterOptionsBuilder::with_defaults()
+            .set_row_group_size(300)
+            .build(),
+    );
+    assert_eq!(rows, [vec![300; 16], vec![200]].concat());
+
+    // Exactly filled row groups do not leave an empty one behind.
+    let rows = row_group_rows(
+        FileWriterOptionsBuilder::with_defaults()
+            .set_row_group_size(500)
+            .build(),
+    );
+    assert_eq!(rows, vec![500; 10]);
+
+    // The byte limit is checked after every EncUnit, so a row group overshoots it by at most one.
+    let rows = row_group_rows(
+        FileWriterOptionsBuilder::with_defaults()
+            .set_encoding_unit_len(100)
+            .set_max_row_group_bytes(1)
+            .build(),
+    );
+    assert_eq!(rows, vec![100; 50]);
+}
+
+#[test]
+fn test_encunit_len_independent_of_batch_size() {
+    let batches = (0..70)
+        .map(|i| {
+            let a = Int64Array::from_iter_values((0..1000).map(|j| i * 1000 + j));
+            let b = Int32Array::from_iter((0..1000).map(|j| (j % 5 != 0).then_some(j)));
+            RecordBatch::try_from_iter([
+                ("a", Arc::new(a) as ArrayRef),
+                ("b", Arc::new(b) as ArrayRef),
+            ])
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let num_encunits = |options: FileWriterOptions| {
+        let mut file = tempfile::tempfile().unwrap();
+        write_batches(&mut file, &batches, options);
+        let file = Arc::new(file);
+        test_read(
+            file.clone(),
+            &batches,
+            Projection::default(),
+            Selection::default(),
+        );
+        let report = verify_file(file);
+        assert!(report.is_ok(), "{:?}", report.findings);
+        report.num_encunits
+    };
+
+    // 70000 rows make 17 full EncUnits of 4096 rows and a remainder, per column.
+    let options = FileWriterOptionsBuilder::with_defaults()
+        .set_dictionary_type(DictionaryTypeOptions::NoDictionary)
+        .set_encoding_unit_len(4096)
+        .build();
+    assert_eq!(num_encunits(options), 2 * 18);
+
+    // Batches smaller than a custom EncUnit length are buffered as well.
+    let options = FileWriterOptionsBuilder::with_defaults()
+        .set_dictionary_type(DictionaryTypeOptions::NoDictionary)
+        .set_encoding_unit_len(4096)
+        .set_custom_encunit_len([(1, 10000)].into())
+        .build();
+    assert_eq!(num_encunits(options), 18 + 7);
+
+    // Remainders are flushed at the end of every row group.
+    let options = FileWriterOptionsBuilder::with_defaults()
+        .set_dictionary_type(DictionaryTypeOptions::NoDictionary)
+        .set_encoding_unit_len(4096)
+        .set_row_group_size(35000)
+        .build();
+    assert_eq!(num_encunits(options), 2 * 2 * 9);
+
+    // 8-byte values cut EncUnits at 1024 rows before reaching 4096 rows.
+    let options = FileWriterOptionsBuilder::with_defaults()
+        .set_dictionary_type(DictionaryTypeOptions::NoDictionary)
+        .set_encoding_unit_len(4096)
+        .set_encoding_unit_size(Some(8 * 1024))
+        .build();
+    assert!(num_encunits(options) > 68 + 18);
+}
+
+#[test]
+fn test_column_encoding_policies() {
+    use fff_format::File::fff::flatbuf::CompressionType;
+    use fff_poc::options::ColumnEncodingPolicy;
+
+    let x_field = Arc::new(Field::new("x", DataType::Utf8, true));
+    let y_field = Arc::new(Field::new("y", DataType::Int32, true));
+    let batches = (0..10)
+        .map(|i| {
+            let a = Int64Array::from_iter_values((0..1000).map(|j| i * 1000 + j));
+            let x = arrow::array::StringArray::from_iter(
+                (0..1000).map(|j| (j % 7 != 0).then(|| format!("value{}", j % 13))),
+            );
+            let y = Int32Array::from_iter((0..1000).map(|j| (j % 5 != 0).then_some(j)));
+            let s = arrow::array::StructArray::from(vec![
+                (x_field.clone(), Arc::new(x) as ArrayRef),
+                (y_field.clone(), Arc::new(y) as ArrayRef),
+            ]);
+            let mut l = ListBuilder::new(Int32Builder::new());
+            for j in 0..1000 {
+                l.append_value((0..j % 4).map(|k| Some(k * j)));
+            }
+            RecordBatch::try_from_iter([
+                ("a", Arc::new(a) as ArrayRef),
+                ("s", Arc::new(s) as ArrayRef),
+                ("l", Arc::new(l.finish()) as ArrayRef),
+            ])
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let options = || {
+        FileWriterOptionsBuilder::with_defaults()
+            .set_column_policy(
+                "a",
+                ColumnEncodingPolicy::default()
+                    .with_dictionary_type(DictionaryTypeOptions::NoDictionary)
+                    .with_compression_type(CompressionType::Zstd)
+                    .with_compression_level(19)
+                    .with_encoding_unit_len(4096),
+            )
+            .set_column_policy(
+                "s",
+                ColumnEncodingPolicy::default().with_compression_type(CompressionType::Lz4),
+            )
+            .set_column_policy(
+                "s.x",
+                ColumnEncodingPolicy::default()
+                    .with_dictionary_type(DictionaryTypeOptions::LocalDictionary),
+            )
+            .set_column_policy(
+                "l.item",
+                ColumnEncodingPolicy::default().with_excluded_vortex_compressors(["vortex.dict"]),
+            )
+    };
+    test_read_file_roundtrip(
+        &batches,
+        Projection::default(),
+        options().build(),
+        Selection::default(),
+    );
+
+    let file = tempfile::tempfile().unwrap();
+    let unknown = options()
+        .set_column_policy("s.z", ColumnEncodingPolicy::default())
+        .build();
+    assert!(FileWriter::try_new(batches[0].schema(), file, unknown).is_err());
+
+    // A misspelled compressor would otherwise stay enabled.
+    let file = tempfile::tempfile().unwrap();
+    let unknown = options()
+        .set_column_policy(
+            "l.item",
+            ColumnEncodingPolicy::default().with_excluded_vortex_compressors(["vortex.dictionary"]),
+        )
+        .build();
+    assert!(FileWriter::try_new(batches[0].schema(), file, unknown).is_err());
+}
+
+/// Vortex exposed as a plugin, counting the EncUnits it decodes natively.
+#[derive(Debug)]
+struct VortexPlugin {
+    id: &'static str,
+    wasm: bool,
+    decoded: std::sync::atomic::AtomicUsize,
+}
+
+impl VortexPlugin {
+    fn new(id: &'static str, wasm: bool) -> Arc<Self> {
+        Arc::new(Self {
+            id,
+            wasm,
+            decoded: Default::default(),
+        })
+    }
+}
+
+impl fff_poc::registry::EncodingPlugin for VortexPlugin {
+    fn id(&self) -> &str {
+        self.id
+    }
+
+    fn version(&self) -> semver::Version {
+        semver::Version::new(1, 0, 0)
+    }
+
+    fn create_encoder(
+        &self,
+        _data_type: &DataType,
+    ) -> fff_core::errors::Result<std::rc::Rc<dyn fff_encoding::schemes::Encoder>> {
+        Ok(std::rc::Rc::new(
+            fff_encoding::schemes::vortex::VortexEncoder::default(),
+        ))
+    }
+
+    fn create_decoder(
+        &self,
+        data: bytes::Bytes,
+        output_type: &DataType,
+        _num_rows: u64,
+    ) -> fff_core::errors::Result<Box<dyn fff_poc::decoder::encunit::EncUnitDecoder>> {
+        self.decoded
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(Box::new(
+            fff_poc::decoder::encunit::VortexEncUnitDecoder::new(data, output_type.clone()),
+        ))
+    }
+
+    fn wasm_decoder(&self) -> Option<Vec<u8>> {
+        self.wasm
+            .then(|| std::fs::read(fff_test_util::BUILTIN_WASM_PATH.as_path()).unwrap())
+    }
+}
+
+fn plugin_batches() -> Vec<RecordBatch> {
+    (0..4)
+        .map(|i| {
+            let a = Int64Array::from_iter((0..10000).map(|j| (j % 3 != 0).then_some(i * j)));
+            let b = Int32Array::from_iter_values(0..10000);
+            RecordBatch::try_from_iter([
+                ("a", Arc::new(a) as ArrayRef),
+                ("b", Arc::new(b) as ArrayRef),
+            ])
+            .unwrap()
+        })
+        .collect()
+}
+
+fn plugin_options(id: &str) -> FileWriterOptions {
+    use fff_poc::options::{ColumnEncodingPolicy, CustomEncoderSelection};
+    FileWriterOptionsBuilder::with_defaults()
+        .set_column_policy(
+            "a",
+            ColumnEncodingPolicy::default()
+                .with_encoder(CustomEncoderSelection::Plugin(id.to_string())),
+        )
+        .build()
+}
+
+#[test]
+fn test_encoding_plugin() {
+    use fff_poc::registry::{register_encoding, unregister_encoding};
+
+    let plugin = VortexPlugin::new("test.e2e_plugin", false);
+    let batches = plugin_batches();
+    let mut file = tempfile::tempfile().unwrap();
+    // Not registered yet.
+    assert!(FileWriter::try_new(
+        batches[0].schema(),
+        file.try_clone().unwrap(),
+        plugin_options(plugin.id)
+    )
+    .is_err());
+
+    register_encoding(plugin.clone()).unwrap();
+    write_batches(&mut file, &batches, plugin_options(plugin.id));
+    file.rewind().unwrap();
+    let file = Arc::new(file);
+    test_read(
+        file.clone(),
+        &batches,
+        Projection::default(),
+        Selection::default(),
+    );
+    assert!(plugin.decoded.load(std::sync::atomic::Ordering::Relaxed) > 0);
+    // The plugin did not embed a Wasm decoder.
+    let mut reader = FileReaderV2Builder::new(file.clone())
+        .with_decoder_policy(DecoderPolicy::ForceWasm)
+        .build()
+        .unwrap();
+    assert!(reader.read_file().is_err());
+
+    // Without a native implementation nor an embedded Wasm decoder, the column cannot be read.
+    assert!(unregister_encoding(plugin.id, &semver::Version::new(1, 0, 0)).unwrap());
+    let mut reader = FileReaderV2Builder::new(file).build().unwrap();
+    assert!(reader.read_file().is_err());
+}
+
+#[test]
+#[ignore]
+fn test_encoding_plugin_wasm_fallback() {
+    use fff_poc::registry::{register_encoding, unregister_encoding};
+
+    let plugin = VortexPlugin::new("test.e2e_plugin_wasm", true);
+    let batches = plugin_batches();
+    register_encoding(plugin.clone()).unwrap();
+    let mut file = tempfile::tempfile().unwrap();
+    write_batches(&mut file, &batches, plugin_options(plugin.id));
+    file.rewind().unwrap();
+    let file = Arc::new(file);
+    assert!(unregister_encoding(plugin.id, &semver::Version::new(1, 0, 0)).unwrap());
+    test_read(file, &batches, Projection::default(), Selection::default());
+    assert_eq!(plugin.decoded.load(std::sync::atomic::Ordering::Relaxed), 0);
+}
+
+#[test]
+fn test_compatibility_report() {
+    use fff_poc::registry::{register_encoding, unregister_encoding};
+
+    let plugin = VortexPlugin::new("test.e2e_plugin_compat", false);
+    let batches = plugin_batches();
+    register_encoding(plugin.clone()).unwrap();
+    let mut file = tempfile::tempfile().unwrap();
+    let options = FileWriterOptionsBuilder::with_defaults()
+        .enable_encunit_encoding_versions(true)
+        .set_column_policy(
+            "a",
+            fff_poc::options::ColumnEncodingPolicy::default().with_encoder(
+                fff_poc::options::CustomEncoderSelection::Plugin(plugin.id.to_string()),
+            ),
+        )
+        .build();
+    write_batches(&mut file, &batches, options);
+    file.rewind().unwrap();
+    let file = Arc::new(file);
+    test_read(
+        file.clone(),
+        &batches,
+        Projection::default(),
+        Selection::default(),
+    );
+    let report = FileReaderV2Builder::new(file.clone())
+        .build()
+        .unwrap()
+        .compatibility_report()
+        .unwrap();
+    assert!(report.is_native(), "{report}");
+    assert_eq!(report.columns.len(), 2);
+    assert!(report.columns.iter().all(|column| column.num_native > 0));
+
+    assert!(unregister_encoding(plugin.id, &semver::Version::new(1, 0, 0)).unwrap());
+    let report = FileReaderV2Builder::new(file)
+        .with_projections(Projection::LeafColumnIndexes(vec![1, 0]))
+        .build()
+        .unwrap()
+        .compatibility_report()
+        .unwrap();
+    assert!(report.wasm_columns().is_empty());
+    assert_eq!(report.unreadable_columns(), vec![0]);
+    assert!(report.to_string().contains("Can not decode column 0"));
+}
+
+#[test]
+#[ignore]
+fn test_decoder_policy() {
+    use fff_poc::registry::{register_encoding, unregister_encoding};
+    use std::sync::atomic::Ordering;
+
+    let plugin = VortexPlugin::new("test.e2e_plugin_policy", true);
+    let batches = plugin_batches();
+    register_encoding(plugin.clone()).unwrap();
+    let mut file = tempfile::tempfile().unwrap();
+    write_batches(&mut file, &batches, plugin_options(plugin.id));
+    file.rewind().unwrap();
+    let file = Arc::new(file);
+    let expected = concat_batches(batches[0].schema_ref(), &batches).unwrap();
+    let read = |policy| {
+        let mut reader = FileReaderV2Builder::new(file.clone())
+            .with_decoder_policy(policy)
+            .build()
+            .unwrap();
+        let output = reader.read_file().unwrap();
+        let output = concat_batches(output[0].schema_ref(), &output).unwrap();
+        assert_eq!(output, expected);
+        reader.decoder_verification()
+    };
+
+    let verification = read(DecoderPolicy::Verify);
+    assert!(verification.is_ok(), "{:?}", verification.mismatches);
+    assert!(verification.num_verified > 0);
+    let decoded = plugin.decoded.load(Ordering::Relaxed);
+    assert!(decoded > 0);
+
+    read(DecoderPolicy::ForceWasm);
+    assert_eq!(plugin.decoded.load(Ordering::Relaxed), decoded);
+    assert!(unregister_encoding(plugin.id, &semver::Version::new(1, 0, 0)).unwrap());
+}
+
 #[apply(enable_built_in_wasm)]
 #[ignore]
 fn test_core(#[case] enable_built_in_wasm: bool) {
@@ -730,6 +1238,40 @@ fn test_pco_custom_wasm() {
     );
 }
 
+#[test]
+#[ignore]
+fn test_pco_sandboxed_custom_wasm() {
+    let a = Int32Array::from_iter_values(0..65536);
+    let b = Int64Array::from_iter((0..65536).map(|i| (i % 7 != 0).then_some(i * i)));
+    let c = arrow::array::StringArray::from_iter_values((0..65536).map(|i| format!("s{}", i % 10)));
+    let batches = vec![RecordBatch::try_from_iter([
+        ("a", Arc::new(a) as ArrayRef),
+        ("b", Arc::new(b) as ArrayRef),
+        ("c", Arc::new(c) as ArrayRef),
+    ])
+    .unwrap()];
+    // The same module encodes in the writer and decodes in the reader.
+    let wasm = std::fs::read(fff_test_util::PCO_REAL_WASM_PATH.as_path()).unwrap();
+    let wasms = HashMap::from([(
+        WASMId(0),
+        WasmLib::try_new_sandboxed(&wasm, wasm.clone()).unwrap(),
+    )]);
+    let data_type_to_wasm_id =
+        HashMap::from([(DataType::Int32, WASMId(0)), (DataType::Int64, WASMId(0))]);
+    test_read_file_roundtrip(
+        &batches,
+        Projection::default(),
+        FileWriterOptions::builder()
+            .set_custom_encoding_options(CustomEncodingOptions::new(wasms, data_type_to_wasm_id))
+            .build(),
+        Selection::default(),
+    );
+
+    // Decoder-only modules cannot encode.
+    let decoder_only = std::fs::read(fff_test_util::BUILTIN_WASM_PATH.as_path()).unwrap();
+    assert!(WasmLib::try_new_sandboxed(&decoder_only, decoder_only.clone()).is_err());
+}
+
 #[apply(enable_built_in_wasm)]
 fn test_compression(#[case] enable_built_in_wasm: bool) {
     use arrow_array::{Int8Array, RecordBatch};
@@ -798,4 +1340,188 @@ fn test_compression(#[case] enable_built_in_wasm: bool) {
             .build(),
         Selection::default(),
     );
+
+    for compression_type in [CompressionType::Snappy, CompressionType::Brotli] {
+        test_read_file_roundtrip(
+            &batches,
+            Projection::default(),
+            FileWriterOptionsBuilder::with_defaults()
+                .write_built_in_wasm(enable_built_in_wasm)
+                .set_compression_type(compression_type)
+                .build(),
+            Selection::default(),
+        );
+    }
+
+    // EncUnits that Zstd does not shrink enough are stored uncompressed, with and without mini EncUnits.
+    for mini_encunit_len in [None, Some(1024)] {
+        let mut options = FileWriterOptionsBuilder::with_defaults()
+            .write_built_in_wasm(enable_built_in_wasm)
+            .set_compression_type(CompressionType::Zstd)
+            .set_compression_level(19)
+            .set_min_compression_savings(50);
+        if let Some(mini_encunit_len) = mini_encunit_len {
+            options = options.set_mini_encunit_len(mini_encunit_len);
+        }
+        test_read_file_roundtrip(
+            &batches,
+            Projection::default(),
+            options.build(),
+            Selection::default(),
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn test_stateful_wasm_decoder() {
+    use fff_poc::decoder::encunit::{create_wasm_decoder, EncUnitDecoder};
+    use fff_ude_wasm::{EntryPoint, Runtime};
+
+    let wasm = std::fs::read(fff_test_util::ADV_WASM_PATH.as_path()).unwrap();
+    let rt = Arc::new(Runtime::try_new(&wasm).unwrap());
+    assert_eq!(rt.entry_point().unwrap(), EntryPoint::Stateful);
+    let array = Arc::new(Int32Array::from_iter(
+        (0..65536).map(|i| (i % 7 != 0).then_some(i % 128)),
+    )) as ArrayRef;
+    let data = bytes::Bytes::from(wasm_test_encoders::encode_fff_general(array.clone()));
+    let decoder = create_wasm_decoder(data, rt, DataType::Int32, 65536, HashMap::new()).unwrap();
+    let mut num_rows = 0;
+    while let Some(vector) = decoder.decode_v2().unwrap() {
+        assert_eq!(&vector, &array.slice(num_rows, vector.len()));
+        num_rows += vector.len();
+    }
+    assert_eq!(num_rows, array.len());
+    assert!(decoder.decode_v2().unwrap().is_none());
+}
+
+#[rstest]
+#[case(DictionaryTypeOptions::LocalDictionary)]
+#[case(DictionaryTypeOptions::GlobalDictionary)]
+fn test_dictionary_arrays(#[case] dictionary_type: DictionaryTypeOptions) {
+    use arrow::compute::cast;
+    use arrow_array::{types::Int32Type, DictionaryArray, StringArray};
+
+    let values = StringArray::from_iter_values((0..100).map(|i| format!("value_{i}")));
+    let keys = Int32Array::from_iter((0..20000).map(|i| (i % 13 != 0).then_some(i % 100)));
+    let dictionary = DictionaryArray::<Int32Type>::try_new(keys, Arc::new(values)).unwrap();
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "dict",
+        dictionary.data_type().clone(),
+        true,
+    )]));
+    let batch = RecordBatch::try_new(schema, vec![Arc::new(dictionary) as ArrayRef]).unwrap();
+    let expected = cast(batch.column(0), &DataType::Utf8).unwrap();
+    let mut file = tempfile::tempfile().unwrap();
+    let options = FileWriterOptions::builder()
+        .set_dictionary_type(dictionary_type.clone())
+        .set_encoding_unit_len(1000)
+        .set_row_group_size(5000)
+        .build();
+    write_batches(&mut file, &[batch], options);
+    file.rewind().unwrap();
+    let file = Arc::new(file);
+
+    // The file stores the values of the dictionary.
+    let mut reader = FileReaderV2Builder::new(file.clone()).build().unwrap();
+    assert_eq!(reader.schema().field(0).data_type(), &DataType::Utf8);
+    let output = reader
+        .read_file()
+        .unwrap()
+        .iter()
+        .map(|batch| cast(batch.column(0), &DataType::Utf8).unwrap())
+        .collect::<Vec<_>>();
+    let output =
+        arrow::compute::concat(&output.iter().map(|a| a.as_ref()).collect::<Vec<_>>()).unwrap();
+    assert_eq!(&output, &expected);
+
+    assert!(FileReaderV2Builder::new(file.clone())
+        .with_dictionary_arrays(DataType::Utf8)
+        .is_err());
+    let dict_data_type = DataType::Dictionary(Box::new(DataType::UInt16), Box::new(DataType::Utf8));
+    let mut reader = FileReaderV2Builder::new(file)
+        .with_dictionary_arrays(DataType::UInt16)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(reader.schema().field(0).data_type(), &dict_data_type);
+    let batches = reader.read_file().unwrap();
+    let mut num_rows = 0;
+    for batch in &batches {
+        let column = batch.column(0);
+        assert_eq!(column.data_type(), &dict_data_type);
+        assert_eq!(
+            cast(column, &DataType::Utf8).unwrap().as_ref(),
+            expected.slice(num_rows, column.len()).as_ref()
+        );
+        num_rows += column.len();
+    }
+    assert_eq!(num_rows, expected.len());
+    // EncUnits of a chunk referencing a shared dictionary share its values.
+    if matches!(dictionary_type, DictionaryTypeOptions::GlobalDictionary) {
+        let values = |i: usize| batches[i].column(0).as_any_dictionary().values().to_data();
+        assert!(values(0).ptr_eq(&values(1)));
+    }
+}
+
+#[test]
+fn test_dictionary_arrays_after_struct() {
+    use arrow::compute::cast;
+    use arrow_array::{types::Int32Type, DictionaryArray, StringArray, StructArray};
+
+    let values = StringArray::from_iter_values((0..10).map(|i| format!("value_{i}")));
+    let keys = Int32Array::from_iter_values((0..5000).map(|i| i % 10));
+    let dictionary = DictionaryArray::<Int32Type>::try_new(keys, Arc::new(values)).unwrap();
+    let structs = StructArray::from(vec![
+        (
+            Arc::new(Field::new("a", DataType::Int32, false)),
+            Arc::new(Int32Array::from_iter_values(0..5000)) as ArrayRef,
+        ),
+        (
+            Arc::new(Field::new("b", DataType::Int64, false)),
+            Arc::new(Int64Array::from_iter_values(0..5000)) as ArrayRef,
+        ),
+    ]);
+    let batch = RecordBatch::try_from_iter([
+        ("struct", Arc::new(structs) as ArrayRef),
+        ("dict", Arc::new(dictionary) as ArrayRef),
+    ])
+    .unwrap();
+    let expected = cast(batch.column(1), &DataType::Utf8).unwrap();
+    let mut file = tempfile::tempfile().unwrap();
+    let options = FileWriterOptions::builder()
+        .set_dictionary_type(DictionaryTypeOptions::LocalDictionary)
+        .build();
+    write_batches(&mut file, &[batch.clone()], options);
+    file.rewind().unwrap();
+    let file = Arc::new(file);
+
+    let dict_data_type = DataType::Dictionary(Box::new(DataType::UInt16), Box::new(DataType::Utf8));
+    let mut reader = FileReaderV2Builder::new(file.clone())
+        .with_dictionary_arrays(DataType::UInt16)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(
+        reader.schema().field(0).data_type(),
+        batch.schema().field(0).data_type()
+    );
+    assert_eq!(reader.schema().field(1).data_type(), &dict_data_type);
+    let batches = reader.read_file().unwrap();
+    let output = batches
+        .iter()
+        .map(|batch| cast(batch.column(1), &DataType::Utf8).unwrap())
+        .collect::<Vec<_>>();
+    let output =
+        arrow::compute::concat(&output.iter().map(|a| a.as_ref()).collect::<Vec<_>>()).unwrap();
+    assert_eq!(&output, &expected);
+
+    // The struct and its two fields take physical columns 0 to 2.
+    let reader = FileReaderV2Builder::new(file)
+        .with_projections(Projection::LeafColumnIndexes(vec![3]))
+        .with_dictionary_arrays(DataType::UInt16)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(reader.schema().field(1).data_type(), &dict_data_type);
 }
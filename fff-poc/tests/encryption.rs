@@ -0,0 +1,175 @@
+use std::{collections::HashMap, sync::Arc};
+
+use arrow::compute::{cast, concat_batches};
+use arrow_array::{Array, ArrayRef, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use fff_poc::{
+    bloom_filter::BloomFilterOptions,
+    encryption::{
+        EncryptionKey, FileEncryptionOptions, FileKeyRetriever, InMemoryKeyRetriever, KeyRetriever,
+    },
+    options::FileWriterOptions,
+    reader::{CorruptionKind, FileReaderV2Builder, FileVerifier, Projection, Selection},
+    writer::FileWriter,
+};
+
+const NUM_ROWS: usize = 3 * 64 * 1024;
+const SSN_KEY: [u8; 32] = [7; 32];
+const FOOTER_KEY: [u8; 16] = [3; 16];
+
+fn test_batch() -> RecordBatch {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("ssn", DataType::Utf8, true),
+    ]));
+    let ids = Int64Array::from_iter_values(0..NUM_ROWS as i64);
+    let ssns = StringArray::from_iter(
+        (0..NUM_ROWS).map(|i| (i % 5 != 0).then(|| format!("secret_ssn_{i:08}"))),
+    );
+    RecordBatch::try_new(
+        schema,
+        vec![Arc::new(ids) as ArrayRef, Arc::new(ssns) as ArrayRef],
+    )
+    .unwrap()
+}
+
+fn write_file(encryption: FileEncryptionOptions) -> (Arc<std::fs::File>, RecordBatch) {
+    let batch = test_batch();
+    let file = Arc::new(tempfile::tempfile().unwrap());
+    let options = FileWriterOptions::builder()
+        .set_encryption(encryption)
+        .build();
+    let mut writer = FileWriter::try_new(batch.schema(), file.clone(), options).unwrap();
+    writer.write_batch(&batch).unwrap();
+    writer.finish().unwrap();
+    (file, batch)
+}
+
+fn ssn_encryption() -> FileEncryptionOptions {
+    FileEncryptionOptions::new().with_column_key(1, EncryptionKey::new("ssn_key", SSN_KEY))
+}
+
+fn key_retriever() -> Arc<dyn KeyRetriever> {
+    Arc::new(
+        InMemoryKeyRetriever::new()
+            .with_key("ssn_key", SSN_KEY)
+            .with_key("footer_key", FOOTER_KEY),
+    )
+}
+
+fn file_contains(file: &std::fs::File, needle: &[u8]) -> bool {
+    let mut buf = vec![0; file.metadata().unwrap().len() as usize];
+    std::os::unix::fs::FileExt::read_exact_at(file, &mut buf, 0).unwrap();
+    buf.windows(needle.len()).any(|w| w == needle)
+}
+
+fn assert_same(output: &[RecordBatch], expected: &RecordBatch) {
+    let output = concat_batches(&output[0].schema(), output).unwrap();
+    assert_eq!(output.num_rows(), expected.num_rows());
+    for (actual, expected) in output.columns().iter().zip(expected.columns()) {
+        assert_eq!(
+            cast(actual, expected.data_type()).unwrap().as_ref(),
+            expected.as_ref()
+        );
+    }
+}
+
+#[test]
+fn test_encrypted_column_roundtrip() {
+    let (file, batch) = write_file(ssn_encryption());
+    assert!(!file_contains(&file, b"secret_ssn_"));
+    let mut reader = FileReaderV2Builder::new(file)
+        .with_key_retriever(key_retriever())
+        .build()
+        .unwrap();
+    assert_same(&reader.read_file().unwrap(), &batch);
+}
+
+#[test]
+fn test_encrypted_column_point_access() {
+    let (file, batch) = write_file(ssn_encryption());
+    for row_id in [1, 64 * 1024 + 3, NUM_ROWS - 1] {
+        let mut reader = FileReaderV2Builder::new(file.clone())
+            .with_key_retriever(key_retriever())
+            .with_selection(Selection::RowIndexes(vec![row_id as u64]))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_same(&reader.read_file().unwrap(), &batch.slice(row_id, 1));
+    }
+}
+
+#[test]
+fn test_missing_or_wrong_key() {
+    let (file, batch) = write_file(ssn_encryption());
+    // Without keys, only the plaintext columns can be read.
+    assert!(FileReaderV2Builder::new(file.clone()).build().is_err());
+    let mut reader = FileReaderV2Builder::new(file.clone())
+        .with_projections(Projection::new([0]))
+        .build()
+        .unwrap();
+    assert_same(&reader.read_file().unwrap(), &batch.project(&[0]).unwrap());
+
+    let wrong_keys = Arc::new(InMemoryKeyRetriever::new().with_key("ssn_key", [8; 32]));
+    assert!(FileReaderV2Builder::new(file)
+        .with_key_retriever(wrong_keys)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_verify_and_salvage_without_key() {
+    let (file, batch) = write_file(ssn_encryption());
+    let report = FileVerifier::new(file.clone())
+        .with_key_retriever(key_retriever())
+        .verify();
+    assert!(report.is_ok(), "{:?}", report.findings);
+
+    let salvaged = FileVerifier::new(file).salvage();
+    assert!(salvaged
+        .report
+        .findings
+        .iter()
+        .all(|f| f.kind == CorruptionKind::DecryptionFailure));
+    let ids = salvaged
+        .row_groups
+        .iter()
+        .map(|row_group| {
+            assert_eq!(row_group.missing_fields, vec![1]);
+            row_group.batch.clone()
+        })
+        .collect::<Vec<_>>();
+    assert_same(&ids, &batch.project(&[0]).unwrap());
+}
+
+#[test]
+fn test_encrypted_footer() {
+    let (file, batch) =
+        write_file(ssn_encryption().with_footer_key(EncryptionKey::new("footer_key", FOOTER_KEY)));
+    assert!(!file_contains(&file, b"secret_ssn_"));
+    // Column names are only in the footer.
+    assert!(!file_contains(&file, b"ssn\0"));
+    assert!(FileReaderV2Builder::new(file.clone()).build().is_err());
+
+    let key_dir = tempfile::tempdir().unwrap();
+    std::fs::write(key_dir.path().join("ssn_key.key"), SSN_KEY).unwrap();
+    std::fs::write(key_dir.path().join("footer_key.key"), FOOTER_KEY).unwrap();
+    for read_ahead in [false, true] {
+        let mut reader = FileReaderV2Builder::new(file.clone())
+            .with_key_retriever(Arc::new(FileKeyRetriever::new(key_dir.path())))
+            .with_read_ahead(read_ahead)
+            .build()
+            .unwrap();
+        assert_same(&reader.read_file().unwrap(), &batch);
+    }
+}
+
+#[test]
+fn test_no_plaintext_stats_for_encrypted_columns() {
+    let batch = test_batch();
+    let options = FileWriterOptions::builder()
+        .set_encryption(ssn_encryption())
+        .set_bloom_filter_columns(HashMap::from([(1, BloomFilterOptions::default())]))
+        .build();
+    assert!(FileWriter::try_new(batch.schema(), tempfile::tempfile().unwrap(), options).is_err());
+}
@@ -174,6 +174,46 @@ mod io_reader_tests {
     }
 }
 
+#[cfg(test)]
+mod writer_options_tests {
+    use std::sync::Arc;
+
+    use arrow_schema::{DataType, Field, Schema};
+    use fff_poc::options::{ColumnEncodingPolicy, FileWriterOptionsBuilder};
+    use fff_poc::writer::FileWriter;
+
+    fn try_new_writer(builder: FileWriterOptionsBuilder) -> String {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let result = FileWriter::try_new(schema, Vec::<u8>::new(), builder.build());
+        match result {
+            Ok(_) => panic!("Expected invalid options to be rejected"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_zero_row_group_size() {
+        let err_msg =
+            try_new_writer(FileWriterOptionsBuilder::with_defaults().set_row_group_size(0));
+        assert!(err_msg.contains("row_group_size"), "{}", err_msg);
+    }
+
+    #[test]
+    fn test_min_compression_savings_above_100_percent() {
+        let err_msg = try_new_writer(
+            FileWriterOptionsBuilder::with_defaults().set_min_compression_savings(101),
+        );
+        assert!(err_msg.contains("min_compression_savings"), "{}", err_msg);
+
+        // Column policies are validated as well.
+        let err_msg = try_new_writer(FileWriterOptionsBuilder::with_defaults().set_column_policy(
+            "a",
+            ColumnEncodingPolicy::default().with_min_compression_savings(101),
+        ));
+        assert!(err_msg.contains("min_compression_savings"), "{}", err_msg);
+    }
+}
+
 #[cfg(test)]
 mod dictionary_tests {
     // TODO: Add tests for dict/shared_dictionary_context.rs when Task 1.2 is implemented
@@ -0,0 +1,402 @@
+//! Golden files: F3 files written by earlier versions of the writer, checked in under
+//! `tests/golden/v<major>.<minor>/`, which the current reader must keep reading exactly.
+//!
+//! Each `<case>.f3` sits next to `<case>.arrow`, the Arrow IPC file of the batch written to it.
+//! Golden files are never rewritten. When `MINOR_VERSION` (or `MAJOR_VERSION`) changes, freeze the
+//! files of the new version with
+//!
+//! ```sh
+//! cargo test -p fff-poc --test golden -- --ignored freeze_golden_files
+//! ```
+//!
+//! after building the built-in Wasm decoder, see `fff_test_util::BUILTIN_WASM_PATH`.
+
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{
+        BinaryArray, BooleanArray, Date32Array, Date64Array, Float32Array, Float64Array,
+        Int16Array, Int32Array, Int32Builder, Int64Array, Int8Array, LargeBinaryArray,
+        LargeStringArray, ListBuilder, StringArray, StringBuilder, StructArray,
+        Time64MicrosecondArray, TimestampMicrosecondArray, UInt16Array, UInt32Array, UInt64Array,
+        UInt8Array,
+    },
+    compute::{cast, concat_batches},
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field};
+use fff_format::{
+    File::fff::flatbuf::CompressionType, MAJOR_VERSION, MINOR_VERSION, POSTSCRIPT_SIZE,
+};
+use fff_poc::{
+    common::checksum::ChecksumType,
+    options::{DictionaryTypeOptions, FileWriterOptions, FileWriterOptionsBuilder},
+    reader::FileReaderV2Builder,
+    writer::FileWriter,
+};
+
+const NUM_ROWS: usize = 4096;
+
+/// A representative file: its batch and the options it is written with.
+struct GoldenCase {
+    name: &'static str,
+    batch: fn() -> RecordBatch,
+    options: fn() -> FileWriterOptions,
+}
+
+fn cases() -> Vec<GoldenCase> {
+    macro_rules! case {
+        ($name:expr, $batch:expr, $options:expr) => {
+            GoldenCase {
+                name: $name,
+                batch: $batch,
+                options: $options,
+            }
+        };
+    }
+    fn defaults() -> FileWriterOptionsBuilder {
+        FileWriterOptionsBuilder::with_defaults()
+    }
+    fn dictionary(dictionary_type: DictionaryTypeOptions) -> FileWriterOptions {
+        defaults().set_dictionary_type(dictionary_type).build()
+    }
+    fn compression(compression_type: CompressionType) -> FileWriterOptions {
+        defaults().set_compression_type(compression_type).build()
+    }
+    fn checksums(checksum_type: ChecksumType) -> FileWriterOptions {
+        defaults()
+            .set_checksum_type(checksum_type)
+            .enable_io_unit_checksum(true)
+            .enable_encunit_checksum(true)
+            .build()
+    }
+    vec![
+        case!("primitives", primitives, || defaults().build()),
+        case!("nested", nested, || defaults().build()),
+        case!("no_dictionary", low_cardinality, || dictionary(
+            DictionaryTypeOptions::NoDictionary
+        )),
+        case!("encoder_dictionary", low_cardinality, || dictionary(
+            DictionaryTypeOptions::EncoderDictionary
+        )),
+        case!("local_dictionary", low_cardinality, || dictionary(
+            DictionaryTypeOptions::LocalDictionary
+        )),
+        case!("global_dictionary", low_cardinality, || dictionary(
+            DictionaryTypeOptions::GlobalDictionary
+        )),
+        case!("fixed_scope_dictionary", low_cardinality, || dictionary(
+            DictionaryTypeOptions::FixedScopeDictionary(1024)
+        )),
+        case!("multi_column_dictionary", low_cardinality, || dictionary(
+            DictionaryTypeOptions::GlobalDictionaryMultiColSharing
+        )),
+        case!("gl_best_dictionary", low_cardinality, || dictionary(
+            DictionaryTypeOptions::GLBest(None)
+        )),
+        case!("zstd", low_cardinality, || compression(
+            CompressionType::Zstd
+        )),
+        case!("lz4", low_cardinality, || compression(CompressionType::Lz4)),
+        case!("snappy", low_cardinality, || compression(
+            CompressionType::Snappy
+        )),
+        case!("brotli", low_cardinality, || compression(
+            CompressionType::Brotli
+        )),
+        case!("xxhash", primitives, || checksums(ChecksumType::XxHash)),
+        case!("crc32c", primitives, || checksums(ChecksumType::Crc32c)),
+        case!("xxh3", primitives, || checksums(ChecksumType::Xxh3)),
+        case!("blake3", primitives, || checksums(ChecksumType::Blake3)),
+        case!("built_in_wasm", primitives, || defaults()
+            .write_built_in_wasm(true)
+            .build()),
+        case!("row_groups", primitives, || defaults()
+            .set_row_group_size(1000)
+            .build()),
+        case!("mini_encunits", primitives, || defaults()
+            .set_mini_encunit_len(1024)
+            .build()),
+        case!("encunit_encoding_versions", primitives, || defaults()
+            .enable_encunit_encoding_versions(true)
+            .build()),
+    ]
+}
+
+/// The non-nested types, each with nulls.
+fn primitives() -> RecordBatch {
+    let valid = |i| i % 7 != 3;
+    let rows = || (0..NUM_ROWS).map(move |i| (i, valid(i)));
+    macro_rules! column {
+        ($array:ty, |$i:ident| $value:expr) => {
+            Arc::new(<$array>::from_iter(
+                rows().map(|($i, valid)| valid.then_some($value)),
+            )) as ArrayRef
+        };
+    }
+    RecordBatch::try_from_iter([
+        ("boolean", column!(BooleanArray, |i| i % 3 == 0)),
+        ("int8", column!(Int8Array, |i| (i % 256) as i8)),
+        ("int16", column!(Int16Array, |i| (i * 7) as i16)),
+        ("int32", column!(Int32Array, |i| (i * i) as i32)),
+        ("int64", column!(Int64Array, |i| (i as i64) << 33)),
+        ("uint8", column!(UInt8Array, |i| (i % 256) as u8)),
+        ("uint16", column!(UInt16Array, |i| (i * 13) as u16)),
+        ("uint32", column!(UInt32Array, |i| (i * 31) as u32)),
+        ("uint64", column!(UInt64Array, |i| u64::MAX - i as u64)),
+        ("float32", column!(Float32Array, |i| i as f32 / 8.0)),
+        ("float64", column!(Float64Array, |i| i as f64 * 1.1)),
+        ("date32", column!(Date32Array, |i| i as i32 + 19000)),
+        ("date64", column!(Date64Array, |i| i as i64 * 86_400_000)),
+        (
+            "timestamp",
+            column!(TimestampMicrosecondArray, |i| 1_700_000_000_000_000
+                + i as i64 * 1_000),
+        ),
+        (
+            "time64",
+            column!(Time64MicrosecondArray, |i| i as i64 * 1_000),
+        ),
+        ("utf8", column!(StringArray, |i| format!("value-{i}"))),
+        (
+            "large_utf8",
+            column!(LargeStringArray, |i| "x".repeat(i % 50)),
+        ),
+        ("binary", column!(BinaryArray, |i| i.to_le_bytes())),
+        (
+            "large_binary",
+            column!(LargeBinaryArray, |i| vec![i as u8; i % 10]),
+        ),
+    ])
+    .unwrap()
+}
+
+/// Lists, lists of lists and structs.
+fn nested() -> RecordBatch {
+    let mut list = ListBuilder::new(Int32Builder::new());
+    let mut list_of_list = ListBuilder::new(ListBuilder::new(Int32Builder::new()));
+    let mut list_of_utf8 = ListBuilder::new(StringBuilder::new());
+    for i in 0..NUM_ROWS {
+        if i % 11 == 5 {
+            list.append_null();
+            list_of_list.append_null();
+            list_of_utf8.append_null();
+            continue;
+        }
+        for j in 0..i % 5 {
+            list.values()
+                .append_option((j != 2).then_some((i + j) as i32));
+            for k in 0..j {
+                list_of_list.values().values().append_value(k as i32);
+            }
+            list_of_list.values().append(true);
+            list_of_utf8.values().append_value(format!("{i}-{j}"));
+        }
+        list.append(true);
+        list_of_list.append(true);
+        list_of_utf8.append(true);
+    }
+    let b = Arc::new(Int32Array::from_iter(
+        (0..NUM_ROWS).map(|i| (i % 3 != 0).then_some(i as i32)),
+    )) as ArrayRef;
+    let c = Arc::new(StringArray::from_iter(
+        (0..NUM_ROWS).map(|i| (i % 4 != 0).then(|| format!("s{}", i % 100))),
+    )) as ArrayRef;
+    let structs = StructArray::from(vec![
+        (Arc::new(Field::new("b", DataType::Int32, true)), b),
+        (Arc::new(Field::new("c", DataType::Utf8, true)), c),
+    ]);
+    RecordBatch::try_from_iter([
+        ("list", Arc::new(list.finish()) as ArrayRef),
+        ("list_of_list", Arc::new(list_of_list.finish()) as ArrayRef),
+        ("list_of_utf8", Arc::new(list_of_utf8.finish()) as ArrayRef),
+        ("struct", Arc::new(structs) as ArrayRef),
+    ])
+    .unwrap()
+}
+
+/// Repetitive values, which dictionaries and compression apply to.
+/// The two integer columns overlap, so that they can share a dictionary.
+fn low_cardinality() -> RecordBatch {
+    RecordBatch::try_from_iter([
+        (
+            "a",
+            Arc::new(Int32Array::from_iter_values(
+                (0..NUM_ROWS).map(|i| (i % 100) as i32),
+            )) as ArrayRef,
+        ),
+        (
+            "b",
+            Arc::new(Int32Array::from_iter_values(
+                (0..NUM_ROWS).map(|i| (i % 150) as i32 + 50),
+            )) as ArrayRef,
+        ),
+        (
+            "s",
+            Arc::new(StringArray::from_iter(
+                (0..NUM_ROWS).map(|i| (i % 9 != 0).then(|| format!("category-{}", i % 20))),
+            )) as ArrayRef,
+        ),
+    ])
+    .unwrap()
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// The frozen format versions, oldest first, with their directories.
+fn frozen_versions() -> Vec<((u16, u16), PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(golden_dir()) else {
+        return vec![];
+    };
+    let mut versions = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let (major, minor) = name.strip_prefix('v')?.split_once('.')?;
+            Some(((major.parse().ok()?, minor.parse().ok()?), path))
+        })
+        .collect::<Vec<_>>();
+    versions.sort();
+    versions
+}
+
+fn write_golden_file(dir: &Path, case: &GoldenCase) {
+    let batch = (case.batch)();
+    let file = File::create(dir.join(format!("{}.f3", case.name))).unwrap();
+    let mut writer = FileWriter::try_new(batch.schema(), file, (case.options)()).unwrap();
+    writer.write_batch(&batch).unwrap();
+    writer.finish().unwrap();
+
+    let file = File::create(dir.join(format!("{}.arrow", case.name))).unwrap();
+    let mut writer = arrow_ipc::writer::FileWriter::try_new(file, &batch.schema()).unwrap();
+    writer.write(&batch).unwrap();
+    writer.finish().unwrap();
+}
+
+/// Read `<name>.f3` and compare it with `<name>.arrow`.
+/// Columns are compared after casting to the written type, e.g., from Utf8View to Utf8.
+fn check_golden_file(dir: &Path, name: &str) {
+    let expected_batch = {
+        let file = File::open(dir.join(format!("{name}.arrow"))).unwrap();
+        let reader = arrow_ipc::reader::FileReader::try_new(file, None).unwrap();
+        let schema = reader.schema();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        concat_batches(&schema, &batches).unwrap()
+    };
+    let file = Arc::new(File::open(dir.join(format!("{name}.f3"))).unwrap());
+    let mut reader = FileReaderV2Builder::new(file)
+        .build()
+        .unwrap_or_else(|e| panic!("{dir:?}/{name}.f3: {e}"));
+    let output = reader
+        .read_file()
+        .unwrap_or_else(|e| panic!("{dir:?}/{name}.f3: {e}"));
+    let output = concat_batches(&reader.schema(), &output).unwrap();
+    assert_eq!(output.num_columns(), expected_batch.num_columns(), "{name}");
+    let fields = expected_batch.schema_ref().fields();
+    for ((field, expected), actual) in fields
+        .iter()
+        .zip(expected_batch.columns())
+        .zip(output.columns())
+    {
+        let actual = cast(actual, expected.data_type()).unwrap();
+        assert_eq!(
+            expected.as_ref(),
+            actual.as_ref(),
+            "{dir:?}/{name}.f3, column {}",
+            field.name()
+        );
+    }
+}
+
+#[test]
+fn test_golden_files() {
+    let versions = frozen_versions();
+    if versions.is_empty() {
+        eprintln!("No golden files are frozen in {:?} yet", golden_dir());
+    }
+    for (_, dir) in versions {
+        let mut names = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "f3"))
+            .map(|path| path.file_stem().unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert!(!names.is_empty(), "No golden files in {dir:?}");
+        for name in names {
+            check_golden_file(&dir, &name);
+        }
+    }
+}
+
+/// Fails once the format version changes until the golden files of the new version are frozen.
+/// Nothing is checked before the first version is frozen.
+#[test]
+fn test_golden_files_frozen() {
+    let current = (MAJOR_VERSION, MINOR_VERSION);
+    match frozen_versions().last() {
+        Some((latest, _)) => assert!(
+            *latest >= current,
+            "Format version {}.{} has no golden files yet, the latest are of {}.{}. Freeze them \
+             with `cargo test -p fff-poc --test golden -- --ignored freeze_golden_files`",
+            current.0,
+            current.1,
+            latest.0,
+            latest.1
+        ),
+        None => eprintln!(
+            "No golden files are frozen yet, freeze those of format version {}.{} with \
+             `cargo test -p fff-poc --test golden -- --ignored freeze_golden_files`",
+            current.0, current.1
+        ),
+    }
+}
+
+/// Write the golden files of the current format version. Never overwrites frozen ones.
+#[test]
+#[ignore]
+fn freeze_golden_files() {
+    let dir = golden_dir().join(format!("v{MAJOR_VERSION}.{MINOR_VERSION}"));
+    assert!(
+        !dir.exists(),
+        "The golden files of format version {MAJOR_VERSION}.{MINOR_VERSION} are already frozen in {dir:?}"
+    );
+    std::fs::create_dir_all(golden_dir()).unwrap();
+    // Written aside first, so that a failing case leaves no partial version behind.
+    let staging = tempfile::tempdir_in(golden_dir()).unwrap();
+    for case in cases() {
+        write_golden_file(staging.path(), &case);
+        check_golden_file(staging.path(), case.name);
+    }
+    std::fs::rename(staging.into_path(), &dir).unwrap();
+}
+
+/// Files of a newer minor version of the same major version are still readable.
+#[test]
+fn test_newer_minor_version() {
+    let case = &GoldenCase {
+        name: "newer_minor_version",
+        batch: low_cardinality,
+        options: FileWriterOptions::default,
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write_golden_file(dir.path(), case);
+    let mut file = File::options()
+        .write(true)
+        .open(dir.path().join(format!("{}.f3", case.name)))
+        .unwrap();
+    // The minor version is at bytes 28..30 of the postscript.
+    file.seek(SeekFrom::End(28 - POSTSCRIPT_SIZE as i64))
+        .unwrap();
+    file.write_all(&(MINOR_VERSION + 1).to_le_bytes()).unwrap();
+    drop(file);
+    check_golden_file(dir.path(), case.name);
+}
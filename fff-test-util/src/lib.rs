@@ -94,4 +94,26 @@ pub static BUILTIN_WASM_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
 });
 pub const WASM_FUNC_GENERAL: &str = "decode_general_ffi";
 
+/// Exports both the decoder and the sandboxed encoder of Pco.
+pub static PCO_REAL_WASM_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    find_wasm_path(
+        "FFF_PCO_REAL_WASM_PATH",
+        &[
+            BASE_PATH.join("target/wasm32-wasip1/opt-size-lvl3/fff_ude_example_pco_real.wasm"),
+            BASE_PATH.join("target/wasm32-wasip1/release/fff_ude_example_pco_real.wasm"),
+        ],
+    )
+});
+
+/// Stateful decoder of the built-in encoding, exporting init_ffi and decode_ffi.
+pub static ADV_WASM_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    find_wasm_path(
+        "FFF_ADV_WASM_PATH",
+        &[
+            BASE_PATH.join("target/wasm32-wasip1/opt-size-lvl3/adv_ude_fff.wasm"),
+            BASE_PATH.join("target/wasm32-wasip1/release/adv_ude_fff.wasm"),
+        ],
+    )
+});
+
 pub const TEST_SCHEMES: [&str; 6] = ["pco", "lz4", "flsbp", "fff", "gzip", "zstd"];
@@ -0,0 +1,706 @@
+//! Conformance checks for third-party Wasm decoders.
+//!
+//! A decoder module is loaded with [`Runtime`] and checked for:
+//! - the exports required by the host and a compatible ABI version,
+//! - round-trip correctness on data generated by lance-datagen for every supported Arrow type,
+//!   or on sample EncUnits with their expected output,
+//! - the handling of the `spd` and `ppd` kwargs (stateful decoders only),
+//! - bounded memory growth across repeated calls on the same instance,
+//! - deterministic output across calls and instances.
+//!
+//! Use [`check`] from tests, or the `fff-ude-conformance` command for a module on disk.
+
+use std::{
+    any::Any,
+    fmt,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+use arrow::compute::{cast, concat, kernels::cmp::eq, take};
+use arrow_array::{cast::AsArray, types::Int32Type, Array, ArrayRef, Int32Array, UInt32Array};
+use arrow_buffer::Buffer;
+use arrow_schema::{DataType, TimeUnit};
+use fff_core::{
+    errors::Result,
+    util::buffer_to_array::{array_from_arrow_buffers_iter, num_rows_from_arrow_buffers},
+};
+use fff_ude::kwargs::{kwargs_serialize, ppd_serialize, Operator, PPDExpr, ScalarValue, PPD, SPD};
+use fff_ude_wasm::{Instance, Runtime};
+use lance_datagen::{array, gen, RowCount, Seed};
+use roaring::RoaringBitmap;
+
+/// Exports the host calls on every decoder module, besides the decode functions.
+pub const REQUIRED_EXPORTS: [&str; 6] = [
+    "memory",
+    "alloc",
+    "dealloc",
+    "buffer_iterator_next",
+    "buffer_iterator_drop",
+    "buffer_drop",
+];
+/// Major version of the ABI implemented by the host, see `fff_ude::ffi`.
+pub const ABI_MAJOR_VERSION: u8 = 1;
+/// Default export of `#[fff_ude::decoder]`.
+pub const DEFAULT_EXPORT: &str = "decode_general_ffi";
+pub const INIT_EXPORT: &str = "init_ffi";
+pub const DECODE_EXPORT: &str = "decode_ffi";
+pub const ARROW_EXPORT: &str = "decode_arrow_ffi";
+/// Export releasing the output of [`ARROW_EXPORT`].
+pub const ARROW_RELEASE_EXPORT: &str = "arrow_array_release";
+
+/// How the host calls the decoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecoderAbi {
+    /// A decoder exported with `#[fff_ude::decoder]` under the given name.
+    General(String),
+    /// A decoder exported with `#[fff_ude::stateful_decoder]`, which also receives kwargs.
+    Stateful,
+    /// A decoder exported with `#[fff_ude::arrow_decoder]`, whose output is imported in place.
+    Arrow,
+}
+
+impl Default for DecoderAbi {
+    fn default() -> Self {
+        Self::General(DEFAULT_EXPORT.to_string())
+    }
+}
+
+/// Encodes an array into an EncUnit understood by the decoder under test.
+pub type NativeEncoder = Box<dyn Fn(ArrayRef) -> Result<Vec<u8>>>;
+
+/// An encoded EncUnit and the array it must decode to.
+pub struct Sample {
+    pub name: String,
+    pub encoded: Vec<u8>,
+    pub expected: ArrayRef,
+}
+
+/// Where the EncUnits under test come from.
+pub enum EncUnitSource {
+    /// Encode generated data of every type in [`ConformanceOptions::data_types`].
+    Native(NativeEncoder),
+    /// Use previously encoded EncUnits.
+    Samples(Vec<Sample>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ConformanceOptions {
+    pub abi: DecoderAbi,
+    /// Types to generate data for with a native encoder.
+    /// Types the encoder rejects are reported as skipped.
+    pub data_types: Vec<DataType>,
+    pub num_rows: usize,
+    pub null_probability: f64,
+    pub seed: u64,
+    /// Number of decodes on the same instance for the memory check.
+    pub leak_check_iterations: usize,
+    /// Largest growth of the linear memory in bytes allowed over the memory check.
+    pub max_memory_growth: usize,
+}
+
+impl Default for ConformanceOptions {
+    fn default() -> Self {
+        Self {
+            abi: DecoderAbi::default(),
+            data_types: default_data_types(),
+            num_rows: 4096,
+            null_probability: 0.1,
+            seed: 42,
+            leak_check_iterations: 64,
+            max_memory_growth: 1 << 20,
+        }
+    }
+}
+
+/// Non-nested types, which the built-in encoding stores whole in an EncUnit.
+/// Nested types are decoded with the layout of `format/wasm_buffers.md` when set in
+/// [`ConformanceOptions::data_types`].
+pub fn default_data_types() -> Vec<DataType> {
+    vec![
+        DataType::Boolean,
+        DataType::Int8,
+        DataType::Int16,
+        DataType::Int32,
+        DataType::Int64,
+        DataType::UInt8,
+        DataType::UInt16,
+        DataType::UInt32,
+        DataType::UInt64,
+        DataType::Float16,
+        DataType::Float32,
+        DataType::Float64,
+        DataType::Decimal128(38, 10),
+        DataType::Date32,
+        DataType::Date64,
+        DataType::Time32(TimeUnit::Millisecond),
+        DataType::Time64(TimeUnit::Microsecond),
+        DataType::Timestamp(TimeUnit::Microsecond, None),
+        DataType::Duration(TimeUnit::Millisecond),
+        DataType::Utf8,
+        DataType::LargeUtf8,
+        DataType::Binary,
+        DataType::LargeBinary,
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    /// The check does not apply, e.g., the decoder ignores an optional kwarg.
+    Skipped(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Default)]
+pub struct ConformanceReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    fn push(&mut self, name: impl Into<String>, outcome: Outcome) {
+        self.checks.push(CheckResult {
+            name: name.into(),
+            outcome,
+        });
+    }
+
+    /// No check failed.
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks
+            .iter()
+            .filter(|c| matches!(c.outcome, Outcome::Failed(_)))
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+        for check in &self.checks {
+            match &check.outcome {
+                Outcome::Passed => {
+                    passed += 1;
+                    writeln!(f, "PASS {}", check.name)?;
+                }
+                Outcome::Failed(reason) => {
+                    failed += 1;
+                    writeln!(f, "FAIL {}: {}", check.name, reason)?;
+                }
+                Outcome::Skipped(reason) => {
+                    skipped += 1;
+                    writeln!(f, "SKIP {}: {}", check.name, reason)?;
+                }
+            }
+        }
+        write!(f, "{passed} passed, {failed} failed, {skipped} skipped")
+    }
+}
+
+/// Run every check of the decoder in `wasm` on the EncUnits of `source`.
+pub fn check(
+    wasm: &[u8],
+    source: &EncUnitSource,
+    options: &ConformanceOptions,
+) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+    let rt = match Runtime::try_new(wasm) {
+        Ok(rt) => rt,
+        Err(e) => {
+            report.push("exports", Outcome::Failed(format!("{e:#}")));
+            return report;
+        }
+    };
+    report.push("exports", check_exports(&rt, &options.abi));
+    if !report.passed() {
+        return report;
+    }
+
+    let cases = match source {
+        EncUnitSource::Native(encoder) => options
+            .data_types
+            .iter()
+            .filter_map(|data_type| {
+                let name = data_type.to_string();
+                match generate_case(data_type, encoder, options) {
+                    Ok(case) => Some(case),
+                    Err(reason) => {
+                        report.push(format!("encode[{name}]"), Outcome::Skipped(reason));
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>(),
+        EncUnitSource::Samples(samples) => samples
+            .iter()
+            .map(|s| Case {
+                name: s.name.clone(),
+                encoded: s.encoded.clone(),
+                expected: s.expected.clone(),
+            })
+            .collect(),
+    };
+
+    for case in &cases {
+        let name = &case.name;
+        report.push(
+            format!("roundtrip[{name}]"),
+            check_roundtrip(&rt, case, options),
+        );
+        report.push(
+            format!("determinism[{name}]"),
+            check_determinism(&rt, case, options),
+        );
+        report.push(format!("memory[{name}]"), check_memory(&rt, case, options));
+        if options.abi == DecoderAbi::Stateful {
+            report.push(
+                format!("kwargs.unknown[{name}]"),
+                check_unknown_kwarg(&rt, case),
+            );
+            report.push(format!("kwargs.spd[{name}]"), check_spd(&rt, case));
+            report.push(format!("kwargs.ppd[{name}]"), check_ppd(&rt, case));
+        }
+    }
+    report
+}
+
+/// An EncUnit and the array it must decode to.
+struct Case {
+    name: String,
+    encoded: Vec<u8>,
+    expected: ArrayRef,
+}
+
+fn check_exports(rt: &Runtime, abi: &DecoderAbi) -> Outcome {
+    let (major, minor) = rt.abi_version();
+    if major != ABI_MAJOR_VERSION {
+        return Outcome::Failed(format!(
+            "ABI version {major}.{minor} is not compatible with {ABI_MAJOR_VERSION}.x"
+        ));
+    }
+    if *abi == DecoderAbi::Arrow && minor < 3 {
+        return Outcome::Failed(format!(
+            "ABI version {major}.{minor} has no Arrow decoders, which need 1.3"
+        ));
+    }
+    let decode_exports = match abi {
+        DecoderAbi::General(export) => vec![export.as_str()],
+        DecoderAbi::Stateful => vec![INIT_EXPORT, DECODE_EXPORT],
+        DecoderAbi::Arrow => vec![ARROW_EXPORT, ARROW_RELEASE_EXPORT],
+    };
+    let missing = REQUIRED_EXPORTS
+        .iter()
+        .chain(decode_exports.iter())
+        .filter(|export| !rt.functions().any(|f| f == **export))
+        .copied()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Outcome::Failed(format!("missing exports: {}", missing.join(", ")));
+    }
+    // The exports exist, now check their signatures.
+    match Instance::new(rt) {
+        Ok(_) => Outcome::Passed,
+        Err(e) => Outcome::Failed(format!("failed to instantiate: {e:#}")),
+    }
+}
+
+fn generate_case(
+    data_type: &DataType,
+    encoder: &NativeEncoder,
+    options: &ConformanceOptions,
+) -> std::result::Result<Case, String> {
+    let expected = catch_panic(|| generate(data_type, options))?;
+    let encoded = catch_panic(|| encoder(expected.clone()))?;
+    Ok(Case {
+        name: data_type.to_string(),
+        encoded,
+        expected,
+    })
+}
+
+fn generate(data_type: &DataType, options: &ConformanceOptions) -> Result<ArrayRef> {
+    let mut builder = gen().with_seed(Seed::from(options.seed));
+    builder.with_random_nulls(options.null_probability);
+    let batch = builder
+        .col("c", array::rand_type(data_type))
+        .into_batch_rows(RowCount::from(options.num_rows as u64))?;
+    Ok(batch.column(0).clone())
+}
+
+fn check_roundtrip(rt: &Runtime, case: &Case, options: &ConformanceOptions) -> Outcome {
+    let decoded = match decode_array(rt, &options.abi, case, &[], case.expected.len()) {
+        Ok(decoded) => decoded,
+        Err(reason) => return Outcome::Failed(reason),
+    };
+    compare(&case.expected, &decoded)
+}
+
+/// Decodes twice on one instance and once on another, all must be equal.
+fn check_determinism(rt: &Runtime, case: &Case, options: &ConformanceOptions) -> Outcome {
+    let num_rows = case.expected.len();
+    let run = || -> std::result::Result<Vec<ArrayRef>, String> {
+        let instance = new_instance(rt)?;
+        let first = decode_on(&instance, &options.abi, case, &[])?;
+        let second = decode_on(&instance, &options.abi, case, &[])?;
+        let other = decode_on(&new_instance(rt)?, &options.abi, case, &[])?;
+        [first, second, other]
+            .into_iter()
+            .map(|batches| to_array(case.expected.data_type(), batches, num_rows))
+            .collect()
+    };
+    match run() {
+        Ok(arrays) if arrays[0] != arrays[1] => {
+            Outcome::Failed("output differs between calls on the same instance".to_string())
+        }
+        Ok(arrays) if arrays[0] != arrays[2] => {
+            Outcome::Failed("output differs between instances".to_string())
+        }
+        Ok(_) => Outcome::Passed,
+        Err(reason) => Outcome::Failed(reason),
+    }
+}
+
+/// Every output buffer is dropped before the next decode, so the linear memory must stop growing
+/// once the allocator has warmed up. Growth beyond that means buffers are not freed by `dealloc`
+/// or `buffer_drop`, or stateful decoders are not freed once exhausted.
+fn check_memory(rt: &Runtime, case: &Case, options: &ConformanceOptions) -> Outcome {
+    let run = || -> std::result::Result<(usize, usize), String> {
+        let instance = new_instance(rt)?;
+        let decode = || decode_on(&instance, &options.abi, case, &[]);
+        // Warm up the allocator and the cached input allocation of the instance.
+        for _ in 0..2 {
+            drop(decode()?);
+        }
+        let before = memory_size(&instance)?;
+        for _ in 0..options.leak_check_iterations {
+            drop(decode()?);
+        }
+        Ok((before, memory_size(&instance)?))
+    };
+    match run() {
+        Ok((before, after)) if after.saturating_sub(before) > options.max_memory_growth => {
+            Outcome::Failed(format!(
+                "memory grew from {before} to {after} bytes over {} decodes",
+                options.leak_check_iterations
+            ))
+        }
+        Ok(_) => Outcome::Passed,
+        Err(reason) => Outcome::Failed(reason),
+    }
+}
+
+/// Decoders must ignore kwargs they do not know.
+fn check_unknown_kwarg(rt: &Runtime, case: &Case) -> Outcome {
+    let kwargs = kwargs_serialize(&[(b"fff_conformance_unknown".as_slice(), [1u8].as_slice())]);
+    match decode_array(
+        rt,
+        &DecoderAbi::Stateful,
+        case,
+        &kwargs,
+        case.expected.len(),
+    ) {
+        Ok(decoded) => compare(&case.expected, &decoded),
+        Err(reason) => Outcome::Failed(reason),
+    }
+}
+
+/// With `spd`, only the selected rows are decoded out.
+fn check_spd(rt: &Runtime, case: &Case) -> Outcome {
+    let selection = (0..case.expected.len() as u32)
+        .step_by(3)
+        .collect::<RoaringBitmap>();
+    let mut word = vec![];
+    if let Err(e) = selection.serialize_into(&mut word) {
+        return Outcome::Failed(format!("failed to serialize spd: {e}"));
+    }
+    let kwargs = kwargs_serialize(&[(SPD.as_bytes(), word.as_slice())]);
+    let batches = match decode_on_new(rt, case, &kwargs) {
+        Ok(batches) => batches,
+        Err(reason) => return Outcome::Failed(reason),
+    };
+    let indices = UInt32Array::from_iter_values(selection.iter());
+    let selected = match take(case.expected.as_ref(), &indices, None) {
+        Ok(selected) => selected,
+        Err(e) => return Outcome::Failed(e.to_string()),
+    };
+    expect_pushdown(case, batches, &selected, "spd")
+}
+
+/// With `ppd`, the decoder outputs the result of the predicate for every row.
+/// Predicates only compare Int32 values for now.
+fn check_ppd(rt: &Runtime, case: &Case) -> Outcome {
+    if case.expected.data_type() != &DataType::Int32 {
+        return Outcome::Skipped("ppd only applies to Int32".to_string());
+    }
+    let values = case.expected.as_primitive::<Int32Type>();
+    let Some(value) = values.iter().flatten().next() else {
+        return Outcome::Skipped("no value to compare with".to_string());
+    };
+    let word = ppd_serialize(PPDExpr::new(Operator::Eq, ScalarValue::I32(value)));
+    let kwargs = kwargs_serialize(&[(PPD.as_bytes(), word.as_slice())]);
+    let batches = match decode_on_new(rt, case, &kwargs) {
+        Ok(batches) => batches,
+        Err(reason) => return Outcome::Failed(reason),
+    };
+    let result = match eq(values, &Int32Array::new_scalar(value)) {
+        Ok(result) => Arc::new(result) as ArrayRef,
+        Err(e) => return Outcome::Failed(e.to_string()),
+    };
+    expect_pushdown(case, batches, &result, "ppd")
+}
+
+/// Decoders may ignore pushdown kwargs and decode everything, which is reported as skipped.
+fn expect_pushdown(
+    case: &Case,
+    batches: Vec<Vec<Buffer>>,
+    expected: &ArrayRef,
+    key: &str,
+) -> Outcome {
+    if let Ok(decoded) = to_array(expected.data_type(), batches.clone(), expected.len()) {
+        if compare(expected, &decoded) == Outcome::Passed {
+            return Outcome::Passed;
+        }
+    }
+    match to_array(case.expected.data_type(), batches, case.expected.len()) {
+        Ok(decoded) if compare(&case.expected, &decoded) == Outcome::Passed => {
+            Outcome::Skipped(format!("{key} is ignored"))
+        }
+        _ => Outcome::Failed(format!(
+            "output is neither the {key} result nor the full data"
+        )),
+    }
+}
+
+fn compare(expected: &ArrayRef, decoded: &ArrayRef) -> Outcome {
+    // Decoders may output an equivalent type, e.g., Utf8View for Utf8.
+    let decoded = if decoded.data_type() == expected.data_type() {
+        decoded.clone()
+    } else {
+        match cast(decoded, expected.data_type()) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                return Outcome::Failed(format!(
+                    "output type {} is not {}: {e}",
+                    decoded.data_type(),
+                    expected.data_type()
+                ))
+            }
+        }
+    };
+    if decoded.len() != expected.len() {
+        return Outcome::Failed(format!(
+            "decoded {} rows instead of {}",
+            decoded.len(),
+            expected.len()
+        ));
+    }
+    if &decoded != expected {
+        let (nulls, values) = (0..expected.len()).fold((0, 0), |(nulls, values), i| {
+            if decoded.is_null(i) != expected.is_null(i) {
+                (nulls + 1, values)
+            } else if expected.is_valid(i) && decoded.slice(i, 1) != expected.slice(i, 1) {
+                (nulls, values + 1)
+            } else {
+                (nulls, values)
+            }
+        });
+        return Outcome::Failed(format!(
+            "output differs from the input: {nulls} rows with wrong validity, {values} with wrong values"
+        ));
+    }
+    Outcome::Passed
+}
+
+fn decode_array(
+    rt: &Runtime,
+    abi: &DecoderAbi,
+    case: &Case,
+    kwargs: &[u8],
+    num_rows: usize,
+) -> std::result::Result<ArrayRef, String> {
+    let batches = decode_on(&new_instance(rt)?, abi, case, kwargs)?;
+    to_array(case.expected.data_type(), batches, num_rows)
+}
+
+fn decode_on_new(
+    rt: &Runtime,
+    case: &Case,
+    kwargs: &[u8],
+) -> std::result::Result<Vec<Vec<Buffer>>, String> {
+    decode_on(&new_instance(rt)?, &DecoderAbi::Stateful, case, kwargs)
+}
+
+fn new_instance(rt: &Runtime) -> std::result::Result<Arc<Mutex<Instance>>, String> {
+    Instance::new(rt)
+        .map(|instance| Arc::new(Mutex::new(instance)))
+        .map_err(|e| format!("failed to instantiate: {e:#}"))
+}
+
+fn memory_size(instance: &Arc<Mutex<Instance>>) -> std::result::Result<usize, String> {
+    Ok(instance.lock().map_err(|e| e.to_string())?.memory_size())
+}
+
+/// Decode the EncUnit of `case` on `instance`, returning the buffers of every batch.
+/// The instance lock is released before the buffers are read or dropped, since both take it again.
+fn decode_on(
+    instance: &Arc<Mutex<Instance>>,
+    abi: &DecoderAbi,
+    case: &Case,
+    kwargs: &[u8],
+) -> std::result::Result<Vec<Vec<Buffer>>, String> {
+    let input = case.encoded.as_slice();
+    let lock = || instance.lock().map_err(|e| e.to_string());
+    let wasm_error = |e: anyhow::Error| format!("{e:#}");
+    catch_panic(|| match abi {
+        DecoderAbi::General(export) => {
+            let iter = lock()?
+                .call_generic_function(export, input, instance.clone())
+                .map_err(wasm_error)?;
+            Ok(vec![iter.collect()])
+        }
+        DecoderAbi::Stateful => {
+            let decoder = lock()?.call_init(input, kwargs).map_err(wasm_error)?.ptr();
+            let mut batches = vec![];
+            loop {
+                let next = lock()?
+                    .call_decode(decoder, instance.clone())
+                    .map_err(wasm_error)?;
+                match next {
+                    Some(iter) => batches.push(iter.collect()),
+                    None => return Ok(batches),
+                }
+            }
+        }
+        DecoderAbi::Arrow => {
+            let array = lock()?
+                .call_arrow_function(
+                    ARROW_EXPORT,
+                    input,
+                    case.expected.data_type(),
+                    instance.clone(),
+                )
+                .map_err(wasm_error)?;
+            Ok(vec![array.buffers])
+        }
+    })
+}
+
+/// Assemble the buffers of every batch into an array of `data_type`.
+fn to_array(
+    data_type: &DataType,
+    batches: Vec<Vec<Buffer>>,
+    num_rows: usize,
+) -> std::result::Result<ArrayRef, String> {
+    let assemble = |buffers: Vec<Buffer>, num_rows: usize| {
+        array_from_arrow_buffers_iter(data_type, buffers.into_iter(), num_rows as u64)
+            .map_err(|e| format!("invalid output buffers: {e}"))
+    };
+    match batches.len() {
+        0 => Err("decoder returned no batch".to_string()),
+        1 => assemble(batches.into_iter().next().unwrap(), num_rows),
+        _ => {
+            let arrays = batches
+                .into_iter()
+                .map(|buffers| {
+                    let rows =
+                        num_rows_from_arrow_buffers(data_type, &buffers).ok_or_else(|| {
+                            format!("cannot infer the number of rows of a {data_type} batch")
+                        })?;
+                    assemble(buffers, rows as usize)
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            concat(&arrays.iter().map(|a| a.as_ref()).collect::<Vec<_>>())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Run `f`, turning both errors and panics into a message.
+/// Iterating output buffers panics on errors in the Wasm module.
+fn catch_panic<T, E: fmt::Display>(
+    f: impl FnOnce() -> std::result::Result<T, E>,
+) -> std::result::Result<T, String> {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(payload) => Err(format!("panicked: {}", panic_message(payload.as_ref()))),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_array() {
+        let batch = |values: &[i32]| {
+            vec![
+                Buffer::from(Vec::<u8>::new()),
+                Buffer::from_slice_ref(values),
+            ]
+        };
+        let array = to_array(&DataType::Int32, vec![batch(&[1, 2]), batch(&[3, 4, 5])], 5).unwrap();
+        let expected = Arc::new(Int32Array::from(vec![1, 2, 3, 4, 5])) as ArrayRef;
+        assert_eq!(compare(&expected, &array), Outcome::Passed);
+        assert!(to_array(&DataType::Boolean, vec![vec![], vec![]], 5).is_err());
+
+        let other = Arc::new(Int32Array::from(vec![
+            Some(1),
+            None,
+            Some(0),
+            Some(4),
+            Some(5),
+        ]));
+        assert_eq!(
+            compare(&expected, &(other as ArrayRef)),
+            Outcome::Failed(
+                "output differs from the input: 1 rows with wrong validity, 1 with wrong values"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_invalid_module() {
+        let report = check(
+            b"not wasm",
+            &EncUnitSource::Samples(vec![]),
+            &ConformanceOptions::default(),
+        );
+        assert!(!report.passed());
+        assert_eq!(report.checks[0].name, "exports");
+    }
+
+    #[test]
+    #[ignore]
+    fn test_builtin_decoder() {
+        let wasm = std::fs::read(fff_test_util::BUILTIN_WASM_PATH.as_path()).unwrap();
+        let encoder: NativeEncoder =
+            Box::new(|array| Ok(wasm_test_encoders::encode_fff_general(array)));
+        let source = EncUnitSource::Native(encoder);
+        for abi in [DecoderAbi::default(), DecoderAbi::Arrow] {
+            let options = ConformanceOptions {
+                abi,
+                ..Default::default()
+            };
+            let report = check(&wasm, &source, &options);
+            assert!(report.passed(), "{report}");
+        }
+    }
+}
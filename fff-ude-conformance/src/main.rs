@@ -0,0 +1,156 @@
+//! Check a Wasm decoder against the UDE conformance suite.
+//!
+//! ```text
+//! fff-ude-conformance decoder.wasm                       # general decoder, Vortex encoder
+//! fff-ude-conformance decoder.wasm --stateful            # init_ffi/decode_ffi
+//! fff-ude-conformance decoder.wasm --arrow               # decode_arrow_ffi
+//! fff-ude-conformance decoder.wasm --wasm-encoder e.wasm # encode with the encode_ffi of e.wasm
+//! fff-ude-conformance decoder.wasm --samples dir/        # <name>.bin with <name>.arrow
+//! ```
+
+use std::{fs::File, path::PathBuf, process::ExitCode, sync::Arc};
+
+use arrow::compute::concat;
+use arrow_array::ArrayRef;
+use arrow_ipc::{reader::FileReader, writer::StreamWriter};
+use arrow_schema::{Field, Schema};
+use clap::{Parser, ValueEnum};
+use fff_core::errors::{Error, Result};
+use fff_ude_conformance::{
+    check, ConformanceOptions, DecoderAbi, EncUnitSource, NativeEncoder, Sample, DEFAULT_EXPORT,
+};
+use fff_ude_wasm::Runtime;
+use wasm_test_encoders::{encode_fff_general, encode_pco_real_general};
+
+/// Export of the sandboxed encoders, see `fff_ude::ffi::encode_wrapper`.
+const WASM_ENCODE_FUNC: &str = "encode_ffi";
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum BuiltinEncoder {
+    /// The Vortex encoder of the built-in decoder.
+    Vortex,
+    /// Pco for numeric types.
+    Pco,
+}
+
+#[derive(Parser, Debug)]
+#[command(about = "Check a Wasm decoder against the UDE conformance suite")]
+struct Args {
+    /// The Wasm decoder module.
+    decoder: PathBuf,
+    /// Export of a general decoder.
+    #[arg(long, default_value = DEFAULT_EXPORT)]
+    export: String,
+    /// The decoder is stateful, i.e., exports init_ffi and decode_ffi.
+    #[arg(long, conflicts_with = "export")]
+    stateful: bool,
+    /// The decoder exports decode_arrow_ffi, i.e., outputs an Arrow C Data Interface array.
+    #[arg(long, conflicts_with_all = ["export", "stateful"])]
+    arrow: bool,
+    /// Native encoder for the generated data.
+    #[arg(long, value_enum, conflicts_with_all = ["wasm_encoder", "samples"])]
+    encoder: Option<BuiltinEncoder>,
+    /// Wasm module exporting encode_ffi for the generated data.
+    #[arg(long, conflicts_with = "samples")]
+    wasm_encoder: Option<PathBuf>,
+    /// Directory of sample EncUnits <name>.bin, each with its expected output <name>.arrow
+    /// as an Arrow IPC file.
+    #[arg(long)]
+    samples: Option<PathBuf>,
+    #[arg(long, default_value_t = 4096)]
+    num_rows: usize,
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+    /// Number of decodes on the same instance for the memory check.
+    #[arg(long, default_value_t = 64)]
+    iterations: usize,
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    let wasm = std::fs::read(&args.decoder)?;
+    let source = if let Some(dir) = &args.samples {
+        EncUnitSource::Samples(read_samples(dir)?)
+    } else if let Some(path) = &args.wasm_encoder {
+        EncUnitSource::Native(wasm_encoder(&std::fs::read(path)?)?)
+    } else {
+        EncUnitSource::Native(builtin_encoder(
+            args.encoder.unwrap_or(BuiltinEncoder::Vortex),
+        ))
+    };
+    let options = ConformanceOptions {
+        abi: if args.stateful {
+            DecoderAbi::Stateful
+        } else if args.arrow {
+            DecoderAbi::Arrow
+        } else {
+            DecoderAbi::General(args.export)
+        },
+        num_rows: args.num_rows,
+        seed: args.seed,
+        leak_check_iterations: args.iterations,
+        ..Default::default()
+    };
+    let report = check(&wasm, &source, &options);
+    println!("{report}");
+    Ok(if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn builtin_encoder(encoder: BuiltinEncoder) -> NativeEncoder {
+    match encoder {
+        BuiltinEncoder::Vortex => Box::new(|array| Ok(encode_fff_general(array))),
+        BuiltinEncoder::Pco => Box::new(|array| Ok(encode_pco_real_general(array))),
+    }
+}
+
+fn wasm_encoder(binary: &[u8]) -> Result<NativeEncoder> {
+    let rt = Runtime::try_new(binary)?;
+    if !rt.functions().any(|f| f == WASM_ENCODE_FUNC) {
+        return Err(Error::General(format!(
+            "the encoder module does not export {WASM_ENCODE_FUNC}"
+        )));
+    }
+    Ok(Box::new(move |array: ArrayRef| {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "",
+            array.data_type().clone(),
+            true,
+        )]));
+        let mut ipc = vec![];
+        let mut writer = StreamWriter::try_new(&mut ipc, &schema)?;
+        writer.write(&arrow_array::RecordBatch::try_new(schema, vec![array])?)?;
+        writer.finish()?;
+        drop(writer);
+        Ok(rt.call_encode(WASM_ENCODE_FUNC, &ipc)?)
+    }))
+}
+
+fn read_samples(dir: &PathBuf) -> Result<Vec<Sample>> {
+    let mut samples = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "bin") {
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let reader = FileReader::try_new(File::open(path.with_extension("arrow"))?, None)?;
+        let columns = reader
+            .map(|batch| Ok(batch?.column(0).clone()))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        let expected = concat(&columns.iter().map(|c| c.as_ref()).collect::<Vec<_>>())?;
+        samples.push(Sample {
+            name,
+            encoded: std::fs::read(&path)?,
+            expected,
+        });
+    }
+    samples.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(samples)
+}
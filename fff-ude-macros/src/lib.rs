@@ -0,0 +1,208 @@
+//! Attribute macros generating the FFI exports of Wasm decoders.
+//!
+//! Use them as `#[fff_ude::decoder]`, `#[fff_ude::stateful_decoder]` and `#[fff_ude::arrow_decoder]`,
+//! since the generated code refers to items of `fff_ude`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse::Parser, punctuated::Punctuated, Error, Expr, ExprLit, Ident, ItemFn, Lit, LitStr, Meta,
+    Result, Token,
+};
+
+/// Export looked up by the reader for general decoders.
+const DEFAULT_DECODE_EXPORT: &str = "decode_general_ffi";
+
+/// Export a general decoder `fn(input: &[u8]) -> fff_ude::Result<Box<dyn Iterator<Item = Buffer>>>`.
+///
+/// The export is named `decode_general_ffi` unless set with `#[fff_ude::decoder(export = "...")]`.
+#[proc_macro_attribute]
+pub fn decoder(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand_decoder(attr.into(), item.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Export the `init` of a stateful decoder
+/// `fn(input: &[u8], kwargs: fff_ude::kwargs::Kwargs) -> fff_ude::Result<impl StatefulWasmDecoder>`,
+/// together with the matching `decode`. The exports are named `init_ffi` and `decode_ffi`.
+#[proc_macro_attribute]
+pub fn stateful_decoder(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand_stateful_decoder(attr.into(), item.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Export a decoder `fn(input: &[u8]) -> fff_ude::Result<ArrayRef>` whose output the reader
+/// imports in place through the Arrow C Data Interface. The export is named `decode_arrow_ffi`.
+#[proc_macro_attribute]
+pub fn arrow_decoder(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand_arrow_decoder(attr.into(), item.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_decoder(attr: TokenStream2, item: TokenStream2) -> Result<TokenStream2> {
+    let export = parse_export(attr)?;
+    let user_fn: ItemFn = syn::parse2(item)?;
+    check_signature(&user_fn, 1)?;
+    let name = &user_fn.sig.ident;
+    let abi_version = abi_version_symbol(name);
+    Ok(quote! {
+        #user_fn
+
+        #abi_version
+
+        #[no_mangle]
+        #[allow(clippy::missing_safety_doc)]
+        pub unsafe extern "C" fn #export(
+            ptr: *const u8,
+            len: usize,
+            out: *mut ::fff_ude::ffi::CSlice,
+        ) -> i32 {
+            ::fff_ude::ffi::general_wrapper(#name, ptr, len, out)
+        }
+    })
+}
+
+fn expand_stateful_decoder(attr: TokenStream2, item: TokenStream2) -> Result<TokenStream2> {
+    if !attr.is_empty() {
+        return Err(Error::new_spanned(
+            attr,
+            "stateful decoders are always exported as `init_ffi` and `decode_ffi`",
+        ));
+    }
+    let user_fn: ItemFn = syn::parse2(item)?;
+    check_signature(&user_fn, 2)?;
+    let name = &user_fn.sig.ident;
+    let init = format_ident!("__fff_ude_init_{}", name);
+    let abi_version = abi_version_symbol(name);
+    Ok(quote! {
+        #user_fn
+
+        #abi_version
+
+        fn #init(
+            input: &[u8],
+            kwargs: &[u8],
+        ) -> ::fff_ude::Result<::std::boxed::Box<dyn ::fff_ude::StatefulWasmDecoder>> {
+            let kwargs = ::fff_ude::kwargs::Kwargs::parse(kwargs)?;
+            let decoder: ::std::boxed::Box<dyn ::fff_ude::StatefulWasmDecoder> =
+                ::std::boxed::Box::new(#name(input, kwargs)?);
+            Ok(decoder)
+        }
+
+        #[no_mangle]
+        #[allow(clippy::missing_safety_doc)]
+        pub unsafe extern "C" fn init_ffi(
+            input_ptr: *const u8,
+            input_len: usize,
+            kwargs_ptr: *const u8,
+            kwargs_len: usize,
+            out: *mut ::fff_ude::ffi::CSlice,
+        ) -> i32 {
+            ::fff_ude::ffi::init_wrapper(#init, input_ptr, input_len, kwargs_ptr, kwargs_len, out)
+        }
+
+        #[no_mangle]
+        #[allow(clippy::missing_safety_doc)]
+        pub unsafe extern "C" fn decode_ffi(
+            decoder: *mut ::fff_ude::ffi::WasmDecoder,
+            out: *mut ::fff_ude::ffi::CSlice,
+        ) -> i32 {
+            ::fff_ude::ffi::decode_wrapper(::fff_ude::ffi::decode_next, decoder, out)
+        }
+    })
+}
+
+fn expand_arrow_decoder(attr: TokenStream2, item: TokenStream2) -> Result<TokenStream2> {
+    if !attr.is_empty() {
+        return Err(Error::new_spanned(
+            attr,
+            "arrow decoders are always exported as `decode_arrow_ffi`",
+        ));
+    }
+    let user_fn: ItemFn = syn::parse2(item)?;
+    check_signature(&user_fn, 1)?;
+    let name = &user_fn.sig.ident;
+    let abi_version = abi_version_symbol(name);
+    Ok(quote! {
+        #user_fn
+
+        #abi_version
+
+        #[no_mangle]
+        #[allow(clippy::missing_safety_doc)]
+        pub unsafe extern "C" fn decode_arrow_ffi(
+            ptr: *const u8,
+            len: usize,
+            out: *mut ::fff_ude::ffi::CSlice,
+        ) -> i32 {
+            ::fff_ude::ffi::arrow_wrapper(#name, ptr, len, out)
+        }
+    })
+}
+
+/// Parse `export = "..."`. The runtime only finds exports whose names end with `ffi`.
+fn parse_export(attr: TokenStream2) -> Result<Ident> {
+    let mut export = LitStr::new(DEFAULT_DECODE_EXPORT, Span::call_site());
+    for meta in Punctuated::<Meta, Token![,]>::parse_terminated.parse2(attr)? {
+        match meta {
+            Meta::NameValue(nv) if nv.path.is_ident("export") => match nv.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => export = s,
+                other => return Err(Error::new_spanned(other, "expected a string literal")),
+            },
+            other => {
+                return Err(Error::new_spanned(
+                    other,
+                    "unknown argument, expected `export = \"...\"`",
+                ))
+            }
+        }
+    }
+    if !export.value().ends_with("ffi") {
+        return Err(Error::new_spanned(
+            &export,
+            "the export name must end with `ffi` to be found by the runtime",
+        ));
+    }
+    let ident = syn::parse_str::<Ident>(&export.value())
+        .map_err(|_| Error::new_spanned(&export, "the export name must be an identifier"))?;
+    Ok(Ident::new(&ident.to_string(), export.span()))
+}
+
+fn check_signature(user_fn: &ItemFn, num_inputs: usize) -> Result<()> {
+    let sig = &user_fn.sig;
+    if let Some(asyncness) = sig.asyncness {
+        return Err(Error::new_spanned(asyncness, "decoders cannot be async"));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "decoders cannot be generic",
+        ));
+    }
+    if sig.inputs.len() != num_inputs {
+        return Err(Error::new_spanned(
+            &sig.inputs,
+            format!("expected {} arguments", num_inputs),
+        ));
+    }
+    Ok(())
+}
+
+/// Keep the ABI version symbol of fff_ude, which the runtime checks, in the final module.
+fn abi_version_symbol(name: &Ident) -> TokenStream2 {
+    let symbol = format_ident!("__FFF_UDE_ABI_VERSION_{}", name.to_string().to_uppercase());
+    quote! {
+        #[used]
+        #[doc(hidden)]
+        static #symbol: &() = &::fff_ude::ffi::ABI_VERSION;
+    }
+}
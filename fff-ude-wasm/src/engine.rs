@@ -0,0 +1,213 @@
+//! Configuration of the wasmtime engines compiling the decoders, and tiered compilation.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+};
+
+use anyhow::{bail, Result};
+use wasmtime::{Engine, Module, OptLevel, Strategy};
+
+static DEFAULT_ENGINE: OnceLock<Engine> = OnceLock::new();
+
+/// The compiler of the Wasm modules.
+#[derive(Debug, Clone)]
+pub enum Compiler {
+    /// Cranelift at the given optimization level.
+    Cranelift(OptLevel),
+    /// Winch, the baseline compiler: it compiles much faster than Cranelift into slower code.
+    /// Only available on x86_64, and without SIMD.
+    Winch,
+}
+
+/// How accesses to the linear memory are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundsChecks {
+    /// Rely on guard pages after the memory to elide most checks, at the cost of virtual memory.
+    GuardPages,
+    /// No guard pages, every access is checked in the compiled code.
+    /// The memory is still reserved up front, since decoded buffers point into it.
+    Explicit,
+}
+
+/// Configuration of a wasmtime [`Engine`].
+///
+/// The default compiles quickly with Cranelift and no optimization, as decoders usually run
+/// shortly after being read from a file.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    compiler: Compiler,
+    simd: Option<bool>,
+    relaxed_simd: Option<bool>,
+    bounds_checks: BoundsChecks,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            compiler: Compiler::Cranelift(OptLevel::None),
+            simd: None,
+            relaxed_simd: None,
+            bounds_checks: BoundsChecks::GuardPages,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Optimized code for hot decoders, e.g., for the tier-up engine of
+    /// [`Config::tier_up`](crate::Config::tier_up).
+    pub fn optimized() -> Self {
+        Self::default().compiler(Compiler::Cranelift(OptLevel::Speed))
+    }
+
+    pub fn compiler(mut self, compiler: Compiler) -> Self {
+        self.compiler = compiler;
+        self
+    }
+
+    /// Enable SIMD, which is on by default except with Winch.
+    pub fn simd(mut self, enable: bool) -> Self {
+        self.simd = Some(enable);
+        self
+    }
+
+    /// Enable relaxed SIMD, which is on by default if SIMD is.
+    pub fn relaxed_simd(mut self, enable: bool) -> Self {
+        self.relaxed_simd = Some(enable);
+        self
+    }
+
+    pub fn bounds_checks(mut self, bounds_checks: BoundsChecks) -> Self {
+        self.bounds_checks = bounds_checks;
+        self
+    }
+
+    pub fn build(&self) -> Result<Engine> {
+        let mut config = wasmtime::Config::new();
+        config.parallel_compilation(true);
+        let winch = match &self.compiler {
+            Compiler::Cranelift(level) => {
+                config
+                    .strategy(Strategy::Cranelift)
+                    .cranelift_opt_level(level.clone());
+                false
+            }
+            Compiler::Winch => {
+                config.strategy(Strategy::Winch);
+                true
+            }
+        };
+        let simd = self.simd.unwrap_or(!winch);
+        let relaxed_simd = self.relaxed_simd.unwrap_or(simd);
+        if relaxed_simd && !simd {
+            bail!("relaxed SIMD requires SIMD");
+        }
+        config.wasm_simd(simd).wasm_relaxed_simd(relaxed_simd);
+        if self.bounds_checks == BoundsChecks::Explicit {
+            config.memory_guard_size(0);
+        }
+        Engine::new(&config)
+    }
+}
+
+/// The engine of runtimes created without one, e.g., with
+/// [`Runtime::try_new`](crate::Runtime::try_new).
+pub fn default_engine() -> &'static Engine {
+    DEFAULT_ENGINE.get_or_init(|| {
+        EngineConfig::default()
+            .build()
+            .expect("failed to create wasmtime Engine - this is a critical initialization failure")
+    })
+}
+
+/// Set the default engine. Fails if it was already created, i.e., once a runtime was created
+/// without an engine.
+pub fn init_default_engine(config: &EngineConfig) -> Result<&'static Engine> {
+    let engine = config.build()?;
+    if DEFAULT_ENGINE.set(engine).is_err() {
+        bail!("the default engine is already initialized");
+    }
+    Ok(default_engine())
+}
+
+/// Recompiles a module with another engine in the background once it is hot.
+pub(crate) struct TierUp {
+    binary: Arc<[u8]>,
+    engine: Engine,
+    after_calls: u64,
+    calls: AtomicU64,
+    module: Arc<OnceLock<Module>>,
+}
+
+impl TierUp {
+    pub(crate) fn new(binary: &[u8], engine: Engine, after_calls: u64) -> Self {
+        let tier_up = Self {
+            binary: binary.into(),
+            engine,
+            after_calls,
+            calls: AtomicU64::new(0),
+            module: Arc::new(OnceLock::new()),
+        };
+        if after_calls == 0 {
+            tier_up.compile();
+        }
+        tier_up
+    }
+
+    /// Count a call, and start compiling on the `after_calls`-th.
+    pub(crate) fn record_call(&self) {
+        if self.calls.fetch_add(1, Ordering::Relaxed) + 1 == self.after_calls {
+            self.compile();
+        }
+    }
+
+    /// The recompiled module, once ready.
+    pub(crate) fn module(&self) -> Option<&Module> {
+        self.module.get()
+    }
+
+    fn compile(&self) {
+        let (binary, engine, module) = (
+            self.binary.clone(),
+            self.engine.clone(),
+            self.module.clone(),
+        );
+        // Decoding goes on with the first module meanwhile, and for good if this fails.
+        let _ = thread::Builder::new()
+            .name("fff-wasm-tier-up".to_string())
+            .spawn(move || {
+                if let Ok(compiled) = Module::from_binary(&engine, &binary) {
+                    let _ = module.set(compiled);
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoundsChecks, Compiler, EngineConfig};
+
+    #[test]
+    fn test_engine_config() {
+        EngineConfig::optimized()
+            .bounds_checks(BoundsChecks::Explicit)
+            .build()
+            .unwrap();
+        // Relaxed SIMD follows SIMD unless set.
+        EngineConfig::default().simd(false).build().unwrap();
+        assert!(EngineConfig::default()
+            .simd(false)
+            .relaxed_simd(true)
+            .build()
+            .is_err());
+        if cfg!(target_arch = "x86_64") {
+            EngineConfig::default()
+                .compiler(Compiler::Winch)
+                .build()
+                .unwrap();
+        }
+    }
+}
@@ -18,27 +18,60 @@
 
 use anyhow::{anyhow, bail, ensure, Context};
 use arrow_buffer::Buffer;
+use arrow_schema::DataType;
+use engine::{default_engine, TierUp};
+use pool::InstancePool;
 use ram_file::{RamFile, RamFileRef};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::ops::Range;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use wasi_common::{sync::WasiCtxBuilder, WasiCtx};
+use wasm_array::WasmArray;
 use wasm_buffer::WasmBuffer;
 use wasmtime::*;
 
+pub mod engine;
+mod pool;
 mod ram_file;
-// pub mod wasm_array;
+pub mod registry;
+pub mod wasm_array;
 pub mod wasm_buffer;
 
+pub use pool::PoolStats;
+
 /// 128 is not working for pco, lz4, flsbp
 const INPUT_ALIGNMENT: u32 = 4;
 
+/// Exports of stateful decoders.
+pub const INIT_FUNC: &str = "init_ffi";
+pub const DECODE_FUNC: &str = "decode_ffi";
+/// Default export of general decoders.
+pub const GENERAL_DECODE_FUNC: &str = "decode_general_ffi";
+/// Export of decoders outputting an Arrow C Data Interface array.
+pub const ARROW_DECODE_FUNC: &str = "decode_arrow_ffi";
+
+/// How to decode an EncUnit with a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryPoint {
+    /// A single call of the export returns every buffer of the EncUnit.
+    General(String),
+    /// [`INIT_FUNC`] takes the EncUnit and its kwargs, then [`DECODE_FUNC`] returns one vector per call.
+    Stateful,
+    /// [`ARROW_DECODE_FUNC`] returns an Arrow array that is imported without copying its buffers.
+    Arrow,
+}
+
 /// The WASM UDF runtime.
 ///
 /// This runtime contains an instance pool and can be shared by multiple threads.
+/// See [`Config`] for the bounds of the pool, and [`registry::RuntimeRegistry`] to share runtimes
+/// across files.
 pub struct Runtime {
     module: Module,
+    /// The module recompiled once hot, see [`Config::tier_up`].
+    tier_up: Option<TierUp>,
     /// Configurations.
     config: Config,
     /// Function names.
@@ -46,19 +79,27 @@ pub struct Runtime {
     /// User-defined types.
     types: HashMap<String, String>,
     /// Instance pool.
-    instances: Mutex<VecDeque<Arc<Mutex<Instance>>>>,
+    pool: InstancePool,
     /// ABI version. (major, minor)
     abi_version: (u8, u8),
 }
 
 /// Configurations.
-#[derive(Default)]
+#[derive(Default, Clone)]
 // #[non_exhaustive]
 pub struct Config {
     /// Memory size limit in bytes.
     memory_size_limit: Option<usize>,
     /// File size limit in bytes.
     file_size_limit: Option<usize>,
+    /// Maximum number of idle instances in the pool, unbounded by default.
+    max_pool_size: Option<usize>,
+    /// How long an instance may stay idle in the pool.
+    idle_timeout: Option<Duration>,
+    /// Linear memory in bytes above which an instance is dropped instead of going back to the pool.
+    reset_memory_above: Option<usize>,
+    /// Engine recompiling the module once hot, and the number of calls after which it is.
+    tier_up: Option<(Engine, u64)>,
 }
 
 impl Config {
@@ -73,6 +114,38 @@ impl Config {
         self.file_size_limit = Some(limit);
         self
     }
+
+    /// Set the maximum number of idle instances kept in the pool.
+    /// Instances finishing a call while the pool is full are dropped.
+    pub fn max_pool_size(mut self, size: usize) -> Self {
+        self.max_pool_size = Some(size);
+        self
+    }
+
+    /// Drop the instances that stay idle in the pool for `timeout`.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Drop the instances whose linear memory grew beyond `limit` bytes after a call,
+    /// so that the next call starts with a fresh instance.
+    /// Linear memory never shrinks, so this bounds the memory kept by the pool.
+    pub fn reset_memory_above(mut self, limit: usize) -> Self {
+        self.reset_memory_above = Some(limit);
+        self
+    }
+
+    /// Recompile the module with `engine` in a background thread after `after_calls` calls,
+    /// e.g., with an [`EngineConfig::optimized`](engine::EngineConfig::optimized) engine
+    /// while the runtime starts decoding with quickly compiled code.
+    /// Instances created from then on run the recompiled module.
+    ///
+    /// Runtimes created from AOT-compiled binaries are not recompiled.
+    pub fn tier_up(mut self, engine: Engine, after_calls: u64) -> Self {
+        self.tier_up = Some((engine, after_calls));
+        self
+    }
 }
 
 impl Debug for Config {
@@ -80,6 +153,13 @@ impl Debug for Config {
         f.debug_struct("Config")
             .field("memory_size_limit", &self.memory_size_limit)
             .field("file_size_limit", &self.file_size_limit)
+            .field("max_pool_size", &self.max_pool_size)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("reset_memory_above", &self.reset_memory_above)
+            .field(
+                "tier_up_after_calls",
+                &self.tier_up.as_ref().map(|(_, after_calls)| after_calls),
+            )
             .finish()
     }
 }
@@ -102,6 +182,8 @@ pub struct Instance {
     // decode_ffi
     // extern "C" fn(decoder: *mut WasmDecoder,out: *mut CSlice) -> i32
     decode: Option<TypedFunc<(u32, u32), i32>>,
+    // extern "C" fn(array: *mut FFI_ArrowArray)
+    arrow_array_release: Option<TypedFunc<u32, ()>>,
     // extern "C" fn(ptr: *const u8, len: usize, out: *mut CSlice) -> i32
     functions: HashMap<String, TypedFunc<(u32, u32, u32), i32>>,
     // Input pointer which can be reused during the lifetime of this instance
@@ -113,6 +195,8 @@ pub struct Instance {
     store: Store<(WasiCtx, StoreLimits)>,
     stdout: RamFileRef,
     stderr: RamFileRef,
+    /// Whether the module was recompiled by the tier-up engine.
+    optimized: bool,
 }
 
 impl Debug for Runtime {
@@ -121,10 +205,8 @@ impl Debug for Runtime {
             .field("config", &self.config)
             .field("functions", &self.functions)
             .field("types", &self.types)
-            .field(
-                "instances",
-                &self.instances.lock().map(|g| g.len()).unwrap_or(0),
-            )
+            .field("instances", &self.pool.len())
+            .field("optimized", &self.is_optimized())
             .finish()
     }
 }
@@ -151,31 +233,19 @@ impl CacheStore for MyCacheStore {
     }
 }
 
-static ENGINE: once_cell::sync::Lazy<Engine> = once_cell::sync::Lazy::new(|| {
-    Engine::new(
-        wasmtime::Config::new()
-            // this not work
-            // .enable_incremental_compilation(Arc::new(MyCacheStore) as Arc<dyn CacheStore>)
-            // .unwrap()
-            // .debug_info(true)
-            .cranelift_opt_level(wasmtime::OptLevel::None)
-            .parallel_compilation(true),
-    )
-    .expect("failed to create wasmtime Engine - this is a critical initialization failure")
-});
-
 impl Runtime {
     /// Create a new UDF runtime from a WASM binary.
     pub fn try_new(binary: &[u8]) -> Result<Self> {
-        Self::with_config_engine(binary, Config::default(), &ENGINE)
+        Self::with_config_engine(binary, Config::default(), default_engine())
     }
 
     /// Create a new UDF runtime from an AOT compiled binary.
     pub fn try_new_from_aot(aot_binary: &[u8]) -> Result<Self> {
-        Self::with_config_engine_from_aot(aot_binary, Config::default(), &ENGINE)
+        Self::with_config_engine_from_aot(aot_binary, Config::default(), default_engine())
     }
 
-    fn init_from_module(module: Module, config: Config) -> Result<Self> {
+    /// `binary` is recompiled by the tier-up engine, if any.
+    fn init_from_module(module: Module, config: Config, binary: Option<&[u8]>) -> Result<Self> {
         // check abi version
         let version = module
             .exports()
@@ -199,12 +269,19 @@ impl Runtime {
             // }
         }
 
+        let tier_up = match (&config.tier_up, binary) {
+            (Some((engine, after_calls)), Some(binary)) => {
+                Some(TierUp::new(binary, engine.clone(), *after_calls))
+            }
+            _ => None,
+        };
         Ok(Self {
             module,
+            tier_up,
+            pool: InstancePool::new(&config),
             config,
             functions,
             types,
-            instances: Mutex::new(vec![].into()),
             abi_version: (major, minor),
         })
     }
@@ -212,7 +289,7 @@ impl Runtime {
     /// Create a new UDF runtime from a WASM binary with a customized engine.
     pub fn with_config_engine(binary: &[u8], config: Config, engine: &Engine) -> Result<Self> {
         let module = Module::from_binary(engine, binary).context("failed to load wasm binary")?;
-        Self::init_from_module(module, config)
+        Self::init_from_module(module, config, Some(binary))
     }
 
     /// Create a new UDF runtime from a WASM AOT-compiled binary with a customized engine.
@@ -224,7 +301,7 @@ impl Runtime {
         let module = unsafe {
             Module::deserialize(engine, aot_binary).context("failed to load wasm binary")?
         };
-        Self::init_from_module(module, config)
+        Self::init_from_module(module, config, None)
     }
 
     /// Return available functions.
@@ -242,6 +319,35 @@ impl Runtime {
         self.abi_version
     }
 
+    /// Find how to decode with this module from its exports.
+    /// Stateful decoders are only used from ABI 1.2 on and Arrow decoders from ABI 1.3 on,
+    /// earlier modules are called as general decoders.
+    pub fn entry_point(&self) -> Result<EntryPoint> {
+        if self.abi_version >= (1, 2)
+            && self.functions.contains(INIT_FUNC)
+            && self.functions.contains(DECODE_FUNC)
+        {
+            return Ok(EntryPoint::Stateful);
+        }
+        if self.abi_version >= (1, 3) && self.functions.contains(ARROW_DECODE_FUNC) {
+            return Ok(EntryPoint::Arrow);
+        }
+        if self.functions.contains(GENERAL_DECODE_FUNC) {
+            return Ok(EntryPoint::General(GENERAL_DECODE_FUNC.to_string()));
+        }
+        let mut decoders = self.functions.iter().filter(|f| {
+            *f != DECODE_FUNC
+                && *f != ARROW_DECODE_FUNC
+                && f.starts_with("decode_")
+                && f.ends_with("_ffi")
+        });
+        match (decoders.next(), decoders.next()) {
+            (Some(name), None) => Ok(EntryPoint::General(name.clone())),
+            (None, _) => bail!("no decoder exported"),
+            (Some(_), Some(_)) => bail!("several decoders exported but no {GENERAL_DECODE_FUNC}"),
+        }
+    }
+
     /// Given a function signature that inlines struct types, find the function name.
     ///
     /// # Example
@@ -342,44 +448,55 @@ impl Runtime {
             bail!("function not found: {name}");
         }
 
-        let mut instance = if let Some(instance) = self
-            .instances
-            .lock()
-            .map_err(|e| anyhow!("instance pool lock poisoned: {}", e))?
-            .pop_front()
-        {
-            instance
-        } else {
-            Arc::new(Mutex::new(Instance::new(self)?))
-        };
+        let instance = self.acquire()?;
         // call the function
-        let mut guard = instance
-            .lock()
-            .map_err(|e| anyhow!("instance lock poisoned: {}", e))?;
-        let mut output = guard.call_generic_function(name, input, instance.clone());
+        let output = lock_instance(&instance)?.call_generic_function(name, input, instance.clone());
 
         // put the instance back to the pool
         if output.is_ok() {
-            self.instances
-                .lock()
-                .map_err(|e| anyhow!("instance pool lock poisoned: {}", e))?
-                .push_back(instance.clone());
-        } else {
-            drop(guard);
-            // We drop the instance here, but it may still be Arc'ed in some output Arrow Arrays.
-            drop(instance);
-            instance = Arc::new(Mutex::new(Instance::new(self)?));
-            guard = instance
-                .lock()
-                .map_err(|e| anyhow!("instance lock poisoned: {}", e))?;
-            output = guard.call_generic_function(name, input, instance.clone());
-            ensure!(output.is_ok(), "WASM function call failed on retry");
-            self.instances
-                .lock()
-                .map_err(|e| anyhow!("instance pool lock poisoned: {}", e))?
-                .push_back(instance.clone());
+            self.pool.put(instance)?;
+            return output;
+        }
+        // We drop the instance here, but it may still be Arc'ed in some output Arrow Arrays.
+        drop(instance);
+        let instance = self.new_instance()?;
+        let output = lock_instance(&instance)?.call_generic_function(name, input, instance.clone());
+        ensure!(output.is_ok(), "WASM function call failed on retry");
+        self.pool.put(instance)?;
+        output
+    }
+
+    /// Call an encoding function, which takes an Arrow IPC stream and returns the encoded bytes.
+    pub fn call_encode(&self, name: &str, input: &[u8]) -> Result<Vec<u8>> {
+        if !self.functions.contains(name) {
+            bail!("function not found: {name}");
+        }
+
+        let instance = self.acquire()?;
+        let output = lock_instance(&instance)?.call_encode(name, input);
+
+        // A trapped instance may be left in an inconsistent state, so it is not reused.
+        if output.is_ok() {
+            self.pool.put(instance)?;
+        }
+        output
+    }
+
+    /// Call an Arrow decoder and import its output as an array of `data_type`.
+    /// The instance goes back to the pool, the array stays in its memory until its buffers are dropped.
+    pub fn call_arrow(&self, name: &str, input: &[u8], data_type: &DataType) -> Result<WasmArray> {
+        if !self.functions.contains(name) {
+            bail!("function not found: {name}");
         }
 
+        let instance = self.acquire()?;
+        let output =
+            lock_instance(&instance)?.call_arrow_function(name, input, data_type, instance.clone());
+
+        // A trapped instance may be left in an inconsistent state, so it is not reused.
+        if output.is_ok() {
+            self.pool.put(instance)?;
+        }
         output
     }
 
@@ -398,23 +515,17 @@ impl Runtime {
         let instance = if let Some(instance) = reused_instance {
             instance
         } else {
-            Arc::new(Mutex::new(Instance::new(self)?))
+            self.new_instance()?
         };
 
         // call the function
-        let mut guard = instance
-            .lock()
-            .map_err(|e| anyhow!("instance lock poisoned: {}", e))?;
-        let output = guard.read_batch(name, input, selection, instance.clone())?;
+        let output =
+            lock_instance(&instance)?.read_batch(name, input, selection, instance.clone())?;
         // put the instance back to the pool if no more results
         if let Some(output) = output {
-            drop(guard);
             Ok(StreamReadResult::Batch((output, instance)))
         } else {
-            self.instances
-                .lock()
-                .map_err(|e| anyhow!("instance pool lock poisoned: {}", e))?
-                .push_back(instance.clone());
+            self.pool.put(instance)?;
             Ok(StreamReadResult::End)
         }
     }
@@ -424,19 +535,79 @@ impl Runtime {
         Instance::new(self)
     }
 
-    // WARNING: This function is for testing only.
+    /// Linear memory of the idle instances in the pool, in bytes.
     pub fn memory_size(&self) -> usize {
-        let guard = self
-            .instances
-            .lock()
-            .expect("instance pool lock poisoned in memory_size");
-        let guard2 = guard[0]
-            .lock()
-            .expect("instance lock poisoned in memory_size");
-        guard2.memory.data_size(&guard2.store)
+        self.pool
+            .stats()
+            .map(|stats| stats.idle_memory)
+            .unwrap_or(0)
+    }
+
+    /// Statistics of the instance pool.
+    pub fn pool_stats(&self) -> Result<PoolStats> {
+        self.pool.stats()
+    }
+
+    /// Drop the instances idle for [`Config::idle_timeout`], returning how many.
+    /// The pool also evicts them whenever an instance is taken or put back,
+    /// so this is only needed to release memory while no call is made.
+    pub fn evict_idle(&self) -> Result<usize> {
+        self.pool.evict_idle()
+    }
+
+    /// Whether new instances run the module recompiled by the tier-up engine,
+    /// see [`Config::tier_up`].
+    pub fn is_optimized(&self) -> bool {
+        self.tier_up.as_ref().is_some_and(|t| t.module().is_some())
+    }
+
+    /// Create an instance outside of the pool, e.g., for a stateful decoder.
+    /// Counts as a call towards [`Config::tier_up`].
+    pub fn instantiate(&self) -> Result<Instance> {
+        self.record_call();
+        Instance::new(self)
+    }
+
+    /// The module new instances run.
+    fn module(&self) -> (&Module, bool) {
+        match self.tier_up.as_ref().and_then(|t| t.module()) {
+            Some(module) => (module, true),
+            None => (&self.module, false),
+        }
+    }
+
+    fn record_call(&self) {
+        if let Some(tier_up) = &self.tier_up {
+            tier_up.record_call();
+        }
+    }
+
+    /// Take the most recently used instance from the pool, or create one.
+    fn acquire(&self) -> Result<Arc<Mutex<Instance>>> {
+        self.record_call();
+        let optimized = self.is_optimized();
+        while let Some(instance) = self.pool.take()? {
+            // Instances of the module compiled before the tier-up are dropped.
+            if lock_instance(&instance)?.optimized == optimized {
+                return Ok(instance);
+            }
+        }
+        self.new_instance()
+    }
+
+    fn new_instance(&self) -> Result<Arc<Mutex<Instance>>> {
+        let instance = Arc::new(Mutex::new(Instance::new(self)?));
+        self.pool.record_created();
+        Ok(instance)
     }
 }
 
+fn lock_instance(instance: &Mutex<Instance>) -> Result<std::sync::MutexGuard<'_, Instance>> {
+    instance
+        .lock()
+        .map_err(|e| anyhow!("instance lock poisoned: {}", e))
+}
+
 pub enum StreamReadResult<Iter>
 where
     Iter: Iterator<Item = Buffer>,
@@ -551,7 +722,7 @@ impl WasmSlice {
 impl Instance {
     /// Create a new instance.
     pub fn new(rt: &Runtime) -> Result<Self> {
-        let module = &rt.module;
+        let (module, optimized) = rt.module();
         let engine = module.engine();
         let mut linker = Linker::new(engine);
         wasi_common::sync::add_to_linker(&mut linker, |(wasi, _)| wasi)?;
@@ -603,6 +774,9 @@ impl Instance {
         let buffer_drop = instance.get_typed_func(&mut store, "buffer_drop")?;
         let init = instance.get_typed_func(&mut store, "init_ffi").ok();
         let decode = instance.get_typed_func(&mut store, "decode_ffi").ok();
+        let arrow_array_release = instance
+            .get_typed_func(&mut store, "arrow_array_release")
+            .ok();
         let memory = instance
             .get_memory(&mut store, "memory")
             .context("no memory")?;
@@ -615,6 +789,7 @@ impl Instance {
             buffer_drop,
             init,
             decode,
+            arrow_array_release,
             memory,
             store,
             functions,
@@ -622,6 +797,7 @@ impl Instance {
             cached_alloc_len: None,
             stdout,
             stderr,
+            optimized,
         })
     }
 
@@ -701,6 +877,16 @@ impl Instance {
         result.map(|o| (o, out_ptr))
     }
 
+    /// Call an encoding function and copy its output out of the instance memory.
+    pub fn call_encode(&mut self, name: &str, input: &[u8]) -> Result<Vec<u8>> {
+        let (out_bytes, out_ptr) = self.call_scalar_function(name, input)?;
+        let encoded = out_bytes.to_vec();
+        if !encoded.is_empty() {
+            self.dealloc(out_ptr, encoded.len() as u32, 1)?;
+        }
+        Ok(encoded)
+    }
+
     /// Call a generic function that returns an iterator of Buffers. Those buffers together form an Arrow Array.
     pub fn call_generic_function(
         &mut self,
@@ -1142,7 +1328,15 @@ impl Instance {
         Ok(())
     }
 
-    /// WARNING: This function is for testing only.
+    fn arrow_array_release(&mut self, ptr: u32) -> Result<()> {
+        self.arrow_array_release
+            .as_ref()
+            .context("arrow_array_release not exported")?
+            .call(&mut self.store, ptr)?;
+        Ok(())
+    }
+
+    /// Size of the linear memory in bytes.
     pub fn memory_size(&self) -> usize {
         self.memory.data_size(&self.store)
     }
@@ -1231,7 +1425,7 @@ mod tests {
     use wasm_test_encoders::encode_fff_general;
     use wasmtime::Engine;
 
-    use crate::{Config, Instance, Runtime};
+    use crate::{engine::EngineConfig, Config, EntryPoint, Instance, Runtime, ARROW_DECODE_FUNC};
 
     #[test]
     #[ignore]
@@ -1299,4 +1493,77 @@ mod tests {
             primitive_array_from_arrow_buffers_iter(array.data_type(), iter, full_size).unwrap();
         assert_eq!(*array, *out);
     }
+
+    #[test]
+    #[ignore]
+    fn test_arrow() {
+        let rt =
+            Runtime::try_new(&std::fs::read(fff_test_util::BUILTIN_WASM_PATH.as_path()).unwrap())
+                .unwrap();
+        assert_eq!(rt.entry_point().unwrap(), EntryPoint::Arrow);
+        let array = Arc::new(UInt32Array::from_iter((0..65536u32).map(|x| {
+            if x % 7 == 0 {
+                None
+            } else {
+                Some(x % 128)
+            }
+        }))) as ArrayRef;
+        let encoded = encode_fff_general(array.clone());
+        let out = rt
+            .call_arrow(ARROW_DECODE_FUNC, &encoded, array.data_type())
+            .unwrap();
+        assert_eq!(out.num_rows, 65536);
+        let out = primitive_array_from_arrow_buffers_iter(
+            array.data_type(),
+            out.buffers.into_iter(),
+            65536,
+        )
+        .unwrap();
+        assert_eq!(*array, *out);
+        // A malformed EncUnit fails without poisoning the pool.
+        assert!(rt
+            .call_arrow(ARROW_DECODE_FUNC, b"not an EncUnit", array.data_type())
+            .is_err());
+        assert!(rt
+            .call_arrow(ARROW_DECODE_FUNC, &encoded, array.data_type())
+            .is_ok());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_tier_up() {
+        let optimized = EngineConfig::optimized().build().unwrap();
+        let rt = Runtime::with_config_engine(
+            &std::fs::read(fff_test_util::BUILTIN_WASM_PATH.as_path()).unwrap(),
+            Config::default().tier_up(optimized, 2),
+            &EngineConfig::default().build().unwrap(),
+        )
+        .unwrap();
+        let array = Arc::new(UInt32Array::from_iter_values(0..1024)) as ArrayRef;
+        let encoded = encode_fff_general(array.clone());
+        let decode = || {
+            let out = rt
+                .call_arrow(ARROW_DECODE_FUNC, &encoded, array.data_type())
+                .unwrap();
+            let out = primitive_array_from_arrow_buffers_iter(
+                array.data_type(),
+                out.buffers.into_iter(),
+                1024,
+            )
+            .unwrap();
+            assert_eq!(*array, *out);
+        };
+        decode();
+        assert!(!rt.is_optimized());
+        // The second call starts the compilation in the background.
+        decode();
+        let start = std::time::Instant::now();
+        while !rt.is_optimized() {
+            assert!(start.elapsed() < std::time::Duration::from_secs(60));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // The pooled instance of the first module is replaced.
+        decode();
+        assert_eq!(rt.pool_stats().unwrap().created, 2);
+    }
 }
@@ -0,0 +1,192 @@
+//! The pool of idle instances of a [`Runtime`](crate::Runtime).
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{Config, Instance};
+
+/// Statistics of an instance pool, or of all the pools of a
+/// [`RuntimeRegistry`](crate::registry::RuntimeRegistry).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Instances created.
+    pub created: u64,
+    /// Calls served by an instance from the pool.
+    pub reused: u64,
+    /// Instances dropped because the pool was full.
+    pub evicted_full: u64,
+    /// Instances dropped after staying idle for the idle timeout.
+    pub evicted_idle: u64,
+    /// Instances dropped because their linear memory grew beyond the reset threshold.
+    pub reset: u64,
+    /// Instances in the pool.
+    pub idle: usize,
+    /// Linear memory of the instances in the pool, in bytes.
+    pub idle_memory: usize,
+}
+
+impl std::ops::Add for PoolStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            created: self.created + other.created,
+            reused: self.reused + other.reused,
+            evicted_full: self.evicted_full + other.evicted_full,
+            evicted_idle: self.evicted_idle + other.evicted_idle,
+            reset: self.reset + other.reset,
+            idle: self.idle + other.idle,
+            idle_memory: self.idle_memory + other.idle_memory,
+        }
+    }
+}
+
+struct IdleInstance {
+    instance: Arc<Mutex<Instance>>,
+    since: Instant,
+}
+
+#[derive(Default)]
+struct Counters {
+    created: AtomicU64,
+    reused: AtomicU64,
+    evicted_full: AtomicU64,
+    evicted_idle: AtomicU64,
+    reset: AtomicU64,
+}
+
+/// Idle instances, most recently used last.
+///
+/// Instances may still be referenced by the buffers they output when they are pooled or dropped.
+/// Neither the pool nor the runtime must hold the lock of an instance when putting it back,
+/// since the pool locks it to read its memory size.
+pub(crate) struct InstancePool {
+    idle: Mutex<VecDeque<IdleInstance>>,
+    max_size: Option<usize>,
+    idle_timeout: Option<Duration>,
+    reset_memory_above: Option<usize>,
+    counters: Counters,
+}
+
+impl InstancePool {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            idle: Mutex::new(VecDeque::new()),
+            max_size: config.max_pool_size,
+            idle_timeout: config.idle_timeout,
+            reset_memory_above: config.reset_memory_above,
+            counters: Counters::default(),
+        }
+    }
+
+    /// Take the most recently used instance, if any.
+    pub(crate) fn take(&self) -> Result<Option<Arc<Mutex<Instance>>>> {
+        let (instance, expired) = {
+            let mut idle = self.lock()?;
+            let expired = self.drain_expired(&mut idle);
+            (idle.pop_back().map(|i| i.instance), expired)
+        };
+        drop(expired);
+        if instance.is_some() {
+            self.counters.reused.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(instance)
+    }
+
+    /// Count an instance created for lack of an idle one.
+    pub(crate) fn record_created(&self) {
+        self.counters.created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Put back an instance after a successful call, unless its memory has grown too large
+    /// or the pool is full.
+    pub(crate) fn put(&self, instance: Arc<Mutex<Instance>>) -> Result<()> {
+        if let Some(limit) = self.reset_memory_above {
+            let memory = instance
+                .lock()
+                .map_err(|e| anyhow!("instance lock poisoned: {}", e))?
+                .memory_size();
+            if memory > limit {
+                // The linear memory never shrinks, so start over with a fresh instance.
+                self.counters.reset.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+        let expired = {
+            let mut idle = self.lock()?;
+            let expired = self.drain_expired(&mut idle);
+            if self.max_size.is_some_and(|max| idle.len() >= max) {
+                self.counters.evicted_full.fetch_add(1, Ordering::Relaxed);
+            } else {
+                idle.push_back(IdleInstance {
+                    instance,
+                    since: Instant::now(),
+                });
+            }
+            expired
+        };
+        drop(expired);
+        Ok(())
+    }
+
+    /// Drop the instances idle for the idle timeout. Returns how many were dropped.
+    pub(crate) fn evict_idle(&self) -> Result<usize> {
+        let expired = self.drain_expired(&mut self.lock()?);
+        Ok(expired.len())
+    }
+
+    pub(crate) fn stats(&self) -> Result<PoolStats> {
+        let idle = self.lock()?;
+        let mut idle_memory = 0;
+        for i in idle.iter() {
+            idle_memory += i
+                .instance
+                .lock()
+                .map_err(|e| anyhow!("instance lock poisoned: {}", e))?
+                .memory_size();
+        }
+        Ok(PoolStats {
+            created: self.counters.created.load(Ordering::Relaxed),
+            reused: self.counters.reused.load(Ordering::Relaxed),
+            evicted_full: self.counters.evicted_full.load(Ordering::Relaxed),
+            evicted_idle: self.counters.evicted_idle.load(Ordering::Relaxed),
+            reset: self.counters.reset.load(Ordering::Relaxed),
+            idle: idle.len(),
+            idle_memory,
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.idle.lock().map(|idle| idle.len()).unwrap_or(0)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, VecDeque<IdleInstance>>> {
+        self.idle
+            .lock()
+            .map_err(|e| anyhow!("instance pool lock poisoned: {}", e))
+    }
+
+    /// Remove the expired instances, which the caller drops once the pool is unlocked.
+    fn drain_expired(&self, idle: &mut VecDeque<IdleInstance>) -> Vec<IdleInstance> {
+        let Some(timeout) = self.idle_timeout else {
+            return vec![];
+        };
+        // The least recently used instances are first.
+        let num_expired = idle
+            .iter()
+            .take_while(|i| i.since.elapsed() >= timeout)
+            .count();
+        self.counters
+            .evicted_idle
+            .fetch_add(num_expired as u64, Ordering::Relaxed);
+        idle.drain(..num_expired).collect()
+    }
+}
@@ -0,0 +1,194 @@
+//! Runtimes shared by every reader of a process, keyed by the hash of their Wasm binary.
+//!
+//! Files written with the same decoders embed the same binaries, so a server reading many files
+//! compiles each decoder once and bounds the instances of all readers together.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use wasmtime::Engine;
+
+use crate::{engine::default_engine, Config, PoolStats, Runtime};
+
+/// Idle instances kept per runtime by [`RuntimeRegistry::global`] unless configured otherwise.
+pub const DEFAULT_MAX_POOL_SIZE: usize = 16;
+/// How long instances of [`RuntimeRegistry::global`] stay idle unless configured otherwise.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Linear memory above which instances of [`RuntimeRegistry::global`] are reset unless configured
+/// otherwise.
+pub const DEFAULT_RESET_MEMORY_ABOVE: usize = 256 << 20;
+
+static GLOBAL: OnceLock<RuntimeRegistry> = OnceLock::new();
+
+/// A registry of runtimes keyed by the BLAKE3 hash of their Wasm binary.
+/// Every runtime it creates has the same [`Config`], in particular the same pool bounds.
+pub struct RuntimeRegistry {
+    config: Config,
+    engine: Engine,
+    runtimes: Mutex<HashMap<[u8; 32], Arc<Runtime>>>,
+}
+
+impl RuntimeRegistry {
+    /// A registry compiling with the [default engine](crate::engine::default_engine).
+    pub fn new(config: Config) -> Self {
+        Self::with_engine(config, default_engine().clone())
+    }
+
+    pub fn with_engine(config: Config, engine: Engine) -> Self {
+        Self {
+            config,
+            engine,
+            runtimes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The registry of the process, created with the default bounds on first use
+    /// unless [`RuntimeRegistry::init_global`] was called before.
+    pub fn global() -> &'static RuntimeRegistry {
+        GLOBAL.get_or_init(|| {
+            Self::new(
+                Config::default()
+                    .max_pool_size(DEFAULT_MAX_POOL_SIZE)
+                    .idle_timeout(DEFAULT_IDLE_TIMEOUT)
+                    .reset_memory_above(DEFAULT_RESET_MEMORY_ABOVE),
+            )
+        })
+    }
+
+    /// Create the registry of the process with `config`.
+    /// Fails if it was already created, e.g., by a reader.
+    pub fn init_global(config: Config) -> Result<&'static RuntimeRegistry> {
+        Self::init_global_with(Self::new(config))
+    }
+
+    /// Make `registry`, e.g., one created with [`RuntimeRegistry::with_engine`], the registry of
+    /// the process. Fails if it was already created.
+    pub fn init_global_with(registry: RuntimeRegistry) -> Result<&'static RuntimeRegistry> {
+        let mut registry = Some(registry);
+        let global = GLOBAL.get_or_init(|| registry.take().unwrap());
+        if registry.is_some() {
+            bail!("the global runtime registry is already initialized");
+        }
+        Ok(global)
+    }
+
+    /// The runtime of `binary`, compiled on first use.
+    pub fn get_or_try_insert(&self, binary: &[u8]) -> Result<Arc<Runtime>> {
+        let key = *blake3::hash(binary).as_bytes();
+        if let Some(rt) = self.lock()?.get(&key) {
+            return Ok(rt.clone());
+        }
+        // Compile without the lock. Another thread may compile the same binary meanwhile,
+        // in which case its runtime is kept.
+        let rt = Arc::new(Runtime::with_config_engine(
+            binary,
+            self.config.clone(),
+            &self.engine,
+        )?);
+        Ok(self.lock()?.entry(key).or_insert(rt).clone())
+    }
+
+    /// Number of runtimes in the registry.
+    pub fn len(&self) -> usize {
+        self.runtimes.lock().map(|r| r.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Evict the idle instances of every runtime, then drop the runtimes that have no idle
+    /// instance and are not used outside of the registry. Returns the number of evicted instances.
+    pub fn evict_idle(&self) -> Result<usize> {
+        let runtimes = self.lock()?.values().cloned().collect::<Vec<_>>();
+        let mut evicted = 0;
+        for rt in &runtimes {
+            evicted += rt.evict_idle()?;
+        }
+        drop(runtimes);
+        let unused = {
+            let mut runtimes = self.lock()?;
+            let unused = runtimes
+                .iter()
+                .filter(|(_, rt)| Arc::strong_count(rt) == 1 && rt.pool.len() == 0)
+                .map(|(key, _)| *key)
+                .collect::<Vec<_>>();
+            unused
+                .iter()
+                .filter_map(|key| runtimes.remove(key))
+                .collect::<Vec<_>>()
+        };
+        drop(unused);
+        Ok(evicted)
+    }
+
+    /// Pool statistics summed over every runtime.
+    pub fn pool_stats(&self) -> Result<PoolStats> {
+        let runtimes = self.lock()?.values().cloned().collect::<Vec<_>>();
+        runtimes
+            .iter()
+            .try_fold(PoolStats::default(), |acc, rt| Ok(acc + rt.pool_stats()?))
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<[u8; 32], Arc<Runtime>>>> {
+        self.runtimes
+            .lock()
+            .map_err(|e| anyhow!("runtime registry lock poisoned: {}", e))
+    }
+}
+
+impl std::fmt::Debug for RuntimeRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuntimeRegistry")
+            .field("config", &self.config)
+            .field("runtimes", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RuntimeRegistry;
+    use crate::{Config, GENERAL_DECODE_FUNC};
+
+    #[test]
+    #[ignore]
+    fn test_registry() {
+        let wasm = std::fs::read(fff_test_util::BUILTIN_WASM_PATH.as_path()).unwrap();
+        let registry = RuntimeRegistry::new(
+            Config::default()
+                .max_pool_size(1)
+                .idle_timeout(Duration::ZERO),
+        );
+        let rt = registry.get_or_try_insert(&wasm).unwrap();
+        assert!(std::sync::Arc::ptr_eq(
+            &rt,
+            &registry.get_or_try_insert(&wasm).unwrap()
+        ));
+        assert_eq!(registry.len(), 1);
+
+        let encoded = wasm_test_encoders::encode_fff_general(std::sync::Arc::new(
+            arrow_array::UInt32Array::from_iter_values(0..1024),
+        ));
+        // The instance is pooled, then evicted as soon as it is idle.
+        drop(rt.call_multi_buf(GENERAL_DECODE_FUNC, &encoded).unwrap());
+        assert_eq!(registry.pool_stats().unwrap().created, 1);
+        assert_eq!(registry.evict_idle().unwrap(), 1);
+        let stats = registry.pool_stats().unwrap();
+        assert_eq!((stats.idle, stats.evicted_idle), (0, 1));
+
+        // The runtime is kept while used outside of the registry.
+        registry.evict_idle().unwrap();
+        assert_eq!(registry.len(), 1);
+        drop(rt);
+        registry.evict_idle().unwrap();
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! Import of the Arrow C Data Interface arrays output by `decode_arrow_ffi` exports,
+//! see `fff_ude::ffi::arrow_wrapper`.
+//!
+//! The buffers of the array are not copied: they reference the linear memory of the instance,
+//! which keeps the array until all of them are dropped.
+
+use std::{
+    ops::Range,
+    ptr::NonNull,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use arrow_buffer::Buffer;
+use arrow_schema::DataType;
+
+use crate::Instance;
+
+/// Size of an `ArrowArray` on wasm32: five i64 followed by five 32-bit pointers, padded to 8.
+const ARROW_ARRAY_SIZE: usize = 64;
+
+/// The buffers of an array output by a Wasm decoder, laid out as in `format/wasm_buffers.md`.
+pub struct WasmArray {
+    pub num_rows: u64,
+    pub buffers: Vec<Buffer>,
+}
+
+/// Releases the exported array once the host has dropped every buffer referencing it.
+struct ReleaseOnDrop {
+    array_ptr: u32,
+    instance: Arc<Mutex<Instance>>,
+}
+
+impl Drop for ReleaseOnDrop {
+    fn drop(&mut self) {
+        if let Ok(mut instance) = self.instance.lock() {
+            let _ = instance.arrow_array_release(self.array_ptr);
+        }
+    }
+}
+
+/// An `ArrowArray` read from the linear memory.
+struct RawArray {
+    length: usize,
+    null_count: i64,
+    buffers: Vec<u32>,
+    children: Vec<u32>,
+}
+
+impl RawArray {
+    fn read(mem: &[u8], ptr: u32) -> Result<Self> {
+        let bytes = span(mem, ptr, ARROW_ARRAY_SIZE)?;
+        let bytes = &mem[bytes];
+        let i64_at = |i: usize| i64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let length = usize::try_from(i64_at(0)).context("negative array length")?;
+        ensure!(i64_at(16) == 0, "exported arrays must have offset 0");
+        ensure!(u32_at(48) == 0, "dictionary arrays cannot be imported");
+        let buffers = read_ptrs(mem, u32_at(40), i64_at(24))?;
+        let children = read_ptrs(mem, u32_at(44), i64_at(32))?;
+        Ok(Self {
+            length,
+            null_count: i64_at(8),
+            buffers,
+            children,
+        })
+    }
+
+    fn expect_layout(&self, data_type: &DataType, buffers: usize, children: usize) -> Result<()> {
+        ensure!(
+            self.buffers.len() == buffers && self.children.len() == children,
+            "expected {buffers} buffers and {children} children for {data_type}, got {} and {}",
+            self.buffers.len(),
+            self.children.len()
+        );
+        Ok(())
+    }
+
+    fn validity(&self, mem: &[u8]) -> Result<Range<usize>> {
+        match self.buffers[0] {
+            0 => Ok(0..0),
+            _ if self.null_count == 0 => Ok(0..0),
+            ptr => span(mem, ptr, self.length.div_ceil(8)),
+        }
+    }
+}
+
+/// Read `n` 32-bit pointers at `ptr`.
+fn read_ptrs(mem: &[u8], ptr: u32, n: i64) -> Result<Vec<u32>> {
+    let n = usize::try_from(n).context("negative number of buffers or children")?;
+    let bytes = span(mem, ptr, n.checked_mul(4).context("too many pointers")?)?;
+    Ok(mem[bytes]
+        .chunks_exact(4)
+        .map(|p| u32::from_le_bytes(p.try_into().unwrap()))
+        .collect())
+}
+
+/// The range of `len` bytes at `ptr`, checked against the bounds of `mem`.
+fn span(mem: &[u8], ptr: u32, len: usize) -> Result<Range<usize>> {
+    if len == 0 {
+        return Ok(0..0);
+    }
+    ensure!(ptr != 0, "null pointer to {len} bytes");
+    let start = ptr as usize;
+    let end = start.checked_add(len).context("buffer out of bounds")?;
+    ensure!(end <= mem.len(), "buffer out of bounds");
+    Ok(start..end)
+}
+
+fn byte_len(length: usize, width: usize) -> Result<usize> {
+    length.checked_mul(width).context("buffer too large")
+}
+
+/// Append the spans of the buffers of the array at `ptr`, of the declared `data_type`,
+/// in the order of `format/wasm_buffers.md`. Returns the length of the array.
+fn collect_spans(
+    mem: &[u8],
+    ptr: u32,
+    data_type: &DataType,
+    spans: &mut Vec<Range<usize>>,
+) -> Result<usize> {
+    let array = RawArray::read(mem, ptr)?;
+    let len = array.length;
+    match data_type {
+        DataType::Null => {
+            array.expect_layout(data_type, 0, 0)?;
+            spans.push(0..0);
+        }
+        DataType::Boolean => {
+            array.expect_layout(data_type, 2, 0)?;
+            spans.push(array.validity(mem)?);
+            spans.push(span(mem, array.buffers[1], len.div_ceil(8))?);
+        }
+        DataType::Utf8
+        | DataType::LargeUtf8
+        | DataType::Binary
+        | DataType::LargeBinary
+        | DataType::Utf8View
+        | DataType::BinaryView => {
+            // Views: validity, views, data buffers, then the sizes of the data buffers.
+            ensure!(
+                array.buffers.len() >= 3 && array.children.is_empty(),
+                "expected views for {data_type}"
+            );
+            let num_data = array.buffers.len() - 3;
+            let sizes = span(mem, array.buffers[num_data + 2], byte_len(num_data, 8)?)?;
+            let sizes = mem[sizes]
+                .chunks_exact(8)
+                .map(|s| usize::try_from(i64::from_le_bytes(s.try_into().unwrap())))
+                .collect::<Result<Vec<_>, _>>()
+                .context("negative data buffer size")?;
+            spans.push(array.validity(mem)?);
+            spans.push(span(mem, array.buffers[1], byte_len(len, 16)?)?);
+            for (&ptr, size) in array.buffers[2..num_data + 2].iter().zip(sizes) {
+                spans.push(span(mem, ptr, size)?);
+            }
+        }
+        DataType::FixedSizeBinary(size) => {
+            array.expect_layout(data_type, 2, 0)?;
+            spans.push(array.validity(mem)?);
+            spans.push(span(mem, array.buffers[1], byte_len(len, *size as usize)?)?);
+        }
+        DataType::List(field) | DataType::LargeList(field) | DataType::Map(field, _) => {
+            array.expect_layout(data_type, 2, 1)?;
+            let width = if matches!(data_type, DataType::LargeList(_)) {
+                8
+            } else {
+                4
+            };
+            spans.push(array.validity(mem)?);
+            spans.push(span(mem, array.buffers[1], byte_len(len + 1, width)?)?);
+            // A Null child stands for values decoded with the child column.
+            let child = RawArray::read(mem, array.children[0])?;
+            if field.data_type() == &DataType::Null || !child.buffers.is_empty() {
+                collect_spans(mem, array.children[0], field.data_type(), spans)?;
+            }
+        }
+        DataType::FixedSizeList(field, _) => {
+            array.expect_layout(data_type, 1, 1)?;
+            spans.push(array.validity(mem)?);
+            collect_spans(mem, array.children[0], field.data_type(), spans)?;
+        }
+        DataType::Struct(fields) => {
+            array.expect_layout(data_type, 1, fields.len())?;
+            spans.push(array.validity(mem)?);
+            for (field, &child) in fields.iter().zip(&array.children) {
+                collect_spans(mem, child, field.data_type(), spans)?;
+            }
+        }
+        dt => match dt.primitive_width() {
+            Some(width) => {
+                array.expect_layout(data_type, 2, 0)?;
+                spans.push(array.validity(mem)?);
+                spans.push(span(mem, array.buffers[1], byte_len(len, width)?)?);
+            }
+            None => bail!("{dt} cannot be imported from Wasm"),
+        },
+    }
+    Ok(len)
+}
+
+impl Instance {
+    /// Call a `decode_arrow_ffi` export and import its output as an array of `data_type`.
+    ///
+    /// Like the buffers of [`WasmBuffer`](crate::wasm_buffer::WasmBuffer), the imported buffers
+    /// point into the linear memory and assume that it is not moved while they are alive.
+    pub fn call_arrow_function(
+        &mut self,
+        name: &str,
+        input: &[u8],
+        data_type: &DataType,
+        instance_arc: Arc<Mutex<Instance>>,
+    ) -> Result<WasmArray> {
+        ensure!(
+            self.arrow_array_release.is_some(),
+            "arrow_array_release not exported"
+        );
+        let (_, array_ptr) = self.call_scalar_function(name, input)?;
+        let mut spans = vec![];
+        let num_rows = match collect_spans(
+            self.memory.data(&self.store),
+            array_ptr,
+            data_type,
+            &mut spans,
+        ) {
+            Ok(num_rows) => num_rows as u64,
+            Err(e) => {
+                self.arrow_array_release(array_ptr)?;
+                return Err(e);
+            }
+        };
+        if spans.iter().all(|s| s.is_empty()) {
+            // No buffer would keep the array alive.
+            self.arrow_array_release(array_ptr)?;
+            return Ok(WasmArray {
+                num_rows,
+                buffers: spans
+                    .iter()
+                    .map(|_| Buffer::from_vec(Vec::<u8>::new()))
+                    .collect(),
+            });
+        }
+        let base = self.memory.data_ptr(&self.store) as usize;
+        let owner = Arc::new(ReleaseOnDrop {
+            array_ptr,
+            instance: instance_arc,
+        });
+        let buffers = spans
+            .into_iter()
+            .map(|s| {
+                if s.is_empty() {
+                    return Buffer::from_vec(Vec::<u8>::new());
+                }
+                // SAFETY: the span was checked to be in the linear memory, which `owner`
+                // keeps alive together with the exported array.
+                unsafe {
+                    Buffer::from_custom_allocation(
+                        NonNull::new_unchecked((base + s.start) as *mut u8),
+                        s.len(),
+                        owner.clone(),
+                    )
+                }
+            })
+            .collect();
+        Ok(WasmArray { num_rows, buffers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_schema::{DataType, Field};
+
+    use super::collect_spans;
+
+    /// Write an `ArrowArray` at `ptr` with its buffer and child pointers right after it.
+    fn write_array(mem: &mut [u8], ptr: usize, length: i64, buffers: &[u32], children: &[u32]) {
+        let buffers_ptr = ptr + 64;
+        let children_ptr = buffers_ptr + 4 * buffers.len();
+        let fields = [length, 0, 0, buffers.len() as i64, children.len() as i64];
+        for (i, field) in fields.iter().enumerate() {
+            mem[ptr + 8 * i..ptr + 8 * i + 8].copy_from_slice(&field.to_le_bytes());
+        }
+        let ptr_or_null = |n: usize, p: usize| if n == 0 { 0 } else { p as u32 };
+        mem[ptr + 40..ptr + 44]
+            .copy_from_slice(&ptr_or_null(buffers.len(), buffers_ptr).to_le_bytes());
+        mem[ptr + 44..ptr + 48]
+            .copy_from_slice(&ptr_or_null(children.len(), children_ptr).to_le_bytes());
+        for (i, p) in buffers.iter().chain(children).enumerate() {
+            mem[buffers_ptr + 4 * i..buffers_ptr + 4 * i + 4].copy_from_slice(&p.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_collect_spans() {
+        let mut mem = vec![0u8; 512];
+        // List of 2 rows with a dummy Null child, then an Int32 array of 3 rows.
+        write_array(&mut mem, 8, 2, &[0, 400], &[100]);
+        write_array(&mut mem, 100, 3, &[], &[]);
+        write_array(&mut mem, 200, 3, &[0, 420], &[]);
+
+        let mut spans = vec![];
+        collect_spans(&mem, 200, &DataType::Int32, &mut spans).unwrap();
+        assert_eq!(spans, vec![0..0, 420..432]);
+
+        let list = DataType::List(Arc::new(Field::new("item", DataType::Int32, true)));
+        let mut spans = vec![];
+        assert_eq!(collect_spans(&mem, 8, &list, &mut spans).unwrap(), 2);
+        assert_eq!(spans, vec![0..0, 400..412]);
+
+        // The values of 3 rows do not fit in the memory.
+        write_array(&mut mem, 200, 3, &[0, 504], &[]);
+        assert!(collect_spans(&mem, 200, &DataType::Int32, &mut vec![]).is_err());
+        // Not the layout of the declared type.
+        assert!(collect_spans(&mem, 8, &DataType::Int32, &mut vec![]).is_err());
+        assert!(collect_spans(&mem, 600, &DataType::Int32, &mut vec![]).is_err());
+    }
+}
@@ -2,11 +2,16 @@
 
 //! FFI interfaces.
 
+use std::io::Cursor;
+
+use arrow_array::{ffi::FFI_ArrowArray, Array};
 use arrow_buffer::Buffer;
+use arrow_ipc::reader::StreamReader;
 use fff_core::errors::Error;
 
 use crate::{
-    Decode, GeneralDecode, GeneralDecodeV2, Init, ScalarDecode, StatefulWasmDecoder, StringDecode,
+    unsliced, Decode, Encode, GeneralDecode, GeneralDecodeV2, GeneralDecodeV3, Init, ScalarDecode,
+    StatefulWasmDecoder, StringDecode,
 };
 
 /// A symbol indicating the ABI version.
@@ -18,9 +23,17 @@ use crate::{
 /// # Changelog
 ///
 /// - 1.0: Initial version.
+/// - 1.1: Encoders exported with [`encode_wrapper`].
+/// - 1.2: Readers decode with the `init_ffi` and `decode_ffi` exports of [`init_wrapper`] and
+///   [`decode_wrapper`] if present, and pass them the kwargs of the EncUnit.
+/// - 1.3: Readers decode with the `decode_arrow_ffi` export of [`arrow_wrapper`] if present,
+///   importing its output in place and releasing it with [`arrow_array_release`].
 #[no_mangle]
 #[used]
-pub static FFFUDE_VERSION_1_0: () = ();
+pub static FFFUDE_VERSION_1_3: () = ();
+
+/// The ABI version symbol of this version of the crate.
+pub use self::FFFUDE_VERSION_1_3 as ABI_VERSION;
 
 /// Allocate memory.
 ///
@@ -186,7 +199,7 @@ fn call_string(function: StringDecode, input_bytes: &[u8]) -> Result<Vec<Box<[u8
 
 /// An opaque type for iterating over Buffers.
 pub struct BufferIter {
-    iter: Box<dyn Iterator<Item = Buffer>>,
+    pub(crate) iter: Box<dyn Iterator<Item = Buffer>>,
 }
 
 /// A wrapper for calling general decoding functions from C.
@@ -276,6 +289,121 @@ fn call_generalv2(
     Ok(Box::new(BufferIter { iter }))
 }
 
+/// A wrapper for calling decoding functions that output an Arrow C Data Interface array.
+///
+/// The input encoded data is read from the buffer pointed to by `ptr` and `len`.
+///
+/// The return value is 0 on success, -1 on error.
+/// If successful, a pointer to an `ArrowArray` is written to `out_slice`. Its buffers stay in
+/// the linear memory until the caller releases it with [`arrow_array_release`].
+/// If failed, the error message is written to the buffer.
+///
+/// Only the `ArrowArray` is exported, not its `ArrowSchema`: the caller knows the type of the
+/// EncUnit, see `format/wasm_buffers.md` for how the decoded arrays map to it.
+///
+/// # Safety
+///
+/// `ptr`, `len`, `out_slice` must point to a valid buffer.
+pub unsafe fn arrow_wrapper(
+    function: GeneralDecodeV3,
+    ptr: *const u8,
+    len: usize,
+    out_slice: *mut CSlice,
+) -> i32 {
+    let input = std::slice::from_raw_parts(ptr, len);
+    match call_arrow(function, input) {
+        Ok(array) => {
+            out_slice.write(CSlice {
+                ptr: Box::into_raw(array) as *const u8,
+                len: std::mem::size_of::<FFI_ArrowArray>(),
+            });
+            0
+        }
+        Err(err) => {
+            let msg = err.to_string().into_boxed_str();
+            out_slice.write(CSlice {
+                ptr: msg.as_ptr(),
+                len: msg.len(),
+            });
+            std::mem::forget(msg);
+            -1
+        }
+    }
+}
+
+fn call_arrow(function: GeneralDecodeV3, input_bytes: &[u8]) -> Result<Box<FFI_ArrowArray>, Error> {
+    let data = unsliced(function(input_bytes)?.to_data());
+    Ok(Box::new(FFI_ArrowArray::new(&data)))
+}
+
+/// Release an `ArrowArray` output by [`arrow_wrapper`], together with its buffers.
+///
+/// # Safety
+///
+/// `array` must be a pointer written by a successful call of [`arrow_wrapper`].
+#[no_mangle]
+pub unsafe extern "C" fn arrow_array_release(array: *mut FFI_ArrowArray) {
+    drop(Box::from_raw(array));
+}
+
+/// A wrapper for calling encoding functions from C.
+///
+/// The input is a single-column record batch in the Arrow IPC streaming format,
+/// read from the buffer pointed to by `ptr` and `len`.
+///
+/// The encoded bytes are written to the buffer pointed to by `out_slice`.
+/// The caller is responsible for deallocating the output buffer, whose alignment is 1.
+///
+/// The return value is 0 on success, -1 on error.
+/// If failed, the error message is written to the buffer.
+///
+/// # Safety
+///
+/// `ptr`, `len`, `out_slice` must point to a valid buffer.
+pub unsafe fn encode_wrapper(
+    function: Encode,
+    ptr: *const u8,
+    len: usize,
+    out_slice: *mut CSlice,
+) -> i32 {
+    let input = std::slice::from_raw_parts(ptr, len);
+    match call_encode(function, input) {
+        Ok(data) => {
+            out_slice.write(CSlice {
+                ptr: data.as_ptr(),
+                len: data.len(),
+            });
+            std::mem::forget(data);
+            0
+        }
+        Err(err) => {
+            let msg = err.to_string().into_boxed_str();
+            out_slice.write(CSlice {
+                ptr: msg.as_ptr(),
+                len: msg.len(),
+            });
+            std::mem::forget(msg);
+            -1
+        }
+    }
+}
+
+/// The internal wrapper that returns a Result.
+fn call_encode(function: Encode, input_bytes: &[u8]) -> Result<Box<[u8]>, Error> {
+    let mut reader = StreamReader::try_new(Cursor::new(input_bytes), None)?;
+    let batch = reader
+        .next()
+        .ok_or_else(|| Error::General("Empty input of encode".to_string()))??;
+    if batch.num_columns() != 1 {
+        return Err(Error::General(format!(
+            "Input of encode must have a single column, got {}",
+            batch.num_columns()
+        )));
+    }
+    // The boxed slice has no spare capacity, so the host can deallocate it by its length.
+    Ok(function(batch.column(0).clone())?.into_boxed_slice())
+}
+
 /// Get the next Buffer from the iterator.
 ///
 /// The output Buffer is written to the buffer pointed to by `out`.
@@ -433,4 +561,18 @@ pub unsafe fn decode_wrapper(
         }
     }
 }
+/// The `Decode` API of decoders created with [`init_wrapper`].
+/// The decoder is freed once it has nothing more to decode or fails.
+#[allow(clippy::not_unsafe_ptr_arg_deref)] // The signature is fixed by `Decode`.
+pub fn decode_next(
+    decoder: *mut WasmDecoder,
+) -> Result<Option<Box<dyn Iterator<Item = Buffer>>>, Error> {
+    let mut decoder = unsafe { Box::from_raw(decoder) };
+    let res = decoder.decode()?;
+    if res.is_some() {
+        // Do not free the decoder if there is still some to decode.
+        let _ = Box::into_raw(decoder);
+    }
+    Ok(res)
+}
 //----------END APIs with advanced features support (kwargs) ----------//
@@ -4,42 +4,71 @@ use std::collections::HashMap;
 ///
 use rkyv::{rancor::Error, Archive, Deserialize, Serialize};
 
-/// num_keys (i32)
-/// key_lens (i32 * num_keys)
-/// word_lens (i32 * num_keys)
-/// key-word * num_keys (var len)
-pub fn kwargs_serialize(kwargs: &[(&[u8], &[u8])]) -> Vec<u8> {
-    let num_keys = kwargs.len() as i32;
-    let mut key_lens = Vec::with_capacity(kwargs.len());
-    let mut word_lens = Vec::with_capacity(kwargs.len());
-
-    for &(key, word) in kwargs {
-        key_lens.push(key.len() as i32);
-        word_lens.push(word.len() as i32);
-    }
+pub use fff_core::util::kwargs::{kwargs_serialize, PARTIAL_DECODE, PPD, SPD};
 
-    let mut result = Vec::new();
+/// Kwargs of an EncUnit as passed to a stateful decoder.
+#[derive(Debug, Default)]
+pub struct Kwargs<'a> {
+    words: HashMap<&'a [u8], &'a [u8]>,
+}
 
-    // Serialize num_keys
-    result.extend_from_slice(&num_keys.to_le_bytes());
+impl<'a> Kwargs<'a> {
+    /// Unlike [`kwargs_deserialize`], malformed kwargs are an error instead of a panic.
+    /// Readers pass no bytes at all if there are no kwargs.
+    pub fn parse(bytes: &'a [u8]) -> crate::Result<Self> {
+        if bytes.is_empty() {
+            return Ok(Self::default());
+        }
+        let malformed = || fff_core::errors::Error::General("Malformed kwargs".to_string());
+        let take = |offset: usize, len: usize| {
+            offset
+                .checked_add(len)
+                .and_then(|end| bytes.get(offset..end))
+                .ok_or_else(malformed)
+        };
+        let read_len = |offset: usize| -> crate::Result<usize> {
+            let len = i32::from_le_bytes(take(offset, 4)?.try_into().map_err(|_| malformed())?);
+            usize::try_from(len).map_err(|_| malformed())
+        };
+        let num_keys = read_len(0)?;
+        // Check the lengths fit before computing offsets from num_keys.
+        take(4, num_keys.checked_mul(8).ok_or_else(malformed)?)?;
+        let mut data_offset = 4 + num_keys * 8;
+        let mut words = HashMap::with_capacity(num_keys);
+        for i in 0..num_keys {
+            let key_len = read_len(4 + i * 4)?;
+            let word_len = read_len(4 + (num_keys + i) * 4)?;
+            let key = take(data_offset, key_len)?;
+            let word = take(data_offset + key_len, word_len)?;
+            data_offset += key_len + word_len;
+            words.insert(key, word);
+        }
+        Ok(Self { words })
+    }
 
-    // Serialize key_lens array
-    for len in &key_lens {
-        result.extend_from_slice(&len.to_le_bytes());
+    pub fn get(&self, key: &str) -> Option<&'a [u8]> {
+        self.words.get(key.as_bytes()).copied()
     }
 
-    // Serialize word_lens array
-    for len in &word_lens {
-        result.extend_from_slice(&len.to_le_bytes());
+    /// Rows to decode, as a serialized roaring bitmap.
+    pub fn selection(&self) -> Option<&'a [u8]> {
+        self.get(SPD)
     }
 
-    // Serialize each key and word in order
-    for &(key, word) in kwargs {
-        result.extend_from_slice(key);
-        result.extend_from_slice(word);
+    pub fn predicate(&self) -> crate::Result<Option<&'a ArchivedPPDExpr>> {
+        self.get(PPD)
+            .map(|word| {
+                rkyv::access::<ArchivedPPDExpr, Error>(word)
+                    .map_err(|e| fff_core::errors::Error::General(format!("Malformed ppd: {}", e)))
+            })
+            .transpose()
     }
 
-    result
+    pub fn partial_decode(&self) -> bool {
+        self.get(PARTIAL_DECODE)
+            .and_then(|word| word.first())
+            .is_some_and(|enabled| *enabled != 0)
+    }
 }
 
 pub fn kwargs_deserialize(bytes: &[u8]) -> HashMap<&[u8], &[u8]> {
@@ -1,12 +1,19 @@
 #![allow(clippy::missing_safety_doc)]
 
+use arrow_array::ArrayRef;
 use arrow_buffer::{Buffer, MutableBuffer};
-use arrow_data::ArrayData;
+use arrow_data::{transform::MutableArrayData, ArrayData};
 pub use fff_core::errors::Result;
 use ffi::WasmDecoder;
 
 pub mod ffi;
 pub mod kwargs;
+pub mod testing;
+
+pub use fff_ude_macros::{arrow_decoder, decoder, stateful_decoder};
+
+// Lets the macros refer to `::fff_ude` in this crate as well.
+extern crate self as fff_ude;
 
 /// Decode scalar data like int32 and float32. i.e., single type, single buffer as input/output
 pub type ScalarDecode = fn(input: &[u8]) -> Result<Box<[u8]>>;
@@ -18,12 +25,24 @@ pub type StringDecode = fn(input: &[u8]) -> Result<Vec<Box<[u8]>>>;
 pub type GeneralDecode = fn(input: &[u8]) -> Result<Box<dyn Iterator<Item = Buffer>>>;
 /// An experimental API for more than one byte sequence of input
 pub type GeneralDecodeV2 = fn(inputs: &[&[u8]]) -> Result<Box<dyn Iterator<Item = Buffer>>>;
-/// An experiemntal API using Arrow FFI
-pub type GeneralDecodeV3 = fn(inputs: &[u8]) -> Result<arrow_array::ffi::FFI_ArrowArray>;
+/// A decode function whose output is handed over to the host as an Arrow C Data Interface array
+/// by [`ffi::arrow_wrapper`], so that the host reads its buffers in place instead of copying them.
+pub type GeneralDecodeV3 = fn(input: &[u8]) -> Result<ArrayRef>;
+
+/// Encode an Arrow Array into the bytes that the decode function of the same encoding takes as input.
+pub type Encode = fn(input: ArrayRef) -> Result<Vec<u8>>;
 
+/// Append the buffers of `array_data` and its children to `res` in the layout the reader
+/// expects from Wasm decoders, see `format/wasm_buffers.md`.
+/// Sliced arrays are copied first, since the layout has no room for offsets.
 pub fn arraydata_to_buffers(res: &mut Vec<Buffer>, array_data: &ArrayData) {
+    if array_data.offset() != 0 {
+        let mut data = MutableArrayData::new(vec![array_data], false, array_data.len());
+        data.extend(0, 0, array_data.len());
+        return arraydata_to_buffers(res, &data.freeze());
+    }
     res.push(match array_data.nulls() {
-        Some(nulls) => nulls.buffer().clone(),
+        Some(nulls) => nulls.inner().sliced(),
         None => MutableBuffer::new(0).into(),
     });
     // let ptr = res[0].as_ptr();
@@ -38,11 +57,31 @@ pub fn arraydata_to_buffers(res: &mut Vec<Buffer>, array_data: &ArrayData) {
     }
 }
 
+/// Copy `array_data` if it or any of its descendants is sliced, since neither the buffers layout
+/// nor the host import of [`ffi::arrow_wrapper`] support offsets.
+pub fn unsliced(array_data: ArrayData) -> ArrayData {
+    fn is_sliced(data: &ArrayData) -> bool {
+        data.offset() != 0 || data.child_data().iter().any(is_sliced)
+    }
+    if !is_sliced(&array_data) {
+        return array_data;
+    }
+    let mut data = MutableArrayData::new(vec![&array_data], false, array_data.len());
+    data.extend(0, 0, array_data.len());
+    data.freeze()
+}
+
 /// Stateful WasmDecoder for the Prepare-Init-Decode APIs
 pub trait StatefulWasmDecoder {
     fn decode(&mut self) -> Result<Option<Box<dyn Iterator<Item = Buffer>>>>;
 }
 
+impl<T: StatefulWasmDecoder + ?Sized> StatefulWasmDecoder for Box<T> {
+    fn decode(&mut self) -> Result<Option<Box<dyn Iterator<Item = Buffer>>>> {
+        (**self).decode()
+    }
+}
+
 /// Init API
 pub type Init = fn(input: &[u8], kwargs: &[u8]) -> Result<Box<dyn StatefulWasmDecoder>>;
 /// Decode API
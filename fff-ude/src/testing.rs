@@ -0,0 +1,224 @@
+//! Native test harness for decoders exported with [`decoder`](crate::decoder),
+//! [`stateful_decoder`](crate::stateful_decoder) and [`arrow_decoder`](crate::arrow_decoder).
+//!
+//! It calls the exports through the same ABI as the Wasm runtime, so a decoder can be tested
+//! with `cargo test` on the host before being compiled to Wasm.
+
+use arrow_array::{
+    ffi::{from_ffi_and_data_type, FFI_ArrowArray},
+    make_array, ArrayRef,
+};
+use arrow_buffer::Buffer;
+use arrow_schema::DataType;
+use fff_core::errors::Error;
+
+use crate::{
+    ffi::{BufferIter, CSlice, WasmDecoder},
+    kwargs::kwargs_serialize,
+    Result,
+};
+
+/// Signature of the exports of [`decoder`](crate::decoder).
+pub type GeneralDecodeFfi = unsafe extern "C" fn(*const u8, usize, *mut CSlice) -> i32;
+/// Signature of the `init_ffi` export of [`stateful_decoder`](crate::stateful_decoder).
+pub type InitFfi = unsafe extern "C" fn(*const u8, usize, *const u8, usize, *mut CSlice) -> i32;
+/// Signature of the `decode_ffi` export of [`stateful_decoder`](crate::stateful_decoder).
+pub type DecodeFfi = unsafe extern "C" fn(*mut WasmDecoder, *mut CSlice) -> i32;
+/// Signature of the `decode_arrow_ffi` export of [`arrow_decoder`](crate::arrow_decoder).
+pub type ArrowDecodeFfi = GeneralDecodeFfi;
+
+fn empty_slice() -> CSlice {
+    CSlice {
+        ptr: std::ptr::null(),
+        len: 0,
+    }
+}
+
+/// Decode `input` with a general decoder export, e.g., `decode_general_ffi`.
+pub fn call_decoder(export: GeneralDecodeFfi, input: &[u8]) -> Result<Vec<Buffer>> {
+    let mut out = empty_slice();
+    match unsafe { export(input.as_ptr(), input.len(), &mut out) } {
+        0 => Ok(unsafe { take_buffers(out) }),
+        _ => Err(unsafe { take_error(out) }),
+    }
+}
+
+/// Decode `input` with the `init_ffi` and `decode_ffi` exports of a stateful decoder.
+/// Returns the buffers of every `decode` call until the decoder is exhausted.
+pub fn call_stateful_decoder(
+    init: InitFfi,
+    decode: DecodeFfi,
+    input: &[u8],
+    kwargs: &[(&[u8], &[u8])],
+) -> Result<Vec<Vec<Buffer>>> {
+    // Like the reader, pass no bytes at all without kwargs.
+    let kwargs = if kwargs.is_empty() {
+        vec![]
+    } else {
+        kwargs_serialize(kwargs)
+    };
+    let mut out = empty_slice();
+    let status = unsafe {
+        init(
+            input.as_ptr(),
+            input.len(),
+            kwargs.as_ptr(),
+            kwargs.len(),
+            &mut out,
+        )
+    };
+    if status != 0 {
+        return Err(unsafe { take_error(out) });
+    }
+    let decoder = out.ptr as *mut WasmDecoder;
+    let mut batches = vec![];
+    loop {
+        let mut out = empty_slice();
+        match unsafe { decode(decoder, &mut out) } {
+            0 => batches.push(unsafe { take_buffers(out) }),
+            1 => return Ok(batches),
+            _ => return Err(unsafe { take_error(out) }),
+        }
+    }
+}
+
+/// Decode `input` with the `decode_arrow_ffi` export of an arrow decoder, importing its output
+/// as an array of `data_type`.
+pub fn call_arrow_decoder(
+    export: ArrowDecodeFfi,
+    input: &[u8],
+    data_type: &DataType,
+) -> Result<ArrayRef> {
+    let mut out = empty_slice();
+    match unsafe { export(input.as_ptr(), input.len(), &mut out) } {
+        0 => {
+            let array = unsafe { Box::from_raw(out.ptr as *mut FFI_ArrowArray) };
+            let data = unsafe { from_ffi_and_data_type(*array, data_type.clone())? };
+            Ok(make_array(data))
+        }
+        _ => Err(unsafe { take_error(out) }),
+    }
+}
+
+/// # Safety
+///
+/// `out` must hold a `BufferIter` written by a successful call.
+unsafe fn take_buffers(out: CSlice) -> Vec<Buffer> {
+    Box::from_raw(out.ptr as *mut BufferIter).iter.collect()
+}
+
+/// # Safety
+///
+/// `out` must hold the error message written by a failed call.
+unsafe fn take_error(out: CSlice) -> Error {
+    let msg = Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+        out.ptr as *mut u8,
+        out.len,
+    ));
+    Error::General(String::from_utf8_lossy(&msg).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Array, Int32Array};
+
+    use super::*;
+    use crate::{
+        kwargs::{Kwargs, PARTIAL_DECODE},
+        StatefulWasmDecoder,
+    };
+
+    #[crate::decoder(export = "decode_copy_ffi")]
+    fn decode_copy(input: &[u8]) -> Result<Box<dyn Iterator<Item = Buffer>>> {
+        if input.is_empty() {
+            return Err(Error::General("empty input".to_string()));
+        }
+        Ok(Box::new(std::iter::once(Buffer::from(input.to_vec()))))
+    }
+
+    /// Decodes the input in chunks of 2 bytes, or of 1 byte with partial_decode.
+    struct ChunkDecoder {
+        input: Vec<u8>,
+        chunk_size: usize,
+    }
+
+    impl StatefulWasmDecoder for ChunkDecoder {
+        fn decode(&mut self) -> Result<Option<Box<dyn Iterator<Item = Buffer>>>> {
+            if self.input.is_empty() {
+                return Ok(None);
+            }
+            let rest = self.input.split_off(self.chunk_size.min(self.input.len()));
+            let chunk = std::mem::replace(&mut self.input, rest);
+            Ok(Some(Box::new(std::iter::once(Buffer::from(chunk)))))
+        }
+    }
+
+    #[crate::stateful_decoder]
+    fn init_chunks(input: &[u8], kwargs: Kwargs) -> Result<ChunkDecoder> {
+        Ok(ChunkDecoder {
+            input: input.to_vec(),
+            chunk_size: if kwargs.partial_decode() { 1 } else { 2 },
+        })
+    }
+
+    /// Decodes each byte to an Int32, or to a null for 0, and skips the first one.
+    #[crate::arrow_decoder]
+    fn decode_bytes(input: &[u8]) -> Result<ArrayRef> {
+        if input.is_empty() {
+            return Err(Error::General("empty input".to_string()));
+        }
+        let array = Int32Array::from_iter(input.iter().map(|&b| (b != 0).then_some(b as i32)));
+        Ok(std::sync::Arc::new(array.slice(1, input.len() - 1)))
+    }
+
+    #[test]
+    fn test_decoder() {
+        let buffers = call_decoder(decode_copy_ffi, b"abc").unwrap();
+        assert_eq!(buffers, vec![Buffer::from(b"abc".to_vec())]);
+        let err = call_decoder(decode_copy_ffi, b"").unwrap_err();
+        assert!(err.to_string().contains("empty input"));
+    }
+
+    #[test]
+    fn test_arrow_decoder() {
+        let array = call_arrow_decoder(decode_arrow_ffi, &[9, 1, 0, 3], &DataType::Int32).unwrap();
+        assert_eq!(array.offset(), 0);
+        assert_eq!(
+            array.as_ref(),
+            &Int32Array::from(vec![Some(1), None, Some(3)]) as &dyn Array
+        );
+        let err = call_arrow_decoder(decode_arrow_ffi, b"", &DataType::Int32).unwrap_err();
+        assert!(err.to_string().contains("empty input"));
+    }
+
+    #[test]
+    fn test_stateful_decoder() {
+        let lens = |batches: Vec<Vec<Buffer>>| {
+            batches
+                .iter()
+                .map(|buffers| buffers[0].len())
+                .collect::<Vec<_>>()
+        };
+        let batches = call_stateful_decoder(init_ffi, decode_ffi, b"abcde", &[]).unwrap();
+        assert_eq!(lens(batches), vec![2, 2, 1]);
+        let batches = call_stateful_decoder(
+            init_ffi,
+            decode_ffi,
+            b"abc",
+            &[(PARTIAL_DECODE.as_bytes(), [1u8].as_slice())],
+        )
+        .unwrap();
+        assert_eq!(lens(batches), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn test_malformed_kwargs() {
+        let mut out = empty_slice();
+        let kwargs = 3i32.to_le_bytes();
+        let status = unsafe { init_ffi(b"a".as_ptr(), 1, kwargs.as_ptr(), 4, &mut out) };
+        assert_eq!(status, -1);
+        assert!(unsafe { take_error(out) }
+            .to_string()
+            .contains("Malformed kwargs"));
+    }
+}
@@ -1,11 +1,7 @@
-use fff_ude::ffi::general_wrapper;
-use wasm_test_encoders::decode_custom;
+use arrow_buffer::Buffer;
+use fff_ude::Result;
 
-#[no_mangle]
-pub unsafe extern "C" fn decode_general_ffi(
-    ptr: *const u8,
-    len: usize,
-    out: *mut fff_ude::ffi::CSlice,
-) -> i32 {
-    general_wrapper(decode_custom, ptr, len, out)
+#[fff_ude::decoder]
+fn decode(input: &[u8]) -> Result<Box<dyn Iterator<Item = Buffer>>> {
+    wasm_test_encoders::decode_custom(input)
 }
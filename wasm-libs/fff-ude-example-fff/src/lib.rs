@@ -1,5 +1,5 @@
-use fff_ude::ffi::general_wrapper;
-use wasm_test_encoders::decode_fff_general;
+use fff_ude::ffi::{arrow_wrapper, general_wrapper};
+use wasm_test_encoders::{decode_fff_arrow, decode_fff_general};
 
 // use talc::*;
 
@@ -22,3 +22,12 @@ pub unsafe extern "C" fn decode_general_ffi(
 ) -> i32 {
     general_wrapper(decode_fff_general, ptr, len, out)
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn decode_arrow_ffi(
+    ptr: *const u8,
+    len: usize,
+    out: *mut fff_ude::ffi::CSlice,
+) -> i32 {
+    arrow_wrapper(decode_fff_arrow, ptr, len, out)
+}
@@ -1,5 +1,8 @@
-use fff_ude::ffi::general_wrapper;
-use wasm_test_encoders::decode_pco_real_general;
+use fff_ude::{
+    ffi::{encode_wrapper, general_wrapper},
+    Result,
+};
+use wasm_test_encoders::{decode_pco_real_general, encode_pco_real_general};
 
 #[no_mangle]
 pub unsafe extern "C" fn decode_general_ffi(
@@ -9,3 +12,17 @@ pub unsafe extern "C" fn decode_general_ffi(
 ) -> i32 {
     general_wrapper(decode_pco_real_general, ptr, len, out)
 }
+
+fn encode(input: arrow_array::ArrayRef) -> Result<Vec<u8>> {
+    Ok(encode_pco_real_general(input))
+}
+
+/// The same encoder as `fff-ude-example-pco-real-encoder`, but run in the Wasm sandbox of the writer.
+#[no_mangle]
+pub unsafe extern "C" fn encode_ffi(
+    ptr: *const u8,
+    len: usize,
+    out: *mut fff_ude::ffi::CSlice,
+) -> i32 {
+    encode_wrapper(encode, ptr, len, out)
+}
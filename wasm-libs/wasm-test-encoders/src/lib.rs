@@ -10,7 +10,9 @@ use std::{
 use arrow_array::{
     cast::AsArray,
     ffi::{FFI_ArrowArray, FFI_ArrowSchema},
-    make_array, Array, ArrayRef, ArrowPrimitiveType, PrimitiveArray,
+    make_array,
+    types::{Int32Type, Int64Type},
+    Array, ArrayRef, ArrowPrimitiveType, PrimitiveArray,
 };
 use arrow_buffer::{Buffer, MutableBuffer};
 use arrow_schema::DataType;
@@ -18,8 +20,9 @@ use bytemuck::AnyBitPattern;
 use byteorder::{LittleEndian, ReadBytesExt};
 use bytes::Bytes;
 use fastlanes::BitPacking;
+use fff_core::{errors::Error, util::buffer_to_array::new_list_offsets_validity_from_buffers};
 use fff_encoding::schemes::{
-    vortex::{VortexDecoder, VortexEncoder},
+    vortex::{vortex_deser_all_to_arrow, VortexDecoder, VortexEncoder},
     Decoder, Encoder,
 };
 use fff_ude::{arraydata_to_buffers, Result};
@@ -66,31 +69,67 @@ pub fn decode_fff_general_normal_ver(input: &[u8]) -> Result<Box<dyn Iterator<It
 pub fn decode_fff_general(input: &[u8]) -> Result<Box<dyn Iterator<Item = Buffer>>> {
     // We have to always copy here, since the vortx decoder may zero-copy from the input to output
     let bytes = Bytes::copy_from_slice(input);
-    // let bytes = unsafe {
-    //     Bytes::from(Vec::from_raw_parts(
-    //         input.as_ptr() as *mut u8,
-    //         input.len(),
-    //         input.len(),
-    //     ))
-    // };
-    let mut vortex_decoder = VortexDecoder::try_new(bytes.clone(), ALL_ENCODINGS_CONTEXT.clone())?;
-    let data = vortex_decoder.decode_all_as_array().unwrap().to_data();
-
-    // Const ptr should not be dropped
-    // std::mem::forget(bytes);
+    let arrays = vortex_deser_all_to_arrow(bytes, ALL_ENCODINGS_CONTEXT.clone())?;
     let mut res: Vec<Buffer> = vec![];
-    arraydata_to_buffers(&mut res, &data);
+    match arrays.as_slice() {
+        [array] => arraydata_to_buffers(&mut res, &array.to_data()),
+        // List EncUnits hold the validity and the offsets, followed by the values for the
+        // List(Struct) layout, see format/wasm_buffers.md.
+        [validity, offsets, values @ ..] if values.len() <= 1 => {
+            let validity = validity.as_boolean_opt().ok_or_else(|| {
+                Error::General("The validity of a List must be Boolean".to_string())
+            })?;
+            res.push(validity.values().sliced());
+            let mut offsets_buffers = vec![];
+            arraydata_to_buffers(&mut offsets_buffers, &offsets.to_data());
+            res.push(offsets_buffers.swap_remove(1));
+            for values in values {
+                arraydata_to_buffers(&mut res, &values.to_data());
+            }
+        }
+        _ => {
+            return Err(Error::General(format!(
+                "Unexpected EncUnit of {} arrays",
+                arrays.len()
+            )))
+        }
+    }
     Ok(Box::new(res.into_iter()))
 }
 
-// pub fn decode_fff_general_ffi(input: &[u8]) -> Result<ffi::FFI_ArrowArray> {
-//     // We have to always copy here, since the vortx decoder may zero-copy from the input to output
-//     let bytes = Bytes::copy_from_slice(input);
-
-//     let mut vortex_decoder = VortexDecoder::try_new(bytes.clone(), ALL_ENCODINGS_CONTEXT.clone())?;
-//     let data = vortex_decoder.decode_all_as_array().unwrap().to_data();
-//     Ok(ffi::FFI_ArrowArray::new(&data))
-// }
+/// Like [`decode_fff_general`], for the Arrow C Data Interface ABI. List EncUnits decode to a
+/// List whose values are dummy nulls unless they hold the values of the List(Struct) layout.
+pub fn decode_fff_arrow(input: &[u8]) -> Result<ArrayRef> {
+    // We have to always copy here, since the vortx decoder may zero-copy from the input to output
+    let bytes = Bytes::copy_from_slice(input);
+    let arrays = vortex_deser_all_to_arrow(bytes, ALL_ENCODINGS_CONTEXT.clone())?;
+    match arrays.as_slice() {
+        [array] => Ok(array.clone()),
+        [validity, offsets, values @ ..] if values.len() <= 1 => {
+            let validity = validity.as_boolean_opt().ok_or_else(|| {
+                Error::General("The validity of a List must be Boolean".to_string())
+            })?;
+            let mut offsets_buffers = vec![];
+            arraydata_to_buffers(&mut offsets_buffers, &offsets.to_data());
+            let buffers = vec![validity.values().sliced(), offsets_buffers.swap_remove(1)];
+            let num_rows = validity.len() as u64;
+            let child = values.first().cloned();
+            match offsets.data_type() {
+                DataType::Int32 => {
+                    new_list_offsets_validity_from_buffers::<Int32Type>(buffers, num_rows, child)
+                }
+                DataType::Int64 => {
+                    new_list_offsets_validity_from_buffers::<Int64Type>(buffers, num_rows, child)
+                }
+                dt => Err(Error::General(format!("Unexpected List offsets of {dt}"))),
+            }
+        }
+        _ => Err(Error::General(format!(
+            "Unexpected EncUnit of {} arrays",
+            arrays.len()
+        ))),
+    }
+}
 
 pub fn encode_flsbp_general<T: fastlanes::FastLanes + BitPacking + 'static>(
     input: &[T],
@@ -243,7 +282,7 @@ pub unsafe extern "C" fn encode_pco_real_general_c(
     RustBuffer::from_vec(encode_pco_real_general(array))
 }
 
-fn encode_pco_real_general(input: ArrayRef) -> Vec<u8> {
+pub fn encode_pco_real_general(input: ArrayRef) -> Vec<u8> {
     use arrow_array::types::*;
     match *input.data_type() {
         DataType::Int8 | DataType::UInt8 => unimplemented!(),